@@ -0,0 +1,171 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - AUTOMATION
+// Breakpoint lanes for mix parameters, evaluated by the callback at the
+// playhead position
+// ============================================================
+
+use serde::{Deserialize, Serialize};
+
+use crate::midi::MidiParam;
+
+/// Lanes the renderer reserves room for; `set_automation` refuses more
+pub const MAX_AUTOMATION_LANES: usize = 32;
+
+/// Parameters other than track volume and pan are applied through their
+/// commands (which recompute filters and the like) once per this many
+/// frames instead of every frame
+pub const AUTOMATION_CONTROL_FRAMES: usize = 32;
+
+/// How a lane moves from one point to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight line to the next point's value
+    #[default]
+    Linear,
+    /// Keep this point's value until the next point
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    /// Position in steps from the loop start; fractions fall between steps
+    pub step: f64,
+    pub value: f64,
+    /// Shape of the segment that starts at this point
+    #[serde(default)]
+    pub curve: Interpolation,
+}
+
+/// One parameter's breakpoints, sorted by step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutomationLane {
+    pub param: MidiParam,
+    pub points: Vec<AutomationPoint>,
+}
+
+impl AutomationLane {
+    /// The lane's value at `step`: the first point's value before it, the
+    /// last point's after it, `None` without points
+    #[inline]
+    pub fn value_at(&self, step: f64) -> Option<f64> {
+        let next = self.points.partition_point(|p| p.step <= step);
+        let Some(from) = next.checked_sub(1).map(|i| self.points[i]) else {
+            return self.points.first().map(|p| p.value);
+        };
+        let Some(to) = self.points.get(next) else {
+            return Some(from.value);
+        };
+        Some(match from.curve {
+            Interpolation::Hold => from.value,
+            Interpolation::Linear => {
+                let t = (step - from.step) / (to.step - from.step);
+                from.value + (to.value - from.value) * t
+            }
+        })
+    }
+}
+
+/// Put `points` in step order, as `AutomationLane` expects
+pub fn sort_points(points: &mut [AutomationPoint]) {
+    points.sort_by(|a, b| a.step.total_cmp(&b.step));
+}
+
+/// Replace `param`'s lane in `lanes`, add it, or (with no points) remove
+/// it. A new lane past `MAX_AUTOMATION_LANES` is dropped; the renderer
+/// keeps that much room reserved, so this never reallocates. Returns the
+/// points no lane holds any more, for the renderer to free elsewhere.
+pub fn set_lane(
+    lanes: &mut Vec<AutomationLane>,
+    param: MidiParam,
+    points: Vec<AutomationPoint>,
+) -> Vec<AutomationPoint> {
+    let existing = lanes.iter().position(|lane| lane.param == param);
+    match existing {
+        Some(i) if points.is_empty() => lanes.remove(i).points,
+        Some(i) => std::mem::replace(&mut lanes[i].points, points),
+        None if !points.is_empty() && lanes.len() < MAX_AUTOMATION_LANES => {
+            lanes.push(AutomationLane { param, points });
+            Vec::new()
+        }
+        None => points,
+    }
+}
+
+/// Follow `removed` leaving the track list: its lanes go to `dropped` and
+/// later tracks' lanes shift down
+pub fn track_removed(
+    lanes: &mut Vec<AutomationLane>,
+    removed: usize,
+    mut dropped: impl FnMut(AutomationLane),
+) {
+    let mut i = 0;
+    while i < lanes.len() {
+        match lanes[i].param.after_track_removed(removed) {
+            Some(param) => {
+                lanes[i].param = param;
+                i += 1;
+            }
+            None => dropped(lanes.remove(i)),
+        }
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(step: f64, value: f64, curve: Interpolation) -> AutomationPoint {
+        AutomationPoint { step, value, curve }
+    }
+
+    #[test]
+    fn test_lane_interpolates_linear_and_hold_segments() {
+        let lane = AutomationLane {
+            param: MidiParam::ReverbMix,
+            points: vec![
+                point(2.0, 0.2, Interpolation::Linear),
+                point(4.0, 0.6, Interpolation::Hold),
+                point(6.0, 0.0, Interpolation::Linear),
+            ],
+        };
+        assert_eq!(lane.value_at(0.0), Some(0.2));
+        assert_eq!(lane.value_at(2.0), Some(0.2));
+        assert!((lane.value_at(3.0).unwrap() - 0.4).abs() < 1e-12);
+        assert_eq!(lane.value_at(4.0), Some(0.6));
+        assert_eq!(lane.value_at(5.99), Some(0.6));
+        assert_eq!(lane.value_at(6.0), Some(0.0));
+        assert_eq!(lane.value_at(60.0), Some(0.0));
+
+        let empty = AutomationLane { param: MidiParam::ReverbMix, points: Vec::new() };
+        assert_eq!(empty.value_at(1.0), None);
+    }
+
+    #[test]
+    fn test_removed_track_drops_and_shifts_lanes() {
+        let points = vec![point(0.0, 0.5, Interpolation::Linear)];
+        let mut lanes = Vec::new();
+        for param in [
+            MidiParam::TrackVolume { track: 1 },
+            MidiParam::TrackPan { track: 3 },
+            MidiParam::StereoWidth,
+        ] {
+            set_lane(&mut lanes, param, points.clone());
+        }
+        let mut dropped = Vec::new();
+        track_removed(&mut lanes, 1, |lane| dropped.push(lane.param));
+        let params: Vec<MidiParam> = lanes.iter().map(|lane| lane.param).collect();
+        assert_eq!(params, [MidiParam::TrackPan { track: 2 }, MidiParam::StereoWidth]);
+        assert_eq!(dropped, [MidiParam::TrackVolume { track: 1 }]);
+
+        // New points hand the old ones back, and no points clears a lane
+        let moved = vec![point(4.0, 0.25, Interpolation::Hold)];
+        assert_eq!(set_lane(&mut lanes, MidiParam::StereoWidth, moved.clone()), points);
+        assert_eq!(set_lane(&mut lanes, MidiParam::StereoWidth, Vec::new()), moved);
+        assert_eq!(lanes.len(), 1);
+    }
+}
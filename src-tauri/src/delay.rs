@@ -0,0 +1,358 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - DELAY
+// Tempo-synced stereo delay for the master bus
+// ============================================================
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mixer::{EqBand, FilterKind, SmoothedParam};
+
+/// Longest delay the buffer can hold
+const MAX_DELAY_SECONDS: f64 = 4.0;
+
+/// Tempo changes glide the read tap over this time instead of jumping
+const DELAY_TIME_SMOOTHING_MS: f64 = 50.0;
+
+/// Feedback is capped below unity so the loop always decays
+pub const MAX_FEEDBACK: f64 = 0.95;
+
+/// Feedback low-pass corner range; at the top the loop stays unfiltered
+pub const MIN_DELAY_TONE_HZ: f64 = 200.0;
+pub const MAX_DELAY_TONE_HZ: f64 = 20000.0;
+
+/// Feedback high-pass corner range
+pub const MIN_DELAY_LOW_CUT_HZ: f64 = 20.0;
+pub const MAX_DELAY_LOW_CUT_HZ: f64 = 2000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteDivision {
+    Quarter,
+    #[default]
+    Eighth,
+    DottedEighth,
+    Sixteenth,
+}
+
+impl NoteDivision {
+    /// Length in quarter-note beats
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::DottedEighth => 0.75,
+            NoteDivision::Sixteenth => 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Delay {
+    buffers: [Vec<f64>; 2],
+    write_pos: usize,
+    // Delay time in (fractional) samples, glided on tempo changes
+    delay_samples: SmoothedParam,
+    division: NoteDivision,
+    bpm: f64,
+    feedback: f64,
+    mix: f64, // 0.0 = dry, 1.0 = wet
+    // Input (summed to mono) enters the left line only and feedback
+    // crosses sides, so echoes alternate left and right
+    pingpong: bool,
+    // Filters on the feedback path, so each repeat is darker (and with the
+    // low cut, thinner) than the one before; the first echo is untouched
+    tone: [EqBand; 2],
+    tone_on: bool,
+    low_cut: [EqBand; 2],
+    low_cut_on: bool,
+    // Track sends queued for the next `process` call
+    send: (f64, f64),
+    sample_rate: f64,
+}
+
+impl Delay {
+    pub fn new(sample_rate: f64) -> Self {
+        let len = (MAX_DELAY_SECONDS * sample_rate) as usize + 2;
+        let division = NoteDivision::default();
+        let bpm = 120.0;
+        Self {
+            buffers: [vec![0.0; len], vec![0.0; len]],
+            write_pos: 0,
+            delay_samples: SmoothedParam::with_ramp_ms(
+                Self::samples_for(division, bpm, sample_rate, len),
+                sample_rate,
+                DELAY_TIME_SMOOTHING_MS,
+            ),
+            division,
+            bpm,
+            feedback: 0.35,
+            mix: 0.0,
+            pingpong: false,
+            tone: [Self::tone_band(sample_rate), Self::tone_band(sample_rate)],
+            tone_on: false,
+            low_cut: [Self::low_cut_band(sample_rate), Self::low_cut_band(sample_rate)],
+            low_cut_on: false,
+            send: (0.0, 0.0),
+            sample_rate,
+        }
+    }
+
+    fn tone_band(sample_rate: f64) -> EqBand {
+        let cutoff = MAX_DELAY_TONE_HZ.min(sample_rate * 0.45);
+        EqBand::with_kind(FilterKind::LowPass, cutoff, 0.0, FRAC_1_SQRT_2, sample_rate)
+    }
+
+    fn low_cut_band(sample_rate: f64) -> EqBand {
+        let cutoff = MIN_DELAY_LOW_CUT_HZ;
+        EqBand::with_kind(FilterKind::HighPass, cutoff, 0.0, FRAC_1_SQRT_2, sample_rate)
+    }
+
+    fn samples_for(division: NoteDivision, bpm: f64, sample_rate: f64, len: usize) -> f64 {
+        let seconds = division.beats() * 60.0 / bpm.max(1.0);
+        (seconds * sample_rate).clamp(1.0, (len - 2) as f64)
+    }
+
+    fn retarget(&mut self) {
+        let len = self.buffers[0].len();
+        let samples = Self::samples_for(self.division, self.bpm, self.sample_rate, len);
+        self.delay_samples.set_target(samples);
+    }
+
+    /// Follow the transport tempo (cheap when unchanged)
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm != self.bpm {
+            self.bpm = bpm;
+            self.retarget();
+        }
+    }
+
+    pub fn set_division(&mut self, division: NoteDivision) {
+        self.division = division;
+        self.retarget();
+    }
+
+    pub fn set_feedback(&mut self, feedback: f64) {
+        self.feedback = feedback.clamp(0.0, MAX_FEEDBACK);
+    }
+
+    pub fn set_mix(&mut self, mix: f64) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn set_pingpong(&mut self, on: bool) {
+        self.pingpong = on;
+    }
+
+    /// Low-pass the feedback at `cutoff` Hz (`MAX_DELAY_TONE_HZ` turns it
+    /// off) and, given `low_cut`, high-pass it there too. A filter that was
+    /// off starts from silence rather than its stale history.
+    pub fn set_tone(&mut self, cutoff: f64, low_cut: Option<f64>) {
+        let sample_rate = self.sample_rate;
+        let cutoff = cutoff.clamp(MIN_DELAY_TONE_HZ, MAX_DELAY_TONE_HZ);
+        let tone_on = cutoff < MAX_DELAY_TONE_HZ;
+        for band in &mut self.tone {
+            if tone_on && !self.tone_on {
+                *band = Self::tone_band(sample_rate);
+            }
+            band.retune(cutoff.min(sample_rate * 0.45), sample_rate);
+        }
+        self.tone_on = tone_on;
+
+        if let Some(hz) = low_cut {
+            let hz = hz.clamp(MIN_DELAY_LOW_CUT_HZ, MAX_DELAY_LOW_CUT_HZ);
+            for band in &mut self.low_cut {
+                if !self.low_cut_on {
+                    *band = Self::low_cut_band(sample_rate);
+                }
+                band.retune(hz, sample_rate);
+            }
+        }
+        self.low_cut_on = low_cut.is_some();
+    }
+
+    /// One repeat's worth of feedback filtering on `channel`
+    #[inline]
+    fn filter_feedback(&mut self, channel: usize, wet: f64) -> f64 {
+        let wet = if self.tone_on { self.tone[channel].process(wet) } else { wet };
+        if self.low_cut_on {
+            self.low_cut[channel].process(wet)
+        } else {
+            wet
+        }
+    }
+
+    /// Linearly interpolated read `delay` samples behind the write head
+    #[inline]
+    fn read(buffer: &[f64], write_pos: usize, delay: f64) -> f64 {
+        let len = buffer.len();
+        let whole = delay as usize;
+        let frac = delay - whole as f64;
+        let a = buffer[(write_pos + len - whole) % len];
+        let b = buffer[(write_pos + len - whole - 1) % len];
+        a + (b - a) * frac
+    }
+
+    /// Add send-bus signal to the delay input of the next `process` call.
+    /// Sends only feed the echoes; they never reach the dry output.
+    #[inline]
+    pub fn add_send(&mut self, left: f64, right: f64) {
+        self.send.0 += left;
+        self.send.1 += right;
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let delay = self.delay_samples.next();
+        let wet_l = Self::read(&self.buffers[0], self.write_pos, delay);
+        let wet_r = Self::read(&self.buffers[1], self.write_pos, delay);
+
+        // The insert path enters at `mix`, so the echo level matches a
+        // dry/wet crossfade
+        let (send_l, send_r) = std::mem::take(&mut self.send);
+        let input_l = left * self.mix + send_l;
+        let input_r = right * self.mix + send_r;
+        let feedback_l = self.filter_feedback(0, wet_l) * self.feedback;
+        let feedback_r = self.filter_feedback(1, wet_r) * self.feedback;
+        let (line_l, line_r) = if self.pingpong {
+            ((input_l + input_r) * 0.5 + feedback_r, feedback_l)
+        } else {
+            (input_l + feedback_l, input_r + feedback_r)
+        };
+        self.buffers[0][self.write_pos] = line_l;
+        self.buffers[1][self.write_pos] = line_r;
+        self.write_pos = (self.write_pos + 1) % self.buffers[0].len();
+
+        let dry = 1.0 - self.mix;
+        (left * dry + wet_l, right * dry + wet_r)
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_lands_on_the_beat() {
+        let mut delay = Delay::new(48000.0);
+        delay.set_division(NoteDivision::Quarter);
+        delay.set_mix(1.0);
+        delay.set_feedback(0.0);
+        // Let the tap glide from the default division to a quarter note
+        for _ in 0..48000 * 2 {
+            delay.process(0.0, 0.0);
+        }
+
+        // 120 BPM quarter note = 0.5 s = 24000 samples
+        let out: Vec<f64> = (0..30000)
+            .map(|i| delay.process(if i == 0 { 1.0 } else { 0.0 }, 0.0).0)
+            .collect();
+        assert_eq!(out[24000], 1.0);
+        assert!(out.iter().enumerate().all(|(i, &x)| i == 24000 || x == 0.0));
+    }
+
+    #[test]
+    fn test_pingpong_echoes_alternate_sides() {
+        let mut delay = Delay::new(48000.0);
+        delay.set_division(NoteDivision::Sixteenth);
+        delay.set_mix(1.0);
+        delay.set_feedback(0.5);
+        delay.set_pingpong(true);
+        for _ in 0..48000 * 2 {
+            delay.process(0.0, 0.0);
+        }
+
+        // A mono impulse: 1/16 at 120 BPM = 6000 samples between echoes
+        let out: Vec<(f64, f64)> = (0..20000)
+            .map(|i| {
+                let x = if i == 0 { 1.0 } else { 0.0 };
+                delay.process(x, x)
+            })
+            .collect();
+        assert_eq!(out[6000], (1.0, 0.0));
+        assert_eq!(out[12000], (0.0, 0.5));
+        assert_eq!(out[18000], (0.25, 0.0));
+        let echoes = [6000, 12000, 18000];
+        assert!(out
+            .iter()
+            .enumerate()
+            .all(|(i, &(l, r))| echoes.contains(&i) || (l == 0.0 && r == 0.0)));
+    }
+
+    #[test]
+    fn test_tone_darkens_each_repeat() {
+        let mut delay = Delay::new(48000.0);
+        delay.set_division(NoteDivision::Sixteenth);
+        delay.set_mix(1.0);
+        delay.set_feedback(0.9);
+        delay.set_tone(3000.0, Some(100.0));
+        for _ in 0..48000 * 2 {
+            delay.process(0.0, 0.0);
+        }
+
+        // Share of each echo's energy in its sample-to-sample differences:
+        // 2.0 for a bare impulse, less the duller the echo. Each window is
+        // centred on one echo, 6000 samples apart.
+        delay.process(1.0, 1.0);
+        for _ in 0..3000 {
+            delay.process(0.0, 0.0);
+        }
+        let brightness: Vec<f64> = (0..6)
+            .map(|_| {
+                let echo: Vec<f64> = (0..6000).map(|_| delay.process(0.0, 0.0).0).collect();
+                let energy: f64 = echo.iter().map(|x| x * x).sum();
+                let edges: f64 = echo.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+                edges / energy
+            })
+            .collect();
+        assert!((brightness[0] - 2.0).abs() < 1e-6, "{:?}", brightness);
+        assert!(brightness.windows(2).all(|w| w[1] < w[0] * 0.9), "{:?}", brightness);
+    }
+
+    #[test]
+    fn test_feedback_is_capped_and_decays() {
+        let mut delay = Delay::new(48000.0);
+        delay.set_division(NoteDivision::Sixteenth);
+        delay.set_feedback(5.0);
+        delay.set_mix(1.0);
+        delay.process(1.0, 1.0);
+
+        let mut last_peak = f64::MAX;
+        for _ in 0..20 {
+            // One 1/16 at 120 BPM = 6000 samples
+            let peak = (0..6000).map(|_| delay.process(0.0, 0.0).0.abs()).fold(0.0, f64::max);
+            assert!(peak <= last_peak);
+            last_peak = peak;
+        }
+        assert!(last_peak < 0.5);
+    }
+
+    #[test]
+    fn test_tempo_change_does_not_click() {
+        let mut delay = Delay::new(48000.0);
+        delay.set_mix(1.0);
+        let sine = |i: usize| (i as f64 * 2.0 * std::f64::consts::PI * 220.0 / 48000.0).sin();
+
+        let mut prev = 0.0;
+        let mut max_jump: f64 = 0.0;
+        for i in 0..96000 {
+            if i == 48000 {
+                delay.set_tempo(90.0);
+            }
+            let (l, _) = delay.process(sine(i), 0.0);
+            if i > 48000 {
+                max_jump = max_jump.max((l - prev).abs());
+            }
+            prev = l;
+        }
+
+        // A 220 Hz sine moves at most ~0.03 per sample; a hard tap jump
+        // would show up as a step several times larger
+        assert!(max_jump < 0.1, "max jump {}", max_jump);
+    }
+}
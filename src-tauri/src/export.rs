@@ -0,0 +1,416 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - EXPORT
+// Offline bounce of the arrangement to a WAV file
+// ============================================================
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::renderer::Renderer;
+use crate::sampler::Sample;
+use crate::sequencer::TimeSignature;
+
+/// Exports are always rendered at this rate, independent of the device
+pub const EXPORT_SAMPLE_RATE: u32 = 48000;
+
+pub const EXPORT_CHANNELS: usize = 2;
+
+// Frames rendered between progress reports
+const BLOCK_FRAMES: usize = 4096;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Sample format of the exported file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    #[serde(rename = "pcm16")]
+    Pcm16,
+    #[serde(rename = "pcm24")]
+    Pcm24,
+    /// Written as-is; needs no dither
+    #[serde(rename = "float32")]
+    Float32,
+}
+
+impl ExportFormat {
+    fn bits(self) -> u16 {
+        match self {
+            ExportFormat::Pcm16 => 16,
+            ExportFormat::Pcm24 => 24,
+            ExportFormat::Float32 => 32,
+        }
+    }
+}
+
+/// Noise added before rounding to integer PCM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    /// Plain rounding; the error follows the signal and turns quiet tails
+    /// into distortion
+    #[default]
+    Off,
+    /// Triangular noise of +-1 LSB, which makes the error white and
+    /// independent of the signal
+    Tpdf,
+    /// TPDF with first-order error feedback, moving the noise up towards
+    /// Nyquist where it is least audible
+    Shaped,
+}
+
+/// Rounds float samples to `bits`-bit integers with the chosen dither.
+/// The noise comes from a fixed-seed generator, so exports are repeatable.
+struct Quantizer {
+    dither: Dither,
+    scale: f64,
+    rng: u32,
+    // Per-channel rounding error of the previous sample, for noise shaping
+    errors: Vec<f64>,
+}
+
+impl Quantizer {
+    fn new(bits: u16, channels: usize, dither: Dither) -> Self {
+        Self {
+            dither,
+            scale: ((1i64 << (bits - 1)) - 1) as f64,
+            rng: 0x9E37_79B9,
+            errors: vec![0.0; channels],
+        }
+    }
+
+    /// Uniform in 0.0..1.0 (xorshift32)
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f64 / 4_294_967_296.0
+    }
+
+    fn quantize(&mut self, sample: f32, channel: usize) -> i32 {
+        let target = sample.clamp(-1.0, 1.0) as f64 * self.scale;
+        let wanted = match self.dither {
+            Dither::Shaped => target - self.errors[channel],
+            _ => target,
+        };
+        let noise = match self.dither {
+            Dither::Off => 0.0,
+            Dither::Tpdf | Dither::Shaped => self.uniform() - self.uniform(),
+        };
+        let value = (wanted + noise).round().clamp(-self.scale - 1.0, self.scale);
+        self.errors[channel] = value - wanted;
+        value as i32
+    }
+}
+
+/// Number of frames `bars` bars of `time_signature` last at `bpm`
+pub fn bar_frames(bars: u32, time_signature: TimeSignature, bpm: u64, sample_rate: u32) -> usize {
+    let samples_per_step = (sample_rate as f64 * 60.0) / (bpm.max(1) as f64 * 4.0);
+    let steps = time_signature.steps_per_bar() as f64 * bars as f64;
+    (samples_per_step * steps).round() as usize
+}
+
+/// Render `bars` bars from `renderer` as interleaved stereo, handing each
+/// block to `write` as soon as it's rendered and calling `progress` with
+/// the completed fraction after it. Returns the number of frames.
+///
+/// This drives the exact same `Renderer::render` the audio callback uses,
+/// so the bounce matches real-time playback sample for sample (short of the
+/// monitor dim / mono, which an offline copy never has).
+pub fn bounce(
+    renderer: &mut Renderer,
+    bars: u32,
+    bpm: u64,
+    mut write: impl FnMut(&[f32]) -> io::Result<()>,
+    mut progress: impl FnMut(f64),
+) -> io::Result<usize> {
+    let total_frames = bar_frames(bars, renderer.time_signature(), bpm, EXPORT_SAMPLE_RATE);
+    let mut buffer = vec![0.0f32; BLOCK_FRAMES * EXPORT_CHANNELS];
+
+    let mut done = 0;
+    while done < total_frames {
+        let frames = BLOCK_FRAMES.min(total_frames - done);
+        let block = &mut buffer[..frames * EXPORT_CHANNELS];
+        renderer.render(block, EXPORT_CHANNELS);
+        write(block)?;
+        done += frames;
+        progress(done as f64 / total_frames as f64);
+    }
+
+    Ok(total_frames)
+}
+
+/// Render `bars` bars of one track's strip output (its voice after trim,
+/// polarity and the strip, before the fader) at the renderer's own rate,
+/// for freezing the track. Uses the same `Renderer::render` as `bounce`,
+/// one frame at a time so the track can be read back after each.
+pub fn render_track(renderer: &mut Renderer, track: usize, bars: u32, bpm: u64) -> Sample {
+    let sample_rate = renderer.sample_rate();
+    let total_frames = bar_frames(bars, renderer.time_signature(), bpm, sample_rate);
+    renderer.capture_track(track);
+
+    let mut left = Vec::with_capacity(total_frames);
+    let mut right = Vec::with_capacity(total_frames);
+    let mut stereo = false;
+    let mut frame = [0.0f32; EXPORT_CHANNELS];
+    for _ in 0..total_frames {
+        renderer.render(&mut frame, EXPORT_CHANNELS);
+        let (l, r) = renderer.captured();
+        left.push(l as f32);
+        right.push(r.unwrap_or(0.0) as f32);
+        stereo |= r.is_some();
+    }
+
+    Sample {
+        data: left,
+        right: stereo.then_some(right),
+        sample_rate,
+    }
+}
+
+/// Size of the data chunk holding `samples` interleaved samples in
+/// `format`. Err if the file would be too big for the 32-bit sizes in a
+/// WAV header (the RIFF size also counts the 36 header bytes after it).
+pub fn wav_data_len(samples: usize, format: ExportFormat) -> Result<u32, String> {
+    let bytes = samples as u64 * (format.bits() / 8) as u64;
+    let max = (u32::MAX - 36) as u64;
+    if bytes > max {
+        return Err(format!("Export would be {} bytes; a WAV file holds at most {}", bytes, max));
+    }
+    Ok(bytes as u32)
+}
+
+/// Writes interleaved float samples to `out` as a WAV file a block at a
+/// time, so an export is never held in memory whole. The header goes out
+/// first, sized for the number of samples promised to `new`.
+pub struct WavWriter<W: Write> {
+    out: W,
+    format: ExportFormat,
+    channels: usize,
+    quantizer: Quantizer,
+    // Samples written so far, which places the next in its channel
+    written: usize,
+}
+
+impl<W: Write> WavWriter<W> {
+    /// Write the header for `samples` samples. `dither` applies to the PCM
+    /// formats only.
+    pub fn new(
+        mut out: W,
+        samples: usize,
+        sample_rate: u32,
+        channels: u16,
+        format: ExportFormat,
+        dither: Dither,
+    ) -> Result<Self, String> {
+        let bits = format.bits();
+        let data_len = wav_data_len(samples, format)?;
+        let block_align = channels * bits / 8;
+        let tag = match format {
+            ExportFormat::Float32 => FORMAT_IEEE_FLOAT,
+            ExportFormat::Pcm16 | ExportFormat::Pcm24 => FORMAT_PCM,
+        };
+
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&tag.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_len.to_le_bytes());
+        out.write_all(&header).map_err(|e| format!("Failed to write WAV header: {}", e))?;
+
+        Ok(Self {
+            out,
+            format,
+            channels: channels as usize,
+            quantizer: Quantizer::new(bits, channels as usize, dither),
+            written: 0,
+        })
+    }
+
+    /// Append the next interleaved samples
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &s in samples {
+            let channel = self.written % self.channels;
+            self.written += 1;
+            match self.format {
+                ExportFormat::Float32 => self.out.write_all(&s.to_le_bytes())?,
+                ExportFormat::Pcm24 => {
+                    let value = self.quantizer.quantize(s, channel).to_le_bytes();
+                    self.out.write_all(&value[..3])?;
+                }
+                ExportFormat::Pcm16 => {
+                    let value = self.quantizer.quantize(s, channel) as i16;
+                    self.out.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush and hand back the output
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Bounce and write a 48 kHz stereo WAV to `path`, block by block
+pub fn export_wav(
+    renderer: &mut Renderer,
+    path: &Path,
+    bars: u32,
+    bpm: u64,
+    format: ExportFormat,
+    dither: Dither,
+    progress: impl FnMut(f64),
+) -> Result<usize, String> {
+    // Refused before anything is rendered or the file is created
+    let frames = bar_frames(bars, renderer.time_signature(), bpm, EXPORT_SAMPLE_RATE);
+    let samples = frames * EXPORT_CHANNELS;
+    wav_data_len(samples, format)?;
+
+    let failed = |e: io::Error| format!("Failed to write {}: {}", path.display(), e);
+    let file = BufWriter::new(File::create(path).map_err(failed)?);
+    let channels = EXPORT_CHANNELS as u16;
+    let mut wav = WavWriter::new(file, samples, EXPORT_SAMPLE_RATE, channels, format, dither)?;
+    bounce(renderer, bars, bpm, |block| wav.write(block), progress).map_err(failed)?;
+    wav.finish().map_err(failed)?;
+    Ok(frames)
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::decode_wav;
+    use crate::{AudioCommand, SharedState};
+    use crossbeam_channel::bounded;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    #[test]
+    fn test_bar_frames() {
+        // One bar of 4/4 at 120 BPM is 2 seconds
+        let four_four = TimeSignature::default();
+        assert_eq!(bar_frames(1, four_four, 120, 48000), 96000);
+        assert_eq!(bar_frames(4, four_four, 120, 44100), 4 * 88200);
+        // 6/8 is three quarter notes long
+        let six_eight = TimeSignature::new(6, 8).unwrap();
+        assert_eq!(bar_frames(1, six_eight, 120, 48000), 72000);
+    }
+
+    /// `samples` as a WAV file in memory, written in blocks of `block`
+    fn encode_wav(samples: &[f32], block: usize, channels: u16, format: ExportFormat) -> Vec<u8> {
+        let (len, dither) = (samples.len(), Dither::Off);
+        let mut wav = WavWriter::new(Vec::new(), len, 48000, channels, format, dither).unwrap();
+        for chunk in samples.chunks(block) {
+            wav.write(chunk).unwrap();
+        }
+        wav.finish().unwrap()
+    }
+
+    #[test]
+    fn test_encode_wav_round_trip() {
+        let samples = [0.0, 0.5, -0.5, 1.0];
+        for (format, tolerance) in [
+            (ExportFormat::Pcm16, 1e-4),
+            (ExportFormat::Pcm24, 1e-6),
+            (ExportFormat::Float32, 0.0),
+        ] {
+            let bytes = encode_wav(&samples, 3, 1, format);
+            let decoded = decode_wav(&bytes).unwrap();
+            assert_eq!(decoded.sample_rate, 48000);
+            for (a, b) in samples.iter().zip(&decoded.data) {
+                assert!((a - b).abs() <= tolerance, "{:?}: {} vs {}", format, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounce_streams_what_one_long_render_makes() {
+        let playing = || {
+            let (state_tx, _) = bounded(1);
+            let (retired_tx, _) = bounded(1);
+            let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+            renderer.apply(AudioCommand::SetStep { track: 0, step: 0, on: true });
+            renderer.apply(AudioCommand::Play);
+            renderer
+        };
+        let (mut streamed, mut blocks) = (Vec::new(), Vec::new());
+        let write = |block: &[f32]| {
+            blocks.push(block.len() / EXPORT_CHANNELS);
+            streamed.extend_from_slice(block);
+            Ok(())
+        };
+        assert_eq!(bounce(&mut playing(), 1, 120, write, |_| {}).unwrap(), 96000);
+        // 23 full blocks and what's left of the bar
+        assert_eq!(blocks.len(), 24);
+        assert_eq!(blocks[23], 96000 - 23 * BLOCK_FRAMES);
+
+        let mut whole = vec![0.0f32; 96000 * EXPORT_CHANNELS];
+        playing().render(&mut whole, EXPORT_CHANNELS);
+        assert!(whole.iter().any(|&s| s != 0.0));
+        assert_eq!(streamed, whole);
+    }
+
+    #[test]
+    fn test_wav_data_len_refuses_files_past_4_gb() {
+        assert_eq!(wav_data_len(1000, ExportFormat::Pcm24), Ok(3000));
+        let max = (u32::MAX - 36) as usize;
+        assert_eq!(wav_data_len(max / 4, ExportFormat::Float32), Ok((max / 4 * 4) as u32));
+        // Two bytes a sample: 8 GB, which used to wrap round to a bogus size
+        assert_eq!(
+            wav_data_len(u32::MAX as usize, ExportFormat::Pcm16),
+            Err("Export would be 8589934590 bytes; a WAV file holds at most 4294967259".into())
+        );
+    }
+
+    /// Geometric over arithmetic mean of the 16-bit error power spectrum:
+    /// 1.0 for white error, near 0 when it piles up in a few bins
+    fn error_flatness(samples: &[f32], dither: Dither) -> f64 {
+        let mut quantizer = Quantizer::new(16, 1, dither);
+        let mut error: Vec<Complex<f64>> = samples
+            .iter()
+            .map(|&s| {
+                let value = quantizer.quantize(s, 0) as f64 / quantizer.scale;
+                Complex::new(value - s as f64, 0.0)
+            })
+            .collect();
+        FftPlanner::new().plan_fft_forward(error.len()).process(&mut error);
+
+        let power: Vec<f64> =
+            error[1..error.len() / 2].iter().map(|c| c.norm_sqr() + 1e-30).collect();
+        let log_mean = power.iter().map(|p| p.ln()).sum::<f64>() / power.len() as f64;
+        let mean = power.iter().sum::<f64>() / power.len() as f64;
+        log_mean.exp() / mean
+    }
+
+    #[test]
+    fn test_dither_whitens_quantization_error() {
+        // A slow ramp across a few LSBs, like a fade's last moments
+        let n = 8192;
+        let lsb = 1.0 / 32767.0;
+        let ramp: Vec<f32> =
+            (0..n).map(|i| ((i as f64 / n as f64 - 0.5) * 8.0 * lsb) as f32).collect();
+
+        let plain = error_flatness(&ramp, Dither::Off);
+        let dithered = error_flatness(&ramp, Dither::Tpdf);
+        assert!(dithered > 0.4, "dithered flatness {}", dithered);
+        assert!(dithered > plain * 2.0, "dithered {} vs plain {}", dithered, plain);
+    }
+}
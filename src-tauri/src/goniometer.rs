@@ -0,0 +1,120 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - GONIOMETER
+// Recent master (L, R) pairs for a stereo-image display, fed lock-free by
+// the callback and rotated into mid / side on a helper thread
+// ============================================================
+
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+/// Points per `goniometer` event / `get_goniometer` call
+pub const GONIOMETER_POINTS: usize = 512;
+
+/// Only every `DECIMATION`th master frame is kept; 512 points then span
+/// ~43 ms at 48 kHz, about one display frame
+const DECIMATION: usize = 4;
+
+/// One frame in goniometer coordinates: the L/R plane rotated 45° so mono
+/// lies on the vertical axis and out-of-phase content on the horizontal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct GoniometerPoint {
+    /// (R - L) / √2; left-only content leans left
+    pub side: f32,
+    /// (L + R) / √2
+    pub mid: f32,
+}
+
+impl GoniometerPoint {
+    fn from_stereo(left: f32, right: f32) -> Self {
+        Self {
+            side: (right - left) * FRAC_1_SQRT_2,
+            mid: (left + right) * FRAC_1_SQRT_2,
+        }
+    }
+}
+
+/// The newest `GONIOMETER_POINTS` decimated master frames, overwritten in
+/// place. Each frame is one atomic (both f32s packed), so L and R never
+/// tear apart; the callback only counts and stores.
+pub struct GoniometerFeed {
+    frames: Vec<AtomicU64>, // left bits << 32 | right bits
+    // Frames offered by the callback, kept or not (wrapping)
+    seen: AtomicUsize,
+}
+
+impl GoniometerFeed {
+    pub fn new() -> Self {
+        Self {
+            frames: (0..GONIOMETER_POINTS).map(|_| AtomicU64::new(0)).collect(),
+            seen: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn push(&self, left: f32, right: f32) {
+        let seen = self.seen.load(Ordering::Relaxed);
+        if seen.is_multiple_of(DECIMATION) {
+            let packed = (left.to_bits() as u64) << 32 | right.to_bits() as u64;
+            let slot = (seen / DECIMATION) % GONIOMETER_POINTS;
+            self.frames[slot].store(packed, Ordering::Relaxed);
+        }
+        self.seen.store(seen.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Frames offered so far (wrapping); unchanged means nothing new
+    pub fn seen(&self) -> usize {
+        self.seen.load(Ordering::Acquire)
+    }
+
+    /// The buffered frames as mid / side points, oldest first
+    pub fn points(&self) -> Vec<GoniometerPoint> {
+        let start = self.seen().div_ceil(DECIMATION);
+        (0..GONIOMETER_POINTS)
+            .map(|i| {
+                let packed = self.frames[start.wrapping_add(i) % GONIOMETER_POINTS]
+                    .load(Ordering::Relaxed);
+                let left = f32::from_bits((packed >> 32) as u32);
+                let right = f32::from_bits(packed as u32);
+                GoniometerPoint::from_stereo(left, right)
+            })
+            .collect()
+    }
+}
+
+impl Default for GoniometerFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mono_signal_lies_on_the_mid_axis() {
+        let feed = GoniometerFeed::new();
+        for i in 0..GONIOMETER_POINTS * DECIMATION * 2 {
+            let x = (0.5 * (i as f64 * 0.01).sin()) as f32;
+            feed.push(x, x);
+        }
+        let points = feed.points();
+        assert_eq!(points.len(), GONIOMETER_POINTS);
+        assert!(points.iter().all(|p| p.side.abs() < 1e-6));
+        let widest = points.iter().map(|p| p.mid.abs()).fold(0.0, f32::max);
+        assert!(widest > 0.5, "mid only reaches {}", widest);
+
+        // Left-only content sits on the upper-left diagonal
+        let feed = GoniometerFeed::new();
+        feed.push(1.0, 0.0);
+        let newest = feed.points()[GONIOMETER_POINTS - 1];
+        assert!((newest.side + FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((newest.mid - FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+}
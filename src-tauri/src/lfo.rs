@@ -0,0 +1,64 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - LFO
+// Low-frequency modulation shapes for per-track movement effects
+// ============================================================
+
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::delay::NoteDivision;
+
+pub const MIN_LFO_RATE: f64 = 0.01;
+pub const MAX_LFO_RATE: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    /// Linear sweeps between the extremes
+    Triangle,
+    /// Up for the first half cycle, down for the second (rhythmic gating)
+    Square,
+}
+
+/// LFO speed: free-running in Hz, or one cycle per note division of the
+/// transport. Sent as a number (`2.5`) or a division name (`"eighth"`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LfoRate {
+    Hz(f64),
+    Synced(NoteDivision),
+}
+
+impl Default for LfoRate {
+    fn default() -> Self {
+        LfoRate::Hz(1.0)
+    }
+}
+
+impl LfoRate {
+    /// Cycles completed at a transport position of `seconds` / `beats`
+    #[inline]
+    pub fn cycles(self, seconds: f64, beats: f64) -> f64 {
+        match self {
+            LfoRate::Hz(hz) => seconds * hz.clamp(MIN_LFO_RATE, MAX_LFO_RATE),
+            LfoRate::Synced(division) => beats / division.beats(),
+        }
+    }
+}
+
+impl LfoShape {
+    /// -1.0..=1.0 at `phase` cycles; every shape starts at 0 heading up
+    #[inline]
+    pub fn value(self, phase: f64) -> f64 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            LfoShape::Sine => (2.0 * PI * phase).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            LfoShape::Square if phase < 0.5 => 1.0,
+            LfoShape::Square => -1.0,
+        }
+    }
+}
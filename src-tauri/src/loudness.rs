@@ -0,0 +1,374 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - LOUDNESS
+// ITU-R BS.1770 / EBU R128 loudness (LUFS) of the master output, and the
+// auto-gain that levels it
+// ============================================================
+
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// Reported for silence, and the absolute gate for integrated loudness
+pub const LUFS_FLOOR: f64 = -70.0;
+
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+// Measurements are built from 100 ms blocks: momentary is the last 4,
+// short-term the last 30
+const BLOCK_SECONDS: f64 = 0.1;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+
+// Gated blocks are kept as a histogram (0.1 LU bins from the floor up to
+// +10 LUFS) so integration never allocates on the audio thread
+const HISTOGRAM_BINS: usize = 800;
+const BIN_LU: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Loudness {
+    pub momentary: f64,
+    pub short_term: f64,
+    pub integrated: f64,
+}
+
+impl Default for Loudness {
+    fn default() -> Self {
+        Self {
+            momentary: LUFS_FLOOR,
+            short_term: LUFS_FLOOR,
+            integrated: LUFS_FLOOR,
+        }
+    }
+}
+
+impl Loudness {
+    pub fn to_bits(self) -> [u64; 3] {
+        [self.momentary, self.short_term, self.integrated].map(f64::to_bits)
+    }
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    if mean_square <= 0.0 {
+        return LUFS_FLOOR;
+    }
+    (-0.691 + 10.0 * mean_square.log10()).max(LUFS_FLOOR)
+}
+
+// ============================================================
+// K-WEIGHTING
+// ============================================================
+
+/// Direct form I biquad with a0 normalized to 1
+#[derive(Clone, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    #[inline]
+    fn process(&mut self, input: f64) -> f64 {
+        let out = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [out, self.y[0]];
+        out
+    }
+}
+
+/// BS.1770 pre-filter (high shelf, head acoustics) followed by the RLB
+/// high-pass, designed for any sample rate (matches the published 48 kHz
+/// coefficients)
+#[derive(Clone, Debug)]
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let shelf = {
+            let k = (PI * 1_681.974_450_955_533 / sample_rate).tan();
+            let q = 0.707_175_236_955_419_6;
+            let vh = 10f64.powf(3.999_843_853_973_347 / 20.0);
+            let vb = vh.powf(0.499_666_774_154_541_6);
+            let a0 = 1.0 + k / q + k * k;
+            Biquad {
+                b: [
+                    (vh + vb * k / q + k * k) / a0,
+                    2.0 * (k * k - vh) / a0,
+                    (vh - vb * k / q + k * k) / a0,
+                ],
+                a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+                ..Default::default()
+            }
+        };
+        let highpass = {
+            let k = (PI * 38.135_470_876_024_44 / sample_rate).tan();
+            let q = 0.500_327_037_323_877_3;
+            let a0 = 1.0 + k / q + k * k;
+            Biquad {
+                b: [1.0, -2.0, 1.0],
+                a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+                ..Default::default()
+            }
+        };
+        Self { shelf, highpass }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64) -> f64 {
+        self.highpass.process(self.shelf.process(input))
+    }
+}
+
+// ============================================================
+// LOUDNESS METER
+// ============================================================
+
+/// Momentary (400 ms), short-term (3 s) and gated integrated loudness of a
+/// stereo signal
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+    filters: [KWeighting; 2],
+    block_len: usize,
+    // Sum of weighted squares in the block being filled
+    block_sum: f64,
+    block_pos: usize,
+    // Mean square of the most recent blocks (ring, newest at `ring_pos - 1`)
+    blocks: [f64; SHORT_TERM_BLOCKS],
+    ring_pos: usize,
+    blocks_seen: usize,
+    // Gating blocks above the absolute gate: count and summed mean square
+    histogram_count: [u32; HISTOGRAM_BINS],
+    histogram_energy: [f64; HISTOGRAM_BINS],
+    loudness: Loudness,
+    sample_rate: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            filters: [KWeighting::new(sample_rate), KWeighting::new(sample_rate)],
+            block_len: ((BLOCK_SECONDS * sample_rate) as usize).max(1),
+            block_sum: 0.0,
+            block_pos: 0,
+            blocks: [0.0; SHORT_TERM_BLOCKS],
+            ring_pos: 0,
+            blocks_seen: 0,
+            histogram_count: [0; HISTOGRAM_BINS],
+            histogram_energy: [0.0; HISTOGRAM_BINS],
+            loudness: Loudness::default(),
+            sample_rate,
+        }
+    }
+
+    /// Start a fresh measurement
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sample_rate);
+    }
+
+    pub fn loudness(&self) -> Loudness {
+        self.loudness
+    }
+
+    /// Whether a full short-term window has been measured since the reset
+    pub fn short_term_ready(&self) -> bool {
+        self.blocks_seen >= SHORT_TERM_BLOCKS
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) {
+        let l = self.filters[0].process(left);
+        let r = self.filters[1].process(right);
+        self.block_sum += l * l + r * r;
+        self.block_pos += 1;
+        if self.block_pos >= self.block_len {
+            self.finish_block();
+        }
+    }
+
+    fn finish_block(&mut self) {
+        self.blocks[self.ring_pos] = self.block_sum / self.block_len as f64;
+        self.ring_pos = (self.ring_pos + 1) % SHORT_TERM_BLOCKS;
+        self.blocks_seen += 1;
+        self.block_sum = 0.0;
+        self.block_pos = 0;
+
+        let momentary = self.recent_mean_square(MOMENTARY_BLOCKS);
+        self.loudness.momentary = lufs(momentary);
+        self.loudness.short_term = lufs(self.recent_mean_square(SHORT_TERM_BLOCKS));
+
+        // Each 400 ms window (75% overlap) is one gating block
+        if self.blocks_seen >= MOMENTARY_BLOCKS && self.loudness.momentary > LUFS_FLOOR {
+            let bin = ((self.loudness.momentary - LUFS_FLOOR) / BIN_LU) as usize;
+            let bin = bin.min(HISTOGRAM_BINS - 1);
+            self.histogram_count[bin] += 1;
+            self.histogram_energy[bin] += momentary;
+            self.loudness.integrated = self.integrated();
+        }
+    }
+
+    /// Mean square over the newest `count` blocks
+    fn recent_mean_square(&self, count: usize) -> f64 {
+        let sum: f64 = (1..=count)
+            .map(|i| self.blocks[(self.ring_pos + SHORT_TERM_BLOCKS - i) % SHORT_TERM_BLOCKS])
+            .sum();
+        sum / count as f64
+    }
+
+    /// Mean of the gating blocks above the relative gate
+    fn integrated(&self) -> f64 {
+        let gated_mean = |from_bin: usize| {
+            let count: u32 = self.histogram_count[from_bin..].iter().sum();
+            let energy: f64 = self.histogram_energy[from_bin..].iter().sum();
+            if count == 0 {
+                0.0
+            } else {
+                energy / count as f64
+            }
+        };
+
+        let relative_gate = lufs(gated_mean(0)) + RELATIVE_GATE_LU;
+        let from_bin = ((relative_gate - LUFS_FLOOR).max(0.0) / BIN_LU) as usize;
+        lufs(gated_mean(from_bin.min(HISTOGRAM_BINS - 1)))
+    }
+}
+
+// ============================================================
+// AUTO-GAIN
+// ============================================================
+
+/// Auto-gain never moves the master further than this either way
+pub const MAX_AUTOGAIN_DB: f64 = 12.0;
+
+pub const DEFAULT_AUTOGAIN_TARGET: f64 = -14.0;
+
+/// Time constant of the correction; short-term loudness itself lags by
+/// ~1.5 s, so much faster than this would overshoot and pump
+const AUTOGAIN_SECONDS: f64 = 4.0;
+
+/// Slow master gain that steers short-term loudness toward a target.
+///
+/// Corrections only happen while the transport runs; when disabled the gain
+/// glides back to unity at the same rate instead of jumping.
+#[derive(Clone, Debug)]
+pub struct AutoGain {
+    pub enabled: bool,
+    // Frozen while the transport is stopped
+    pub running: bool,
+    target: f64, // LUFS
+    gain_db: f64,
+    // Fraction of the remaining error corrected per sample
+    rate: f64,
+}
+
+impl AutoGain {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            enabled: false,
+            running: false,
+            target: DEFAULT_AUTOGAIN_TARGET,
+            gain_db: 0.0,
+            rate: 1.0 / (AUTOGAIN_SECONDS * sample_rate),
+        }
+    }
+
+    pub fn set_target(&mut self, lufs: f64) {
+        self.target = lufs.clamp(LUFS_FLOOR, 0.0);
+    }
+
+    /// Advance one sample, steering against `meter`'s latest short-term
+    /// reading; returns the linear gain to apply
+    #[inline]
+    pub fn process(&mut self, meter: &LoudnessMeter) -> f64 {
+        if !self.enabled {
+            self.gain_db -= self.gain_db * self.rate;
+        } else if self.running && meter.short_term_ready() {
+            // Silence says nothing about how loud the music is
+            let short_term = meter.loudness().short_term;
+            if short_term > LUFS_FLOOR {
+                let gain_db = self.gain_db + (self.target - short_term) * self.rate;
+                self.gain_db = gain_db.clamp(-MAX_AUTOGAIN_DB, MAX_AUTOGAIN_DB);
+            }
+        }
+        10f64.powf(self.gain_db / 20.0)
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_sine(meter: &mut LoudnessMeter, amplitude: f64, seconds: f64) {
+        let sample_rate = meter.sample_rate;
+        for i in 0..(seconds * sample_rate) as usize {
+            let x = amplitude * (2.0 * PI * 997.0 * i as f64 / sample_rate).sin();
+            meter.process(x, x);
+        }
+    }
+
+    #[test]
+    fn test_minus_20_dbfs_sine_reads_minus_20_lufs() {
+        // A 1 kHz sine in both channels reads its peak level in LUFS
+        let mut meter = LoudnessMeter::new(48000.0);
+        feed_sine(&mut meter, 0.1, 1.0);
+        let loudness = meter.loudness();
+        assert!((loudness.momentary + 20.0).abs() < 0.2, "momentary {}", loudness.momentary);
+        assert!((loudness.integrated + 20.0).abs() < 0.2, "integrated {}", loudness.integrated);
+    }
+
+    #[test]
+    fn test_integrated_gates_out_silence_and_resets() {
+        let mut meter = LoudnessMeter::new(44100.0);
+        feed_sine(&mut meter, 0.1, 2.0);
+        feed_sine(&mut meter, 0.0, 5.0);
+        let loudness = meter.loudness();
+        assert_eq!(loudness.momentary, LUFS_FLOOR);
+        // Only the windows straddling the end of the tone pull it down
+        assert!((loudness.integrated + 20.0).abs() < 0.5, "integrated {}", loudness.integrated);
+
+        meter.reset();
+        assert_eq!(meter.loudness(), Loudness::default());
+    }
+
+    #[test]
+    fn test_autogain_raises_quiet_signal_toward_target() {
+        let sample_rate = 8000.0;
+        let mut meter = LoudnessMeter::new(sample_rate);
+        let mut autogain = AutoGain::new(sample_rate);
+        autogain.enabled = true;
+        autogain.running = true;
+        autogain.set_target(-20.0);
+
+        // A -30 LUFS sine, measured after the auto-gain like on the master
+        let mut short_term_at = Vec::new();
+        for second in 0..30 {
+            for i in 0..sample_rate as usize {
+                let t = (second * sample_rate as usize + i) as f64 / sample_rate;
+                let x = 0.0316 * (2.0 * PI * 997.0 * t).sin() * autogain.process(&meter);
+                meter.process(x, x);
+            }
+            short_term_at.push(meter.loudness().short_term);
+        }
+
+        // Slow: still well short of the target after five seconds
+        assert!(short_term_at[4] < -24.0, "after 5 s: {:?}", short_term_at);
+        let last = short_term_at[29];
+        assert!((last + 20.0).abs() < 1.0, "after 30 s: {}", last);
+        assert!(autogain.gain_db > 0.0 && autogain.gain_db <= MAX_AUTOGAIN_DB);
+
+        // Stopped transport holds the gain
+        autogain.running = false;
+        let held = autogain.gain_db;
+        autogain.process(&meter);
+        assert_eq!(autogain.gain_db, held);
+    }
+}
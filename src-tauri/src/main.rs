@@ -1,363 +1,1037 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod automation;
+mod chorus;
+mod delay;
+mod export;
+mod goniometer;
+mod lfo;
+mod loudness;
+mod meter;
+mod metronome;
+mod midi;
 mod mixer;
-
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+mod modulation;
+mod oversample;
+mod record;
+mod renderer;
+mod reverb;
+mod sampler;
+mod session;
+mod sequencer;
+mod spectrum;
+mod synth;
+mod undo;
+mod validate;
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::{bounded, Receiver, Sender};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
 use cpal::traits::{HostTrait, DeviceTrait, StreamTrait};
-use mixer::{Mixer, EqBand};
+use automation::{AutomationPoint, MAX_AUTOMATION_LANES};
+use chorus::{MAX_CHORUS_RATE, MIN_CHORUS_RATE};
+use delay::{
+    NoteDivision, MAX_DELAY_LOW_CUT_HZ, MAX_DELAY_TONE_HZ, MIN_DELAY_LOW_CUT_HZ, MIN_DELAY_TONE_HZ,
+};
+use export::{Dither, ExportFormat};
+use goniometer::{GoniometerFeed, GoniometerPoint, GONIOMETER_POINTS};
+use lfo::{LfoRate, LfoShape, MAX_LFO_RATE, MIN_LFO_RATE};
+use loudness::Loudness;
+use meter::{db_to_gain, gain_to_db, MeterBank, MeterState, MIN_DBFS};
+use midi::{Cc, MidiAction, MidiInputs, MidiParam};
+use record::{InputSample, RecordInput, RecordTake};
+use mixer::{
+    index_after_removal, ClipMode, LimiterRelease, Listen, MasterStage, Mixer, PanLaw, PanMode,
+    TransferEffect, TransferPoint, GATE_OFF_DB, MASTER_STAGES, MAX_ALLPASS_HZ, MAX_CLIP_AMOUNT,
+    MAX_CLIP_MAKEUP_DB, MAX_COMP_KNEE_DB, MAX_CRUSH_BITS, MAX_CRUSH_DOWNSAMPLE,
+    MAX_GATE_LOOKAHEAD_MS, MAX_HPF_HZ, MAX_STEREO_WIDTH, MAX_TRANSFER_POINTS, MIN_ALLPASS_HZ,
+    MIN_HPF_HZ, MIN_OUTPUT_CEILING_DB,
+};
+use modulation::MAX_MOD_ROUTES;
+use renderer::{Renderer, RendererSlot, Retired, SoloMode};
+use sampler::{LoadedSample, Sample, MAX_LOOP_CROSSFADE_MS, MAX_SAMPLE_SPEED, MIN_SAMPLE_SPEED};
+use session::SessionState;
+use spectrum::{SpectrumAnalyzer, SpectrumFeed, SPECTRUM_BINS, SPECTRUM_FLOOR_DB};
+use synth::{
+    Waveform, MAX_FREQUENCY, MAX_UNISON_DETUNE_CENTS, MAX_UNISON_VOICES, MIN_FREQUENCY,
+};
+use undo::UndoHistory;
 
 // ============================================================
 // AUDIO THREAD TYPES
 // ============================================================
 
+/// Commands sent from the UI to the audio thread.
+///
+/// Serialized with an internal `type` tag (e.g. `{"type": "set_track_pan",
+/// "track": 2, "value": -0.5}`), so unknown or malformed commands are
+/// rejected at deserialization instead of being silently ignored.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AudioCommand {
-    pub cmd_type: String,
-    pub track: Option<usize>,
-    pub value: Option<f64>,
-    pub data: Option<Vec<u8>>,
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AudioCommand {
+    Play,
+    /// Click `bars` bars of metronome before starting playback
+    PlayWithCountIn { bars: u32 },
+    Stop,
+    /// Move the playhead to `step` (wrapped into the loop while looping)
+    SetPosition { step: usize },
+    /// Master volume as a linear gain, 0.0..=1.0 (`set_master_gain_db`
+    /// sends this too, converted from dB)
+    SetVolume { value: f64 },
+    SetTrackVolume { track: usize, value: f64 },
+    SetTrackPan { track: usize, value: f64 },
+    /// Whether the track's pan control pans or balances
+    SetTrackPanMode { track: usize, mode: PanMode },
+    ToggleMute { track: usize },
+    ToggleSolo { track: usize },
+    /// Append a track (up to `MAX_TRACKS`)
+    AddTrack,
+    /// Remove a track; later tracks shift down by one
+    RemoveTrack { track: usize },
+    /// Add or drop tracks at the end until there are `count`
+    SetTrackCount { count: usize },
+    SetMute { track: usize, on: bool },
+    SetSolo { track: usize, on: bool },
+    /// Solo-safe tracks keep playing while other tracks are soloed
+    SetTrackSoloSafe { track: usize, on: bool },
+    /// Additive solo, or exclusive (soloing one track un-solos the rest)
+    SetSoloMode { mode: SoloMode },
+    /// Flip a track's signal (multiply by -1) before it is mixed
+    ToggleTrackPolarity { track: usize },
+    SetTrackPolarity { track: usize, inverted: bool },
+    /// Input gain in dB ahead of the track's processing (+/-24)
+    SetTrackTrim { track: usize, value: f64 },
+    SetTrackEqLow { track: usize, value: f64 },
+    SetTrackEqMid { track: usize, value: f64 },
+    SetTrackEqHigh { track: usize, value: f64 },
+    /// Bit depth 1..=16 and sample-and-hold factor; 16 bits / 1 is off
+    SetTrackBitcrush { track: usize, bits: u32, downsample: u32 },
+    /// Rumble filter ahead of the rest of the strip, corner in Hz
+    SetTrackHpf { track: usize, freq_hz: f64, on: bool },
+    /// Phase shift after the high-pass, for aligning sources; the phase
+    /// turns through -180° at `freq_hz` and the level is unchanged
+    SetTrackAllpass { track: usize, freq_hz: f64, on: bool },
+    /// Threshold in dB (-100 or below = off); attack, hold, release in ms
+    SetTrackGate { track: usize, threshold: f64, attack: f64, hold: f64, release: f64 },
+    /// How far the gate's detector runs ahead of the audio, in ms
+    /// (0..=`MAX_GATE_LOOKAHEAD_MS`); the other tracks are delayed to match
+    SetTrackGateLookahead { track: usize, ms: f64 },
+    /// Track compressor threshold in dB (-60..=0), after the EQ
+    SetTrackCompThreshold { track: usize, value: f64 },
+    /// Track compressor ratio n:1 (1..=20); 1 is off
+    SetTrackCompRatio { track: usize, value: f64 },
+    /// Track compressor attack in ms (0.1..=200)
+    SetTrackCompAttack { track: usize, value: f64 },
+    /// Track compressor release in ms (10..=2000)
+    SetTrackCompRelease { track: usize, value: f64 },
+    /// Drive the track compressor from `source`'s input level (`None` keys
+    /// it from the track itself)
+    SetTrackSidechain { track: usize, source: Option<usize> },
+    /// Sweep the track's pan around its static position: LFO rate in Hz,
+    /// depth 0.0 (off) to 1.0 (hard left to hard right from center)
+    SetTrackAutopan { track: usize, rate: f64, depth: f64, shape: LfoShape },
+    /// Dip the track's volume with an LFO, in Hz or synced to a note
+    /// division; depth 0.0 (off) to 1.0 (down to silence)
+    SetTrackTremolo { track: usize, rate: LfoRate, depth: f64, shape: LfoShape },
+    /// Oscillator pitch in Hz
+    SetTrackFrequency { track: usize, hz: f64 },
+    /// Oscillator envelope: attack, decay, release in ms; sustain level 0.0..=1.0
+    SetTrackAdsr { track: usize, attack: f64, decay: f64, sustain: f64, release: f64 },
+    /// Post-fader send levels (0.0..=1.0) to the master delay / reverb
+    SetTrackSendDelay { track: usize, value: f64 },
+    SetTrackSendReverb { track: usize, value: f64 },
+    /// Play the sample `pending` delivers once a loader thread has decoded
+    /// and resampled it (see `load_sample`)
+    #[serde(skip)]
+    LoadSample { track: usize, pending: Receiver<LoadedSample> },
+    /// Play `sample` (a render of the track from `freeze_track`) in place
+    /// of the track's voice and strip
+    #[serde(skip)]
+    FreezeTrack { track: usize, sample: Arc<Sample> },
+    /// Back to live synthesis and processing
+    UnfreezeTrack { track: usize },
+    /// Every mix parameter back to its default, with `mixer` (built off the
+    /// audio thread) replacing the old one so no effect tails linger
+    #[serde(skip)]
+    ResetMixer { mixer: Box<Mixer> },
+    /// Silence everything now: voices cut, and `mixer` (built off the
+    /// audio thread) takes over the current settings with empty delay,
+    /// reverb and filter state. The transport keeps going.
+    #[serde(skip)]
+    Panic { mixer: Box<Mixer> },
+    /// Follow `points` (sorted by step) for `param` during playback,
+    /// replacing its lane; no points removes the lane
+    SetAutomation { param: MidiParam, points: Vec<AutomationPoint> },
+    /// Remove every automation lane
+    ClearAutomation,
+    /// Reshape one of the mod matrix LFOs
+    SetModLfo { lfo: usize, rate: LfoRate, shape: LfoShape, phase: f64 },
+    /// Route a mod matrix LFO onto a parameter, or change that route's depth
+    AddModRoute { lfo: usize, dest: MidiParam, depth: f64 },
+    /// Disconnect an LFO from a parameter; with no routes left the
+    /// parameter returns to its own value
+    RemoveModRoute { lfo: usize, dest: MidiParam },
+    /// Remove every mod route
+    ClearModRoutes,
+    TriggerSample { track: usize },
+    /// Sound a track now, as if its step had come up (MIDI note-on)
+    TriggerTrack { track: usize },
+    /// Release a track's oscillator envelope (MIDI note-off)
+    ReleaseTrack { track: usize },
+    /// Sample playback speed ratio, pitch included (0.5 = octave down)
+    SetTrackSampleSpeed { track: usize, ratio: f64 },
+    /// Loop the sample instead of playing it once
+    SetTrackSampleLoop { track: usize, on: bool },
+    /// Crossfade a looping sample's end into its start over `ms`
+    SetTrackLoopCrossfade { track: usize, ms: f64 },
+    /// Play the sample from its end toward its start
+    SetTrackReverse { track: usize, on: bool },
+    /// Stack detuned oscillator copies, the outer ones `detune_cents` away
+    SetTrackUnison { track: usize, voices: usize, detune_cents: f64 },
+    /// Restart the track's oscillator whenever `master`'s wraps (`None`
+    /// runs it free)
+    SetTrackSync { track: usize, master: Option<usize> },
+    SetWaveform { track: usize, waveform: Waveform },
+    SetStep { track: usize, step: usize, on: bool },
+    ClearPattern { track: usize },
+    /// Steps before the pattern repeats (1..=64)
+    SetLoopLength { steps: usize },
+    /// Off lets the playhead count on past the loop end
+    SetLoopEnabled { on: bool },
+    /// Push odd 16ths late by this fraction of a step (0.0..=0.75)
+    SetSwing { amount: f64 },
+    SetBpm { bpm: u64 },
+    /// Beats per bar and beat unit, for the reported bar / beat / tick
+    SetTimeSignature { numerator: u32, denominator: u32 },
+    SetMetronome { on: bool },
+    /// Drop the live output by `mixer::MONITOR_DIM_DB`; exports are unaffected
+    SetMonitorDim { on: bool },
+    /// Fold the live output to mono; exports are unaffected
+    SetMonitorMono { on: bool },
+    /// Put a track's pre- or after-fader signal on the live output in
+    /// place of the main mix; exports are unaffected
+    SetTrackListen { track: usize, mode: Listen },
+    SetEqLow { value: f64 },
+    SetEqMid { value: f64 },
+    SetEqHigh { value: f64 },
+    /// EQ mid and side separately: the `SetEq*` bands then act on the mid,
+    /// the `SetSideEq*` bands on the side
+    SetEqMsMode { on: bool },
+    SetSideEqLow { value: f64 },
+    SetSideEqMid { value: f64 },
+    SetSideEqHigh { value: f64 },
+    SetCompThreshold { value: f64 },
+    SetCompRatio { value: f64 },
+    SetCompAttack { value: f64 },
+    SetCompRelease { value: f64 },
+    SetCompMakeup { value: f64 },
+    /// Soft-knee width in dB around the threshold (0 = hard knee)
+    SetCompKnee { value: f64 },
+    SetDelayTimeDivision { division: NoteDivision },
+    SetDelayFeedback { value: f64 },
+    SetDelayMix { value: f64 },
+    /// Echoes alternate left and right, the input summed to mono
+    SetDelayPingPong { on: bool },
+    /// Filters in the delay feedback, darkening each repeat: a low-pass
+    /// corner in Hz (20000 = open) and an optional high-pass corner
+    SetDelayTone { cutoff: f64, low_cut: Option<f64> },
+    SetReverbSize { value: f64 },
+    SetReverbDamping { value: f64 },
+    SetReverbMix { value: f64 },
+    /// Master chorus LFO rate in Hz (0.05 to 5)
+    SetChorusRate { value: f64 },
+    /// Master chorus sweep depth, 0.0 to 1.0 (5 to 25 ms at full depth)
+    SetChorusDepth { value: f64 },
+    SetChorusMix { value: f64 },
+    SetLimiter { value: f64 },
+    /// Drive the limiter from 4x-interpolated (inter-sample) peaks
+    SetLimiterTruePeak { on: bool },
+    /// Limiter release curve: exponential, linear or program-dependent dual
+    SetLimiterReleaseMode { mode: LimiterRelease },
+    SetStereoWidth { value: f64 },
+    SetMono { on: bool },
+    SetPanLaw { law: PanLaw },
+    SetClipAmount { value: f64 },
+    /// Saturation curve of the soft clipper
+    SetClipMode { mode: ClipMode },
+    /// Run the soft clipper at 1x (off), 2x or 4x the sample rate
+    SetOversampling { factor: usize },
+    SetClipBypass { on: bool },
+    SetClipMakeup { value: f64 },
+    /// Final output ceiling in dBFS, applied after the soft clipper
+    SetOutputCeiling { value: f64 },
+    SetDcBlock { on: bool },
+    /// Headphone crossfeed, 0.0 (off) to 1.0
+    SetCrossfeed { value: f64 },
+    /// Run the master stages in this order (each exactly once)
+    SetMasterChainOrder { order: [MasterStage; MASTER_STAGES] },
+    /// Slowly level the master toward the auto-gain target (+/-12 dB)
+    SetAutogain { on: bool },
+    /// Auto-gain target short-term loudness, LUFS
+    SetAutogainTarget { value: f64 },
+    /// Send a track to a sub-bus (`None` = straight to the master)
+    SetTrackBus { track: usize, bus: Option<usize> },
+    SetBusVolume { bus: usize, value: f64 },
+    /// Silences the bus members, sends included
+    SetBusMute { bus: usize, on: bool },
+    /// Bus EQ gains in dB
+    SetBusEq { bus: usize, low: f64, mid: f64, high: f64 },
+    /// Bus compressor: threshold/makeup in dB, times in ms
+    SetBusCompressor {
+        bus: usize,
+        threshold: f64,
+        ratio: f64,
+        attack: f64,
+        release: f64,
+        makeup: f64,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioState {
     pub is_playing: bool,
     pub current_step: usize,
+    /// `current_step` in the session's time signature
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: u32,
     pub bpm: u64,
     pub cpu_usage: f64,
+    /// The master volume setting in dB (0.0 = unity)
+    pub master_gain_db: f64,
+    /// Louder of the master L / R peak meters, in dBFS
+    pub master_peak_dbfs: f64,
 }
 
-// ============================================================
-// TRACK STATE (for per-track volume/pan/mute/solo)
-// ============================================================
+/// Bumped whenever a command is removed or changes its arguments; new
+/// commands only show up in `EngineInfo::commands`
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Master and channel-strip processors built into every engine
+const EFFECTS: &[&str] = &[
+    "noise",
+    "hpf",
+    "allpass",
+    "gate",
+    "eq",
+    "track_compressor",
+    "sidechain",
+    "bitcrush",
+    "autopan",
+    "tremolo",
+    "sub_buses",
+    "master_eq",
+    "master_compressor",
+    "chorus",
+    "delay",
+    "reverb",
+    "limiter",
+    "true_peak_limiter",
+    "soft_clipper",
+    "oversampling",
+    "dc_block",
+    "crossfeed",
+    "autogain",
+    "latency_compensation",
+];
+
+/// Optional Cargo features and whether this build has them
+const CARGO_FEATURES: &[(&str, bool)] = &[("midi", cfg!(feature = "midi"))];
+
+/// What this engine build supports, for the UI to enable controls against
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineInfo {
+    pub version: &'static str,
+    pub protocol_version: u32,
+    pub commands: &'static [&'static str],
+    pub num_tracks: usize,
+    pub max_tracks: usize,
+    pub sample_rate: u32,
+    pub effects: &'static [&'static str],
+    /// Enabled optional features, e.g. `midi`
+    pub features: Vec<&'static str>,
+}
 
-#[derive(Clone)]
-struct TrackState {
-    volume: f64,
-    pan: f64,
-    muted: bool,
-    soloed: bool,
+/// Everything the engine reports back to the webview
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    State(AudioState),
+    /// Fraction (0.0..=1.0) of the running WAV export that is done
+    ExportProgress(f64),
+    /// Beats left before a count-in starts playback (0 = started)
+    CountIn { beats_remaining: usize },
+    /// Output device lost / reconnect progress
+    Device(DeviceStatus),
 }
 
-// ============================================================
-// MASTER EFFECTS STATE
-// ============================================================
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeviceStatus {
+    /// The stream reported an error; it will be rebuilt on the default device
+    Lost { error: String },
+    Reconnecting { attempt: u32, max_attempts: u32 },
+    Reconnected { device: String },
+    /// Every retry failed; output stays off until a device is picked
+    Failed { error: String },
+}
+
+/// One entry of the output device's `supported_output_configs()`, for the
+/// UI's sample rate / buffer size pickers
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputConfig {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// `None` when the device doesn't report a buffer size range
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+    pub sample_format: String,
+}
+
+impl From<&cpal::SupportedStreamConfigRange> for OutputConfig {
+    fn from(range: &cpal::SupportedStreamConfigRange) -> Self {
+        let (min_buffer_size, max_buffer_size) = match *range.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } => (Some(min), Some(max)),
+            cpal::SupportedBufferSize::Unknown => (None, None),
+        };
+        Self {
+            channels: range.channels(),
+            min_sample_rate: range.min_sample_rate().0,
+            max_sample_rate: range.max_sample_rate().0,
+            min_buffer_size,
+            max_buffer_size,
+            sample_format: range.sample_format().to_string(),
+        }
+    }
+}
 
+/// Atomics shared between the Tauri side and the audio callback
 #[derive(Clone)]
-struct MasterEffects {
-    eq_low: f64,    // dB
-    eq_mid: f64,    // dB
-    eq_high: f64,   // dB
-    limiter_threshold: f64,
-    clip_amount: f64,
+pub struct SharedState {
+    pub is_running: Arc<AtomicBool>,
+    pub current_step: Arc<AtomicU64>,
+    pub bpm: Arc<AtomicU64>,
+    pub cpu_usage: Arc<AtomicU64>, // f64 bits, 0.0..=1.0
+    /// Rate the renderer is actually running at (the device's rate)
+    pub sample_rate: Arc<AtomicU32>,
+    pub meters: Arc<MeterBank>,
+    /// Newest master output samples, for the spectrum analyzer thread
+    pub spectrum: Arc<SpectrumFeed>,
+    /// Newest master L/R pairs, for the goniometer thread
+    pub goniometer: Arc<GoniometerFeed>,
+    /// Frames per callback the device is actually delivering (0 until the
+    /// first callback)
+    pub buffer_frames: Arc<AtomicU32>,
+    /// Held while the renderer changes rate, and while a mixer for the
+    /// current rate is built and queued, so one never crosses the other
+    pub rate_change: Arc<Mutex<()>>,
 }
 
-impl Default for MasterEffects {
-    fn default() -> Self {
+impl SharedState {
+    pub fn new(bpm: u64, num_tracks: usize) -> Self {
         Self {
-            eq_low: 0.0,
-            eq_mid: 0.0,
-            eq_high: 0.0,
-            limiter_threshold: 0.95,
-            clip_amount: 2.0,
+            is_running: Arc::new(AtomicBool::new(false)),
+            current_step: Arc::new(AtomicU64::new(0)),
+            bpm: Arc::new(AtomicU64::new(bpm)),
+            cpu_usage: Arc::new(AtomicU64::new(0.0_f64.to_bits())),
+            sample_rate: Arc::new(AtomicU32::new(DEFAULT_SAMPLE_RATE)),
+            meters: Arc::new(MeterBank::new(num_tracks)),
+            spectrum: Arc::new(SpectrumFeed::new()),
+            goniometer: Arc::new(GoniometerFeed::new()),
+            buffer_frames: Arc::new(AtomicU32::new(0)),
+            rate_change: Arc::new(Mutex::new(())),
         }
     }
 }
 
+/// Rate the renderer starts at, until a device reports its own
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// Tracks in a new session
+pub const DEFAULT_NUM_TRACKS: usize = 7;
+
+/// Upper bound for `add_track`; track storage is reserved up to this so
+/// adding tracks doesn't reallocate on the audio thread
+pub const MAX_TRACKS: usize = 16;
+
+/// Smoothing factor for the CPU load moving average (per callback)
+const CPU_SMOOTHING: f64 = 0.05;
+
+/// Read an f64 stored as raw bits in an `AtomicU64`
+fn load_f64(atomic: &AtomicU64) -> f64 {
+    f64::from_bits(atomic.load(Ordering::Relaxed))
+}
+
+// ============================================================
+// ENGINE CONTROL (handled by the audio thread, outside the callback)
+// ============================================================
+
+/// Requests that need the stream itself torn down or rebuilt
+pub enum EngineControl {
+    SwitchDevice {
+        name: String,
+        reply: Sender<Result<String, String>>,
+    },
+    /// Rebuild the stream with a fixed buffer size (`None` = the device
+    /// default). A size the device rejects keeps the old stream.
+    SetBufferSize {
+        frames: Option<u32>,
+        reply: Sender<Result<String, String>>,
+    },
+    /// Report what the current output device supports
+    Capabilities {
+        reply: Sender<Result<Vec<OutputConfig>, String>>,
+    },
+    /// Hand back an offline copy of the current session (see
+    /// `Renderer::offline_copy`). The stream keeps running, silent while
+    /// the copy is made.
+    Snapshot {
+        sample_rate: u32,
+        reply: Sender<Result<Renderer, String>>,
+    },
+    /// Record from this input device instead of the system default
+    SetInputDevice {
+        name: String,
+        reply: Sender<Result<String, String>>,
+    },
+    /// Open an input stream and start a new take
+    StartRecord {
+        reply: Sender<Result<String, String>>,
+    },
+    /// Close the input stream and hand back the take, with the number of
+    /// frames lost to a full ring
+    StopRecord {
+        reply: Sender<Result<(Sample, usize), String>>,
+    },
+    /// Drop the stream and end the audio thread
+    Shutdown,
+}
+
+/// How long to wait for a dropped stream to hand its renderer back
+const RENDERER_RECLAIM_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Buffers the renderer can hand back between two passes of the control
+/// loop (at most 100 ms apart) before it has to free one itself
+const RETIRED_CAPACITY: usize = 64;
+
+/// Wait between attempts to rebuild a stream that reported an error
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Pending stream rebuild after a stream error
+struct Reconnect {
+    attempt: u32,
+    next_try: Instant,
+}
+
+/// Whether `frames` is in the device's reported buffer size range (devices
+/// that don't report one get to try it)
+fn buffer_size_supported(supported: &cpal::SupportedBufferSize, frames: u32) -> bool {
+    match *supported {
+        cpal::SupportedBufferSize::Range { min, max } => (min..=max).contains(&frames),
+        cpal::SupportedBufferSize::Unknown => true,
+    }
+}
+
+/// Look up an output device by its reported name
+fn find_output_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    host.output_devices()
+        .ok()?
+        .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+}
+
+/// Look up an input device by its reported name
+fn find_input_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    host.input_devices()
+        .ok()?
+        .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+}
+
+/// Build an input stream whose callback queues `T` samples into `input`
+fn build_input_stream<T: cpal::SizedSample + InputSample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut input: RecordInput,
+) -> Result<cpal::Stream, String> {
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| input.push(data),
+            |err: cpal::StreamError| eprintln!("[AudioThread] Input stream error: {}", err),
+            None,
+        )
+        .map_err(|e| format!("Failed to build input stream: {}", e))
+}
+
 // ============================================================
 // AUDIO ENGINE (REAL-TIME THREAD)
 // ============================================================
 
 struct AudioEngine {
-    sample_rate: u32,
     command_rx: Receiver<AudioCommand>,
-    state_tx: Sender<AudioState>,
-    is_running: Arc<AtomicBool>,
-    current_step: Arc<AtomicU64>,
-    bpm: Arc<AtomicU64>,
+    control_rx: Receiver<EngineControl>,
+    state_tx: Sender<EngineEvent>,
+    shared: SharedState,
+    // Stream error callbacks report here so the run loop can rebuild
+    fault_tx: Sender<String>,
+    fault_rx: Receiver<String>,
+    /// Requested frames per callback; `None` leaves it to the device
+    buffer_size: Option<u32>,
+    /// Input device to record from; `None` uses the system default
+    input_device: Option<String>,
+    // What the renderer let go of, freed here rather than in the callback
+    retired_tx: Sender<Retired>,
+    retired_rx: Receiver<Retired>,
+    // Borrowing the renderer from a running stream (see `RendererSlot`)
+    lend: Arc<AtomicBool>,
+    lent_tx: Sender<Renderer>,
+    lent_rx: Receiver<Renderer>,
 }
 
 impl AudioEngine {
     fn new(
         command_rx: Receiver<AudioCommand>,
-        state_tx: Sender<AudioState>,
-        is_running: Arc<AtomicBool>,
-        current_step: Arc<AtomicU64>,
-        bpm: Arc<AtomicU64>,
+        control_rx: Receiver<EngineControl>,
+        state_tx: Sender<EngineEvent>,
+        shared: SharedState,
     ) -> Self {
+        let (fault_tx, fault_rx) = bounded(1);
+        let (retired_tx, retired_rx) = bounded(RETIRED_CAPACITY);
+        let (lent_tx, lent_rx) = bounded(1);
         Self {
-            sample_rate: 48000,
             command_rx,
+            control_rx,
             state_tx,
-            is_running,
-            current_step,
-            bpm,
+            shared,
+            fault_tx,
+            fault_rx,
+            buffer_size: None,
+            input_device: None,
+            retired_tx,
+            retired_rx,
+            lend: Arc::new(AtomicBool::new(false)),
+            lent_tx,
+            lent_rx,
         }
     }
 
-    fn run(self) {
+    fn report(&self, status: DeviceStatus) {
+        let _ = self.state_tx.try_send(EngineEvent::Device(status));
+    }
+
+    fn new_renderer(&self) -> Renderer {
+        Renderer::new(
+            DEFAULT_SAMPLE_RATE,
+            self.shared.clone(),
+            self.state_tx.clone(),
+            self.retired_tx.clone(),
+        )
+    }
+
+    /// Take the renderer back after its stream was dropped
+    fn reclaim_renderer(&self, renderer_rx: &Receiver<Renderer>) -> Renderer {
+        renderer_rx
+            .recv_timeout(RENDERER_RECLAIM_TIMEOUT)
+            .unwrap_or_else(|_| {
+                eprintln!("[AudioThread] Renderer was not returned, starting fresh");
+                self.new_renderer()
+            })
+    }
+
+    /// Offline copy of the renderer at `sample_rate`. A running stream
+    /// lends it for as long as copying takes rather than being torn down;
+    /// converting the copy to the new rate happens after it's back.
+    fn snapshot(
+        &self,
+        streaming: bool,
+        renderer_rx: &Receiver<Renderer>,
+        home: &Sender<Renderer>,
+        sample_rate: u32,
+    ) -> Result<Renderer, String> {
+        let renderer = if streaming {
+            self.lend.store(true, Ordering::Release);
+            renderer_rx.recv_timeout(RENDERER_RECLAIM_TIMEOUT).or_else(|_| {
+                // Still unclaimed means the callback isn't running; claimed
+                // just now means the renderer is on its way
+                if self.lend.swap(false, Ordering::AcqRel) {
+                    return Err("Audio callback did not hand over the session".to_string());
+                }
+                renderer_rx.recv().map_err(|e| e.to_string())
+            })?
+        } else {
+            self.reclaim_renderer(renderer_rx)
+        };
+
+        let mut copy = renderer.offline_copy(renderer.sample_rate());
+        let back = if streaming { &self.lent_tx } else { home };
+        let _ = back.try_send(renderer);
+        copy.set_sample_rate(sample_rate);
+        Ok(copy)
+    }
+
+    fn run(mut self) {
         println!("[AudioThread] Starting real-time audio engine with Mixer");
 
         // Initialize cpal audio output
         let host = cpal::default_host();
 
-        let device = match host.default_output_device() {
-            Some(d) => d,
-            None => {
-                eprintln!("[AudioThread] No output device available");
-                return;
-            }
-        };
-
-        let supported_config = match device.default_output_config() {
-            Ok(c) => c,
-            Err(e) => {
-                eprintln!("[AudioThread] Failed to get output config: {}", e);
-                return;
-            }
-        };
+        // The renderer lives inside the active stream callback and is sent
+        // back here whenever that stream is dropped
+        let (renderer_tx, renderer_rx): (Sender<Renderer>, Receiver<Renderer>) = bounded(1);
+        let renderer = self.new_renderer();
 
-        println!("[AudioThread] Device: {:?}", device.name());
-        println!("[AudioThread] Config: {:?}", supported_config);
+        let mut device = host.default_output_device();
+        let mut stream = self
+            .start_stream(device.as_ref(), renderer, &renderer_tx)
+            .map_err(|e| eprintln!("[AudioThread] {}", e))
+            .ok();
 
-        let sample_rate = supported_config.sample_rate().0;
-        let channels = supported_config.channels();
-        let stream_config: cpal::StreamConfig = supported_config.into();
-
-        // Initialize mixer with master effects
-        let mixer = Arc::new(parking_lot::RwLock::new(Mixer::new(sample_rate as f64)));
-
-        // Track states (volume, pan, muted, soloed) - 7 tracks
-        let track_states: Arc<parking_lot::RwLock<Vec<TrackState>>> = Arc::new(
-            parking_lot::RwLock::new(
-                (0..7)
-                    .map(|_| TrackState {
-                        volume: 0.7,
-                        pan: 0.0,
-                        muted: false,
-                        soloed: false,
-                    })
-                    .collect()
-            )
-        );
+        let mut reconnect: Option<Reconnect> = None;
 
-        // Master effects state
-        let master_effects = Arc::new(parking_lot::RwLock::new(MasterEffects::default()));
+        // Input stream and take while recording
+        let mut recording: Option<(cpal::Stream, RecordTake)> = None;
 
-        let is_running_clone = self.is_running.clone();
-        let current_step_clone = self.current_step.clone();
-        let bpm_clone = self.bpm.clone();
-        let command_rx_clone = self.command_rx.clone();
-        let state_tx_clone = self.state_tx.clone();
+        // Keep thread alive and service control requests
+        loop {
+            if let Some((_, take)) = recording.as_mut() {
+                take.drain();
+            }
+            while self.retired_rx.try_recv().is_ok() {}
+
+            if let Ok(error) = self.fault_rx.try_recv() {
+                if stream.is_some() {
+                    eprintln!("[AudioThread] Stream lost, reconnecting: {}", error);
+                    drop(stream.take());
+                    self.report(DeviceStatus::Lost { error });
+                    reconnect = Some(Reconnect {
+                        attempt: 0,
+                        next_try: Instant::now() + RECONNECT_BACKOFF,
+                    });
+                }
+            }
 
-        let master_volume = Arc::new(parking_lot::RwLock::new(0.8));
-        let master_volume_clone = master_volume.clone();
-        let track_states_clone = track_states.clone();
-        let mixer_clone = mixer.clone();
-        let effects_clone = master_effects.clone();
+            // A device switch or buffer size change may have restarted it already
+            if stream.is_some() {
+                reconnect = None;
+            }
 
-        let err_fn = |err| eprintln!("[AudioThread] Stream error: {}", err);
+            if let Some(pending) = reconnect.as_mut().filter(|r| Instant::now() >= r.next_try) {
+                pending.attempt += 1;
+                self.report(DeviceStatus::Reconnecting {
+                    attempt: pending.attempt,
+                    max_attempts: MAX_RECONNECT_ATTEMPTS,
+                });
+
+                let renderer = self.reclaim_renderer(&renderer_rx);
+                device = host.default_output_device();
+                match self.start_stream(device.as_ref(), renderer, &renderer_tx) {
+                    Ok(s) => {
+                        stream = Some(s);
+                        reconnect = None;
+                        let name = device.as_ref().and_then(|d| d.name().ok()).unwrap_or_default();
+                        self.report(DeviceStatus::Reconnected { device: name });
+                    }
+                    Err(error) if pending.attempt >= MAX_RECONNECT_ATTEMPTS => {
+                        eprintln!("[AudioThread] Giving up on reconnecting: {}", error);
+                        reconnect = None;
+                        self.report(DeviceStatus::Failed { error });
+                    }
+                    Err(_) => pending.next_try = Instant::now() + RECONNECT_BACKOFF,
+                }
+            }
 
-        // Oscillator phases for test synths
-        let phases: Arc<parking_lot::RwLock<Vec<f64>>> = Arc::new(
-            parking_lot::RwLock::new(vec![0.0; 7])
-        );
-        let phases_clone = phases.clone();
-
-        let stream = match device.build_output_stream(
-            &stream_config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                // Non-blocking command check
-                while let Ok(cmd) = command_rx_clone.try_recv() {
-                    match cmd.cmd_type.as_str() {
-                        "set_volume" => {
-                            if let Some(v) = cmd.value {
-                                *master_volume_clone.write() = v.clamp(0.0, 1.0);
-                                mixer_clone.write().master_volume = v.clamp(0.0, 1.0);
-                            }
+            match self.control_rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(EngineControl::SwitchDevice { name, reply }) => {
+                    // Stop the old stream; its callback hands the renderer back
+                    drop(stream.take());
+                    reconnect = None;
+                    let renderer = self.reclaim_renderer(&renderer_rx);
+
+                    let result = match find_output_device(&host, &name) {
+                        Some(d) => {
+                            device = Some(d);
+                            Ok(format!("Output device set to {}", name))
                         }
-                        "set_track_volume" => {
-                            if let (Some(t), Some(v)) = (cmd.track, cmd.value) {
-                                let mut states = track_states_clone.write();
-                                if t < states.len() {
-                                    states[t].volume = v.clamp(0.0, 1.0);
-                                }
-                            }
+                        None => {
+                            device = host.default_output_device();
+                            Err(format!("Output device '{}' not found, using default device", name))
                         }
-                        "set_track_pan" => {
-                            if let (Some(t), Some(v)) = (cmd.track, cmd.value) {
-                                let mut states = track_states_clone.write();
-                                if t < states.len() {
-                                    states[t].pan = v.clamp(-1.0, 1.0);
-                                }
-                            }
+                    };
+
+                    let result = match self.start_stream(device.as_ref(), renderer, &renderer_tx) {
+                        Ok(s) => {
+                            stream = Some(s);
+                            result
                         }
-                        "toggle_mute" => {
-                            if let Some(t) = cmd.track {
-                                let mut states = track_states_clone.write();
-                                if t < states.len() {
-                                    states[t].muted = !states[t].muted;
-                                }
-                            }
+                        Err(e) => Err(e),
+                    };
+
+                    let _ = reply.send(result);
+                }
+                Ok(EngineControl::SetBufferSize { frames, reply }) => {
+                    let supported = device
+                        .as_ref()
+                        .and_then(|d| d.default_output_config().ok())
+                        .map(|c| *c.buffer_size());
+                    let result = match (frames, supported) {
+                        (Some(n), Some(supported)) if !buffer_size_supported(&supported, n) => {
+                            Err(format!("Buffer size {} not supported by this device", n))
                         }
-                        "toggle_solo" => {
-                            if let Some(t) = cmd.track {
-                                let mut states = track_states_clone.write();
-                                if t < states.len() {
-                                    states[t].soloed = !states[t].soloed;
+                        _ => {
+                            drop(stream.take());
+                            reconnect = None;
+                            let previous = std::mem::replace(&mut self.buffer_size, frames);
+                            let renderer = self.reclaim_renderer(&renderer_rx);
+                            match self.start_stream(device.as_ref(), renderer, &renderer_tx) {
+                                Ok(s) => {
+                                    stream = Some(s);
+                                    Ok(match frames {
+                                        Some(n) => format!("Buffer size set to {} frames", n),
+                                        None => "Buffer size set to device default".to_string(),
+                                    })
+                                }
+                                Err(e) => {
+                                    // Back to the size that was working
+                                    self.buffer_size = previous;
+                                    let renderer = self.reclaim_renderer(&renderer_rx);
+                                    stream = self
+                                        .start_stream(device.as_ref(), renderer, &renderer_tx)
+                                        .map_err(|e| eprintln!("[AudioThread] {}", e))
+                                        .ok();
+                                    Err(e)
                                 }
                             }
                         }
-                        "set_bpm" => {
-                            if let Some(v) = cmd.value {
-                                bpm_clone.store(v as u64, Ordering::Relaxed);
-                            }
-                        }
-                        "set_eq_low" => {
-                            if let Some(v) = cmd.value {
-                                effects_clone.write().eq_low = v;
-                            }
-                        }
-                        "set_eq_mid" => {
-                            if let Some(v) = cmd.value {
-                                effects_clone.write().eq_mid = v;
-                            }
-                        }
-                        "set_eq_high" => {
-                            if let Some(v) = cmd.value {
-                                effects_clone.write().eq_high = v;
-                            }
-                        }
-                        "set_limiter" => {
-                            if let Some(v) = cmd.value {
-                                effects_clone.write().limiter_threshold = v;
-                            }
-                        }
-                        "play" => {
-                            is_running_clone.store(true, Ordering::Relaxed);
-                        }
-                        "stop" => {
-                            is_running_clone.store(false, Ordering::Relaxed);
+                    };
+                    let _ = reply.send(result);
+                }
+                Ok(EngineControl::Capabilities { reply }) => {
+                    let result = match device.as_ref() {
+                        Some(d) => d
+                            .supported_output_configs()
+                            .map(|configs| configs.map(|c| OutputConfig::from(&c)).collect())
+                            .map_err(|e| format!("Failed to query output device: {}", e)),
+                        None => Err("No output device available".to_string()),
+                    };
+                    let _ = reply.send(result);
+                }
+                Ok(EngineControl::Snapshot { sample_rate, reply }) => {
+                    let streaming = stream.is_some();
+                    let copy = self.snapshot(streaming, &renderer_rx, &renderer_tx, sample_rate);
+                    let _ = reply.send(copy);
+                }
+                Ok(EngineControl::SetInputDevice { name, reply }) => {
+                    let result = match find_input_device(&host, &name) {
+                        Some(_) => {
+                            let result = Ok(format!("Input device set to {}", name));
+                            self.input_device = Some(name);
+                            result
                         }
-                        _ => {}
-                    }
+                        None => Err(format!("Input device '{}' not found", name)),
+                    };
+                    let _ = reply.send(result);
                 }
-
-                // Update mixer effects
-                {
-                    let effects = effects_clone.read();
-                    let mut mixer_guard = mixer_clone.write();
-                    mixer_guard.set_eq(effects.eq_low, effects.eq_mid, effects.eq_high);
-                    mixer_guard.set_limiter_threshold(effects.limiter_threshold);
-                    mixer_guard.set_clip_amount(effects.clip_amount);
+                Ok(EngineControl::StartRecord { reply }) => {
+                    let result = match recording {
+                        Some(_) => Err("Already recording".to_string()),
+                        None => self.start_recording(&host).map(|(input, take, message)| {
+                            recording = Some((input, take));
+                            message
+                        }),
+                    };
+                    let _ = reply.send(result);
                 }
+                Ok(EngineControl::StopRecord { reply }) => {
+                    let result = match recording.take() {
+                        Some((input, take)) => {
+                            drop(input);
+                            let dropped = take.dropped();
+                            Ok((take.finish(), dropped))
+                        }
+                        None => Err("Not recording".to_string()),
+                    };
+                    let _ = reply.send(result);
+                }
+                Ok(EngineControl::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
 
-                // Calculate step timing
-                let bpm_val = bpm_clone.load(Ordering::Relaxed) as f64;
-                let samples_per_step = (sample_rate as f64 * 60.0) / (bpm_val * 4.0);
-
-                // Step phase accumulator
-                let mut step_phase: f64 = 0.0;
-
-                // Get track states
-                let states = track_states_clone.read();
-                let any_soloed = states.iter().any(|s| s.soloed);
+        // Silence the device before the thread exits
+        drop(stream);
+        println!("[AudioThread] Stopped");
+    }
 
-                // Update phases
-                let mut phases_guard = phases_clone.write();
+    /// Open and start an input stream on the chosen input device, converting
+    /// whatever sample format it runs at
+    fn start_recording(
+        &self,
+        host: &cpal::Host,
+    ) -> Result<(cpal::Stream, RecordTake, String), String> {
+        let device = match &self.input_device {
+            Some(name) => find_input_device(host, name)
+                .ok_or_else(|| format!("Input device '{}' not found", name))?,
+            None => host.default_input_device().ok_or("No input device available")?,
+        };
+        let config = device
+            .default_input_config()
+            .map_err(|e| format!("Failed to get input config: {}", e))?;
+
+        let channels = config.channels();
+        let sample_rate = config.sample_rate().0;
+        let format = config.sample_format();
+        let (input, take) = record::record_channel(channels as usize, sample_rate);
+        let stream_config: cpal::StreamConfig = config.into();
+        let stream = match format {
+            cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, &stream_config, input),
+            cpal::SampleFormat::F64 => build_input_stream::<f64>(&device, &stream_config, input),
+            cpal::SampleFormat::I8 => build_input_stream::<i8>(&device, &stream_config, input),
+            cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, &stream_config, input),
+            cpal::SampleFormat::I32 => build_input_stream::<i32>(&device, &stream_config, input),
+            cpal::SampleFormat::U8 => build_input_stream::<u8>(&device, &stream_config, input),
+            cpal::SampleFormat::U16 => build_input_stream::<u16>(&device, &stream_config, input),
+            cpal::SampleFormat::U32 => build_input_stream::<u32>(&device, &stream_config, input),
+            other => Err(format!("Unsupported input sample format {}", other)),
+        }?;
+        stream.play().map_err(|e| format!("Failed to start input stream: {}", e))?;
+
+        let name = device.name().unwrap_or_default();
+        let message = format!(
+            "Recording from {} ({} channels, {} Hz, {})",
+            name, channels, sample_rate, format
+        );
+        println!("[AudioThread] {}", message);
+        Ok((stream, take, message))
+    }
 
-                // Fill audio buffer
-                for frame in data.chunks_mut(channels as usize) {
-                    let (left, right) = if is_running_clone.load(Ordering::Relaxed) {
-                        // Generate samples for each track
-                        let track_samples: Vec<(f64, f64, f64, bool, bool)> = (0..7)
-                            .map(|i| {
-                                let state = &states[i];
+    /// Start a stream on `device`, or park the renderer if there is none
+    fn start_stream(
+        &self,
+        device: Option<&cpal::Device>,
+        renderer: Renderer,
+        home: &Sender<Renderer>,
+    ) -> Result<cpal::Stream, String> {
+        // Errors from a stream that has since been dropped don't count
+        while self.fault_rx.try_recv().is_ok() {}
+
+        match device {
+            Some(device) => self.build_stream(device, renderer, home),
+            None => {
+                let _ = home.try_send(renderer);
+                Err("No output device available".to_string())
+            }
+        }
+    }
 
-                                // Different frequencies for different tracks
-                                let freqs = [55.0, 110.0, 220.0, 440.0, 880.0, 1760.0, 3520.0];
-                                let freq = freqs[i];
+    /// Build and start an output stream on `device` that owns `renderer`.
+    ///
+    /// On failure the renderer is sent back through `home` like it is when a
+    /// running stream is dropped.
+    fn build_stream(
+        &self,
+        device: &cpal::Device,
+        renderer: Renderer,
+        home: &Sender<Renderer>,
+    ) -> Result<cpal::Stream, String> {
+        // A lend asked of an earlier stream no longer applies
+        self.lend.store(false, Ordering::Release);
+        let mut slot =
+            RendererSlot::new(renderer, home.clone(), self.lend.clone(), self.lent_rx.clone());
+
+        let supported_config = device
+            .default_output_config()
+            .map_err(|e| format!("Failed to get output config: {}", e))?;
 
-                                // Simple oscillator
-                                let sample = (phases_guard[i] * 2.0 * std::f64::consts::PI).sin();
+        println!("[AudioThread] Device: {:?}", device.name());
+        println!("[AudioThread] Config: {:?}", supported_config);
 
-                                // Update phase
-                                phases_guard[i] += freq / sample_rate as f64;
-                                if phases_guard[i] >= 1.0 {
-                                    phases_guard[i] -= 1.0;
-                                }
+        let sample_rate = supported_config.sample_rate().0;
+        let channels = supported_config.channels();
+        let supported_buffer = *supported_config.buffer_size();
+        let mut stream_config: cpal::StreamConfig = supported_config.into();
+        if let Some(frames) = self.buffer_size {
+            // A device switch or reconnect can land on a device that can't
+            // run the requested size
+            if buffer_size_supported(&supported_buffer, frames) {
+                stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+            } else {
+                eprintln!(
+                    "[AudioThread] Buffer size {} not supported, using device default",
+                    frames
+                );
+            }
+        }
 
-                                (sample, state.volume, state.pan, state.muted, state.soloed)
-                            })
-                            .collect();
+        // Commands still queued were built for the old rate (see
+        // `AppState::send_with_mixer`), so they go in before it changes
+        let rate_change = self.shared.rate_change.lock();
+        while let Ok(cmd) = self.command_rx.try_recv() {
+            slot.get().apply(cmd);
+        }
+        slot.get().set_sample_rate(sample_rate);
+        drop(rate_change);
 
-                        // Mix all tracks
-                        let mixer_guard = mixer_clone.read();
-                        let (l, r) = mixer_guard.mix_channels(&track_samples, any_soloed);
+        let command_rx_clone = self.command_rx.clone();
+        let cpu_usage_clone = self.shared.cpu_usage.clone();
+        let buffer_frames = self.shared.buffer_frames.clone();
 
-                        // Drop guard before mutable access
-                        drop(mixer_guard);
+        let fault_tx = self.fault_tx.clone();
+        let err_fn = move |err: cpal::StreamError| {
+            eprintln!("[AudioThread] Stream error: {}", err);
+            let _ = fault_tx.try_send(err.to_string());
+        };
 
-                        (l, r)
-                    } else {
-                        (0.0, 0.0)
+        // Smoothed ratio of callback time to buffer duration
+        let mut cpu_smoothed: f64 = 0.0;
+
+        let stream = device
+            .build_output_stream(
+                &stream_config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let callback_start = Instant::now();
+                    let Some(renderer) = slot.poll() else {
+                        data.fill(0.0);
+                        return;
                     };
 
-                    // Process through master bus
-                    let mut mixer_guard = mixer_clone.write();
-                    let (out_l, out_r) = mixer_guard.process_master(left, right);
-
-                    // Output stereo
-                    if frame.len() >= 2 {
-                        frame[0] = out_l;
-                        frame[1] = out_r;
-                    } else if frame.len() == 1 {
-                        frame[0] = (out_l + out_r) * 0.5;
+                    // Non-blocking command check
+                    while let Ok(cmd) = command_rx_clone.try_recv() {
+                        renderer.apply(cmd);
                     }
 
-                    // Update step counter
-                    step_phase += 1.0;
-                    if step_phase >= samples_per_step {
-                        step_phase = 0.0;
-                        let step = current_step_clone.fetch_add(1, Ordering::Relaxed);
-                        let _ = state_tx_clone.try_send(AudioState {
-                            is_playing: is_running_clone.load(Ordering::Relaxed),
-                            current_step: ((step + 1) % 32) as usize,
-                            bpm: bpm_clone.load(Ordering::Relaxed),
-                            cpu_usage: 0.0,
-                        });
+                    renderer.render(data, channels as usize);
+
+                    let frames = data.len() / channels as usize;
+                    buffer_frames.store(frames as u32, Ordering::Relaxed);
+
+                    // CPU load = time spent in this callback / time the buffer lasts
+                    let buffer_secs = frames as f64 / sample_rate as f64;
+                    if buffer_secs > 0.0 {
+                        let load = callback_start.elapsed().as_secs_f64() / buffer_secs;
+                        cpu_smoothed += CPU_SMOOTHING * (load - cpu_smoothed);
+                        cpu_smoothed = cpu_smoothed.clamp(0.0, 1.0);
+                        cpu_usage_clone.store(cpu_smoothed.to_bits(), Ordering::Relaxed);
                     }
-                }
-            },
-            err_fn,
-            None,
-        ) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("[AudioThread] Failed to build stream: {}", e);
-                return;
-            }
-        };
+                },
+                err_fn,
+                None,
+            )
+            .map_err(|e| format!("Failed to build stream: {}", e))?;
 
         // Start playback stream
-        if let Err(e) = stream.play() {
-            eprintln!("[AudioThread] Failed to start stream: {}", e);
-            return;
-        }
+        stream.play().map_err(|e| format!("Failed to start stream: {}", e))?;
 
         println!("[AudioThread] Audio stream running at {} Hz, {} channels", sample_rate, channels);
         println!("[AudioThread] Mixer with 3-band EQ + Limiter + SoftClip active");
 
-        // Keep thread alive
-        loop {
-            thread::sleep(Duration::from_millis(100));
-        }
+        Ok(stream)
     }
 }
 
@@ -367,9 +1041,223 @@ impl AudioEngine {
 
 pub struct AppState {
     pub command_tx: Sender<AudioCommand>,
-    pub audio_running: Arc<AtomicBool>,
-    pub current_step: Arc<AtomicU64>,
-    pub bpm: Arc<AtomicU64>,
+    pub control_tx: Sender<EngineControl>,
+    pub event_tx: Sender<EngineEvent>,
+    pub shared: SharedState,
+    pub shutdown: Arc<AtomicBool>,
+    pub state_forwarder: Mutex<Option<thread::JoinHandle<()>>>,
+    pub spectrum_analyzer: Mutex<Option<thread::JoinHandle<()>>>,
+    /// Latest master spectrum (dB per bin), for `get_spectrum`
+    pub spectrum: Arc<Mutex<Vec<f32>>>,
+    pub goniometer_thread: Mutex<Option<thread::JoinHandle<()>>>,
+    /// Latest goniometer points, for `get_goniometer`
+    pub goniometer: Arc<Mutex<Vec<GoniometerPoint>>>,
+    pub audio_thread: Mutex<Option<thread::JoinHandle<()>>>,
+    /// Mirror of every parameter sent to the audio thread, for `save_session`
+    pub session: Mutex<SessionState>,
+    /// Mix parameter changes made through `send`, for `undo` / `redo`
+    pub history: Mutex<UndoHistory>,
+    pub midi: MidiInputs,
+    /// Parameter the next incoming CC is assigned to
+    pub midi_learn: Mutex<Option<MidiParam>>,
+    /// Track the next recording loads into
+    pub record_track: Mutex<Option<usize>>,
+    /// Each track's latest sample load, for `get_load_progress`
+    pub sample_loads: Mutex<Vec<Option<Arc<Mutex<LoadProgress>>>>>,
+}
+
+impl AppState {
+    /// Err unless `track` is one of the session's tracks
+    fn check_track(&self, track: usize) -> Result<(), String> {
+        validate::track(track, self.session.lock().tracks.len())
+    }
+
+    /// Send a command to the audio thread and record it in the session and
+    /// undo history
+    fn send(&self, cmd: AudioCommand) -> Result<(), String> {
+        let mut session = self.session.lock();
+        self.history.lock().apply(&mut session, &cmd, Instant::now());
+        drop(session);
+        self.command_tx.send(cmd).map_err(|e| e.to_string())
+    }
+
+    /// Send the command `command` wraps around a fresh mixer for the
+    /// renderer's rate and track count, built here so the audio thread
+    /// doesn't have to
+    fn send_with_mixer(&self, command: fn(Box<Mixer>) -> AudioCommand) -> Result<(), String> {
+        let _rate_change = self.shared.rate_change.lock();
+        let mut session = self.session.lock();
+        let sample_rate = self.shared.sample_rate.load(Ordering::Relaxed) as f64;
+        let cmd = command(Box::new(Mixer::new(sample_rate, session.tracks.len())));
+        self.history.lock().apply(&mut session, &cmd, Instant::now());
+        self.command_tx.send(cmd).map_err(|e| e.to_string())
+    }
+
+    /// Load the sample `decode` produces into `track`'s player. Decoding
+    /// and resampling to the device rate run on a loader thread, which hands
+    /// both buffers to the player when they're done; the original goes
+    /// along for the next device change.
+    fn load_sample<F>(&self, track: usize, decode: F) -> Result<(), String>
+    where
+        F: FnOnce() -> Result<Sample, String> + Send + 'static,
+    {
+        let sample_rate = self.shared.sample_rate.load(Ordering::Relaxed);
+        let progress = Arc::new(Mutex::new(LoadProgress::default()));
+        let (loaded_tx, loaded_rx) = bounded(1);
+        self.send(AudioCommand::LoadSample { track, pending: loaded_rx })?;
+        self.sample_loads.lock()[track] = Some(progress.clone());
+
+        thread::spawn(move || {
+            let source = match decode() {
+                Ok(sample) => Arc::new(sample),
+                Err(e) => {
+                    progress.lock().error = Some(e);
+                    return;
+                }
+            };
+            progress.lock().progress = DECODED_PROGRESS;
+            let sample = if source.sample_rate == sample_rate {
+                source.clone()
+            } else {
+                Arc::new(source.resampled_reporting(sample_rate, |fraction| {
+                    progress.lock().progress =
+                        DECODED_PROGRESS + (1.0 - DECODED_PROGRESS) * fraction;
+                }))
+            };
+            // The player is gone if its track was removed meanwhile
+            let _ = loaded_tx.send(LoadedSample { sample, source });
+            let mut progress = progress.lock();
+            progress.progress = 1.0;
+            progress.done = true;
+        });
+        Ok(())
+    }
+
+    /// Command for an incoming CC, learning it first if a learn is pending
+    fn map_cc(&self, cc: Cc, value: u8) -> Option<AudioCommand> {
+        let mut learning = self.midi_learn.lock();
+        midi::map_cc(&mut self.session.lock().midi_mappings, &mut learning, cc, value)
+    }
+}
+
+// ============================================================
+// STATE FORWARDING (audio thread -> webview)
+// ============================================================
+
+/// How often the forwarder wakes up to check for shutdown while idle
+const STATE_FORWARD_POLL: Duration = Duration::from_millis(50);
+
+/// Interval between `meters` events (~30 fps)
+const METER_EMIT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Drain `state_rx` and emit every event to the webview (`audio_state`,
+/// `export_progress`), plus a `meters` snapshot every `METER_EMIT_INTERVAL`,
+/// until `shutdown` is set or the audio thread hangs up.
+fn spawn_state_forwarder(
+    app_handle: AppHandle,
+    state_rx: Receiver<EngineEvent>,
+    meters: Arc<MeterBank>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        println!("[StateForwarder] Forwarding audio state to webview");
+
+        let mut last_meters = Instant::now();
+        while !shutdown.load(Ordering::Relaxed) {
+            if last_meters.elapsed() >= METER_EMIT_INTERVAL {
+                last_meters = Instant::now();
+                if let Err(e) = app_handle.emit("meters", meters.snapshot()) {
+                    eprintln!("[StateForwarder] Failed to emit meters: {}", e);
+                }
+            }
+
+            match state_rx.recv_timeout(METER_EMIT_INTERVAL.min(STATE_FORWARD_POLL)) {
+                Ok(event) => {
+                    let result = match event {
+                        EngineEvent::State(state) => app_handle.emit("audio_state", state),
+                        EngineEvent::ExportProgress(p) => app_handle.emit("export_progress", p),
+                        EngineEvent::CountIn { beats_remaining } => {
+                            app_handle.emit("count_in", beats_remaining)
+                        }
+                        EngineEvent::Device(status) => app_handle.emit("device_status", status),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("[StateForwarder] Failed to emit event: {}", e);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        println!("[StateForwarder] Stopped");
+    })
+}
+
+/// Interval between `spectrum` events (~30 fps)
+const SPECTRUM_EMIT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Run the master FFT off the audio thread: every `SPECTRUM_EMIT_INTERVAL`
+/// with new samples in `feed`, store the spectrum in `latest` and emit it as
+/// a `spectrum` event, until `shutdown` is set.
+fn spawn_spectrum_analyzer(
+    app_handle: AppHandle,
+    feed: Arc<SpectrumFeed>,
+    latest: Arc<Mutex<Vec<f32>>>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut analyzer = SpectrumAnalyzer::new();
+        let mut last_written = feed.written();
+        while !shutdown.load(Ordering::Relaxed) {
+            thread::sleep(SPECTRUM_EMIT_INTERVAL);
+            let written = feed.written();
+            if written == last_written {
+                continue;
+            }
+            last_written = written;
+
+            let spectrum = analyzer.analyze(&feed).to_vec();
+            if let Err(e) = app_handle.emit("spectrum", &spectrum) {
+                eprintln!("[Spectrum] Failed to emit spectrum: {}", e);
+            }
+            *latest.lock() = spectrum;
+        }
+        println!("[Spectrum] Stopped");
+    })
+}
+
+/// Interval between `goniometer` events (~30 fps)
+const GONIOMETER_EMIT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Rotate the master L/R pairs in `feed` into mid / side points off the
+/// audio thread: every `GONIOMETER_EMIT_INTERVAL` with new frames, store
+/// them in `latest` and emit them as a `goniometer` event, until `shutdown`
+/// is set.
+fn spawn_goniometer(
+    app_handle: AppHandle,
+    feed: Arc<GoniometerFeed>,
+    latest: Arc<Mutex<Vec<GoniometerPoint>>>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_seen = feed.seen();
+        while !shutdown.load(Ordering::Relaxed) {
+            thread::sleep(GONIOMETER_EMIT_INTERVAL);
+            let seen = feed.seen();
+            if seen == last_seen {
+                continue;
+            }
+            last_seen = seen;
+
+            let points = feed.points();
+            if let Err(e) = app_handle.emit("goniometer", &points) {
+                eprintln!("[Goniometer] Failed to emit points: {}", e);
+            }
+            *latest.lock() = points;
+        }
+        println!("[Goniometer] Stopped");
+    })
 }
 
 // ============================================================
@@ -378,167 +1266,1840 @@ pub struct AppState {
 
 #[tauri::command]
 fn start_audio(state: State<AppState>) -> Result<String, String> {
-    state.audio_running.store(true, Ordering::Relaxed);
-    let cmd = AudioCommand {
-        cmd_type: "play".to_string(),
-        track: None,
-        value: None,
-        data: None,
-    };
-    let _ = state.command_tx.send(cmd);
+    state.shared.is_running.store(true, Ordering::Relaxed);
+    let cmd = AudioCommand::Play;
+    let _ = state.send(cmd);
     println!("[Tauri] Audio started");
     Ok("Audio started".to_string())
 }
 
+/// Longest count-in `start_audio_with_countin` accepts
+const MAX_COUNT_IN_BARS: u32 = 8;
+
+/// Start playback after `bars` bars of metronome count-in. The transport
+/// flag flips when the count-in ends, not now.
+#[tauri::command]
+fn start_audio_with_countin(state: State<AppState>, bars: u32) -> Result<String, String> {
+    validate::in_range("Count-in bars", bars as f64, 0.0, MAX_COUNT_IN_BARS as f64)?;
+    let cmd = AudioCommand::PlayWithCountIn { bars };
+    state.send(cmd)?;
+    println!("[Tauri] Audio starting after {} bar count-in", bars);
+    Ok(format!("Counting in {} bars", bars))
+}
+
 #[tauri::command]
 fn stop_audio(state: State<AppState>) -> Result<String, String> {
-    state.audio_running.store(false, Ordering::Relaxed);
-    let cmd = AudioCommand {
-        cmd_type: "stop".to_string(),
-        track: None,
-        value: None,
-        data: None,
-    };
-    let _ = state.command_tx.send(cmd);
+    state.shared.is_running.store(false, Ordering::Relaxed);
+    let cmd = AudioCommand::Stop;
+    let _ = state.send(cmd);
     println!("[Tauri] Audio stopped");
     Ok("Audio stopped".to_string())
 }
 
+/// Restore every mix parameter (master and tracks) to its default and clear
+/// all effect state. Patterns, samples and the transport are kept.
+#[tauri::command]
+fn reset_mixer(state: State<AppState>) -> Result<String, String> {
+    state.send_with_mixer(|mixer| AudioCommand::ResetMixer { mixer })?;
+    Ok("Mixer reset".to_string())
+}
+
+/// All notes off and every effect tail cut, for stuck notes or runaway
+/// feedback. Unlike `stop_audio` nothing keeps ringing, and unlike
+/// `reset_mixer` every setting is kept.
+#[tauri::command]
+fn panic(state: State<AppState>) -> Result<String, String> {
+    state.send_with_mixer(|mixer| AudioCommand::Panic { mixer })?;
+    Ok("All sound stopped".to_string())
+}
+
+/// Rewind the playhead to the first step
+#[tauri::command]
+fn transport_return_to_zero(state: State<AppState>) -> Result<String, String> {
+    let cmd = AudioCommand::SetPosition { step: 0 };
+    state.send(cmd)?;
+    Ok("Returned to zero".to_string())
+}
+
+#[tauri::command]
+fn transport_set_step(state: State<AppState>, step: usize) -> Result<String, String> {
+    let cmd = AudioCommand::SetPosition { step };
+    state.send(cmd)?;
+    Ok(format!("Playhead moved to step {}", step))
+}
+
+/// Master volume as a linear gain, 0.0..=1.0; kept alongside
+/// `set_master_gain_db`, where 1.0 is 0 dB and 0.5 about -6 dB
 #[tauri::command]
 fn set_volume(state: State<AppState>, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_volume".to_string(),
-        track: None,
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    validate::level("Volume", value)?;
+    let cmd = AudioCommand::SetVolume { value };
+    state.send(cmd)?;
     Ok(format!("Volume set to {}", value))
 }
 
+/// Master volume in dB, up to 0 dB (unity), in the units the EQ uses
+#[tauri::command]
+fn set_master_gain_db(state: State<AppState>, db: f64) -> Result<String, String> {
+    validate::in_range("Master gain (dB)", db, MIN_DBFS, 0.0)?;
+    let cmd = AudioCommand::SetVolume { value: db_to_gain(db) };
+    state.send(cmd)?;
+    Ok(format!("Master gain set to {} dB", db))
+}
+
 #[tauri::command]
 fn set_track_volume(state: State<AppState>, track: usize, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_track_volume".to_string(),
-        track: Some(track),
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    state.check_track(track)?;
+    validate::level("Track volume", value)?;
+    let cmd = AudioCommand::SetTrackVolume { track, value };
+    state.send(cmd)?;
     Ok(format!("Track {} volume set to {}", track, value))
 }
 
 #[tauri::command]
 fn set_track_pan(state: State<AppState>, track: usize, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_track_pan".to_string(),
-        track: Some(track),
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    state.check_track(track)?;
+    validate::pan(value)?;
+    let cmd = AudioCommand::SetTrackPan { track, value };
+    state.send(cmd)?;
     Ok(format!("Track {} pan set to {}", track, value))
 }
 
+/// `auto` balances stereo samples and pans mono sources
+#[tauri::command]
+fn set_track_pan_mode(
+    state: State<AppState>,
+    track: usize,
+    mode: PanMode,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::SetTrackPanMode { track, mode };
+    state.send(cmd)?;
+    Ok(format!("Track {} pan mode set to {:?}", track, mode))
+}
+
 #[tauri::command]
 fn toggle_mute(state: State<AppState>, track: usize) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "toggle_mute".to_string(),
-        track: Some(track),
-        value: None,
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    state.check_track(track)?;
+    let cmd = AudioCommand::ToggleMute { track };
+    state.send(cmd)?;
     Ok(format!("Track {} mute toggled", track))
 }
 
+#[tauri::command]
+fn toggle_track_polarity(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::ToggleTrackPolarity { track };
+    state.send(cmd)?;
+    Ok(format!("Track {} polarity toggled", track))
+}
+
 #[tauri::command]
 fn toggle_solo(state: State<AppState>, track: usize) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "toggle_solo".to_string(),
-        track: Some(track),
-        value: None,
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    state.check_track(track)?;
+    let cmd = AudioCommand::ToggleSolo { track };
+    state.send(cmd)?;
     Ok(format!("Track {} solo toggled", track))
 }
 
 #[tauri::command]
-fn set_bpm(state: State<AppState>, bpm: u64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_bpm".to_string(),
-        track: None,
-        value: Some(bpm as f64),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
-    state.bpm.store(bpm, Ordering::Relaxed);
-    Ok(format!("BPM set to {}", bpm))
+fn set_track_solo_safe(state: State<AppState>, track: usize, on: bool) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::SetTrackSoloSafe { track, on };
+    state.send(cmd)?;
+    Ok(format!("Track {} solo safe {}", track, if on { "on" } else { "off" }))
 }
 
-// ============================================================
-// NEW: MASTER EFFECTS COMMANDS
-// ============================================================
+#[tauri::command]
+fn set_solo_mode(state: State<AppState>, mode: SoloMode) -> Result<String, String> {
+    let cmd = AudioCommand::SetSoloMode { mode };
+    state.send(cmd)?;
+    Ok(format!("Solo mode set to {:?}", mode))
+}
 
 #[tauri::command]
-fn set_eq_low(state: State<AppState>, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_eq_low".to_string(),
-        track: None,
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
-    Ok(format!("EQ Low set to {} dB", value))
+fn add_track(state: State<AppState>) -> Result<String, String> {
+    let track = state.session.lock().tracks.len();
+    if track >= MAX_TRACKS {
+        return Err(format!("At most {} tracks are supported", MAX_TRACKS));
+    }
+    state.send(AudioCommand::AddTrack)?;
+    Ok(format!("Track {} added", track))
 }
 
 #[tauri::command]
-fn set_eq_mid(state: State<AppState>, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_eq_mid".to_string(),
-        track: None,
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
-    Ok(format!("EQ Mid set to {} dB", value))
+fn remove_track(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    if state.session.lock().tracks.len() == 1 {
+        return Err("Cannot remove the last track".to_string());
+    }
+    state.send(AudioCommand::RemoveTrack { track })?;
+    let mut learning = state.midi_learn.lock();
+    *learning = learning.and_then(|param| param.after_track_removed(track));
+    drop(learning);
+    let mut record_track = state.record_track.lock();
+    *record_track = index_after_removal(*record_track, track);
+    drop(record_track);
+    let mut loads = state.sample_loads.lock();
+    loads.remove(track);
+    loads.push(None);
+    Ok(format!("Track {} removed", track))
 }
 
 #[tauri::command]
-fn set_eq_high(state: State<AppState>, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_eq_high".to_string(),
-        track: None,
-        value: Some(value),
-        data: None,
-    };
-    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
-    Ok(format!("EQ High set to {} dB", value))
+fn set_metronome(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetMetronome { on };
+    state.send(cmd)?;
+    Ok(format!("Metronome {}", if on { "on" } else { "off" }))
 }
 
+/// Monitor dim (-20 dB) on the live output only, not the mix or exports
 #[tauri::command]
-fn set_limiter(state: State<AppState>, value: f64) -> Result<String, String> {
-    let cmd = AudioCommand {
-        cmd_type: "set_limiter".to_string(),
-        track: None,
-        value: Some(value),
-        data: None,
-    };
+fn set_monitor_dim(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetMonitorDim { on };
+    state.send(cmd)?;
+    Ok(format!("Monitor dim {}", if on { "on" } else { "off" }))
+}
+
+/// Monitor mono fold-down on the live output only, not the mix or exports
+#[tauri::command]
+fn set_monitor_mono(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetMonitorMono { on };
+    state.send(cmd)?;
+    Ok(format!("Monitor mono {}", if on { "on" } else { "off" }))
+}
+
+fn set_track_listen(state: &AppState, track: usize, mode: Listen) -> Result<String, String> {
+    state.check_track(track)?;
+    state.send(AudioCommand::SetTrackListen { track, mode })?;
+    Ok(format!("Track {} listen {:?}", track, mode))
+}
+
+/// Pre-fader listen: while any track is listened to, the live output plays
+/// those tracks after their effects but before volume and pan, instead of
+/// the main mix. Turning it off stops listening to the track either way.
+#[tauri::command]
+fn set_track_pfl(state: State<AppState>, track: usize, on: bool) -> Result<String, String> {
+    set_track_listen(&state, track, if on { Listen::Pfl } else { Listen::Off })
+}
+
+/// After-fader listen: like `set_track_pfl`, with volume and pan applied
+#[tauri::command]
+fn set_track_afl(state: State<AppState>, track: usize, on: bool) -> Result<String, String> {
+    set_track_listen(&state, track, if on { Listen::Afl } else { Listen::Off })
+}
+
+#[tauri::command]
+fn set_bpm(state: State<AppState>, bpm: u64) -> Result<String, String> {
+    validate::bpm(bpm)?;
+    let cmd = AudioCommand::SetBpm { bpm };
+    state.send(cmd)?;
+    state.shared.bpm.store(bpm, Ordering::Relaxed);
+    Ok(format!("BPM set to {}", bpm))
+}
+
+#[tauri::command]
+fn set_track_trim(state: State<AppState>, track: usize, db: f64) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::trim(db)?;
+    let cmd = AudioCommand::SetTrackTrim { track, value: db };
+    state.send(cmd)?;
+    Ok(format!("Track {} trim set to {} dB", track, db))
+}
+
+#[tauri::command]
+fn set_track_eq_low(state: State<AppState>, track: usize, value: f64) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetTrackEqLow { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} EQ Low set to {} dB", track, value))
+}
+
+#[tauri::command]
+fn set_track_eq_mid(state: State<AppState>, track: usize, value: f64) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetTrackEqMid { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} EQ Mid set to {} dB", track, value))
+}
+
+#[tauri::command]
+fn set_track_eq_high(state: State<AppState>, track: usize, value: f64) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetTrackEqHigh { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} EQ High set to {} dB", track, value))
+}
+
+#[tauri::command]
+fn set_track_bitcrush(
+    state: State<AppState>,
+    track: usize,
+    bits: u32,
+    downsample: u32,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Bit depth", bits as f64, 1.0, MAX_CRUSH_BITS as f64)?;
+    validate::in_range("Downsample factor", downsample as f64, 1.0, MAX_CRUSH_DOWNSAMPLE as f64)?;
+    let cmd = AudioCommand::SetTrackBitcrush { track, bits, downsample };
+    state.send(cmd)?;
+    Ok(format!("Track {} bitcrush set to {} bits, {}x downsample", track, bits, downsample))
+}
+
+#[tauri::command]
+fn set_track_hpf(
+    state: State<AppState>,
+    track: usize,
+    freq_hz: f64,
+    on: bool,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("HPF frequency (Hz)", freq_hz, MIN_HPF_HZ, MAX_HPF_HZ)?;
+    let cmd = AudioCommand::SetTrackHpf { track, freq_hz, on };
+    state.send(cmd)?;
+    let status = if on { "on" } else { "off" };
+    Ok(format!("Track {} high-pass {} at {} Hz", track, status, freq_hz))
+}
+
+#[tauri::command]
+fn set_track_allpass(
+    state: State<AppState>,
+    track: usize,
+    freq: f64,
+    on: bool,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("All-pass frequency (Hz)", freq, MIN_ALLPASS_HZ, MAX_ALLPASS_HZ)?;
+    let cmd = AudioCommand::SetTrackAllpass { track, freq_hz: freq, on };
+    state.send(cmd)?;
+    let status = if on { "on" } else { "off" };
+    Ok(format!("Track {} all-pass {} at {} Hz", track, status, freq))
+}
+
+#[tauri::command]
+fn set_track_gate(
+    state: State<AppState>,
+    track: usize,
+    threshold: f64,
+    attack: f64,
+    hold: f64,
+    release: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Gate threshold (dB)", threshold, GATE_OFF_DB, 0.0)?;
+    validate::time_ms("Gate attack (ms)", attack)?;
+    validate::time_ms("Gate hold (ms)", hold)?;
+    validate::time_ms("Gate release (ms)", release)?;
+    let cmd = AudioCommand::SetTrackGate { track, threshold, attack, hold, release };
+    state.send(cmd)?;
+    Ok(format!("Track {} gate threshold set to {} dB", track, threshold))
+}
+
+#[tauri::command]
+fn set_track_gate_lookahead(
+    state: State<AppState>,
+    track: usize,
+    ms: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Gate lookahead (ms)", ms, 0.0, MAX_GATE_LOOKAHEAD_MS)?;
+    let cmd = AudioCommand::SetTrackGateLookahead { track, ms };
+    state.send(cmd)?;
+    Ok(format!("Track {} gate lookahead set to {} ms", track, ms))
+}
+
+#[tauri::command]
+fn set_track_comp_threshold(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::comp_threshold(value)?;
+    let cmd = AudioCommand::SetTrackCompThreshold { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} compressor threshold set to {} dB", track, value))
+}
+
+#[tauri::command]
+fn set_track_comp_ratio(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::comp_ratio(value)?;
+    let cmd = AudioCommand::SetTrackCompRatio { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} compressor ratio set to {}:1", track, value))
+}
+
+#[tauri::command]
+fn set_track_comp_attack(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::comp_attack(value)?;
+    let cmd = AudioCommand::SetTrackCompAttack { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} compressor attack set to {} ms", track, value))
+}
+
+#[tauri::command]
+fn set_track_comp_release(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::comp_release(value)?;
+    let cmd = AudioCommand::SetTrackCompRelease { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} compressor release set to {} ms", track, value))
+}
+
+#[tauri::command]
+fn set_track_sidechain(
+    state: State<AppState>,
+    track: usize,
+    source_track: usize,
+    on: bool,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    state.check_track(source_track)?;
+    let source = on.then_some(source_track);
+    let cmd = AudioCommand::SetTrackSidechain { track, source };
+    state.send(cmd)?;
+    Ok(match source {
+        Some(source) => format!("Track {} compressor keyed from track {}", track, source),
+        None => format!("Track {} sidechain off", track),
+    })
+}
+
+/// `shape` defaults to a sine
+#[tauri::command]
+fn set_track_autopan(
+    state: State<AppState>,
+    track: usize,
+    rate: f64,
+    depth: f64,
+    shape: Option<LfoShape>,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Auto-pan rate (Hz)", rate, MIN_LFO_RATE, MAX_LFO_RATE)?;
+    validate::level("Auto-pan depth", depth)?;
+    let shape = shape.unwrap_or_default();
+    let cmd = AudioCommand::SetTrackAutopan { track, rate, depth, shape };
+    state.send(cmd)?;
+    Ok(format!("Track {} auto-pan at {} Hz, depth {}", track, rate, depth))
+}
+
+/// `rate` is Hz (`4.0`) or a note division (`"eighth"`) of the transport
+#[tauri::command]
+fn set_track_tremolo(
+    state: State<AppState>,
+    track: usize,
+    rate: LfoRate,
+    depth: f64,
+    shape: LfoShape,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::lfo_rate("Tremolo rate (Hz)", rate)?;
+    validate::level("Tremolo depth", depth)?;
+    let cmd = AudioCommand::SetTrackTremolo { track, rate, depth, shape };
+    state.send(cmd)?;
+    Ok(format!("Track {} tremolo at {:?}, depth {}, {:?}", track, rate, depth, shape))
+}
+
+#[tauri::command]
+fn set_track_frequency(state: State<AppState>, track: usize, hz: f64) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Frequency (Hz)", hz, MIN_FREQUENCY, MAX_FREQUENCY)?;
+    let cmd = AudioCommand::SetTrackFrequency { track, hz };
+    state.send(cmd)?;
+    Ok(format!("Track {} frequency set to {} Hz", track, hz))
+}
+
+#[tauri::command]
+fn set_track_note(state: State<AppState>, track: usize, note: u8) -> Result<String, String> {
+    state.check_track(track)?;
+    if note > 127 {
+        return Err(format!("MIDI note {} out of range (0-127)", note));
+    }
+    let hz = synth::midi_to_frequency(note);
+    let cmd = AudioCommand::SetTrackFrequency { track, hz };
+    state.send(cmd)?;
+    Ok(format!("Track {} set to MIDI note {} ({:.2} Hz)", track, note, hz))
+}
+
+#[tauri::command]
+fn set_track_adsr(
+    state: State<AppState>,
+    track: usize,
+    attack: f64,
+    decay: f64,
+    sustain: f64,
+    release: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::time_ms("Envelope attack (ms)", attack)?;
+    validate::time_ms("Envelope decay (ms)", decay)?;
+    validate::level("Envelope sustain", sustain)?;
+    validate::time_ms("Envelope release (ms)", release)?;
+    let cmd = AudioCommand::SetTrackAdsr { track, attack, decay, sustain, release };
+    state.send(cmd)?;
+    Ok(format!(
+        "Track {} envelope set to A {} ms, D {} ms, S {}, R {} ms",
+        track, attack, decay, sustain, release
+    ))
+}
+
+#[tauri::command]
+fn set_track_send_delay(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::level("Delay send", value)?;
+    let cmd = AudioCommand::SetTrackSendDelay { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} delay send set to {}", track, value))
+}
+
+#[tauri::command]
+fn set_track_send_reverb(
+    state: State<AppState>,
+    track: usize,
+    value: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::level("Reverb send", value)?;
+    let cmd = AudioCommand::SetTrackSendReverb { track, value };
+    state.send(cmd)?;
+    Ok(format!("Track {} reverb send set to {}", track, value))
+}
+
+// ============================================================
+// SAMPLER COMMANDS
+// ============================================================
+
+/// Share of a load's progress that decoding accounts for; resampling (if
+/// the file's rate isn't the device's) fills in the rest
+const DECODED_PROGRESS: f64 = 0.5;
+
+/// How far a track's latest load has got, from `get_load_progress`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadProgress {
+    /// 0.0..=1.0 through decoding and resampling
+    pub progress: f64,
+    /// Handed to the audio thread, which plays it from its next buffer
+    pub done: bool,
+    /// Why the load failed; the track keeps its previous sample
+    pub error: Option<String>,
+}
+
+/// Start loading a WAV file into `track` and return at once; decoding and
+/// resampling happen on a loader thread (`get_load_progress` follows it)
+/// and the track keeps playing its previous sample until it's done. A file
+/// that isn't a playable WAV is rejected before anything starts.
+#[tauri::command]
+fn load_sample(state: State<AppState>, track: usize, data: Vec<u8>) -> Result<String, String> {
+    state.check_track(track)?;
+    let megabytes = data.len() as f64 / 1_048_576.0;
+    // A bad file fails here; only the decode itself waits for the loader
+    let wav = sampler::parse_wav(&data).map_err(|e| format!("Invalid WAV data: {}", e))?;
+    state.load_sample(track, move || Ok(wav.decode(&data)))?;
+    Ok(format!("Track {} sample loading ({:.1} MB)", track, megabytes))
+}
+
+/// The latest `load_sample` (or recording) into `track`, `None` if there
+/// hasn't been one
+#[tauri::command]
+fn get_load_progress(state: State<AppState>, track: usize) -> Result<Option<LoadProgress>, String> {
+    state.check_track(track)?;
+    let loads = state.sample_loads.lock();
+    Ok(loads[track].as_ref().map(|progress| progress.lock().clone()))
+}
+
+#[tauri::command]
+fn trigger_sample(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::TriggerSample { track };
+    state.send(cmd)?;
+    Ok(format!("Track {} sample triggered", track))
+}
+
+#[tauri::command]
+fn set_track_sample_speed(
+    state: State<AppState>,
+    track: usize,
+    ratio: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Sample speed", ratio, MIN_SAMPLE_SPEED, MAX_SAMPLE_SPEED)?;
+    let cmd = AudioCommand::SetTrackSampleSpeed { track, ratio };
+    state.send(cmd)?;
+    Ok(format!("Track {} sample speed set to {}", track, ratio))
+}
+
+#[tauri::command]
+fn set_track_sample_loop(state: State<AppState>, track: usize, on: bool) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::SetTrackSampleLoop { track, on };
+    state.send(cmd)?;
+    Ok(format!("Track {} sample loop {}", track, if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_track_loop_crossfade(
+    state: State<AppState>,
+    track: usize,
+    ms: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Loop crossfade (ms)", ms, 0.0, MAX_LOOP_CROSSFADE_MS)?;
+    let cmd = AudioCommand::SetTrackLoopCrossfade { track, ms };
+    state.send(cmd)?;
+    Ok(format!("Track {} loop crossfade set to {} ms", track, ms))
+}
+
+#[tauri::command]
+fn set_track_reverse(state: State<AppState>, track: usize, on: bool) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::SetTrackReverse { track, on };
+    state.send(cmd)?;
+    Ok(format!("Track {} reverse {}", track, if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_track_unison(
+    state: State<AppState>,
+    track: usize,
+    voices: usize,
+    detune_cents: f64,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::in_range("Unison voices", voices as f64, 1.0, MAX_UNISON_VOICES as f64)?;
+    validate::in_range("Unison detune (cents)", detune_cents, 0.0, MAX_UNISON_DETUNE_CENTS)?;
+    let cmd = AudioCommand::SetTrackUnison { track, voices, detune_cents };
+    state.send(cmd)?;
+    Ok(format!("Track {} unison {} voices, {} cents", track, voices, detune_cents))
+}
+
+#[tauri::command]
+fn set_track_sync(
+    state: State<AppState>,
+    track: usize,
+    master_track: Option<usize>,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    if let Some(master) = master_track {
+        state.check_track(master)?;
+    }
+    let cmd = AudioCommand::SetTrackSync { track, master: master_track };
+    state.send(cmd)?;
+    Ok(match master_track {
+        Some(master) => format!("Track {} synced to track {}", track, master),
+        None => format!("Track {} sync off", track),
+    })
+}
+
+// ============================================================
+// SYNTH COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn set_waveform(state: State<AppState>, track: usize, kind: Waveform) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::SetWaveform { track, waveform: kind };
+    state.send(cmd)?;
+    Ok(format!("Track {} waveform set to {:?}", track, kind))
+}
+
+// ============================================================
+// SEQUENCER COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn set_step(state: State<AppState>, track: usize, step: usize, on: bool) -> Result<String, String> {
+    state.check_track(track)?;
+    validate::step(step)?;
+    let cmd = AudioCommand::SetStep { track, step, on };
+    state.send(cmd)?;
+    Ok(format!("Track {} step {} {}", track, step, if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn clear_pattern(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::ClearPattern { track };
+    state.send(cmd)?;
+    Ok(format!("Track {} pattern cleared", track))
+}
+
+#[tauri::command]
+fn set_loop_length(state: State<AppState>, steps: usize) -> Result<String, String> {
+    if steps == 0 || steps > sequencer::MAX_STEPS {
+        return Err(format!("Loop length must be between 1 and {} steps", sequencer::MAX_STEPS));
+    }
+    let cmd = AudioCommand::SetLoopLength { steps };
+    state.send(cmd)?;
+    Ok(format!("Loop length set to {} steps", steps))
+}
+
+#[tauri::command]
+fn set_time_signature(state: State<AppState>, num: u32, den: u32) -> Result<String, String> {
+    let time_signature = sequencer::TimeSignature::new(num, den)?;
+    let cmd = AudioCommand::SetTimeSignature {
+        numerator: time_signature.numerator,
+        denominator: time_signature.denominator,
+    };
+    state.send(cmd)?;
+    Ok(format!("Time signature set to {}/{}", num, den))
+}
+
+#[tauri::command]
+fn set_loop_enabled(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetLoopEnabled { on };
+    state.send(cmd)?;
+    Ok(format!("Loop {}", if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_swing(state: State<AppState>, amount: f64) -> Result<String, String> {
+    validate::in_range("Swing", amount, 0.0, sequencer::MAX_SWING)?;
+    let cmd = AudioCommand::SetSwing { amount };
+    state.send(cmd)?;
+    Ok(format!("Swing set to {}", amount))
+}
+
+// ============================================================
+// NEW: MASTER EFFECTS COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn set_eq_low(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetEqLow { value };
+    state.send(cmd)?;
+    Ok(format!("EQ Low set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_eq_mid(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetEqMid { value };
+    state.send(cmd)?;
+    Ok(format!("EQ Mid set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_eq_high(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetEqHigh { value };
+    state.send(cmd)?;
+    Ok(format!("EQ High set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_eq_ms_mode(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetEqMsMode { on };
+    state.send(cmd)?;
+    Ok(format!("EQ mode set to {}", if on { "mid/side" } else { "stereo" }))
+}
+
+#[tauri::command]
+fn set_side_eq_low(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetSideEqLow { value };
+    state.send(cmd)?;
+    Ok(format!("Side EQ Low set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_side_eq_mid(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetSideEqMid { value };
+    state.send(cmd)?;
+    Ok(format!("Side EQ Mid set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_side_eq_high(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::eq_gain(value)?;
+    let cmd = AudioCommand::SetSideEqHigh { value };
+    state.send(cmd)?;
+    Ok(format!("Side EQ High set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_comp_threshold(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::comp_threshold(value)?;
+    let cmd = AudioCommand::SetCompThreshold { value };
+    state.send(cmd)?;
+    Ok(format!("Compressor threshold set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_comp_ratio(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::comp_ratio(value)?;
+    let cmd = AudioCommand::SetCompRatio { value };
+    state.send(cmd)?;
+    Ok(format!("Compressor ratio set to {}:1", value))
+}
+
+#[tauri::command]
+fn set_comp_attack(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::comp_attack(value)?;
+    let cmd = AudioCommand::SetCompAttack { value };
+    state.send(cmd)?;
+    Ok(format!("Compressor attack set to {} ms", value))
+}
+
+#[tauri::command]
+fn set_comp_release(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::comp_release(value)?;
+    let cmd = AudioCommand::SetCompRelease { value };
+    state.send(cmd)?;
+    Ok(format!("Compressor release set to {} ms", value))
+}
+
+#[tauri::command]
+fn set_comp_makeup(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::comp_makeup(value)?;
+    let cmd = AudioCommand::SetCompMakeup { value };
+    state.send(cmd)?;
+    Ok(format!("Compressor makeup set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_comp_knee(state: State<AppState>, db: f64) -> Result<String, String> {
+    validate::in_range("Compressor knee (dB)", db, 0.0, MAX_COMP_KNEE_DB)?;
+    let cmd = AudioCommand::SetCompKnee { value: db };
+    state.send(cmd)?;
+    Ok(format!("Compressor knee set to {} dB", db))
+}
+
+#[tauri::command]
+fn set_delay_time_division(state: State<AppState>, division: NoteDivision) -> Result<String, String> {
+    let cmd = AudioCommand::SetDelayTimeDivision { division };
+    state.send(cmd)?;
+    Ok(format!("Delay time set to {:?}", division))
+}
+
+#[tauri::command]
+fn set_delay_feedback(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Delay feedback", value, 0.0, delay::MAX_FEEDBACK)?;
+    let cmd = AudioCommand::SetDelayFeedback { value };
+    state.send(cmd)?;
+    Ok(format!("Delay feedback set to {}", value))
+}
+
+#[tauri::command]
+fn set_delay_mix(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Delay mix", value)?;
+    let cmd = AudioCommand::SetDelayMix { value };
+    state.send(cmd)?;
+    Ok(format!("Delay mix set to {}", value))
+}
+
+#[tauri::command]
+fn set_delay_pingpong(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetDelayPingPong { on };
+    state.send(cmd)?;
+    Ok(format!("Delay ping-pong {}", if on { "on" } else { "off" }))
+}
+
+/// Tape-style repeats: each echo passes the low-pass at `cutoff` Hz once
+/// more than the last (20000 leaves the loop open), and with `low_cut` Hz
+/// a high-pass as well
+#[tauri::command]
+fn set_delay_tone(
+    state: State<AppState>,
+    cutoff: f64,
+    low_cut: Option<f64>,
+) -> Result<String, String> {
+    validate::in_range("Delay tone (Hz)", cutoff, MIN_DELAY_TONE_HZ, MAX_DELAY_TONE_HZ)?;
+    if let Some(hz) = low_cut {
+        validate::in_range("Delay low cut (Hz)", hz, MIN_DELAY_LOW_CUT_HZ, MAX_DELAY_LOW_CUT_HZ)?;
+    }
+    let cmd = AudioCommand::SetDelayTone { cutoff, low_cut };
+    state.send(cmd)?;
+    match low_cut {
+        Some(hz) => Ok(format!("Delay feedback filtered to {} - {} Hz", hz, cutoff)),
+        None => Ok(format!("Delay feedback low-passed at {} Hz", cutoff)),
+    }
+}
+
+#[tauri::command]
+fn set_reverb_size(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Reverb size", value)?;
+    let cmd = AudioCommand::SetReverbSize { value };
+    state.send(cmd)?;
+    Ok(format!("Reverb size set to {}", value))
+}
+
+#[tauri::command]
+fn set_reverb_damping(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Reverb damping", value)?;
+    let cmd = AudioCommand::SetReverbDamping { value };
+    state.send(cmd)?;
+    Ok(format!("Reverb damping set to {}", value))
+}
+
+#[tauri::command]
+fn set_reverb_mix(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Reverb mix", value)?;
+    let cmd = AudioCommand::SetReverbMix { value };
+    state.send(cmd)?;
+    Ok(format!("Reverb mix set to {}", value))
+}
+
+#[tauri::command]
+fn set_chorus_rate(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Chorus rate (Hz)", value, MIN_CHORUS_RATE, MAX_CHORUS_RATE)?;
+    let cmd = AudioCommand::SetChorusRate { value };
+    state.send(cmd)?;
+    Ok(format!("Chorus rate set to {} Hz", value))
+}
+
+#[tauri::command]
+fn set_chorus_depth(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Chorus depth", value)?;
+    let cmd = AudioCommand::SetChorusDepth { value };
+    state.send(cmd)?;
+    Ok(format!("Chorus depth set to {}", value))
+}
+
+#[tauri::command]
+fn set_chorus_mix(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Chorus mix", value)?;
+    let cmd = AudioCommand::SetChorusMix { value };
+    state.send(cmd)?;
+    Ok(format!("Chorus mix set to {}", value))
+}
+
+#[tauri::command]
+fn set_limiter(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::level("Limiter threshold", value)?;
+    let cmd = AudioCommand::SetLimiter { value };
+    state.send(cmd)?;
+    Ok(format!("Limiter threshold set to {}", value))
+}
+
+#[tauri::command]
+fn set_limiter_true_peak(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetLimiterTruePeak { on };
+    state.send(cmd)?;
+    Ok(format!("Limiter true-peak detection {}", if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_limiter_release_mode(
+    state: State<AppState>,
+    mode: LimiterRelease,
+) -> Result<String, String> {
+    let cmd = AudioCommand::SetLimiterReleaseMode { mode };
+    state.send(cmd)?;
+    Ok(format!("Limiter release mode set to {:?}", mode))
+}
+
+#[tauri::command]
+fn set_stereo_width(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Stereo width", value, 0.0, MAX_STEREO_WIDTH)?;
+    let cmd = AudioCommand::SetStereoWidth { value };
+    state.send(cmd)?;
+    Ok(format!("Stereo width set to {}", value))
+}
+
+#[tauri::command]
+fn set_mono(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetMono { on };
+    state.send(cmd)?;
+    Ok(format!("Mono {}", if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_pan_law(state: State<AppState>, law: PanLaw) -> Result<String, String> {
+    let cmd = AudioCommand::SetPanLaw { law };
+    state.send(cmd)?;
+    Ok(format!("Pan law set to {:?}", law))
+}
+
+#[tauri::command]
+fn set_clip_mode(state: State<AppState>, mode: ClipMode) -> Result<String, String> {
+    let cmd = AudioCommand::SetClipMode { mode };
+    state.send(cmd)?;
+    Ok(format!("Soft clipper mode set to {:?}", mode))
+}
+
+#[tauri::command]
+fn set_oversampling(state: State<AppState>, factor: usize) -> Result<String, String> {
+    if ![1, 2, 4].contains(&factor) {
+        return Err(format!("Oversampling factor must be 1, 2 or 4, got {}", factor));
+    }
+    let cmd = AudioCommand::SetOversampling { factor };
+    state.send(cmd)?;
+    Ok(format!("Clipper oversampling set to {}x", factor))
+}
+
+#[tauri::command]
+fn set_clip_bypass(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetClipBypass { on };
+    state.send(cmd)?;
+    Ok(format!("Soft clipper {}", if on { "bypassed" } else { "engaged" }))
+}
+
+#[tauri::command]
+fn set_clip_amount(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Clipper drive", value, 0.0, MAX_CLIP_AMOUNT)?;
+    let cmd = AudioCommand::SetClipAmount { value };
+    state.send(cmd)?;
+    Ok(format!("Soft clipper drive set to {}", value))
+}
+
+#[tauri::command]
+fn set_clip_makeup(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Clipper makeup (dB)", value, 0.0, MAX_CLIP_MAKEUP_DB)?;
+    let cmd = AudioCommand::SetClipMakeup { value };
+    state.send(cmd)?;
+    Ok(format!("Soft clipper makeup set to {} dB", value))
+}
+
+#[tauri::command]
+fn set_output_ceiling(state: State<AppState>, value: f64) -> Result<String, String> {
+    validate::in_range("Output ceiling (dBFS)", value, MIN_OUTPUT_CEILING_DB, 0.0)?;
+    let cmd = AudioCommand::SetOutputCeiling { value };
+    state.send(cmd)?;
+    Ok(format!("Output ceiling set to {} dBFS", value))
+}
+
+#[tauri::command]
+fn set_dc_block(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetDcBlock { on };
+    state.send(cmd)?;
+    Ok(format!("DC block {}", if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_crossfeed(state: State<AppState>, amount: f64) -> Result<String, String> {
+    validate::level("Crossfeed", amount)?;
+    let cmd = AudioCommand::SetCrossfeed { value: amount };
+    state.send(cmd)?;
+    Ok(format!("Crossfeed set to {}", amount))
+}
+
+/// Reorder the master chain, e.g. the clipper ahead of the limiter. Every
+/// stage must appear once; the DC blocker and output ceiling stay last.
+#[tauri::command]
+fn set_master_chain_order(
+    state: State<AppState>,
+    order: Vec<MasterStage>,
+) -> Result<String, String> {
+    let order = validate::master_chain(&order)?;
+    let cmd = AudioCommand::SetMasterChainOrder { order };
+    state.send(cmd)?;
+    Ok(format!("Master chain order set to {:?}", order))
+}
+
+#[tauri::command]
+fn set_autogain(state: State<AppState>, on: bool) -> Result<String, String> {
+    let cmd = AudioCommand::SetAutogain { on };
+    state.send(cmd)?;
+    Ok(format!("Auto-gain {}", if on { "on" } else { "off" }))
+}
+
+#[tauri::command]
+fn set_autogain_target(state: State<AppState>, lufs: f64) -> Result<String, String> {
+    validate::in_range("Auto-gain target (LUFS)", lufs, loudness::LUFS_FLOOR, 0.0)?;
+    let cmd = AudioCommand::SetAutogainTarget { value: lufs };
+    state.send(cmd)?;
+    Ok(format!("Auto-gain target set to {} LUFS", lufs))
+}
+
+// ============================================================
+// SUB-BUS COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn set_track_bus(
+    state: State<AppState>,
+    track: usize,
+    bus: Option<usize>,
+) -> Result<String, String> {
+    state.check_track(track)?;
+    if let Some(bus) = bus {
+        validate::bus(bus)?;
+    }
+    let cmd = AudioCommand::SetTrackBus { track, bus };
+    state.send(cmd)?;
+    Ok(match bus {
+        Some(bus) => format!("Track {} routed to bus {}", track, bus),
+        None => format!("Track {} routed to master", track),
+    })
+}
+
+#[tauri::command]
+fn set_bus_volume(state: State<AppState>, bus: usize, value: f64) -> Result<String, String> {
+    validate::bus(bus)?;
+    validate::level("Bus volume", value)?;
+    let cmd = AudioCommand::SetBusVolume { bus, value };
+    state.send(cmd)?;
+    Ok(format!("Bus {} volume set to {}", bus, value))
+}
+
+#[tauri::command]
+fn set_bus_mute(state: State<AppState>, bus: usize, on: bool) -> Result<String, String> {
+    validate::bus(bus)?;
+    let cmd = AudioCommand::SetBusMute { bus, on };
+    state.send(cmd)?;
+    Ok(format!("Bus {} {}", bus, if on { "muted" } else { "unmuted" }))
+}
+
+#[tauri::command]
+fn set_bus_eq(
+    state: State<AppState>,
+    bus: usize,
+    low: f64,
+    mid: f64,
+    high: f64,
+) -> Result<String, String> {
+    validate::bus(bus)?;
+    for gain in [low, mid, high] {
+        validate::eq_gain(gain)?;
+    }
+    let cmd = AudioCommand::SetBusEq { bus, low, mid, high };
+    state.send(cmd)?;
+    Ok(format!("Bus {} EQ set to {} / {} / {} dB", bus, low, mid, high))
+}
+
+#[tauri::command]
+fn set_bus_compressor(
+    state: State<AppState>,
+    bus: usize,
+    threshold: f64,
+    ratio: f64,
+    attack: f64,
+    release: f64,
+    makeup: f64,
+) -> Result<String, String> {
+    validate::bus(bus)?;
+    validate::comp_threshold(threshold)?;
+    validate::comp_ratio(ratio)?;
+    validate::comp_attack(attack)?;
+    validate::comp_release(release)?;
+    validate::comp_makeup(makeup)?;
+    let cmd = AudioCommand::SetBusCompressor { bus, threshold, ratio, attack, release, makeup };
+    state.send(cmd)?;
+    Ok(format!("Bus {} compressor set to {} dB, {}:1", bus, threshold, ratio))
+}
+
+// ============================================================
+// MIDI INPUT COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn list_midi_inputs() -> Result<Vec<String>, String> {
+    MidiInputs::list()
+}
+
+/// Listen to the MIDI input called `name`: notes from C1 up trigger tracks
+/// 0, 1, ..., clock sets the tempo, start / stop run the transport and
+/// learned CCs set their parameters
+#[tauri::command]
+fn set_midi_input(app: AppHandle, state: State<AppState>, name: String) -> Result<String, String> {
+    state.midi.open(&name, move |action| {
+        let state = app.state::<AppState>();
+        let cmd = match action {
+            MidiAction::Command(cmd) => Some(cmd),
+            MidiAction::Control { cc, value } => state.map_cc(cc, value),
+        };
+        if let Some(cmd) = cmd {
+            let _ = state.send(cmd);
+        }
+    })?;
+    println!("[Tauri] MIDI input: {}", name);
+    Ok(format!("MIDI input set to {}", name))
+}
+
+/// Assign the next CC that arrives to `param`; the mapping is saved with
+/// the session
+#[tauri::command]
+fn start_midi_learn(state: State<AppState>, param: MidiParam) -> Result<String, String> {
+    validate::midi_param(param, state.session.lock().tracks.len())?;
+    *state.midi_learn.lock() = Some(param);
+    Ok(format!("Waiting for a MIDI CC for {:?}", param))
+}
+
+// ============================================================
+// AUTOMATION COMMANDS
+// ============================================================
+
+/// Automate `param` through `points` during playback, replacing any lane
+/// it had; an empty list removes the lane. Steps count from the loop
+/// start and values stay within the parameter's CC range
+/// (`MidiParam::range`).
+#[tauri::command]
+fn set_automation(
+    state: State<AppState>,
+    param: MidiParam,
+    mut points: Vec<AutomationPoint>,
+) -> Result<String, String> {
+    if let Some(track) = param.track() {
+        state.check_track(track)?;
+    }
+    validate::automation_points(param, &points)?;
+    {
+        let session = state.session.lock();
+        let lanes = &session.automation;
+        if lanes.len() >= MAX_AUTOMATION_LANES && !lanes.iter().any(|lane| lane.param == param) {
+            return Err(format!("At most {} automation lanes are supported", MAX_AUTOMATION_LANES));
+        }
+    }
+    automation::sort_points(&mut points);
+    let count = points.len();
+    state.send(AudioCommand::SetAutomation { param, points })?;
+    Ok(format!("Automation for {:?} set with {} points", param, count))
+}
+
+/// Remove every automation lane; parameters keep their current values
+#[tauri::command]
+fn clear_automation(state: State<AppState>) -> Result<String, String> {
+    state.send(AudioCommand::ClearAutomation)?;
+    Ok("Automation cleared".to_string())
+}
+
+// ============================================================
+// MODULATION MATRIX COMMANDS
+// ============================================================
+
+/// `rate` is Hz (`0.5`) or a note division (`"bar"`) of the transport;
+/// `phase` offsets the cycle, 0.0..=1.0
+#[tauri::command]
+fn set_mod_lfo(
+    state: State<AppState>,
+    lfo: usize,
+    rate: LfoRate,
+    shape: LfoShape,
+    phase: f64,
+) -> Result<String, String> {
+    validate::mod_lfo(lfo)?;
+    validate::lfo_rate("LFO rate (Hz)", rate)?;
+    validate::lfo_phase(phase)?;
+    state.send(AudioCommand::SetModLfo { lfo, rate, shape, phase })?;
+    Ok(format!("LFO {} at {:?}, {:?}, phase {}", lfo, rate, shape, phase))
+}
+
+/// Move `dest` with LFO `lfo_id`. At depth 1.0 (or -1.0, inverted) the LFO
+/// swings the parameter half its CC range (`MidiParam::range`) either side
+/// of its value; routes to the same parameter add up.
+#[tauri::command]
+fn add_mod_route(
+    state: State<AppState>,
+    lfo_id: usize,
+    dest: MidiParam,
+    depth: f64,
+) -> Result<String, String> {
+    validate::mod_lfo(lfo_id)?;
+    if let Some(track) = dest.track() {
+        state.check_track(track)?;
+    }
+    validate::mod_depth(depth)?;
+    {
+        let session = state.session.lock();
+        let routes = &session.modulation.routes;
+        let exists = routes.iter().any(|r| r.lfo == lfo_id && r.dest == dest);
+        if routes.len() >= MAX_MOD_ROUTES && !exists {
+            return Err(format!("At most {} mod routes are supported", MAX_MOD_ROUTES));
+        }
+    }
+    state.send(AudioCommand::AddModRoute { lfo: lfo_id, dest, depth })?;
+    Ok(format!("LFO {} routed to {:?} at depth {}", lfo_id, dest, depth))
+}
+
+#[tauri::command]
+fn remove_mod_route(
+    state: State<AppState>,
+    lfo_id: usize,
+    dest: MidiParam,
+) -> Result<String, String> {
+    validate::mod_lfo(lfo_id)?;
+    state.send(AudioCommand::RemoveModRoute { lfo: lfo_id, dest })?;
+    Ok(format!("LFO {} no longer moves {:?}", lfo_id, dest))
+}
+
+#[tauri::command]
+fn clear_mod_routes(state: State<AppState>) -> Result<String, String> {
+    state.send(AudioCommand::ClearModRoutes)?;
+    Ok("Mod routes cleared".to_string())
+}
+
+// ============================================================
+// OUTPUT DEVICE COMMANDS
+// ============================================================
+
+/// How long to wait for the audio thread to rebuild the stream
+const DEVICE_SWITCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tauri::command]
+fn list_output_devices() -> Result<Vec<String>, String> {
+    let host = cpal::default_host();
+    let devices = host.output_devices().map_err(|e| e.to_string())?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+#[tauri::command]
+fn set_output_device(state: State<AppState>, name: String) -> Result<String, String> {
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::SwitchDevice { name, reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to device switch".to_string())?
+}
+
+/// Sample rate, channel and buffer size ranges of the output device in use
+#[tauri::command]
+fn get_device_capabilities(state: State<AppState>) -> Result<Vec<OutputConfig>, String> {
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::Capabilities { reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to capabilities query".to_string())?
+}
+
+/// Frames per callback; 0 goes back to the device default. Returns an error
+/// (and keeps the current stream) if the device rejects the size.
+#[tauri::command]
+fn set_buffer_size(state: State<AppState>, frames: u32) -> Result<String, String> {
+    let frames = (frames > 0).then_some(frames);
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::SetBufferSize { frames, reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to buffer size change".to_string())?
+}
+
+/// Output buffer latency: frames per callback / sample rate, in ms
+#[tauri::command]
+fn get_latency_ms(state: State<AppState>) -> Result<f64, String> {
+    let frames = state.shared.buffer_frames.load(Ordering::Relaxed);
+    let sample_rate = state.shared.sample_rate.load(Ordering::Relaxed);
+    Ok(frames as f64 * 1000.0 / sample_rate.max(1) as f64)
+}
+
+// ============================================================
+// INPUT / RECORDING COMMANDS
+// ============================================================
+
+#[tauri::command]
+fn list_input_devices() -> Result<Vec<String>, String> {
+    let host = cpal::default_host();
+    let devices = host.input_devices().map_err(|e| e.to_string())?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+/// Takes effect from the next `start_record`
+#[tauri::command]
+fn set_input_device(state: State<AppState>, name: String) -> Result<String, String> {
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::SetInputDevice { name, reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to input device change".to_string())?
+}
+
+/// Choose the track `stop_record` loads the take into
+#[tauri::command]
+fn arm_track_record(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    *state.record_track.lock() = Some(track);
+    Ok(format!("Track {} armed for recording", track))
+}
+
+#[tauri::command]
+fn start_record(state: State<AppState>) -> Result<String, String> {
+    if state.record_track.lock().is_none() {
+        return Err("No track is armed for recording".to_string());
+    }
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::StartRecord { reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to record request".to_string())?
+}
+
+/// End the take and load it into the armed track as its sample
+#[tauri::command]
+fn stop_record(state: State<AppState>) -> Result<String, String> {
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::StopRecord { reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    let (sample, dropped) = reply_rx
+        .recv_timeout(DEVICE_SWITCH_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to stop request".to_string())??;
+
+    let track = state.record_track.lock().ok_or("No track is armed for recording")?;
+    state.check_track(track)?;
+    if sample.data.is_empty() {
+        return Err("Nothing was recorded".to_string());
+    }
+    let seconds = sample.data.len() as f64 / sample.sample_rate as f64;
+    state.load_sample(track, move || Ok(sample))?;
+    let lost = if dropped > 0 { format!(", {} frames dropped", dropped) } else { String::new() };
+    Ok(format!("Recorded {:.1} s into track {}{}", seconds, track, lost))
+}
+
+// ============================================================
+// EXPORT COMMANDS
+// ============================================================
+
+/// How long to wait for the audio thread to hand over a session copy
+const EXPORT_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest render `freeze_track` accepts. A frozen track keeps its render
+/// in memory at the device rate for as long as it stays frozen.
+const MAX_FREEZE_SECONDS: u32 = 300;
+
+/// Offline copy of the running session at `sample_rate`, already playing
+/// from the top
+fn offline_snapshot(state: &AppState, sample_rate: u32) -> Result<Renderer, String> {
+    let (reply_tx, reply_rx) = bounded(1);
+    state
+        .control_tx
+        .send(EngineControl::Snapshot { sample_rate, reply: reply_tx })
+        .map_err(|e| e.to_string())?;
+    let mut renderer = reply_rx
+        .recv_timeout(EXPORT_SNAPSHOT_TIMEOUT)
+        .map_err(|_| "Audio thread did not respond to snapshot request".to_string())??;
+    renderer.apply(AudioCommand::Play);
+    Ok(renderer)
+}
+
+/// Bounce `bars` bars of the current session to a 48 kHz stereo WAV,
+/// 16-bit unless `format` says otherwise. `dither` (off by default) applies
+/// to the PCM formats. Progress is reported through `export_progress` events.
+#[tauri::command(async)]
+fn export_wav(
+    state: State<AppState>,
+    path: String,
+    bars: u32,
+    format: Option<ExportFormat>,
+    dither: Option<Dither>,
+) -> Result<String, String> {
+    if bars == 0 {
+        return Err("Bar count must be at least 1".to_string());
+    }
+    // The file is written as it renders, so only the WAV format bounds it
+    let bpm = state.shared.bpm.load(Ordering::Relaxed);
+    let format = format.unwrap_or_default();
+    let time_signature = state.session.lock().time_signature;
+    let frames = export::bar_frames(bars, time_signature, bpm, export::EXPORT_SAMPLE_RATE);
+    export::wav_data_len(frames * export::EXPORT_CHANNELS, format)?;
+
+    let mut renderer = offline_snapshot(&state, export::EXPORT_SAMPLE_RATE)?;
+    let path = PathBuf::from(path);
+    let event_tx = state.event_tx.clone();
+    let dither = dither.unwrap_or_default();
+    let frames = export::export_wav(&mut renderer, &path, bars, bpm, format, dither, |progress| {
+        let _ = event_tx.try_send(EngineEvent::ExportProgress(progress));
+    })?;
+
+    Ok(format!("Exported {} bars ({} frames) to {}", bars, frames, path.display()))
+}
+
+/// Render `bars` bars of a track through its strip and play that back in
+/// place of live synthesis, following the playhead. Volume, pan, mute and
+/// sends stay live; anything else changed on the track is heard again only
+/// after `unfreeze_track` (or another freeze).
+#[tauri::command(async)]
+fn freeze_track(state: State<AppState>, track: usize, bars: u32) -> Result<String, String> {
+    state.check_track(track)?;
+    if bars == 0 {
+        return Err("Bar count must be at least 1".to_string());
+    }
+    let sample_rate = state.shared.sample_rate.load(Ordering::Relaxed);
+    let bpm = state.shared.bpm.load(Ordering::Relaxed);
+    let time_signature = state.session.lock().time_signature;
+    let seconds = export::bar_frames(bars, time_signature, bpm, sample_rate) / sample_rate as usize;
+    if seconds > MAX_FREEZE_SECONDS as usize {
+        let max = MAX_FREEZE_SECONDS;
+        return Err(format!("{} bars last {} s; at most {} s can be frozen", bars, seconds, max));
+    }
+
+    let mut renderer = offline_snapshot(&state, sample_rate)?;
+    let sample = export::render_track(&mut renderer, track, bars, bpm);
+    let frames = sample.data.len();
+    let cmd = AudioCommand::FreezeTrack { track, sample: Arc::new(sample) };
+    state.send(cmd)?;
+    Ok(format!("Track {} frozen ({} bars, {} frames)", track, bars, frames))
+}
+
+#[tauri::command]
+fn unfreeze_track(state: State<AppState>, track: usize) -> Result<String, String> {
+    state.check_track(track)?;
+    let cmd = AudioCommand::UnfreezeTrack { track };
+    state.send(cmd)?;
+    Ok(format!("Track {} unfrozen", track))
+}
+
+// ============================================================
+// SESSION COMMANDS
+// ============================================================
+
+/// Write the current mix, patterns and master chain to a JSON file.
+/// Loaded samples are not included.
+#[tauri::command]
+fn save_session(state: State<AppState>, path: String) -> Result<String, String> {
+    let path = PathBuf::from(path);
+    state.session.lock().save(&path)?;
+    Ok(format!("Session saved to {}", path.display()))
+}
+
+/// Read a session file and push every setting in it to the audio thread
+#[tauri::command]
+fn load_session(state: State<AppState>, path: String) -> Result<String, String> {
+    let path = PathBuf::from(path);
+    let mut session = SessionState::load(&path)?;
+    if session.version != session::SESSION_VERSION {
+        eprintln!(
+            "[Session] {} has version {}, expected {}; loading what matches",
+            path.display(),
+            session.version,
+            session::SESSION_VERSION
+        );
+        session.version = session::SESSION_VERSION;
+    }
+    session.normalize();
+    session.validate().map_err(|e| format!("Invalid session file: {}", e))?;
+
+    for cmd in session.commands() {
+        state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    }
+    state.shared.bpm.store(session.bpm, Ordering::Relaxed);
+    *state.session.lock() = session;
+    state.history.lock().clear();
+    Ok(format!("Session loaded from {}", path.display()))
+}
+
+#[tauri::command]
+fn undo(state: State<AppState>) -> Result<String, String> {
+    // Same lock order as `AppState::send`
+    let mut session = state.session.lock();
+    let cmd = state.history.lock().undo(&mut session);
+    drop(session);
+    let cmd = cmd.ok_or("Nothing to undo")?;
     state.command_tx.send(cmd).map_err(|e| e.to_string())?;
-    Ok(format!("Limiter threshold set to {}", value))
+    Ok("Undone".to_string())
+}
+
+#[tauri::command]
+fn redo(state: State<AppState>) -> Result<String, String> {
+    // Same lock order as `AppState::send`
+    let mut session = state.session.lock();
+    let cmd = state.history.lock().redo(&mut session);
+    drop(session);
+    let cmd = cmd.ok_or("Nothing to redo")?;
+    state.command_tx.send(cmd).map_err(|e| e.to_string())?;
+    Ok("Redone".to_string())
+}
+
+/// Rate of the running output device (what step timing is based on)
+#[tauri::command]
+fn get_sample_rate(state: State<AppState>) -> Result<u32, String> {
+    Ok(state.shared.sample_rate.load(Ordering::Relaxed))
+}
+
+#[tauri::command]
+fn get_meters(state: State<AppState>) -> Result<MeterState, String> {
+    Ok(state.shared.meters.snapshot())
+}
+
+/// Reset the latched `clipped` flags on every track and the master
+#[tauri::command]
+fn clear_clip_indicators(state: State<AppState>) -> Result<String, String> {
+    state.shared.meters.clear_clips();
+    Ok("Clip indicators cleared".to_string())
+}
+
+/// Master spectrum in dBFS, one value per bin of `spectrum::FFT_SIZE`
+/// points (bin `k` is at `k * sample_rate / FFT_SIZE` Hz)
+#[tauri::command]
+fn get_spectrum(state: State<AppState>) -> Result<Vec<f32>, String> {
+    Ok(state.spectrum.lock().clone())
+}
+
+/// Recent master frames as goniometer points (mid up, side across), oldest
+/// first; `goniometer::GONIOMETER_POINTS` of them
+#[tauri::command]
+fn get_goniometer(state: State<AppState>) -> Result<Vec<GoniometerPoint>, String> {
+    Ok(state.goniometer.lock().clone())
+}
+
+/// `points` samples of a master processor's input -> output curve with its
+/// current settings, for drawing; see `TransferEffect` for the axes
+#[tauri::command]
+fn get_transfer_curve(
+    state: State<AppState>,
+    effect: TransferEffect,
+    points: usize,
+) -> Result<Vec<TransferPoint>, String> {
+    if !(2..=MAX_TRANSFER_POINTS).contains(&points) {
+        let max = MAX_TRANSFER_POINTS;
+        return Err(format!("Points must be between 2 and {}, got {}", max, points));
+    }
+    Ok(state.session.lock().master.transfer_curve(effect, points))
+}
+
+/// The master EQ's magnitude response in dB at each of `freqs` (Hz), with
+/// the current band gains
+#[tauri::command]
+fn get_eq_response(state: State<AppState>, freqs: Vec<f64>) -> Result<Vec<f64>, String> {
+    if freqs.len() > MAX_TRANSFER_POINTS {
+        let max = MAX_TRANSFER_POINTS;
+        return Err(format!("At most {} frequencies, got {}", max, freqs.len()));
+    }
+    let sample_rate = state.shared.sample_rate.load(Ordering::Relaxed) as f64;
+    for &hz in &freqs {
+        validate::in_range("Frequency (Hz)", hz, 1.0, sample_rate / 2.0)?;
+    }
+    Ok(state.session.lock().master.eq_response(&freqs, sample_rate))
+}
+
+/// Master loudness in LUFS; integrated restarts with each play / stop
+#[tauri::command]
+fn get_loudness(state: State<AppState>) -> Result<Loudness, String> {
+    Ok(state.shared.meters.loudness())
+}
+
+#[tauri::command]
+fn get_engine_info(state: State<AppState>) -> Result<EngineInfo, String> {
+    Ok(EngineInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        protocol_version: PROTOCOL_VERSION,
+        commands: COMMANDS,
+        num_tracks: state.session.lock().tracks.len(),
+        max_tracks: MAX_TRACKS,
+        sample_rate: state.shared.sample_rate.load(Ordering::Relaxed),
+        effects: EFFECTS,
+        features: CARGO_FEATURES.iter().filter_map(|&(name, on)| on.then_some(name)).collect(),
+    })
 }
 
 #[tauri::command]
 fn get_audio_state(state: State<AppState>) -> Result<AudioState, String> {
+    let current_step = state.shared.current_step.load(Ordering::Relaxed) as usize;
+    let (position, master_volume) = {
+        let session = state.session.lock();
+        (session.time_signature.position(current_step), session.master_volume)
+    };
     Ok(AudioState {
-        is_playing: state.audio_running.load(Ordering::Relaxed),
-        current_step: (state.current_step.load(Ordering::Relaxed) % 32) as usize,
-        bpm: state.bpm.load(Ordering::Relaxed),
-        cpu_usage: 0.0,
+        is_playing: state.shared.is_running.load(Ordering::Relaxed),
+        current_step,
+        bar: position.bar,
+        beat: position.beat,
+        tick: position.tick,
+        bpm: state.shared.bpm.load(Ordering::Relaxed),
+        cpu_usage: load_f64(&state.shared.cpu_usage),
+        master_gain_db: gain_to_db(master_volume),
+        master_peak_dbfs: state.shared.meters.master_peak_dbfs(),
     })
 }
 
+// ============================================================
+// COMMAND REGISTRY
+// ============================================================
+
+/// Calls `$then!` with every Tauri command, so the invoke handler and
+/// `COMMANDS` can't list different sets
+macro_rules! with_commands {
+    ($then:ident) => {
+        $then![
+            start_audio,
+            start_audio_with_countin,
+            stop_audio,
+            transport_return_to_zero,
+            transport_set_step,
+            reset_mixer,
+            panic,
+            set_volume,
+            set_master_gain_db,
+            set_track_volume,
+            set_track_pan,
+            set_track_pan_mode,
+            toggle_mute,
+            toggle_track_polarity,
+            toggle_solo,
+            set_track_solo_safe,
+            set_solo_mode,
+            add_track,
+            remove_track,
+            set_track_trim,
+            set_track_eq_low,
+            set_track_eq_mid,
+            set_track_eq_high,
+            set_track_bitcrush,
+            set_track_hpf,
+            set_track_allpass,
+            set_track_gate,
+            set_track_gate_lookahead,
+            set_track_comp_threshold,
+            set_track_comp_ratio,
+            set_track_comp_attack,
+            set_track_comp_release,
+            set_track_sidechain,
+            set_track_autopan,
+            set_track_tremolo,
+            set_track_frequency,
+            set_track_note,
+            set_track_adsr,
+            set_track_send_delay,
+            set_track_send_reverb,
+            load_sample,
+            get_load_progress,
+            trigger_sample,
+            set_track_sample_speed,
+            set_track_sample_loop,
+            set_track_loop_crossfade,
+            set_track_reverse,
+            set_track_unison,
+            set_track_sync,
+            set_waveform,
+            set_step,
+            clear_pattern,
+            set_loop_length,
+            set_loop_enabled,
+            set_time_signature,
+            set_swing,
+            set_bpm,
+            set_metronome,
+            set_monitor_dim,
+            set_monitor_mono,
+            set_track_pfl,
+            set_track_afl,
+            set_eq_low,
+            set_eq_mid,
+            set_eq_high,
+            set_eq_ms_mode,
+            set_side_eq_low,
+            set_side_eq_mid,
+            set_side_eq_high,
+            set_comp_threshold,
+            set_comp_ratio,
+            set_comp_attack,
+            set_comp_release,
+            set_comp_makeup,
+            set_comp_knee,
+            set_delay_time_division,
+            set_delay_feedback,
+            set_delay_mix,
+            set_delay_pingpong,
+            set_delay_tone,
+            set_reverb_size,
+            set_reverb_damping,
+            set_reverb_mix,
+            set_chorus_rate,
+            set_chorus_depth,
+            set_chorus_mix,
+            set_limiter,
+            set_limiter_true_peak,
+            set_limiter_release_mode,
+            set_stereo_width,
+            set_mono,
+            set_pan_law,
+            set_clip_mode,
+            set_oversampling,
+            set_clip_bypass,
+            set_clip_amount,
+            set_clip_makeup,
+            set_output_ceiling,
+            set_dc_block,
+            set_crossfeed,
+            set_master_chain_order,
+            set_autogain,
+            set_autogain_target,
+            set_track_bus,
+            set_bus_volume,
+            set_bus_mute,
+            set_bus_eq,
+            set_bus_compressor,
+            save_session,
+            load_session,
+            undo,
+            redo,
+            get_audio_state,
+            get_meters,
+            clear_clip_indicators,
+            get_loudness,
+            get_transfer_curve,
+            get_eq_response,
+            get_spectrum,
+            get_goniometer,
+            get_sample_rate,
+            list_midi_inputs,
+            set_midi_input,
+            start_midi_learn,
+            set_automation,
+            clear_automation,
+            set_mod_lfo,
+            add_mod_route,
+            remove_mod_route,
+            clear_mod_routes,
+            list_output_devices,
+            set_output_device,
+            get_device_capabilities,
+            set_buffer_size,
+            get_latency_ms,
+            list_input_devices,
+            set_input_device,
+            arm_track_record,
+            start_record,
+            stop_record,
+            export_wav,
+            freeze_track,
+            unfreeze_track,
+            get_engine_info,
+        ]
+    };
+}
+
+macro_rules! command_handler {
+    ($($name:ident),* $(,)?) => {
+        tauri::generate_handler![$($name),*]
+    };
+}
+
+macro_rules! command_names {
+    ($($name:ident),* $(,)?) => {
+        &[$(stringify!($name)),*]
+    };
+}
+
+/// Every command the webview can invoke, by name
+pub const COMMANDS: &[&str] = with_commands!(command_names);
+
 // ============================================================
 // MAIN
 // ============================================================
@@ -546,26 +3107,19 @@ fn get_audio_state(state: State<AppState>) -> Result<AudioState, String> {
 fn main() {
     // Lock-free channels for UI <-> Audio thread communication
     let (command_tx, command_rx): (Sender<AudioCommand>, Receiver<AudioCommand>) = bounded(1024);
-    let (state_tx, _state_rx): (Sender<AudioState>, Receiver<AudioState>) = bounded(64);
+    let (state_tx, state_rx): (Sender<EngineEvent>, Receiver<EngineEvent>) = bounded(64);
+    let (control_tx, control_rx): (Sender<EngineControl>, Receiver<EngineControl>) = bounded(8);
 
     // Shared atomic state
-    let audio_running = Arc::new(AtomicBool::new(false));
-    let current_step = Arc::new(AtomicU64::new(0));
-    let bpm = Arc::new(AtomicU64::new(128));
+    let shared = SharedState::new(session::DEFAULT_BPM, DEFAULT_NUM_TRACKS);
+    let shutdown = Arc::new(AtomicBool::new(false));
 
     // Spawn real-time audio thread
-    let audio_running_clone = audio_running.clone();
-    let current_step_clone = current_step.clone();
-    let bpm_clone = bpm.clone();
+    let shared_clone = shared.clone();
+    let state_tx_clone = state_tx.clone();
 
-    thread::spawn(move || {
-        let engine = AudioEngine::new(
-            command_rx,
-            state_tx,
-            audio_running_clone,
-            current_step_clone,
-            bpm_clone,
-        );
+    let audio_thread = thread::spawn(move || {
+        let engine = AudioEngine::new(command_rx, control_rx, state_tx_clone, shared_clone);
         engine.run();
     });
 
@@ -573,28 +3127,104 @@ fn main() {
     println!("[Main] Tauri starting...");
 
     // Build Tauri app
-    tauri::Builder::default()
+    let app = tauri::Builder::default()
         .manage(AppState {
             command_tx,
-            audio_running,
-            current_step,
-            bpm,
+            control_tx,
+            event_tx: state_tx,
+            shared: shared.clone(),
+            shutdown: shutdown.clone(),
+            state_forwarder: Mutex::new(None),
+            spectrum_analyzer: Mutex::new(None),
+            spectrum: Arc::new(Mutex::new(vec![SPECTRUM_FLOOR_DB; SPECTRUM_BINS])),
+            goniometer_thread: Mutex::new(None),
+            goniometer: Arc::new(Mutex::new(vec![GoniometerPoint::default(); GONIOMETER_POINTS])),
+            audio_thread: Mutex::new(Some(audio_thread)),
+            session: Mutex::new(SessionState::default()),
+            history: Mutex::new(UndoHistory::default()),
+            midi: MidiInputs::default(),
+            midi_learn: Mutex::new(None),
+            record_track: Mutex::new(None),
+            sample_loads: Mutex::new(vec![None; MAX_TRACKS]),
         })
-        .invoke_handler(tauri::generate_handler![
-            start_audio,
-            stop_audio,
-            set_volume,
-            set_track_volume,
-            set_track_pan,
-            toggle_mute,
-            toggle_solo,
-            set_bpm,
-            set_eq_low,
-            set_eq_mid,
-            set_eq_high,
-            set_limiter,
-            get_audio_state,
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(move |app| {
+            let state = app.state::<AppState>();
+            let analyzer = spawn_spectrum_analyzer(
+                app.handle().clone(),
+                shared.spectrum,
+                state.spectrum.clone(),
+                shutdown.clone(),
+            );
+            *state.spectrum_analyzer.lock() = Some(analyzer);
+            let goniometer = spawn_goniometer(
+                app.handle().clone(),
+                shared.goniometer,
+                state.goniometer.clone(),
+                shutdown.clone(),
+            );
+            *state.goniometer_thread.lock() = Some(goniometer);
+            let forwarder =
+                spawn_state_forwarder(app.handle().clone(), state_rx, shared.meters, shutdown);
+            *state.state_forwarder.lock() = Some(forwarder);
+            Ok(())
+        })
+        .invoke_handler(with_commands!(command_handler))
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    app.run(|app_handle, event| {
+        if let RunEvent::Exit = event {
+            let state = app_handle.state::<AppState>();
+
+            // Stop output first so closing the window is silent immediately
+            state.shared.is_running.store(false, Ordering::Relaxed);
+            let _ = state.control_tx.send(EngineControl::Shutdown);
+            if let Some(audio_thread) = state.audio_thread.lock().take() {
+                let _ = audio_thread.join();
+            }
+
+            state.shutdown.store(true, Ordering::Relaxed);
+            if let Some(forwarder) = state.state_forwarder.lock().take() {
+                let _ = forwarder.join();
+            }
+            if let Some(analyzer) = state.spectrum_analyzer.lock().take() {
+                let _ = analyzer.join();
+            }
+            if let Some(goniometer) = state.goniometer_thread.lock().take() {
+                let _ = goniometer.join();
+            }
+            println!("[Main] Shutdown complete");
+        }
+    });
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_registry_lists_every_tauri_command() {
+        let source = include_str!("main.rs");
+        let mut lines = source.lines();
+        let mut defined = Vec::new();
+        while let Some(line) = lines.next() {
+            if line.starts_with("#[tauri::command") {
+                let signature = lines.next().unwrap();
+                let name = signature.trim_start_matches("async ").trim_start_matches("fn ");
+                defined.push(name.split(['(', '<']).next().unwrap());
+            }
+        }
+
+        let mut registered = COMMANDS.to_vec();
+        registered.sort_unstable();
+        let count = registered.len();
+        registered.dedup();
+        assert_eq!(registered.len(), count, "a command is registered twice");
+        defined.sort_unstable();
+        assert_eq!(registered, defined);
+    }
 }
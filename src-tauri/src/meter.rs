@@ -0,0 +1,348 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - METERING
+// Peak / RMS level meters and their lock-free hand-off to the UI
+// ============================================================
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+use crate::loudness::Loudness;
+use crate::{load_f64, MAX_TRACKS};
+
+// Ballistics
+const PEAK_HOLD_SECONDS: f64 = 0.5;
+const PEAK_DECAY_DB_PER_SECOND: f64 = 20.0;
+const RMS_WINDOW_SECONDS: f64 = 0.3;
+
+/// Floor for levels in dBFS; silence reads this instead of -inf, which
+/// JSON can't carry
+pub const MIN_DBFS: f64 = -120.0;
+
+/// dB to a linear gain factor (-6 dB ≈ 0.5, 0 dB = 1.0)
+pub fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// A linear level in dBFS, floored at `MIN_DBFS`
+pub fn gain_to_db(gain: f64) -> f64 {
+    (20.0 * gain.log10()).max(MIN_DBFS)
+}
+
+/// Per-sample peak (with hold + decay) and RMS (~300 ms exponential window)
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    peak: f64,
+    hold_remaining: usize,
+    mean_square: f64,
+    hold_samples: usize,
+    peak_decay: f64,
+    rms_coeff: f64,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            peak: 0.0,
+            hold_remaining: 0,
+            mean_square: 0.0,
+            hold_samples: (PEAK_HOLD_SECONDS * sample_rate) as usize,
+            peak_decay: 10f64.powf(-PEAK_DECAY_DB_PER_SECOND / 20.0 / sample_rate),
+            rms_coeff: 1.0 - (-1.0 / (RMS_WINDOW_SECONDS * sample_rate)).exp(),
+        }
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f64) {
+        let level = input.abs();
+        if level >= self.peak {
+            self.peak = level;
+            self.hold_remaining = self.hold_samples;
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            self.peak *= self.peak_decay;
+        }
+
+        self.mean_square += self.rms_coeff * (input * input - self.mean_square);
+    }
+
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    pub fn rms(&self) -> f64 {
+        self.mean_square.sqrt()
+    }
+}
+
+/// Stereo phase correlation over the same ~300 ms window as the RMS meter:
+/// the running mean of L*R normalised by both channels' RMS. +1.0 is mono /
+/// in phase, 0.0 decorrelated, -1.0 out of phase.
+#[derive(Clone, Debug)]
+pub struct CorrelationMeter {
+    product: f64,
+    left_square: f64,
+    right_square: f64,
+    coeff: f64,
+}
+
+impl CorrelationMeter {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            product: 0.0,
+            left_square: 0.0,
+            right_square: 0.0,
+            coeff: 1.0 - (-1.0 / (RMS_WINDOW_SECONDS * sample_rate)).exp(),
+        }
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) {
+        self.product += self.coeff * (left * right - self.product);
+        self.left_square += self.coeff * (left * left - self.left_square);
+        self.right_square += self.coeff * (right * right - self.right_square);
+    }
+
+    /// -1.0..=1.0; silence (or one silent side) reads as 0.0
+    pub fn correlation(&self) -> f64 {
+        let power = (self.left_square * self.right_square).sqrt();
+        if power < 1e-12 {
+            return 0.0;
+        }
+        (self.product / power).clamp(-1.0, 1.0)
+    }
+}
+
+// ============================================================
+// METER BANK (audio callback -> UI)
+// ============================================================
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Level {
+    pub peak: f64,
+    pub rms: f64,
+    /// `peak` and `rms` in dBFS, for scales that match the EQ and gain
+    /// controls
+    pub peak_dbfs: f64,
+    pub rms_dbfs: f64,
+    /// Went over since the last `clear_clip_indicators`: a track's strip
+    /// output above 0 dBFS pre-fader, or the master above its ceiling
+    pub clipped: bool,
+}
+
+/// Snapshot of all meters, returned by `get_meters` and the `meters` event
+#[derive(Debug, Clone, Serialize)]
+pub struct MeterState {
+    pub tracks: Vec<Level>,
+    pub master_left: Level,
+    pub master_right: Level,
+    /// Peak limiter gain reduction over the last buffer (0.0 = fully open)
+    pub limiter_gain_reduction_db: f64,
+    /// Master L/R phase correlation, -1.0 (out of phase) to +1.0 (mono)
+    pub correlation: f64,
+}
+
+#[derive(Default)]
+struct AtomicLevel {
+    peak: AtomicU64, // f64 bits
+    rms: AtomicU64,  // f64 bits
+    // Latched by the callback, cleared only by `clear_clips`
+    clipped: AtomicBool,
+}
+
+impl AtomicLevel {
+    fn store(&self, meter: &LevelMeter, over: bool) {
+        self.peak.store(meter.peak().to_bits(), Ordering::Relaxed);
+        self.rms.store(meter.rms().to_bits(), Ordering::Relaxed);
+        if over {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn load(&self) -> Level {
+        let peak = load_f64(&self.peak);
+        let rms = load_f64(&self.rms);
+        Level {
+            peak,
+            rms,
+            peak_dbfs: gain_to_db(peak),
+            rms_dbfs: gain_to_db(rms),
+            clipped: self.clipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Latest meter readings, published by the callback once per buffer so the
+/// UI can read them without the audio thread allocating or blocking
+pub struct MeterBank {
+    // Sized for `MAX_TRACKS`; only the first `track_count` are reported
+    tracks: Vec<AtomicLevel>,
+    track_count: AtomicUsize,
+    master: [AtomicLevel; 2],
+    limiter_gain_reduction_db: AtomicU64, // f64 bits
+    correlation: AtomicU64,               // f64 bits
+    // Momentary, short-term, integrated LUFS (f64 bits)
+    loudness: [AtomicU64; 3],
+}
+
+impl MeterBank {
+    pub fn new(num_tracks: usize) -> Self {
+        Self {
+            tracks: (0..MAX_TRACKS).map(|_| AtomicLevel::default()).collect(),
+            track_count: AtomicUsize::new(num_tracks.min(MAX_TRACKS)),
+            master: Default::default(),
+            limiter_gain_reduction_db: AtomicU64::new(0.0_f64.to_bits()),
+            correlation: AtomicU64::new(0.0_f64.to_bits()),
+            loudness: Loudness::default().to_bits().map(AtomicU64::new),
+        }
+    }
+
+    /// `over` latches the track's clip indicator
+    pub fn publish_track(&self, track: usize, meter: &LevelMeter, over: bool) {
+        if let Some(level) = self.tracks.get(track) {
+            level.store(meter, over);
+        }
+    }
+
+    pub fn set_track_count(&self, count: usize) {
+        self.track_count.store(count.min(MAX_TRACKS), Ordering::Relaxed);
+    }
+
+    /// `overs` latch the master L / R clip indicators
+    pub fn publish_master(&self, meters: &[LevelMeter; 2], overs: [bool; 2]) {
+        self.master[0].store(&meters[0], overs[0]);
+        self.master[1].store(&meters[1], overs[1]);
+    }
+
+    /// Reset every clip indicator, tracks and master
+    pub fn clear_clips(&self) {
+        for level in self.tracks.iter().chain(&self.master) {
+            level.clipped.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn publish_gain_reduction(&self, db: f64) {
+        self.limiter_gain_reduction_db.store(db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn publish_correlation(&self, correlation: f64) {
+        self.correlation.store(correlation.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn publish_loudness(&self, loudness: Loudness) {
+        for (slot, bits) in self.loudness.iter().zip(loudness.to_bits()) {
+            slot.store(bits, Ordering::Relaxed);
+        }
+    }
+
+    /// The louder master channel's peak, in dBFS
+    pub fn master_peak_dbfs(&self) -> f64 {
+        let [left, right] = &self.master;
+        gain_to_db(load_f64(&left.peak).max(load_f64(&right.peak)))
+    }
+
+    pub fn loudness(&self) -> Loudness {
+        Loudness {
+            momentary: load_f64(&self.loudness[0]),
+            short_term: load_f64(&self.loudness[1]),
+            integrated: load_f64(&self.loudness[2]),
+        }
+    }
+
+    pub fn snapshot(&self) -> MeterState {
+        MeterState {
+            tracks: self.tracks[..self.track_count.load(Ordering::Relaxed)]
+                .iter()
+                .map(AtomicLevel::load)
+                .collect(),
+            master_left: self.master[0].load(),
+            master_right: self.master[1].load(),
+            limiter_gain_reduction_db: load_f64(&self.limiter_gain_reduction_db),
+            correlation: load_f64(&self.correlation),
+        }
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_db_and_linear_gain_convert_both_ways() {
+        assert!((db_to_gain(-6.0) - 0.501).abs() < 1e-3);
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert!((gain_to_db(0.5) + 6.02).abs() < 1e-2);
+        assert!((gain_to_db(db_to_gain(-18.0)) + 18.0).abs() < 1e-9);
+        assert_eq!(gain_to_db(0.0), MIN_DBFS);
+    }
+
+    #[test]
+    fn test_sine_rms_and_peak() {
+        let sample_rate = 48000.0;
+        let mut meter = LevelMeter::new(sample_rate);
+        for i in 0..48000 {
+            let t = i as f64 / sample_rate;
+            meter.process(0.5 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin());
+        }
+        assert!((meter.peak() - 0.5).abs() < 1e-3);
+        assert!((meter.rms() - 0.5 / 2f64.sqrt()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_peak_holds_then_decays() {
+        let sample_rate = 48000.0;
+        let mut meter = LevelMeter::new(sample_rate);
+        meter.process(1.0);
+
+        // Held for the hold time...
+        for _ in 0..(PEAK_HOLD_SECONDS * sample_rate) as usize {
+            meter.process(0.0);
+        }
+        assert_eq!(meter.peak(), 1.0);
+
+        // ...then falls by the decay rate
+        for _ in 0..sample_rate as usize {
+            meter.process(0.0);
+        }
+        let db = 20.0 * meter.peak().log10();
+        assert!((db + PEAK_DECAY_DB_PER_SECOND).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_correlation_of_mono_inverted_and_uncorrelated_signals() {
+        let sample_rate = 48000.0;
+        let tone = |i: usize| (2.0 * std::f64::consts::PI * 440.0 * i as f64 / sample_rate).sin();
+
+        let mut mono = CorrelationMeter::new(sample_rate);
+        let mut inverted = CorrelationMeter::new(sample_rate);
+        for i in 0..48000 {
+            mono.process(tone(i), tone(i));
+            inverted.process(tone(i), -tone(i));
+        }
+        assert!((mono.correlation() - 1.0).abs() < 1e-9);
+        assert!((inverted.correlation() + 1.0).abs() < 1e-9);
+
+        // Two independent xorshift noise sources
+        let mut seeds = [0x9E37_79B9u32, 0x85EB_CA6B];
+        let mut noise = |channel: usize| {
+            let s = &mut seeds[channel];
+            *s ^= *s << 13;
+            *s ^= *s >> 17;
+            *s ^= *s << 5;
+            *s as f64 / u32::MAX as f64 * 2.0 - 1.0
+        };
+        let mut uncorrelated = CorrelationMeter::new(sample_rate);
+        for _ in 0..48000 {
+            let left = noise(0);
+            uncorrelated.process(left, noise(1));
+        }
+        assert!(uncorrelated.correlation().abs() < 0.1);
+
+        assert_eq!(CorrelationMeter::new(sample_rate).correlation(), 0.0);
+    }
+}
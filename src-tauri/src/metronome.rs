@@ -0,0 +1,47 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - METRONOME
+// Beat click summed into the master, outside the track mix
+// ============================================================
+
+use std::f64::consts::PI;
+
+const CLICK_FREQ: f64 = 1000.0;
+const ACCENT_FREQ: f64 = 1500.0;
+const CLICK_LEVEL: f64 = 0.5;
+const CLICK_DECAY_SECONDS: f64 = 0.015;
+
+#[derive(Clone, Debug, Default)]
+pub struct Metronome {
+    pub enabled: bool,
+    phase: f64,
+    frequency: f64,
+    envelope: f64,
+    decay: f64, // per-sample envelope multiplier
+}
+
+impl Metronome {
+    /// Start a click; `accent` marks the downbeat with a higher pitch.
+    /// Clicks even when disabled, so count-ins always sound.
+    pub fn trigger(&mut self, accent: bool, sample_rate: f64) {
+        self.phase = 0.0;
+        self.frequency = if accent { ACCENT_FREQ } else { CLICK_FREQ };
+        self.envelope = CLICK_LEVEL;
+        self.decay = (-1.0 / (CLICK_DECAY_SECONDS * sample_rate)).exp();
+    }
+
+    /// Silence any click that is still ringing
+    pub fn stop(&mut self) {
+        self.envelope = 0.0;
+    }
+
+    #[inline]
+    pub fn next(&mut self, sample_rate: f64) -> f64 {
+        if self.envelope < 1e-5 {
+            return 0.0;
+        }
+        let out = (self.phase * 2.0 * PI).sin() * self.envelope;
+        self.phase = (self.phase + self.frequency / sample_rate) % 1.0;
+        self.envelope *= self.decay;
+        out
+    }
+}
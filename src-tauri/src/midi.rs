@@ -0,0 +1,504 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - MIDI
+// Controller input: notes trigger tracks, clock drives the tempo and
+// learned CCs move mix parameters
+// ============================================================
+
+use serde::{Deserialize, Serialize};
+
+use crate::mixer::{index_after_removal, MAX_COMP_RATIO, MAX_STEREO_WIDTH, MIN_COMP_THRESHOLD_DB};
+use crate::AudioCommand;
+#[cfg(any(feature = "midi", test))]
+use crate::MAX_TRACKS;
+
+/// Note that triggers track 0; each note above triggers the next track
+/// (36 = C1, the General MIDI kick)
+#[cfg(any(feature = "midi", test))]
+pub const DEFAULT_BASE_NOTE: u8 = 36;
+
+/// MIDI clock sends this many pulses per quarter note
+#[cfg(any(feature = "midi", test))]
+const CLOCK_PPQN: u32 = 24;
+
+/// Clock-derived tempi are clamped to this range
+#[cfg(any(feature = "midi", test))]
+const MIN_CLOCK_BPM: u64 = 20;
+#[cfg(any(feature = "midi", test))]
+const MAX_CLOCK_BPM: u64 = 300;
+
+/// The channel messages and realtime messages the engine reacts to
+#[cfg(any(feature = "midi", test))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    ControlChange { cc: Cc, value: u8 },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+#[cfg(any(feature = "midi", test))]
+impl MidiMessage {
+    /// Parse one complete message; anything else is `None`
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        match (status & 0xF0, status, data) {
+            (_, 0xF8, _) => Some(Self::Clock),
+            (_, 0xFA, _) => Some(Self::Start),
+            (_, 0xFB, _) => Some(Self::Continue),
+            (_, 0xFC, _) => Some(Self::Stop),
+            // Note-on at velocity 0 is a note-off by convention
+            (0x90, _, &[note, 0, ..]) | (0x80, _, &[note, _, ..]) => Some(Self::NoteOff { note }),
+            (0x90, _, &[note, velocity, ..]) => Some(Self::NoteOn { note, velocity }),
+            (0xB0, _, &[controller, value, ..]) => Some(Self::ControlChange {
+                cc: Cc { channel: status & 0x0F, controller },
+                value,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A controller number on one channel (0-15)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cc {
+    pub channel: u8,
+    pub controller: u8,
+}
+
+/// What a router hands back for one message
+#[derive(Clone, Debug)]
+pub enum MidiAction {
+    Command(AudioCommand),
+    /// CCs depend on the learned mappings, which live with the session
+    Control { cc: Cc, value: u8 },
+}
+
+/// Track that `note` triggers, counting up from `base_note`
+#[cfg(any(feature = "midi", test))]
+pub fn track_for_note(note: u8, base_note: u8) -> Option<usize> {
+    let track = note.checked_sub(base_note)? as usize;
+    (track < MAX_TRACKS).then_some(track)
+}
+
+/// Tempo measured over each beat of incoming clock pulses
+#[cfg(any(feature = "midi", test))]
+#[derive(Clone, Debug, Default)]
+struct ClockTempo {
+    // Timestamp (µs) of the pulse that started the current beat
+    beat_start: Option<u64>,
+    pulses: u32,
+}
+
+#[cfg(any(feature = "midi", test))]
+impl ClockTempo {
+    /// Count a pulse at `stamp_us`; returns the tempo once a beat completes
+    fn pulse(&mut self, stamp_us: u64) -> Option<u64> {
+        let Some(start) = self.beat_start else {
+            self.beat_start = Some(stamp_us);
+            return None;
+        };
+        self.pulses += 1;
+        if self.pulses < CLOCK_PPQN {
+            return None;
+        }
+        self.beat_start = Some(stamp_us);
+        self.pulses = 0;
+        let beat_us = stamp_us.checked_sub(start).filter(|&us| us > 0)?;
+        let bpm = (60_000_000.0 / beat_us as f64).round() as u64;
+        Some(bpm.clamp(MIN_CLOCK_BPM, MAX_CLOCK_BPM))
+    }
+
+    /// Start measuring afresh (transport restarted, or the clock stopped)
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Turns raw input messages into engine commands
+#[cfg(any(feature = "midi", test))]
+#[derive(Clone, Debug)]
+pub struct MidiRouter {
+    pub base_note: u8,
+    clock: ClockTempo,
+    // Last tempo sent, so a steady clock doesn't resend it every beat
+    bpm: Option<u64>,
+}
+
+#[cfg(any(feature = "midi", test))]
+impl Default for MidiRouter {
+    fn default() -> Self {
+        Self {
+            base_note: DEFAULT_BASE_NOTE,
+            clock: ClockTempo::default(),
+            bpm: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "midi", test))]
+impl MidiRouter {
+    /// What to do with a message received at `stamp_us`, if anything
+    pub fn handle(&mut self, stamp_us: u64, bytes: &[u8]) -> Option<MidiAction> {
+        let message = MidiMessage::parse(bytes)?;
+        if let MidiMessage::ControlChange { cc, value } = message {
+            return Some(MidiAction::Control { cc, value });
+        }
+        self.command(stamp_us, message).map(MidiAction::Command)
+    }
+
+    fn command(&mut self, stamp_us: u64, message: MidiMessage) -> Option<AudioCommand> {
+        match message {
+            MidiMessage::NoteOn { note, .. } => {
+                let track = track_for_note(note, self.base_note)?;
+                Some(AudioCommand::TriggerTrack { track })
+            }
+            MidiMessage::NoteOff { note } => {
+                let track = track_for_note(note, self.base_note)?;
+                Some(AudioCommand::ReleaseTrack { track })
+            }
+            MidiMessage::Clock => {
+                let bpm = self.clock.pulse(stamp_us)?;
+                if self.bpm == Some(bpm) {
+                    return None;
+                }
+                self.bpm = Some(bpm);
+                Some(AudioCommand::SetBpm { bpm })
+            }
+            MidiMessage::Start | MidiMessage::Continue => {
+                self.clock.reset();
+                Some(AudioCommand::Play)
+            }
+            MidiMessage::Stop => {
+                self.clock.reset();
+                Some(AudioCommand::Stop)
+            }
+            MidiMessage::ControlChange { .. } => None,
+        }
+    }
+}
+
+// ============================================================
+// MIDI LEARN
+// ============================================================
+
+/// Master and track EQ gains a CC sweeps, dB either side of flat
+const CC_EQ_RANGE_DB: f64 = 12.0;
+
+/// A mix parameter a CC can be learned onto
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MidiParam {
+    MasterVolume,
+    TrackVolume { track: usize },
+    TrackPan { track: usize },
+    TrackSendDelay { track: usize },
+    TrackSendReverb { track: usize },
+    EqLow,
+    EqMid,
+    EqHigh,
+    CompThreshold,
+    CompRatio,
+    DelayMix,
+    ReverbMix,
+    ChorusMix,
+    StereoWidth,
+}
+
+impl MidiParam {
+    /// Values CC 0 and CC 127 map to
+    pub fn range(self) -> (f64, f64) {
+        match self {
+            Self::TrackPan { .. } => (-1.0, 1.0),
+            Self::EqLow | Self::EqMid | Self::EqHigh => (-CC_EQ_RANGE_DB, CC_EQ_RANGE_DB),
+            Self::CompThreshold => (MIN_COMP_THRESHOLD_DB, 0.0),
+            Self::CompRatio => (1.0, MAX_COMP_RATIO),
+            Self::StereoWidth => (0.0, MAX_STEREO_WIDTH),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    /// The command that sets this parameter to `value`
+    pub fn command(self, value: f64) -> AudioCommand {
+        match self {
+            Self::MasterVolume => AudioCommand::SetVolume { value },
+            Self::TrackVolume { track } => AudioCommand::SetTrackVolume { track, value },
+            Self::TrackPan { track } => AudioCommand::SetTrackPan { track, value },
+            Self::TrackSendDelay { track } => AudioCommand::SetTrackSendDelay { track, value },
+            Self::TrackSendReverb { track } => AudioCommand::SetTrackSendReverb { track, value },
+            Self::EqLow => AudioCommand::SetEqLow { value },
+            Self::EqMid => AudioCommand::SetEqMid { value },
+            Self::EqHigh => AudioCommand::SetEqHigh { value },
+            Self::CompThreshold => AudioCommand::SetCompThreshold { value },
+            Self::CompRatio => AudioCommand::SetCompRatio { value },
+            Self::DelayMix => AudioCommand::SetDelayMix { value },
+            Self::ReverbMix => AudioCommand::SetReverbMix { value },
+            Self::ChorusMix => AudioCommand::SetChorusMix { value },
+            Self::StereoWidth => AudioCommand::SetStereoWidth { value },
+        }
+    }
+
+    /// The parameter and value a command sets, if it sets one of these
+    pub fn from_command(cmd: &AudioCommand) -> Option<(Self, f64)> {
+        let param = match *cmd {
+            AudioCommand::SetVolume { value } => (Self::MasterVolume, value),
+            AudioCommand::SetTrackVolume { track, value } => (Self::TrackVolume { track }, value),
+            AudioCommand::SetTrackPan { track, value } => (Self::TrackPan { track }, value),
+            AudioCommand::SetTrackSendDelay { track, value } => {
+                (Self::TrackSendDelay { track }, value)
+            }
+            AudioCommand::SetTrackSendReverb { track, value } => {
+                (Self::TrackSendReverb { track }, value)
+            }
+            AudioCommand::SetEqLow { value } => (Self::EqLow, value),
+            AudioCommand::SetEqMid { value } => (Self::EqMid, value),
+            AudioCommand::SetEqHigh { value } => (Self::EqHigh, value),
+            AudioCommand::SetCompThreshold { value } => (Self::CompThreshold, value),
+            AudioCommand::SetCompRatio { value } => (Self::CompRatio, value),
+            AudioCommand::SetDelayMix { value } => (Self::DelayMix, value),
+            AudioCommand::SetReverbMix { value } => (Self::ReverbMix, value),
+            AudioCommand::SetChorusMix { value } => (Self::ChorusMix, value),
+            AudioCommand::SetStereoWidth { value } => (Self::StereoWidth, value),
+            _ => return None,
+        };
+        Some(param)
+    }
+
+    /// The track a per-track parameter belongs to
+    pub fn track(self) -> Option<usize> {
+        match self {
+            Self::TrackVolume { track }
+            | Self::TrackPan { track }
+            | Self::TrackSendDelay { track }
+            | Self::TrackSendReverb { track } => Some(track),
+            _ => None,
+        }
+    }
+
+    /// The same parameter once track `removed` is gone: `None` if it was
+    /// that track's, shifted down if it was a later track's
+    pub fn after_track_removed(self, removed: usize) -> Option<Self> {
+        let Some(track) = self.track() else {
+            return Some(self);
+        };
+        let track = index_after_removal(Some(track), removed)?;
+        Some(match self {
+            Self::TrackVolume { .. } => Self::TrackVolume { track },
+            Self::TrackPan { .. } => Self::TrackPan { track },
+            Self::TrackSendDelay { .. } => Self::TrackSendDelay { track },
+            Self::TrackSendReverb { .. } => Self::TrackSendReverb { track },
+            other => other,
+        })
+    }
+
+    /// The command for a CC value (0-127) scaled across `range`
+    pub fn command_for_cc(self, value: u8) -> AudioCommand {
+        let (min, max) = self.range();
+        self.command(scale_cc(value, min, max))
+    }
+}
+
+/// Map a 7-bit CC value linearly onto `min..=max`
+pub fn scale_cc(value: u8, min: f64, max: f64) -> f64 {
+    min + (max - min) * value.min(127) as f64 / 127.0
+}
+
+/// One learned CC assignment
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CcMapping {
+    pub cc: Cc,
+    pub param: MidiParam,
+}
+
+/// Command for an incoming CC. While `learning` holds a parameter, the CC is
+/// assigned to it first, taking over from whatever either was mapped to
+/// before.
+pub fn map_cc(
+    mappings: &mut Vec<CcMapping>,
+    learning: &mut Option<MidiParam>,
+    cc: Cc,
+    value: u8,
+) -> Option<AudioCommand> {
+    if let Some(param) = learning.take() {
+        mappings.retain(|m| m.cc != cc && m.param != param);
+        mappings.push(CcMapping { cc, param });
+    }
+    let mapping = mappings.iter().find(|m| m.cc == cc)?;
+    Some(mapping.param.command_for_cc(value))
+}
+
+/// Follow `removed` leaving the track list: mappings to its parameters are
+/// dropped and later tracks' mappings shift down
+pub fn track_removed(mappings: &mut Vec<CcMapping>, removed: usize) {
+    mappings.retain_mut(|mapping| match mapping.param.after_track_removed(removed) {
+        Some(param) => {
+            mapping.param = param;
+            true
+        }
+        None => false,
+    });
+}
+
+/// The open MIDI input port, if any
+#[derive(Default)]
+pub struct MidiInputs {
+    #[cfg(feature = "midi")]
+    connection: parking_lot::Mutex<Option<midir::MidiInputConnection<()>>>,
+}
+
+#[cfg(feature = "midi")]
+const CLIENT_NAME: &str = "NEXUS-X";
+
+#[cfg(feature = "midi")]
+impl MidiInputs {
+    /// Names of the available input ports
+    pub fn list() -> Result<Vec<String>, String> {
+        let input = midir::MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        Ok(input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect())
+    }
+
+    /// Open the input port called `name` (closing any open one) and pass
+    /// what each message maps to to `send`, on midir's thread
+    pub fn open(
+        &self,
+        name: &str,
+        mut send: impl FnMut(MidiAction) + Send + 'static,
+    ) -> Result<(), String> {
+        let mut input = midir::MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        // Clock counts as timing, so keep it
+        input.ignore(midir::Ignore::SysexAndActiveSense);
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| input.port_name(port).is_ok_and(|n| n == name))
+            .ok_or_else(|| format!("No MIDI input named {}", name))?;
+
+        // Some backends can't open a port twice, so close it before reopening
+        let mut connection = self.connection.lock();
+        *connection = None;
+        let mut router = MidiRouter::default();
+        let callback = move |stamp_us: u64, bytes: &[u8], _: &mut ()| {
+            if let Some(action) = router.handle(stamp_us, bytes) {
+                send(action);
+            }
+        };
+        *connection = Some(
+            input
+                .connect(&port, CLIENT_NAME, callback, ())
+                .map_err(|e| format!("Failed to open MIDI input {}: {}", name, e))?,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "midi"))]
+const NO_MIDI: &str = "Built without MIDI support (enable the `midi` feature)";
+
+#[cfg(not(feature = "midi"))]
+impl MidiInputs {
+    pub fn list() -> Result<Vec<String>, String> {
+        Err(NO_MIDI.to_string())
+    }
+
+    pub fn open(
+        &self,
+        _name: &str,
+        _send: impl FnMut(MidiAction) + Send + 'static,
+    ) -> Result<(), String> {
+        Err(NO_MIDI.to_string())
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes_map_to_tracks_from_the_base_note() {
+        assert_eq!(track_for_note(36, DEFAULT_BASE_NOTE), Some(0));
+        assert_eq!(track_for_note(38, DEFAULT_BASE_NOTE), Some(2));
+        assert_eq!(track_for_note(35, DEFAULT_BASE_NOTE), None);
+        assert_eq!(track_for_note(36 + MAX_TRACKS as u8, DEFAULT_BASE_NOTE), None);
+        assert_eq!(track_for_note(60, 60), Some(0));
+
+        let mut router = MidiRouter::default();
+        let mut command = |bytes: &[u8]| match router.handle(0, bytes) {
+            Some(MidiAction::Command(cmd)) => Some(cmd),
+            _ => None,
+        };
+        // Note-on on any channel; velocity 0 and note-off both release
+        assert!(matches!(command(&[0x93, 37, 100]), Some(AudioCommand::TriggerTrack { track: 1 })));
+        assert!(matches!(command(&[0x90, 37, 0]), Some(AudioCommand::ReleaseTrack { track: 1 })));
+        assert!(matches!(command(&[0x80, 40, 64]), Some(AudioCommand::ReleaseTrack { track: 4 })));
+        assert!(command(&[0x90, 20, 100]).is_none());
+        assert!(command(&[0x90, 37]).is_none(), "truncated");
+        assert!(matches!(
+            router.handle(0, &[0xB2, 7, 100]),
+            Some(MidiAction::Control { cc: Cc { channel: 2, controller: 7 }, value: 100 })
+        ));
+    }
+
+    #[test]
+    fn test_clock_sets_tempo_once_per_change() {
+        let mut router = MidiRouter::default();
+        // 120 BPM: 24 pulses per 500 ms beat
+        let pulse_us = 500_000 / 24;
+        let bpms: Vec<u64> = (0..=24 * 3)
+            .filter_map(|i| match router.handle(i * pulse_us, &[0xF8]) {
+                Some(MidiAction::Command(AudioCommand::SetBpm { bpm })) => Some(bpm),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bpms, [120]);
+        assert!(matches!(router.handle(0, &[0xFC]), Some(MidiAction::Command(AudioCommand::Stop))));
+    }
+
+    #[test]
+    fn test_cc_values_scale_onto_parameter_ranges() {
+        assert_eq!(scale_cc(0, -1.0, 1.0), -1.0);
+        assert_eq!(scale_cc(127, -1.0, 1.0), 1.0);
+        assert!((scale_cc(64, 0.0, 127.0) - 64.0).abs() < 1e-12);
+        assert!(matches!(
+            MidiParam::CompThreshold.command_for_cc(0),
+            AudioCommand::SetCompThreshold { value } if value == -60.0
+        ));
+        assert!(matches!(
+            MidiParam::TrackPan { track: 3 }.command_for_cc(127),
+            AudioCommand::SetTrackPan { track: 3, value } if value == 1.0
+        ));
+    }
+
+    #[test]
+    fn test_learn_assigns_the_next_cc() {
+        let mut mappings = Vec::new();
+        let mut learning = None;
+        let volume = Cc { channel: 0, controller: 7 };
+        let other = Cc { channel: 1, controller: 7 };
+        assert!(map_cc(&mut mappings, &mut learning, volume, 64).is_none(), "nothing learned");
+
+        learning = Some(MidiParam::TrackVolume { track: 2 });
+        assert!(matches!(
+            map_cc(&mut mappings, &mut learning, volume, 127),
+            Some(AudioCommand::SetTrackVolume { track: 2, value }) if value == 1.0
+        ));
+        assert!(learning.is_none());
+        assert!(map_cc(&mut mappings, &mut learning, other, 64).is_none(), "other channel");
+
+        // Relearning the parameter moves it; the old CC is freed
+        learning = Some(MidiParam::TrackVolume { track: 2 });
+        map_cc(&mut mappings, &mut learning, other, 0);
+        assert!(map_cc(&mut mappings, &mut learning, volume, 64).is_none());
+        assert_eq!(mappings, [CcMapping { cc: other, param: MidiParam::TrackVolume { track: 2 } }]);
+
+        // Learning another parameter on a mapped CC replaces the mapping
+        learning = Some(MidiParam::ReverbMix);
+        map_cc(&mut mappings, &mut learning, other, 0);
+        assert_eq!(mappings, [CcMapping { cc: other, param: MidiParam::ReverbMix }]);
+    }
+}
@@ -3,11 +3,118 @@
 // Multi-Channel Mixer + Master Effects
 // ============================================================
 
-use std::f64::consts::PI;
+use std::collections::VecDeque;
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_2_PI, FRAC_PI_2, PI, SQRT_2};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chorus::Chorus;
+use crate::delay::{Delay, NoteDivision};
+use crate::loudness::{AutoGain, Loudness, LoudnessMeter};
+use crate::meter::{CorrelationMeter, LevelMeter};
+use crate::oversample::{Oversampler, TruePeakDetector, TRUE_PEAK_DELAY};
+use crate::reverb::Reverb;
+use crate::MAX_TRACKS;
+
+/// Recursive state below this is flushed to zero, so decaying filters
+/// don't crawl through denormals (very slow on some CPUs) during silence
+const DENORMAL_THRESHOLD: f64 = 1e-15;
+
+#[inline]
+pub fn flush_denormal(x: f64) -> f64 {
+    if x.abs() < DENORMAL_THRESHOLD {
+        0.0
+    } else {
+        x
+    }
+}
+
+/// Delay an effect adds to the signal passing through it, in samples.
+/// `Mixer` holds every track back to the slowest track's path (strip plus
+/// sub-bus), so all of them stay aligned at the master.
+pub trait Latency {
+    fn report_latency(&self) -> usize {
+        0
+    }
+}
+
+/// Longest path latency the per-track compensation can make up (~43 ms at
+/// 48 kHz); any more and the tracks drift apart by the excess
+pub const MAX_COMPENSATION: usize = 2048;
+
+/// Fixed stereo delay line holding a track back for latency compensation
+/// (or a gate's audio behind its detector). At no delay it's skipped.
+#[derive(Clone, Debug)]
+struct CompensationDelay {
+    buffer: [[f64; 2]; MAX_COMPENSATION],
+    pos: usize,
+    delay: usize,
+}
+
+impl CompensationDelay {
+    fn new() -> Self {
+        Self {
+            buffer: [[0.0; 2]; MAX_COMPENSATION],
+            pos: 0,
+            delay: 0,
+        }
+    }
+
+    /// A new delay starts from silence rather than whatever the line held
+    /// the last time it ran
+    fn set_delay(&mut self, samples: usize) {
+        let delay = samples.min(MAX_COMPENSATION - 1);
+        if delay != self.delay {
+            self.buffer = [[0.0; 2]; MAX_COMPENSATION];
+            self.delay = delay;
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        if self.delay == 0 {
+            return (left, right);
+        }
+        self.buffer[self.pos] = [left, right];
+        let [left, right] =
+            self.buffer[(self.pos + MAX_COMPENSATION - self.delay) % MAX_COMPENSATION];
+        self.pos = (self.pos + 1) % MAX_COMPENSATION;
+        (left, right)
+    }
+}
+
+/// Biquad response shapes (RBJ Audio EQ Cookbook); `gain` only affects
+/// the peak and shelf kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+    #[default]
+    Peak,
+    LowShelf,
+    HighShelf,
+    /// Flat magnitude; the phase turns through -180° at the frequency
+    AllPass,
+}
+
+/// EQ gain range, dB either side of flat
+pub const MAX_EQ_DB: f64 = 24.0;
+
+/// Track high-pass (rumble filter) corner range and default
+pub const MIN_HPF_HZ: f64 = 20.0;
+pub const MAX_HPF_HZ: f64 = 1000.0;
+pub const DEFAULT_HPF_HZ: f64 = 80.0;
+
+/// Track all-pass (phase alignment) frequency range and default
+pub const MIN_ALLPASS_HZ: f64 = 20.0;
+pub const MAX_ALLPASS_HZ: f64 = 20000.0;
+pub const DEFAULT_ALLPASS_HZ: f64 = 100.0;
 
 /// Master EQ Band
 #[derive(Clone, Debug)]
 pub struct EqBand {
+    pub kind: FilterKind,
     pub frequency: f64,
     pub gain: f64,      // dB
     pub q: f64,
@@ -19,20 +126,92 @@ pub struct EqBand {
     y1: f64, y2: f64,
 }
 
+/// Minimum-phase shapes have no delay worth compensating. An all-pass
+/// holds its lows back by its group delay at DC, in samples:
+/// (Σ k·b_k) / Σ b_k - (Σ k·a_k) / Σ a_k, which for its mirrored
+/// coefficients is (2 - 2·a2) / (1 + a1 + a2).
+impl Latency for EqBand {
+    fn report_latency(&self) -> usize {
+        if self.kind != FilterKind::AllPass {
+            return 0;
+        }
+        let delay = (2.0 - 2.0 * self.a2) / (1.0 + self.a1 + self.a2);
+        delay.round().max(0.0) as usize
+    }
+}
+
 impl EqBand {
+    /// Peaking band
     pub fn new(frequency: f64, gain_db: f64, q: f64, sample_rate: f64) -> Self {
+        Self::with_kind(FilterKind::Peak, frequency, gain_db, q, sample_rate)
+    }
+
+    pub fn with_kind(
+        kind: FilterKind,
+        frequency: f64,
+        gain_db: f64,
+        q: f64,
+        sample_rate: f64,
+    ) -> Self {
         let a = 10.0_f64.powf(gain_db / 40.0);
         let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos = w0.cos();
         let alpha = w0.sin() / (2.0 * q);
+        let shelf = 2.0 * a.sqrt() * alpha;
 
-        let b0 = 1.0 + alpha * a;
-        let b1 = -2.0 * w0.cos();
-        let b2 = 1.0 - alpha * a;
-        let a0 = 1.0 + alpha / a;
-        let a1 = 2.0 * w0.cos();
-        let a2 = -(1.0 - alpha / a);
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            FilterKind::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterKind::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterKind::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            FilterKind::AllPass => (
+                1.0 - alpha,
+                -2.0 * cos,
+                1.0 + alpha,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            FilterKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
 
         Self {
+            kind,
             frequency,
             gain: gain_db,
             q,
@@ -51,11 +230,11 @@ impl EqBand {
     /// Process a single sample through the EQ band
     #[inline]
     pub fn process(&mut self, input: f64) -> f64 {
-        let output = self.b0 * input
-            + self.b1 * self.x1
-            + self.b2 * self.x2
-            - self.a1 * self.y1
-            - self.a2 * self.y2;
+        let output = flush_denormal(
+            self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
+                - self.a1 * self.y1
+                - self.a2 * self.y2,
+        );
 
         self.x2 = self.x1;
         self.x1 = input;
@@ -65,208 +244,2110 @@ impl EqBand {
         output
     }
 
+    /// The band's gain in dB at `frequency`, from its coefficients:
+    /// |H(e^jw)| with w = 2π·frequency / sample_rate
+    pub fn magnitude_db(&self, frequency: f64, sample_rate: f64) -> f64 {
+        let w = 2.0 * PI * frequency / sample_rate;
+        let (cos1, sin1, cos2, sin2) = (w.cos(), w.sin(), (2.0 * w).cos(), (2.0 * w).sin());
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = self.b1 * sin1 + self.b2 * sin2;
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = self.a1 * sin1 + self.a2 * sin2;
+        let power = (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+        10.0 * power.log10()
+    }
+
+    /// Recompute coefficients for a new gain (within +/-`MAX_EQ_DB`),
+    /// keeping the filter history so the change doesn't click
     pub fn update(&mut self, gain_db: f64, sample_rate: f64) {
-        *self = Self::new(self.frequency, gain_db, self.q, sample_rate);
+        let gain_db = gain_db.clamp(-MAX_EQ_DB, MAX_EQ_DB);
+        *self = Self {
+            x1: self.x1,
+            x2: self.x2,
+            y1: self.y1,
+            y2: self.y2,
+            ..Self::with_kind(self.kind, self.frequency, gain_db, self.q, sample_rate)
+        };
+    }
+
+    /// Recompute coefficients for a new frequency, keeping the history like
+    /// `update`
+    pub fn retune(&mut self, frequency: f64, sample_rate: f64) {
+        *self = Self {
+            x1: self.x1,
+            x2: self.x2,
+            y1: self.y1,
+            y2: self.y2,
+            ..Self::with_kind(self.kind, frequency, self.gain, self.q, sample_rate)
+        };
     }
 }
 
-/// Master Limiter with Lookahead
+/// Time over which volume and pan changes are ramped in
+pub const PARAM_SMOOTHING_MS: f64 = 10.0;
+
+/// Time over which a track fades out when muted (or soloed away) and back in
+pub const MUTE_FADE_MS: f64 = 5.0;
+
+/// A parameter that moves linearly to a new target over `PARAM_SMOOTHING_MS`
+/// instead of jumping, to avoid zipper noise
 #[derive(Clone, Debug)]
-pub struct Limiter {
-    pub threshold: f64,    // 0.0 to 1.0
-    pub release: f64,      // seconds
-    pub lookahead: usize,  // samples
-    buffer: Vec<f64>,
-    buffer_pos: usize,
-    envelope: f64,
-    sample_rate: f64,
+pub struct SmoothedParam {
+    current: f64,
+    target: f64,
+    step: f64,
+    remaining: usize,
+    ramp_samples: usize,
 }
 
-impl Limiter {
-    pub fn new(sample_rate: f64, threshold: f64, release: f64) -> Self {
-        let lookahead = (sample_rate * 0.005) as usize; // 5ms lookahead
+impl SmoothedParam {
+    pub fn new(value: f64, sample_rate: f64) -> Self {
+        Self::with_ramp_ms(value, sample_rate, PARAM_SMOOTHING_MS)
+    }
+
+    pub fn with_ramp_ms(value: f64, sample_rate: f64, ramp_ms: f64) -> Self {
         Self {
-            threshold,
-            release,
-            lookahead,
-            buffer: vec![0.0; lookahead + 1],
-            buffer_pos: 0,
-            envelope: 0.0,
-            sample_rate,
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+            ramp_samples: ((sample_rate * ramp_ms / 1000.0) as usize).max(1),
+        }
+    }
+
+    #[inline]
+    pub fn set_target(&mut self, target: f64) {
+        if target != self.target {
+            self.target = target;
+            self.remaining = self.ramp_samples;
+            self.step = (target - self.current) / self.ramp_samples as f64;
+        }
+    }
+
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    /// Advance one sample and return the smoothed value
+    #[inline]
+    pub fn next(&mut self) -> f64 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            // Land exactly on the target at the end of the ramp
+            self.current = if self.remaining == 0 {
+                self.target
+            } else {
+                self.current + self.step
+            };
+        }
+        self.current
+    }
+}
+
+/// How pan position maps to left/right gains. Hard-panned tracks get 1.0 on
+/// one side and 0.0 on the other under every law except `ConstantPower`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanLaw {
+    /// Straight-line crossfade; 0.5 per side at center (-6 dB)
+    Linear,
+    /// sin/cos law normalised to unity per side at center (0 dB), so centered
+    /// tracks keep their level; hard pan reaches 1.414 (+3 dB)
+    ConstantPower,
+    /// sin/cos law; 0.707 per side at center (-3 dB)
+    #[default]
+    MinusThreeDb,
+}
+
+impl PanLaw {
+    /// (left, right) gains for `pan` in -1.0..=1.0
+    #[inline]
+    pub fn gains(self, pan: f64) -> (f64, f64) {
+        let pan = pan.clamp(-1.0, 1.0);
+        match self {
+            PanLaw::Linear => ((1.0 - pan) * 0.5, (1.0 + pan) * 0.5),
+            PanLaw::ConstantPower | PanLaw::MinusThreeDb => {
+                let angle = (pan + 1.0) * PI / 4.0; // -1 to 1 -> 0 to PI/2
+                let scale = if self == PanLaw::ConstantPower { SQRT_2 } else { 1.0 };
+                (angle.cos() * scale, angle.sin() * scale)
+            }
+        }
+    }
+}
+
+/// What a track's pan control does. Pan places a source in the field;
+/// balance keeps each side of a stereo source on its side and only fades
+/// the one turned away from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanMode {
+    /// Balance while the track plays stereo, pan while it plays mono
+    #[default]
+    Auto,
+    /// Mono sources follow the pan law; a stereo source's far side folds
+    /// into the near one
+    Pan,
+    /// Each side at unity until turned away from, mono sources included
+    Balance,
+}
+
+/// Lo-fi bit depth and sample-rate reduction
+#[derive(Clone, Debug)]
+pub struct BitCrusher {
+    pub bit_depth: u32,  // 1 to 16; 16 with downsample 1 is off
+    pub downsample: u32, // hold each sample for this many frames
+    held: f64,
+    hold_count: u32,
+}
+
+pub const MAX_CRUSH_BITS: u32 = 16;
+pub const MAX_CRUSH_DOWNSAMPLE: u32 = 64;
+
+impl Default for BitCrusher {
+    fn default() -> Self {
+        Self {
+            bit_depth: MAX_CRUSH_BITS,
+            downsample: 1,
+            held: 0.0,
+            hold_count: 0,
         }
     }
+}
+
+impl Latency for BitCrusher {}
+
+impl BitCrusher {
+    pub fn set(&mut self, bit_depth: u32, downsample: u32) {
+        self.bit_depth = bit_depth.clamp(1, MAX_CRUSH_BITS);
+        self.downsample = downsample.clamp(1, MAX_CRUSH_DOWNSAMPLE);
+    }
 
     #[inline]
     pub fn process(&mut self, input: f64) -> f64 {
-        // Store input in lookahead buffer
-        self.buffer[self.buffer_pos] = input;
+        if self.bit_depth >= MAX_CRUSH_BITS && self.downsample == 1 {
+            return input;
+        }
 
-        // Calculate envelope
-        let abs_input = input.abs();
-        let attack_coeff = 0.9999; // Very fast attack
-        let release_coeff = (-1.0 / (self.release * self.sample_rate)).exp();
+        // Sample-and-hold: only take a new input every `downsample` frames
+        if self.hold_count == 0 {
+            // 2^bits steps across -1..1, with zero on a step so silence
+            // stays silent
+            let step = 1.0 / (1u32 << (self.bit_depth - 1)) as f64;
+            self.held = (input.clamp(-1.0, 1.0) / step).round() * step;
+        }
+        self.hold_count = (self.hold_count + 1) % self.downsample;
+        self.held
+    }
+}
+
+/// Thresholds at or below this leave the gate open (bypassed)
+pub const GATE_OFF_DB: f64 = -100.0;
+
+/// Longest gate lookahead
+pub const MAX_GATE_LOOKAHEAD_MS: f64 = 5.0;
+
+/// Noise gate: opens on any sample above the threshold, stays open for
+/// `hold` after the level drops, then fades to silence over `release`.
+/// With lookahead the audio runs that far behind the detector, so the gate
+/// is already open when a transient arrives.
+#[derive(Clone, Debug)]
+pub struct Gate {
+    threshold: f64, // linear; 0.0 = off
+    attack_coeff: f64,
+    release_coeff: f64,
+    hold_samples: usize,
+    hold_remaining: usize,
+    gain: f64,
+    lookahead: CompensationDelay,
+}
+
+/// The lookahead, which delays the audio whether or not the gate is on
+impl Latency for Gate {
+    fn report_latency(&self) -> usize {
+        self.lookahead.delay
+    }
+}
+
+impl Gate {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut gate = Self {
+            threshold: 0.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            hold_samples: 0,
+            hold_remaining: 0,
+            gain: 1.0,
+            lookahead: CompensationDelay::new(),
+        };
+        gate.set(GATE_OFF_DB, 1.0, 50.0, 100.0, sample_rate);
+        gate
+    }
 
-        if abs_input > self.envelope {
-            self.envelope = attack_coeff * self.envelope + (1.0 - attack_coeff) * abs_input;
+    /// Threshold in dB; attack, hold and release in ms
+    pub fn set(
+        &mut self,
+        threshold_db: f64,
+        attack_ms: f64,
+        hold_ms: f64,
+        release_ms: f64,
+        sample_rate: f64,
+    ) {
+        let coeff = |ms: f64| (-1.0 / (ms.max(0.1) * 0.001 * sample_rate)).exp();
+        self.threshold = if threshold_db <= GATE_OFF_DB {
+            0.0
         } else {
-            self.envelope = release_coeff * self.envelope + (1.0 - release_coeff) * abs_input;
+            10f64.powf(threshold_db.min(0.0) / 20.0)
+        };
+        self.attack_coeff = coeff(attack_ms);
+        self.release_coeff = coeff(release_ms);
+        self.hold_samples = (hold_ms.max(0.0) * 0.001 * sample_rate) as usize;
+    }
+
+    /// How far the detector looks ahead of the audio, up to
+    /// `MAX_GATE_LOOKAHEAD_MS`
+    pub fn set_lookahead(&mut self, ms: f64, sample_rate: f64) {
+        let ms = ms.clamp(0.0, MAX_GATE_LOOKAHEAD_MS);
+        self.lookahead.set_delay((ms * 0.001 * sample_rate).round() as usize);
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f64) -> f64 {
+        let gain = self.next_gain(input.abs());
+        self.lookahead.process(input, 0.0).0 * gain
+    }
+
+    /// Stereo-linked: both channels open and close together
+    #[inline]
+    pub fn process_stereo(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let gain = self.next_gain(left.abs().max(right.abs()));
+        let (left, right) = self.lookahead.process(left, right);
+        (left * gain, right * gain)
+    }
+
+    /// Advance one sample with detector input `level` (absolute value)
+    #[inline]
+    fn next_gain(&mut self, level: f64) -> f64 {
+        if self.threshold == 0.0 {
+            self.gain = 1.0;
+            return 1.0;
         }
 
-        // Calculate gain reduction
-        let gain = if self.envelope > self.threshold {
-            self.threshold / self.envelope
-        } else {
+        let target = if level >= self.threshold {
+            self.hold_remaining = self.hold_samples;
+            1.0
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
             1.0
+        } else {
+            0.0
+        };
+
+        let coeff = if target > self.gain {
+            self.attack_coeff
+        } else {
+            self.release_coeff
         };
+        self.gain = flush_denormal(target + (self.gain - target) * coeff);
+        self.gain
+    }
+}
 
-        // Apply gain to delayed signal
-        let delayed_pos = (self.buffer_pos + 1) % self.lookahead;
-        let output = self.buffer[delayed_pos] * gain;
+/// One track's input to `mix_channels` for the current frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrackInput {
+    /// The mono sample, or the left channel of a stereo source
+    pub left: f64,
+    /// Right channel of a stereo source. Stereo tracks get balance (each
+    /// side kept on its side) instead of pan unless their `PanMode` says
+    /// otherwise.
+    pub right: Option<f64>,
+    pub volume: f64,
+    pub pan: f64,
+    pub muted: bool,
+    pub soloed: bool,
+    pub listen: Listen,
+}
 
-        self.buffer_pos = (self.buffer_pos + 1) % self.lookahead;
+/// Where the monitor taps a track while it's being listened to. Listening
+/// replaces only what the device plays; the main mix goes on unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Listen {
+    #[default]
+    Off,
+    /// Pre-fader listen: after the strip's effects, before volume and pan
+    Pfl,
+    /// After-fader listen: with volume and pan, before the bus
+    Afl,
+}
 
-        output
+/// Where a reference to track `index` points once `removed` leaves the
+/// track list: references to it are dropped, later indices shift down
+pub fn index_after_removal(index: Option<usize>, removed: usize) -> Option<usize> {
+    match index {
+        Some(i) if i == removed => None,
+        Some(i) if i > removed => Some(i - 1),
+        other => other,
     }
 }
 
-/// Soft Clipper for warm saturation
+/// Stereo balance: unity at center, turning toward one side fades the other
+/// out and leaves that side untouched
+#[inline]
+fn balance_gains(balance: f64) -> (f64, f64) {
+    let balance = balance.clamp(-1.0, 1.0);
+    ((1.0 - balance).min(1.0), (1.0 + balance).min(1.0))
+}
+
+/// Stereo pan: the side turned away from fades and its share moves to the
+/// other side, so hard pan sums both channels on one side
+#[inline]
+fn stereo_pan(left: f64, right: f64, pan: f64) -> (f64, f64) {
+    let pan = pan.clamp(-1.0, 1.0);
+    if pan >= 0.0 {
+        (left * (1.0 - pan), right + left * pan)
+    } else {
+        (left - right * pan, right * (1.0 + pan))
+    }
+}
+
+/// Output of `mix_channels`: the dry stereo mix and the post-fader sends
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MixBus {
+    pub dry: (f64, f64),
+    pub delay_send: (f64, f64),
+    pub reverb_send: (f64, f64),
+    /// Sum of the listened-to tracks' taps, if any track is listened to
+    pub listen: Option<(f64, f64)>,
+}
+
+
+/// Input trim range, dB either side of unity
+pub const MAX_TRIM_DB: f64 = 24.0;
+
+/// Per-track processing applied before the pan stage
 #[derive(Clone, Debug)]
-pub struct SoftClipper {
-    pub threshold: f64,
-    pub amount: f64,
+pub struct ChannelStrip {
+    // Applied in `mix_channels`, ahead of the strip
+    polarity_inverted: bool,
+    // Keeps playing while other tracks are soloed
+    solo_safe: bool,
+    pan_mode: PanMode,
+    // Sub-bus the track feeds, or `None` for the master
+    bus: Option<usize>,
+    // Input gain ahead of all processing (linear)
+    trim: f64,
+    // Switchable 12 dB/oct high-pass, first in the chain
+    hpf: [EqBand; 2],
+    hpf_on: bool,
+    // Switchable phase shift after the high-pass, for lining up sources
+    // picked up by several mics
+    allpass: [EqBand; 2],
+    allpass_on: bool,
+    gate: Gate,
+    // EQ Bands (Low, Mid, High); mono sources only use the first set
+    eq: [[EqBand; 3]; 2],
+    // Post-EQ dynamics; 1:1 (the default) leaves the track untouched
+    compressor: Compressor,
+    // Track whose input level drives the compressor instead of this one's
+    sidechain: Option<usize>,
+    crusher: [BitCrusher; 2],
+    // Fader and pan, smoothed toward the values passed to `mix_channels`
+    volume: SmoothedParam,
+    pan: SmoothedParam,
+    // 1.0 while the track is heard, 0.0 while muted or soloed away
+    audible: SmoothedParam,
+    // Post-fader send levels to the master delay and reverb
+    send_delay: f64,
+    send_reverb: f64,
+    // The track plays a freeze of this strip's output, so polarity and the
+    // strip processing are already in its input
+    frozen: bool,
+    // Holds the strip's output back to line up with slower tracks
+    compensation: CompensationDelay,
 }
 
-impl SoftClipper {
-    pub fn new(threshold: f64, amount: f64) -> Self {
-        Self { threshold, amount }
+/// The strip's processing chain, in series. A frozen track reports the
+/// same, since its freeze was rendered through the strip.
+impl Latency for ChannelStrip {
+    fn report_latency(&self) -> usize {
+        let hpf = if self.hpf_on { self.hpf[0].report_latency() } else { 0 };
+        let allpass = if self.allpass_on { self.allpass[0].report_latency() } else { 0 };
+        let eq: usize = self.eq[0].iter().map(Latency::report_latency).sum();
+        hpf + allpass
+            + self.gate.report_latency()
+            + eq
+            + self.compressor.report_latency()
+            + self.crusher[0].report_latency()
+    }
+}
+
+impl ChannelStrip {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            polarity_inverted: false,
+            solo_safe: false,
+            pan_mode: PanMode::Auto,
+            bus: None,
+            trim: 1.0,
+            hpf: [Self::track_hpf(sample_rate), Self::track_hpf(sample_rate)],
+            hpf_on: false,
+            allpass: [Self::track_allpass(sample_rate), Self::track_allpass(sample_rate)],
+            allpass_on: false,
+            gate: Gate::new(sample_rate),
+            eq: [Self::track_eq(sample_rate), Self::track_eq(sample_rate)],
+            compressor: Compressor::new(sample_rate),
+            sidechain: None,
+            crusher: [BitCrusher::default(), BitCrusher::default()],
+            volume: SmoothedParam::new(0.0, sample_rate),
+            pan: SmoothedParam::new(0.0, sample_rate),
+            audible: SmoothedParam::with_ramp_ms(0.0, sample_rate, MUTE_FADE_MS),
+            send_delay: 0.0,
+            send_reverb: 0.0,
+            frozen: false,
+            compensation: CompensationDelay::new(),
+        }
     }
 
+    /// 100Hz low, 1kHz mid, 8kHz high
+    fn track_eq(sample_rate: f64) -> [EqBand; 3] {
+        [
+            EqBand::new(100.0, 0.0, 0.7, sample_rate),
+            EqBand::new(1000.0, 0.0, 1.0, sample_rate),
+            EqBand::new(8000.0, 0.0, 0.7, sample_rate),
+        ]
+    }
+
+    /// Butterworth high-pass at `DEFAULT_HPF_HZ`
+    fn track_hpf(sample_rate: f64) -> EqBand {
+        EqBand::with_kind(FilterKind::HighPass, DEFAULT_HPF_HZ, 0.0, FRAC_1_SQRT_2, sample_rate)
+    }
+
+    /// Second-order all-pass at `DEFAULT_ALLPASS_HZ`
+    fn track_allpass(sample_rate: f64) -> EqBand {
+        EqBand::with_kind(FilterKind::AllPass, DEFAULT_ALLPASS_HZ, 0.0, FRAC_1_SQRT_2, sample_rate)
+    }
+
+    /// `key` is the sidechain level driving the compressor, if any
     #[inline]
-    pub fn process(&self, input: f64) -> f64 {
-        let abs_input = input.abs();
-        if abs_input < self.threshold {
-            input
+    pub fn process(&mut self, input: f64, key: Option<f64>) -> f64 {
+        let input = if self.hpf_on { self.hpf[0].process(input) } else { input };
+        let input = if self.allpass_on { self.allpass[0].process(input) } else { input };
+        let gated = self.gate.process(input * self.trim);
+        let eq = self.eq[0].iter_mut().fold(gated, |x, band| band.process(x));
+        let compressed = self.compressor.process_keyed(eq, eq, key.unwrap_or(eq.abs())).0;
+        self.crusher[0].process(compressed)
+    }
+
+    /// Both channels of a stereo source, with a linked gate
+    #[inline]
+    pub fn process_stereo(&mut self, left: f64, right: f64, key: Option<f64>) -> (f64, f64) {
+        let (left, right) = if self.hpf_on {
+            (self.hpf[0].process(left), self.hpf[1].process(right))
         } else {
-            let sign = input.signum();
-            let excess = abs_input - self.threshold;
-            let clipped = self.threshold + excess / (1.0 + excess * self.amount);
-            sign * clipped.min(1.0)
+            (left, right)
+        };
+        let (left, right) = if self.allpass_on {
+            (self.allpass[0].process(left), self.allpass[1].process(right))
+        } else {
+            (left, right)
+        };
+        let gated = self.gate.process_stereo(left * self.trim, right * self.trim);
+        let mut out = [gated.0, gated.1];
+        for (x, bands) in out.iter_mut().zip(&mut self.eq) {
+            *x = bands.iter_mut().fold(*x, |x, band| band.process(x));
+        }
+        // Linked across the channels, like the gate
+        let key = key.unwrap_or(out[0].abs().max(out[1].abs()));
+        let compressed = self.compressor.process_keyed(out[0], out[1], key);
+        out = [compressed.0, compressed.1];
+        for (x, crusher) in out.iter_mut().zip(&mut self.crusher) {
+            *x = crusher.process(*x);
+        }
+        (out[0], out[1])
+    }
+
+    /// Update EQ band gains (in dB)
+    pub fn set_eq(&mut self, low_db: f64, mid_db: f64, high_db: f64, sample_rate: f64) {
+        for bands in &mut self.eq {
+            bands[0].update(low_db, sample_rate);
+            bands[1].update(mid_db, sample_rate);
+            bands[2].update(high_db, sample_rate);
         }
     }
 }
 
-/// Multi-Channel Mixer with Master Effects
-pub struct Mixer {
-    // EQ Bands (Low, Mid, High)
-    eq_low: EqBand,
-    eq_mid: EqBand,
-    eq_high: EqBand,
+/// How the limiter's envelope falls back after a peak
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimiterRelease {
+    /// One-pole decay toward the signal with the `release` time constant
+    #[default]
+    Exponential,
+    /// Falls at a constant full scale per `release` seconds, then stops at
+    /// the signal level
+    Linear,
+    /// Program-dependent: a fast stage recovers from isolated transients,
+    /// while a slow stage charged by sustained level holds the gain steady
+    /// on dense material instead of pumping
+    Dual,
+}
 
-    // Master Effects
-    limiter: Limiter,
-    clipper: SoftClipper,
+// Dual release stage times, as multiples of `release`
+const DUAL_FAST_RELEASE: f64 = 0.25;
+const DUAL_SLOW_ATTACK: f64 = 2.0;
+const DUAL_SLOW_RELEASE: f64 = 5.0;
 
-    // Settings
-    pub master_volume: f64,
+/// Per-sample one-pole coefficient for a `seconds` time constant
+#[inline]
+fn one_pole(seconds: f64, sample_rate: f64) -> f64 {
+    (-1.0 / (seconds * sample_rate)).exp()
+}
+
+/// Master Limiter with Lookahead
+///
+/// The input is delayed by `lookahead` samples. Gain for each output sample
+/// is derived from the loudest sample anywhere in the lookahead window and
+/// ramped in across the window, so the reduction is fully in place by the
+/// time a transient reaches the output. Both channels share one gain.
+///
+/// In true-peak mode the detector sees the 4x-interpolated waveform instead
+/// of the raw samples, and the audio is held back a further
+/// `TRUE_PEAK_DELAY` samples to stay aligned with it. Gain is still applied
+/// at the base rate.
+#[derive(Clone, Debug)]
+pub struct Limiter {
+    pub threshold: f64,    // 0.0 to 1.0
+    pub release: f64,      // seconds
+    pub lookahead: usize,  // samples
+    pub release_mode: LimiterRelease,
+    buffer: Vec<[f64; 2]>,
+    buffer_pos: usize,
+    // The envelope, or in dual mode its fast stage
+    envelope: f64,
+    // Dual mode's slow stage
+    slow_envelope: f64,
     sample_rate: f64,
+    // Sliding-window peak: (sample index, |x|), magnitudes strictly decreasing
+    peaks: VecDeque<(u64, f64)>,
+    sample_index: u64,
+    // Target gains over the window, averaged to ramp gain changes in
+    gains: Vec<f64>,
+    gain_sum: f64,
+    // Lowest applied gain since the last `reset_gain_reduction`
+    min_gain: f64,
+    true_peak: bool,
+    detectors: [TruePeakDetector; 2],
+    // Aligns the audio with the detectors' estimate in true-peak mode
+    detector_delay: [[f64; 2]; TRUE_PEAK_DELAY],
+    detector_delay_pos: usize,
 }
 
-impl Mixer {
-    pub fn new(sample_rate: f64) -> Self {
-        Self {
-            eq_low: EqBand::new(100.0, 0.0, 0.7, sample_rate),    // 100Hz Low Shelf
-            eq_mid: EqBand::new(1000.0, 0.0, 1.0, sample_rate),   // 1kHz Peak
-            eq_high: EqBand::new(8000.0, 0.0, 0.7, sample_rate),  // 8kHz High Shelf
-            limiter: Limiter::new(sample_rate, 0.95, 0.1),
-            clipper: SoftClipper::new(0.8, 2.0),
-            master_volume: 0.8,
+impl Latency for Limiter {
+    fn report_latency(&self) -> usize {
+        self.lookahead + if self.true_peak { TRUE_PEAK_DELAY } else { 0 }
+    }
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f64, threshold: f64, release: f64) -> Self {
+        let mut limiter = Self {
+            threshold,
+            release,
+            lookahead: 0,
+            release_mode: LimiterRelease::default(),
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            envelope: 0.0,
+            slow_envelope: 0.0,
             sample_rate,
-        }
+            peaks: VecDeque::new(),
+            sample_index: 0,
+            gains: Vec::new(),
+            gain_sum: 0.0,
+            min_gain: 1.0,
+            true_peak: false,
+            detectors: [TruePeakDetector::new(), TruePeakDetector::new()],
+            detector_delay: [[0.0; 2]; TRUE_PEAK_DELAY],
+            detector_delay_pos: 0,
+        };
+        limiter.set_lookahead_ms(5.0); // 5ms lookahead
+        limiter
     }
 
-    /// Mix multiple channels with pan and volume
-    #[inline]
-    pub fn mix_channels(
-        &self,
-        channels: &[(f64, f64, f64, bool, bool)], // (sample, volume, pan, muted, soloed)
-        any_soloed: bool,
-    ) -> (f64, f64) {
-        let mut left = 0.0;
-        let mut right = 0.0;
-
-        for (sample, volume, pan, muted, soloed) in channels {
-            // Skip muted tracks (or non-soloed if any track is soloed)
-            if *muted || (any_soloed && !soloed) {
-                continue;
-            }
+    /// Set the lookahead (and attack) time. Reallocates and clears the
+    /// delay line, so call it outside the audio callback.
+    pub fn set_lookahead_ms(&mut self, ms: f64) {
+        let lookahead = ((self.sample_rate * ms / 1000.0) as usize).max(1);
+        self.lookahead = lookahead;
+        self.buffer = vec![[0.0; 2]; lookahead];
+        self.buffer_pos = 0;
+        self.envelope = 0.0;
+        self.peaks = VecDeque::with_capacity(lookahead + 2);
+        self.sample_index = 0;
+        self.gains = vec![1.0; lookahead];
+        self.gain_sum = lookahead as f64;
+    }
 
-            // Apply volume
-            let vol_sample = sample * volume;
+    /// Detect inter-sample peaks. Switching adds or removes
+    /// `TRUE_PEAK_DELAY` samples of latency; setting the current mode again
+    /// leaves the detectors running.
+    pub fn set_true_peak(&mut self, on: bool) {
+        if on == self.true_peak {
+            return;
+        }
+        self.true_peak = on;
+        for detector in &mut self.detectors {
+            detector.reset();
+        }
+        self.detector_delay = [[0.0; 2]; TRUE_PEAK_DELAY];
+        self.detector_delay_pos = 0;
+    }
 
-            // Apply pan (constant power panning)
-            let angle = (pan + 1.0) * PI / 4.0; // -1 to 1 -> 0 to PI/2
-            let left_gain = angle.cos();
-            let right_gain = angle.sin();
+    /// Loudest absolute sample among the last `lookahead + 1` inputs
+    #[inline]
+    fn window_peak(&mut self, abs_input: f64) -> f64 {
+        while matches!(self.peaks.back(), Some(&(_, p)) if p <= abs_input) {
+            self.peaks.pop_back();
+        }
+        self.peaks.push_back((self.sample_index, abs_input));
 
-            left += vol_sample * left_gain;
-            right += vol_sample * right_gain;
+        let window = self.lookahead as u64;
+        while matches!(self.peaks.front(), Some(&(i, _)) if i + window < self.sample_index) {
+            self.peaks.pop_front();
         }
+        self.sample_index += 1;
 
-        (left, right)
+        self.peaks.front().map_or(0.0, |&(_, p)| p)
     }
 
-    /// Process master bus with EQ, Limiter, Soft Clip
     #[inline]
-    pub fn process_master(&mut self, left: f64, right: f64) -> (f32, f32) {
-        // Apply EQ
-        let eq_l = self.eq_low.process(left);
-        let eq_l = self.eq_mid.process(eq_l);
-        let eq_l = self.eq_high.process(eq_l);
+    pub fn process_stereo(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let (input, input_peak) = if self.true_peak {
+            let peak = self.detectors[0].process(left).max(self.detectors[1].process(right));
+            let delayed = self.detector_delay[self.detector_delay_pos];
+            self.detector_delay[self.detector_delay_pos] = [left, right];
+            self.detector_delay_pos = (self.detector_delay_pos + 1) % TRUE_PEAK_DELAY;
+            (delayed, peak)
+        } else {
+            ([left, right], left.abs().max(right.abs()))
+        };
+
+        // Envelope jumps to the window peak and releases by the mode
+        let peak = self.window_peak(input_peak);
+        let level = self.follow(peak);
+
+        // Calculate gain reduction
+        let target_gain = if level > self.threshold {
+            self.threshold / level
+        } else {
+            1.0
+        };
 
-        let eq_r = self.eq_low.process(right);
-        let eq_r = self.eq_mid.process(eq_r);
-        let eq_r = self.eq_high.process(eq_r);
+        // Average the target over the window so gain ramps instead of jumping;
+        // every target in the window already accounts for the sample leaving it
+        self.gain_sum += target_gain - self.gains[self.buffer_pos];
+        self.gains[self.buffer_pos] = target_gain;
+        let gain = (self.gain_sum / self.gains.len() as f64).min(1.0);
+        self.min_gain = self.min_gain.min(gain);
 
-        // Apply master volume
-        let vol_l = eq_l * self.master_volume;
-        let vol_r = eq_r * self.master_volume;
+        // Apply gain to the oldest buffered frame, then store the new one
+        let [out_l, out_r] = self.buffer[self.buffer_pos];
+        self.buffer[self.buffer_pos] = input;
 
-        // Apply limiter
-        let limited_l = self.limiter.process(vol_l);
-        let limited_r = self.limiter.process(vol_r);
+        self.buffer_pos = (self.buffer_pos + 1) % self.buffer.len();
 
-        // Apply soft clipper for warmth
-        let clipped_l = self.clipper.process(limited_l);
-        let clipped_r = self.clipper.process(limited_r);
+        // Re-sum once per cycle so rounding error can't accumulate
+        if self.buffer_pos == 0 {
+            self.gain_sum = self.gains.iter().sum();
+        }
 
-        (clipped_l as f32, clipped_r as f32)
+        (out_l * gain, out_r * gain)
     }
 
-    /// Update EQ band gains (in dB)
-    pub fn set_eq(&mut self, low_db: f64, mid_db: f64, high_db: f64) {
-        self.eq_low.update(low_db, self.sample_rate);
-        self.eq_mid.update(mid_db, self.sample_rate);
-        self.eq_high.update(high_db, self.sample_rate);
+    /// Advance the envelope to `peak` and return the level driving the gain
+    #[inline]
+    fn follow(&mut self, peak: f64) -> f64 {
+        let release = match self.release_mode {
+            LimiterRelease::Dual => self.release * DUAL_FAST_RELEASE,
+            _ => self.release,
+        };
+        self.envelope = if peak > self.envelope {
+            peak
+        } else if self.release_mode == LimiterRelease::Linear {
+            (self.envelope - 1.0 / (release * self.sample_rate)).max(peak)
+        } else {
+            let coeff = one_pole(release, self.sample_rate);
+            flush_denormal(coeff * self.envelope + (1.0 - coeff) * peak)
+        };
+        if self.release_mode != LimiterRelease::Dual {
+            return self.envelope;
+        }
+
+        // The slow stage only charges toward the peak, so short transients
+        // barely move it
+        let time = if peak > self.slow_envelope {
+            DUAL_SLOW_ATTACK
+        } else {
+            DUAL_SLOW_RELEASE
+        };
+        let coeff = one_pole(self.release * time, self.sample_rate);
+        self.slow_envelope = flush_denormal(coeff * self.slow_envelope + (1.0 - coeff) * peak);
+        self.envelope.max(self.slow_envelope)
     }
 
-    /// Update limiter threshold
-    pub fn set_limiter_threshold(&mut self, threshold: f64) {
-        self.limiter.threshold = threshold.clamp(0.0, 1.0);
+    /// Deepest gain reduction since the last reset, in dB (0.0 = fully open)
+    pub fn current_gain_reduction_db(&self) -> f64 {
+        20.0 * (1.0 / self.min_gain.max(1e-6)).log10()
     }
 
-    /// Update soft clipper amount
-    pub fn set_clip_amount(&mut self, amount: f64) {
-        self.clipper.amount = amount.clamp(0.0, 10.0);
+    /// Start a new gain-reduction hold period (called once per buffer)
+    pub fn reset_gain_reduction(&mut self) {
+        self.min_gain = 1.0;
     }
 }
 
-// ============================================================
-// TESTS
-// ============================================================
+/// Compressor setting ranges (master, track and bus compressors alike);
+/// thresholds run up to 0 dB and makeup from 0 dB
+pub const MIN_COMP_THRESHOLD_DB: f64 = -60.0;
+pub const MAX_COMP_RATIO: f64 = 20.0;
+pub const MIN_COMP_ATTACK_MS: f64 = 0.1;
+pub const MAX_COMP_ATTACK_MS: f64 = 200.0;
+pub const MIN_COMP_RELEASE_MS: f64 = 10.0;
+pub const MAX_COMP_RELEASE_MS: f64 = 2000.0;
+pub const MAX_COMP_MAKEUP_DB: f64 = 24.0;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Widest compressor knee, dB across (centered on the threshold)
+pub const MAX_COMP_KNEE_DB: f64 = 24.0;
+
+/// Stereo-linked master bus compressor
+///
+/// One envelope follows the louder of |L| and |R| with separate attack and
+/// release times, so both channels get the same gain and the image stays put.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    pub threshold: f64, // dB
+    pub ratio: f64,     // n:1
+    pub makeup: f64,    // dB
+    // Width of the soft knee around the threshold; 0 is a hard knee
+    pub knee: f64, // dB
+    attack_coeff: f64,
+    release_coeff: f64,
+    envelope: f64,
+    sample_rate: f64,
+}
+
+impl Latency for Compressor {}
+
+impl Compressor {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut comp = Self {
+            threshold: 0.0,
+            ratio: 1.0,
+            makeup: 0.0,
+            knee: 0.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 0.0,
+            sample_rate,
+        };
+        comp.set_attack_ms(10.0);
+        comp.set_release_ms(100.0);
+        comp
+    }
+
+    fn time_coeff(&self, ms: f64) -> f64 {
+        (-1.0 / (ms.max(0.01) / 1000.0 * self.sample_rate)).exp()
+    }
+
+    pub fn set_attack_ms(&mut self, ms: f64) {
+        self.attack_coeff = self.time_coeff(ms);
+    }
+
+    pub fn set_release_ms(&mut self, ms: f64) {
+        self.release_coeff = self.time_coeff(ms);
+    }
+
+    /// Threshold/makeup in dB, times in ms, each clamped to its range
+    pub fn set(
+        &mut self,
+        threshold_db: f64,
+        ratio: f64,
+        attack_ms: f64,
+        release_ms: f64,
+        makeup_db: f64,
+    ) {
+        self.threshold = threshold_db.clamp(MIN_COMP_THRESHOLD_DB, 0.0);
+        self.ratio = ratio.clamp(1.0, MAX_COMP_RATIO);
+        self.set_attack_ms(attack_ms.clamp(MIN_COMP_ATTACK_MS, MAX_COMP_ATTACK_MS));
+        self.set_release_ms(release_ms.clamp(MIN_COMP_RELEASE_MS, MAX_COMP_RELEASE_MS));
+        self.makeup = makeup_db.clamp(0.0, MAX_COMP_MAKEUP_DB);
+    }
+
+    pub fn set_knee(&mut self, knee_db: f64) {
+        self.knee = knee_db.clamp(0.0, MAX_COMP_KNEE_DB);
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        self.process_keyed(left, right, left.abs().max(right.abs()))
+    }
+
+    /// Gain computer: dB of reduction for a level `env_db`. Inside the knee
+    /// the ratio eases in along a quadratic, from none at its lower edge to
+    /// the full slope at its upper one.
+    #[inline]
+    fn reduction_db(&self, env_db: f64) -> f64 {
+        let over = env_db - self.threshold;
+        let slope = 1.0 - 1.0 / self.ratio;
+        let half_knee = self.knee * 0.5;
+        if over <= -half_knee {
+            0.0
+        } else if over < half_knee {
+            slope * (over + half_knee).powi(2) / (2.0 * self.knee)
+        } else {
+            slope * over
+        }
+    }
+
+    /// The gain computer's static curve over `COMP_CURVE_RANGE_DB`, as it
+    /// acts on a steady level (attack and release don't enter into it)
+    pub fn transfer_curve(&self, points: usize) -> Vec<TransferPoint> {
+        let (from, to) = COMP_CURVE_RANGE_DB;
+        sample_curve(points, from, to, |db| db - self.reduction_db(db) + self.makeup)
+    }
+
+    /// Compress by the envelope of `level` rather than the signal's own
+    #[inline]
+    pub fn process_keyed(&mut self, left: f64, right: f64, level: f64) -> (f64, f64) {
+        let coeff = if level > self.envelope {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.envelope = coeff * self.envelope + (1.0 - coeff) * level;
+
+        let env_db = 20.0 * self.envelope.max(1e-9).log10();
+        let gain = 10f64.powf((self.makeup - self.reduction_db(env_db)) / 20.0);
+
+        (left * gain, right * gain)
+    }
+}
+
+/// Sub-buses tracks can be routed to instead of straight to the master
+pub const NUM_SUB_BUSES: usize = 4;
+
+/// A group of tracks summed and processed together (EQ, compressor, then
+/// fader) before joining the master
+#[derive(Clone, Debug)]
+pub struct SubBus {
+    eq: [[EqBand; 3]; 2],
+    compressor: Compressor,
+    volume: SmoothedParam,
+    // Silences the members' sends as well as their dry signal
+    muted: bool,
+}
+
+impl Latency for SubBus {
+    fn report_latency(&self) -> usize {
+        let eq: usize = self.eq[0].iter().map(Latency::report_latency).sum();
+        eq + self.compressor.report_latency()
+    }
+}
+
+impl SubBus {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            eq: [Mixer::master_eq(sample_rate), Mixer::master_eq(sample_rate)],
+            compressor: Compressor::new(sample_rate),
+            volume: SmoothedParam::new(1.0, sample_rate),
+            muted: false,
+        }
+    }
+
+    /// Fader gain for this frame; a muted bus still advances the ramp
+    #[inline]
+    fn gain(&mut self) -> f64 {
+        let volume = self.volume.next();
+        if self.muted {
+            0.0
+        } else {
+            volume
+        }
+    }
+
+    /// The members' summed signal, processed and scaled by `gain`
+    #[inline]
+    fn process(&mut self, left: f64, right: f64, gain: f64) -> (f64, f64) {
+        let [eq_l, eq_r] = &mut self.eq;
+        let left = eq_l.iter_mut().fold(left, |x, band| band.process(x));
+        let right = eq_r.iter_mut().fold(right, |x, band| band.process(x));
+        let (left, right) = self.compressor.process(left, right);
+        (left * gain, right * gain)
+    }
+}
+
+/// One-pole DC blocking high-pass: `y[n] = x[n] - x[n-1] + r * y[n-1]`
+#[derive(Clone, Debug)]
+pub struct DcBlocker {
+    r: f64,
+    x1: f64,
+    y1: f64,
+}
+
+/// Corner frequency of the DC blocker
+const DC_BLOCK_HZ: f64 = 10.0;
+
+impl DcBlocker {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            r: (-2.0 * PI * DC_BLOCK_HZ / sample_rate).exp(),
+            x1: 0.0,
+            y1: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = input - self.x1 + self.r * self.y1;
+        self.x1 = input;
+        self.y1 = output;
+        output
+    }
+}
+
+/// Crossfeed low-pass corner and interaural delay
+const CROSSFEED_CUTOFF_HZ: f64 = 700.0;
+const CROSSFEED_DELAY_MS: f64 = 0.3;
+
+/// Monitor attenuation while dim is on
+pub const MONITOR_DIM_DB: f64 = -20.0;
+
+/// Listening controls between the finished mix and the device: dim and a
+/// mono fold-down. Owned by the live renderer only, so meters, exports and
+/// freezes never hear them.
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    gain: SmoothedParam,
+    // 0.0 = stereo, 1.0 = both sides carry (L + R) / 2
+    mono: SmoothedParam,
+}
+
+impl Monitor {
+    pub fn new(sample_rate: f64, dim: bool, mono: bool) -> Self {
+        Self {
+            gain: SmoothedParam::new(Self::dim_gain(dim), sample_rate),
+            mono: SmoothedParam::new(if mono { 1.0 } else { 0.0 }, sample_rate),
+        }
+    }
+
+    fn dim_gain(dim: bool) -> f64 {
+        if dim {
+            10f64.powf(MONITOR_DIM_DB / 20.0)
+        } else {
+            1.0
+        }
+    }
+
+    pub fn set_dim(&mut self, on: bool) {
+        self.gain.set_target(Self::dim_gain(on));
+    }
+
+    pub fn set_mono(&mut self, on: bool) {
+        self.mono.set_target(if on { 1.0 } else { 0.0 });
+    }
+
+    pub fn dim(&self) -> bool {
+        self.gain.target() < 1.0
+    }
+
+    pub fn mono(&self) -> bool {
+        self.mono.target() > 0.0
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let (left, right) = (left as f64, right as f64);
+        let mono = self.mono.next();
+        let mid = (left + right) * 0.5;
+        let gain = self.gain.next();
+        let left = (left + (mid - left) * mono) * gain;
+        let right = (right + (mid - right) * mono) * gain;
+        (left as f32, right as f32)
+    }
+}
+
+/// Bauer-style headphone crossfeed: each channel gets a low-passed, slightly
+/// delayed copy of the other, as a speaker pair would deliver it
+#[derive(Clone, Debug)]
+pub struct Crossfeed {
+    amount: f64, // 0.0 = off
+    coeff: f64,
+    lowpassed: [f64; 2],
+    // Ring of low-passed (L, R) pairs, `CROSSFEED_DELAY_MS` long
+    delay: Vec<(f64, f64)>,
+    pos: usize,
+}
+
+impl Crossfeed {
+    pub fn new(sample_rate: f64) -> Self {
+        let delay_samples = ((CROSSFEED_DELAY_MS * 0.001 * sample_rate) as usize).max(1);
+        Self {
+            amount: 0.0,
+            coeff: 1.0 - (-2.0 * PI * CROSSFEED_CUTOFF_HZ / sample_rate).exp(),
+            lowpassed: [0.0; 2],
+            delay: vec![(0.0, 0.0); delay_samples],
+            pos: 0,
+        }
+    }
+
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Filters keep running while off, so turning it on doesn't start from
+    /// stale state
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        for (state, input) in self.lowpassed.iter_mut().zip([left, right]) {
+            *state = flush_denormal(*state + self.coeff * (input - *state));
+        }
+        let (from_l, from_r) = self.delay[self.pos];
+        self.delay[self.pos] = (self.lowpassed[0], self.lowpassed[1]);
+        self.pos = (self.pos + 1) % self.delay.len();
+
+        if self.amount == 0.0 {
+            return (left, right);
+        }
+        // Normalized so centered lows keep their level
+        let norm = 1.0 / (1.0 + self.amount);
+        (
+            (left + self.amount * from_r) * norm,
+            (right + self.amount * from_l) * norm,
+        )
+    }
+}
+
+/// Saturation curve the soft clipper applies above its threshold. Every
+/// mode passes the signal unchanged up to the threshold and leaves it there
+/// with unity slope, so they only differ in how the excess is squashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipMode {
+    /// `excess / (1 + excess * amount)`; gentle, keeps rising until the
+    /// 1.0 ceiling
+    #[default]
+    Rational,
+    /// Smooth tanh saturation, flattening quickly onto the ceiling
+    Tanh,
+    /// Arctangent saturation; softer than tanh, approaching the ceiling slowly
+    ArcTan,
+    /// Linear up to the ceiling, then flat
+    HardClip,
+}
+
+/// Most points `get_transfer_curve` samples, and most frequencies
+/// `get_eq_response` evaluates
+pub const MAX_TRANSFER_POINTS: usize = 1024;
+
+/// Input span of the clipper curve, ± linear amplitude (about +6 dBFS)
+const CLIP_CURVE_RANGE: f64 = 2.0;
+
+/// Input span of the compressor curve, the threshold's range in dBFS
+const COMP_CURVE_RANGE_DB: (f64, f64) = (-60.0, 0.0);
+
+/// A static nonlinearity the UI can draw with `get_transfer_curve`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferEffect {
+    /// Sample in to sample out, linear, from -2.0 to 2.0
+    Clipper,
+    /// Steady level in to level out, dBFS, from -60 to 0 (makeup included)
+    Compressor,
+}
+
+/// One point on a transfer curve
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TransferPoint {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// `points` inputs evenly spread over `from..=to`, each mapped by `f`
+fn sample_curve(points: usize, from: f64, to: f64, f: impl Fn(f64) -> f64) -> Vec<TransferPoint> {
+    let last = (points.max(2) - 1) as f64;
+    (0..points.max(2))
+        .map(|i| {
+            let input = from + (to - from) * i as f64 / last;
+            TransferPoint { input, output: f(input) }
+        })
+        .collect()
+}
+
+/// Clipper drive and makeup (dB) ranges
+pub const MAX_CLIP_AMOUNT: f64 = 10.0;
+pub const MAX_CLIP_MAKEUP_DB: f64 = 12.0;
+
+/// Soft Clipper for warm saturation
+#[derive(Clone, Debug)]
+pub struct SoftClipper {
+    pub threshold: f64,
+    pub amount: f64,
+    pub mode: ClipMode,
+    pub bypass: bool,
+    pub makeup: f64, // linear gain applied after clipping
+}
+
+impl SoftClipper {
+    pub fn new(threshold: f64, amount: f64) -> Self {
+        Self {
+            threshold,
+            amount,
+            mode: ClipMode::default(),
+            bypass: false,
+            makeup: 1.0,
+        }
+    }
+
+    /// The master clipper's defaults
+    pub fn master() -> Self {
+        Self::new(0.8, 2.0)
+    }
+
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(0.0, MAX_CLIP_AMOUNT);
+    }
+
+    pub fn set_makeup_db(&mut self, makeup_db: f64) {
+        self.makeup = 10f64.powf(makeup_db.clamp(0.0, MAX_CLIP_MAKEUP_DB) / 20.0);
+    }
+
+    /// `process` over ±`CLIP_CURVE_RANGE`
+    pub fn transfer_curve(&self, points: usize) -> Vec<TransferPoint> {
+        sample_curve(points, -CLIP_CURVE_RANGE, CLIP_CURVE_RANGE, |x| self.process(x))
+    }
+
+    /// Output level the bounded curves settle at: `1 / amount` above the
+    /// threshold (where the rational curve levels off), but never past 1.0
+    #[inline]
+    fn ceiling(&self) -> f64 {
+        let headroom = 1.0 - self.threshold;
+        self.threshold + (1.0 / self.amount).min(headroom).max(1e-9)
+    }
+
+    #[inline]
+    pub fn process(&self, input: f64) -> f64 {
+        if self.bypass {
+            return input;
+        }
+
+        let abs_input = input.abs();
+        let clipped = if abs_input < self.threshold {
+            input
+        } else {
+            let sign = input.signum();
+            let excess = abs_input - self.threshold;
+            let span = self.ceiling() - self.threshold;
+            let clipped = match self.mode {
+                ClipMode::Rational => self.threshold + excess / (1.0 + excess * self.amount),
+                ClipMode::Tanh => self.threshold + span * (excess / span).tanh(),
+                ClipMode::ArcTan => {
+                    self.threshold + span * FRAC_2_PI * (excess * FRAC_PI_2 / span).atan()
+                }
+                ClipMode::HardClip => abs_input.min(self.ceiling()),
+            };
+            sign * clipped.min(1.0)
+        };
+        clipped * self.makeup
+    }
+}
+
+/// A reorderable step of the master chain. The DC blocker and the output
+/// ceiling always come last, after whichever stages run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MasterStage {
+    Eq,
+    Compressor,
+    Chorus,
+    Delay,
+    Reverb,
+    /// Master volume, with any auto-gain correction
+    Volume,
+    /// Stereo width and mono
+    Width,
+    Crossfeed,
+    Limiter,
+    /// The soft clipper, oversampled if enabled
+    Clipper,
+}
+
+/// Stages in a master chain; every order lists each one once
+pub const MASTER_STAGES: usize = 10;
+
+pub const DEFAULT_MASTER_CHAIN: [MasterStage; MASTER_STAGES] = [
+    MasterStage::Eq,
+    MasterStage::Compressor,
+    MasterStage::Chorus,
+    MasterStage::Delay,
+    MasterStage::Reverb,
+    MasterStage::Volume,
+    MasterStage::Width,
+    MasterStage::Crossfeed,
+    MasterStage::Limiter,
+    MasterStage::Clipper,
+];
+
+/// Widest master stereo width (1.0 leaves the image unchanged)
+pub const MAX_STEREO_WIDTH: f64 = 2.0;
+
+/// Lowest output ceiling, dBFS
+pub const MIN_OUTPUT_CEILING_DB: f64 = -24.0;
+
+/// Multi-Channel Mixer with Master Effects
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    // EQ Bands (Low, Mid, High): one set per channel, L / R or, in M/S mode,
+    // mid / side
+    eq: [[EqBand; 3]; 2],
+    eq_gains: [f64; 3],      // dB; both channels, or mid in M/S mode
+    side_eq_gains: [f64; 3], // dB; side in M/S mode
+    eq_ms: bool,
+
+    // Per-track channel strips (indexed like the mixed channels)
+    strips: Vec<ChannelStrip>,
+    buses: [SubBus; NUM_SUB_BUSES],
+
+    // Master Effects
+    compressor: Compressor,
+    chorus: Chorus,
+    delay: Delay,
+    reverb: Reverb,
+    limiter: Limiter,
+    clipper: SoftClipper,
+    oversamplers: [Oversampler; 2], // around the clipper, one per channel
+    dc_blockers: [DcBlocker; 2],
+    crossfeed: Crossfeed,
+
+    // Post-fader track levels and final output levels (L, R)
+    track_meters: Vec<LevelMeter>,
+    master_meters: [LevelMeter; 2],
+    correlation: CorrelationMeter,
+    loudness: LoudnessMeter,
+    // Tracks whose strip output and master channels that went over full
+    // scale since the last `take_overs`
+    track_overs: [bool; MAX_TRACKS],
+    master_overs: [bool; 2],
+    // Steers master volume from the loudness reading
+    autogain: AutoGain,
+
+    // Settings
+    master_volume: SmoothedParam,
+    stereo_width: f64, // 0.0 = mono, 1.0 = unchanged, 2.0 = wide
+    mono: bool,
+    pan_law: PanLaw,
+    output_ceiling: f64, // linear
+    dc_block: bool,
+    chain: [MasterStage; MASTER_STAGES],
+    sample_rate: f64,
+
+    // Track whose pre-fader signal is kept for `captured` (freezing)
+    capture: Option<usize>,
+    captured: (f64, Option<f64>),
+}
+
+impl Mixer {
+    pub fn new(sample_rate: f64, num_tracks: usize) -> Self {
+        Self {
+            strips: Self::per_track(ChannelStrip::new(sample_rate), num_tracks),
+            buses: std::array::from_fn(|_| SubBus::new(sample_rate)),
+            eq: [Self::master_eq(sample_rate), Self::master_eq(sample_rate)],
+            eq_gains: [0.0; 3],
+            side_eq_gains: [0.0; 3],
+            eq_ms: false,
+            compressor: Compressor::new(sample_rate),
+            chorus: Chorus::new(sample_rate),
+            delay: Delay::new(sample_rate),
+            reverb: Reverb::new(sample_rate),
+            limiter: Limiter::new(sample_rate, 0.95, 0.1),
+            clipper: SoftClipper::master(),
+            oversamplers: [Oversampler::new(), Oversampler::new()],
+            dc_blockers: [DcBlocker::new(sample_rate), DcBlocker::new(sample_rate)],
+            crossfeed: Crossfeed::new(sample_rate),
+            track_meters: Self::per_track(LevelMeter::new(sample_rate), num_tracks),
+            master_meters: [LevelMeter::new(sample_rate), LevelMeter::new(sample_rate)],
+            correlation: CorrelationMeter::new(sample_rate),
+            loudness: LoudnessMeter::new(sample_rate),
+            track_overs: [false; MAX_TRACKS],
+            master_overs: [false; 2],
+            autogain: AutoGain::new(sample_rate),
+            master_volume: SmoothedParam::new(0.8, sample_rate),
+            stereo_width: 1.0,
+            mono: false,
+            pan_law: PanLaw::default(),
+            output_ceiling: 1.0,
+            dc_block: true,
+            chain: DEFAULT_MASTER_CHAIN,
+            sample_rate,
+            capture: None,
+            captured: (0.0, None),
+        }
+    }
+
+    /// The master EQ's combined response in dB at each of `frequencies`,
+    /// with band gains `gains_db` (low, mid, high)
+    pub fn eq_response(gains_db: [f64; 3], frequencies: &[f64], sample_rate: f64) -> Vec<f64> {
+        let mut bands = Self::master_eq(sample_rate);
+        for (band, gain) in bands.iter_mut().zip(gains_db) {
+            band.update(gain, sample_rate);
+        }
+        frequencies
+            .iter()
+            .map(|&hz| bands.iter().map(|band| band.magnitude_db(hz, sample_rate)).sum())
+            .collect()
+    }
+
+    /// 100Hz low shelf, 1kHz peak, 8kHz high shelf
+    fn master_eq(sample_rate: f64) -> [EqBand; 3] {
+        [
+            EqBand::with_kind(FilterKind::LowShelf, 100.0, 0.0, 0.7, sample_rate),
+            EqBand::new(1000.0, 0.0, 1.0, sample_rate),
+            EqBand::with_kind(FilterKind::HighShelf, 8000.0, 0.0, 0.7, sample_rate),
+        ]
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.strips.len()
+    }
+
+    // Track vectors keep room for `MAX_TRACKS` so adding one never reallocates
+    fn per_track<T: Clone>(value: T, num_tracks: usize) -> Vec<T> {
+        let mut v = Vec::with_capacity(MAX_TRACKS);
+        v.resize(num_tracks, value);
+        v
+    }
+
+    /// Append a channel strip and meter for a new track
+    pub fn add_track(&mut self) {
+        self.strips.push(ChannelStrip::new(self.sample_rate));
+        self.track_meters.push(LevelMeter::new(self.sample_rate));
+        self.align_latency();
+    }
+
+    pub fn remove_track(&mut self, track: usize) {
+        if track < self.strips.len() {
+            self.strips.remove(track);
+            self.track_meters.remove(track);
+            for strip in &mut self.strips {
+                strip.sidechain = index_after_removal(strip.sidechain, track);
+            }
+            self.align_latency();
+        }
+    }
+
+    /// Latency compensation: delay every track by the difference between
+    /// the slowest track's path (strip plus sub-bus) and its own. Call after
+    /// anything that can change a track's or bus's reported latency.
+    fn align_latency(&mut self) {
+        let mut latencies = [0; MAX_TRACKS];
+        for (latency, strip) in latencies.iter_mut().zip(&self.strips) {
+            let bus = strip.bus.map_or(0, |b| self.buses[b].report_latency());
+            *latency = strip.report_latency() + bus;
+        }
+        let slowest = latencies.iter().copied().max().unwrap_or(0);
+        for (strip, latency) in self.strips.iter_mut().zip(latencies) {
+            strip.compensation.set_delay(slowest - latency);
+        }
+    }
+
+    /// Mix multiple channels through their channel strips with pan and
+    /// volume, then through their sub-buses
+    #[inline]
+    pub fn mix_channels(&mut self, channels: &[TrackInput], any_soloed: bool) -> MixBus {
+        let mut bus = MixBus::default();
+        let mut bus_inputs = [(0.0, 0.0); NUM_SUB_BUSES];
+        let mut bus_gains = [0.0; NUM_SUB_BUSES];
+        for (gain, sub_bus) in bus_gains.iter_mut().zip(&mut self.buses) {
+            *gain = sub_bus.gain();
+        }
+
+        // Sidechain keys are the sources' trimmed input levels, read before
+        // any track is processed (and whether or not the source is muted)
+        let mut key_levels = [0.0; MAX_TRACKS];
+        for ((level, input), strip) in key_levels.iter_mut().zip(channels).zip(&self.strips) {
+            *level = input.left.abs().max(input.right.map_or(0.0, f64::abs)) * strip.trim;
+        }
+
+        let mut listen = (0.0, 0.0);
+        let tracks = channels.iter().zip(&mut self.strips).zip(&mut self.track_meters);
+        for (index, ((input, strip), meter)) in tracks.enumerate() {
+            strip.volume.set_target(input.volume);
+            strip.pan.set_target(input.pan);
+            let pan = strip.pan.next();
+
+            // Muted tracks (or non-soloed, non-solo-safe ones if any track
+            // is soloed) fade out, then are skipped
+            let silenced = input.muted || (any_soloed && !input.soloed && !strip.solo_safe);
+            strip.audible.set_target(if silenced { 0.0 } else { 1.0 });
+            let volume = strip.volume.next() * strip.audible.next();
+            if volume == 0.0 && silenced {
+                strip.compensation.process(0.0, 0.0);
+                meter.process(0.0);
+                continue;
+            }
+
+            // Apply polarity and track EQ, then volume, then pan or balance
+            // (by default pan for mono, balance for stereo)
+            let polarity = if strip.polarity_inverted { -1.0 } else { 1.0 };
+            let key = strip.sidechain.and_then(|source| key_levels.get(source).copied());
+            let (left, right) = match input.right {
+                Some(right) => {
+                    let (l, r) = if strip.frozen {
+                        (input.left, right)
+                    } else {
+                        strip.process_stereo(input.left * polarity, right * polarity, key)
+                    };
+                    if self.capture == Some(index) {
+                        self.captured = (l, Some(r));
+                    }
+                    self.track_overs[index] |= l.abs() > 1.0 || r.abs() > 1.0;
+                    let (l, r) = strip.compensation.process(l, r);
+                    if input.listen == Listen::Pfl {
+                        listen.0 += l;
+                        listen.1 += r;
+                    }
+                    let (l, r) = (l * volume, r * volume);
+                    // The louder side drives the track meter
+                    meter.process(if l.abs() >= r.abs() { l } else { r });
+                    if strip.pan_mode == PanMode::Pan {
+                        stereo_pan(l, r, pan)
+                    } else {
+                        let (left_gain, right_gain) = balance_gains(pan);
+                        (l * left_gain, r * right_gain)
+                    }
+                }
+                None => {
+                    let dry = if strip.frozen {
+                        input.left
+                    } else {
+                        strip.process(input.left * polarity, key)
+                    };
+                    if self.capture == Some(index) {
+                        self.captured = (dry, None);
+                    }
+                    self.track_overs[index] |= dry.abs() > 1.0;
+                    let dry = strip.compensation.process(dry, 0.0).0;
+                    if input.listen == Listen::Pfl {
+                        listen.0 += dry;
+                        listen.1 += dry;
+                    }
+                    let vol_sample = dry * volume;
+                    meter.process(vol_sample);
+                    let (left_gain, right_gain) = if strip.pan_mode == PanMode::Balance {
+                        balance_gains(pan)
+                    } else {
+                        self.pan_law.gains(pan)
+                    };
+                    (vol_sample * left_gain, vol_sample * right_gain)
+                }
+            };
+            if input.listen == Listen::Afl {
+                listen.0 += left;
+                listen.1 += right;
+            }
+
+            // Sends leave from the track, but follow its bus fader
+            let send_gain = match strip.bus {
+                Some(b) => {
+                    bus_inputs[b].0 += left;
+                    bus_inputs[b].1 += right;
+                    bus_gains[b]
+                }
+                None => {
+                    bus.dry.0 += left;
+                    bus.dry.1 += right;
+                    1.0
+                }
+            };
+            let (left, right) = (left * send_gain, right * send_gain);
+            bus.delay_send.0 += left * strip.send_delay;
+            bus.delay_send.1 += right * strip.send_delay;
+            bus.reverb_send.0 += left * strip.send_reverb;
+            bus.reverb_send.1 += right * strip.send_reverb;
+        }
+
+        // Buses keep processing while empty, so their filters ring out
+        let buses = self.buses.iter_mut().zip(bus_inputs).zip(bus_gains);
+        for ((sub_bus, (left, right)), gain) in buses {
+            let (left, right) = sub_bus.process(left, right, gain);
+            bus.dry.0 += left;
+            bus.dry.1 += right;
+        }
+
+        let listening = channels.iter().any(|input| input.listen != Listen::Off);
+        bus.listen = listening.then_some(listen);
+        bus
+    }
+
+    /// Process master bus with EQ, Limiter, Soft Clip. Send buses join the
+    /// dry signal at the delay and reverb inputs.
+    #[inline]
+    pub fn process_master(&mut self, bus: MixBus) -> (f32, f32) {
+        // The delay and reverb take their sends whenever they run
+        self.delay.add_send(bus.delay_send.0, bus.delay_send.1);
+        self.reverb.add_send(bus.reverb_send.0, bus.reverb_send.1);
+        let chain = self.chain;
+        let mut frame = bus.dry;
+        for stage in chain {
+            frame = self.process_stage(stage, frame.0, frame.1);
+        }
+        let (clipped_l, clipped_r) = frame;
+
+        // Remove DC from the clipper's asymmetric shaping. This is the last
+        // filter in the chain; only the ceiling follows, so it stays a hard
+        // guarantee. The blockers keep running while disabled so toggling
+        // them back on doesn't start from stale state.
+        let blocked_l = self.dc_blockers[0].process(clipped_l);
+        let blocked_r = self.dc_blockers[1].process(clipped_r);
+        let (clipped_l, clipped_r) = if self.dc_block {
+            (blocked_l, blocked_r)
+        } else {
+            (clipped_l, clipped_r)
+        };
+
+        // Map full scale onto the output ceiling, then hard-stop anything
+        // still above it (clipper bypassed or makeup gain). That is an over
+        // for the clip indicators.
+        self.master_overs[0] |= clipped_l.abs() > 1.0;
+        self.master_overs[1] |= clipped_r.abs() > 1.0;
+        let ceiling = self.output_ceiling;
+        let out_l = (clipped_l * ceiling).clamp(-ceiling, ceiling);
+        let out_r = (clipped_r * ceiling).clamp(-ceiling, ceiling);
+
+        self.master_meters[0].process(out_l);
+        self.master_meters[1].process(out_r);
+        self.correlation.process(out_l, out_r);
+        self.loudness.process(out_l, out_r);
+
+        (out_l as f32, out_r as f32)
+    }
+
+    /// One master stage on one frame
+    #[inline]
+    fn process_stage(&mut self, stage: MasterStage, left: f64, right: f64) -> (f64, f64) {
+        match stage {
+            MasterStage::Eq => self.process_eq(left, right),
+            MasterStage::Compressor => self.compressor.process(left, right),
+            MasterStage::Chorus => self.chorus.process(left, right),
+            // Tempo-synced, with the sends added in `process_master`
+            MasterStage::Delay => self.delay.process(left, right),
+            // Mixes in the reverb's parallel wet path
+            MasterStage::Reverb => self.reverb.process(left, right),
+            MasterStage::Volume => {
+                let gain = self.master_volume.next() * self.autogain.process(&self.loudness);
+                (left * gain, right * gain)
+            }
+            MasterStage::Width => {
+                let mid = (left + right) * 0.5;
+                let side = if self.mono {
+                    0.0
+                } else {
+                    (left - right) * 0.5 * self.stereo_width
+                };
+                (mid + side, mid - side)
+            }
+            MasterStage::Crossfeed => self.crossfeed.process(left, right),
+            MasterStage::Limiter => self.limiter.process_stereo(left, right),
+            MasterStage::Clipper => {
+                let clipper = &self.clipper;
+                (
+                    self.oversamplers[0].process(left, |x| clipper.process(x)),
+                    self.oversamplers[1].process(right, |x| clipper.process(x)),
+                )
+            }
+        }
+    }
+
+    /// Master EQ, on L / R or on mid / side
+    #[inline]
+    fn process_eq(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let (a, b) = if self.eq_ms {
+            ((left + right) * 0.5, (left - right) * 0.5)
+        } else {
+            (left, right)
+        };
+        let [eq_a, eq_b] = &mut self.eq;
+        let a = eq_a.iter_mut().fold(a, |x, band| band.process(x));
+        let b = eq_b.iter_mut().fold(b, |x, band| band.process(x));
+        if self.eq_ms {
+            (a + b, a - b)
+        } else {
+            (a, b)
+        }
+    }
+
+    pub fn track_meters(&self) -> &[LevelMeter] {
+        &self.track_meters
+    }
+
+    pub fn master_meters(&self) -> &[LevelMeter; 2] {
+        &self.master_meters
+    }
+
+    /// Master L/R phase correlation, -1.0..=1.0
+    pub fn correlation(&self) -> f64 {
+        self.correlation.correlation()
+    }
+
+    pub fn loudness(&self) -> Loudness {
+        self.loudness.loudness()
+    }
+
+    /// Restart the integrated loudness measurement
+    pub fn reset_loudness(&mut self) {
+        self.loudness.reset();
+    }
+
+    pub fn set_autogain(&mut self, on: bool) {
+        self.autogain.enabled = on;
+    }
+
+    /// Short-term loudness auto-gain aims for, in LUFS
+    pub fn set_autogain_target(&mut self, lufs: f64) {
+        self.autogain.set_target(lufs);
+    }
+
+    /// Auto-gain only adjusts while the transport runs
+    pub fn set_autogain_running(&mut self, running: bool) {
+        self.autogain.running = running;
+    }
+
+    /// Which tracks (pre-fader) and master channels went over full scale
+    /// since the last call
+    pub fn take_overs(&mut self) -> ([bool; MAX_TRACKS], [bool; 2]) {
+        (std::mem::take(&mut self.track_overs), std::mem::take(&mut self.master_overs))
+    }
+
+    /// Peak limiter gain reduction (dB) since the last call; resets the hold
+    pub fn take_limiter_gain_reduction_db(&mut self) -> f64 {
+        let reduction = self.limiter.current_gain_reduction_db();
+        self.limiter.reset_gain_reduction();
+        reduction
+    }
+
+    /// Master volume target (0.0 to 1.0)
+    pub fn master_volume(&self) -> f64 {
+        self.master_volume.target()
+    }
+
+    /// Ramp the master volume to `volume`
+    pub fn set_master_volume(&mut self, volume: f64) {
+        self.master_volume.set_target(volume.clamp(0.0, 1.0));
+    }
+
+    /// Update EQ band gains (in dB); these set the mid in M/S mode
+    pub fn set_eq(&mut self, low_db: f64, mid_db: f64, high_db: f64) {
+        self.eq_gains = [low_db, mid_db, high_db];
+        self.update_eq();
+    }
+
+    /// Side EQ band gains (in dB), used in M/S mode
+    pub fn set_side_eq(&mut self, low_db: f64, mid_db: f64, high_db: f64) {
+        self.side_eq_gains = [low_db, mid_db, high_db];
+        self.update_eq();
+    }
+
+    /// EQ mid and side separately instead of both channels alike
+    pub fn set_eq_ms_mode(&mut self, on: bool) {
+        self.eq_ms = on;
+        self.update_eq();
+    }
+
+    fn update_eq(&mut self) {
+        let second = if self.eq_ms { self.side_eq_gains } else { self.eq_gains };
+        for (bands, gains) in self.eq.iter_mut().zip([self.eq_gains, second]) {
+            for (band, gain) in bands.iter_mut().zip(gains) {
+                band.update(gain, self.sample_rate);
+            }
+        }
+    }
+
+    /// Update a track's EQ band gains (in dB)
+    pub fn set_track_eq(&mut self, track: usize, low_db: f64, mid_db: f64, high_db: f64) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.set_eq(low_db, mid_db, high_db, self.sample_rate);
+        }
+    }
+
+    pub fn set_track_bitcrush(&mut self, track: usize, bit_depth: u32, downsample: u32) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            for crusher in &mut strip.crusher {
+                crusher.set(bit_depth, downsample);
+            }
+        }
+    }
+
+    pub fn set_track_polarity(&mut self, track: usize, inverted: bool) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.polarity_inverted = inverted;
+        }
+    }
+
+    pub fn set_track_solo_safe(&mut self, track: usize, on: bool) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.solo_safe = on;
+        }
+    }
+
+    pub fn set_track_pan_mode(&mut self, track: usize, mode: PanMode) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.pan_mode = mode;
+        }
+    }
+
+    /// Bypass polarity and the strip for a track playing back its freeze
+    pub fn set_track_frozen(&mut self, track: usize, on: bool) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.frozen = on;
+        }
+    }
+
+    /// Keep `track`'s pre-fader signal (after its strip, before volume and
+    /// pan) each frame, for `captured`
+    pub fn set_capture(&mut self, track: Option<usize>) {
+        self.capture = track;
+        self.captured = (0.0, None);
+    }
+
+    /// The captured track's pre-fader frame from the last `mix_channels`
+    pub fn captured(&self) -> (f64, Option<f64>) {
+        self.captured
+    }
+
+    /// Route a track to a sub-bus, or to the master with `None` (or an
+    /// out-of-range bus)
+    pub fn set_track_bus(&mut self, track: usize, bus: Option<usize>) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.bus = bus.filter(|&b| b < NUM_SUB_BUSES);
+        }
+        self.align_latency();
+    }
+
+    /// Ramp a sub-bus fader to `volume` (0.0 to 1.0)
+    pub fn set_bus_volume(&mut self, bus: usize, volume: f64) {
+        if let Some(sub_bus) = self.buses.get_mut(bus) {
+            sub_bus.volume.set_target(volume.clamp(0.0, 1.0));
+        }
+    }
+
+    pub fn set_bus_muted(&mut self, bus: usize, muted: bool) {
+        if let Some(sub_bus) = self.buses.get_mut(bus) {
+            sub_bus.muted = muted;
+        }
+    }
+
+    /// Update a sub-bus's EQ band gains (in dB)
+    pub fn set_bus_eq(&mut self, bus: usize, low_db: f64, mid_db: f64, high_db: f64) {
+        if let Some(sub_bus) = self.buses.get_mut(bus) {
+            for bands in &mut sub_bus.eq {
+                for (band, gain) in bands.iter_mut().zip([low_db, mid_db, high_db]) {
+                    band.update(gain, self.sample_rate);
+                }
+            }
+        }
+    }
+
+    /// Same ranges as the master `set_compressor`
+    pub fn set_bus_compressor(
+        &mut self,
+        bus: usize,
+        threshold_db: f64,
+        ratio: f64,
+        attack_ms: f64,
+        release_ms: f64,
+        makeup_db: f64,
+    ) {
+        if let Some(sub_bus) = self.buses.get_mut(bus) {
+            sub_bus
+                .compressor
+                .set(threshold_db, ratio, attack_ms, release_ms, makeup_db);
+        }
+    }
+
+    /// Input trim in dB, clamped to +/-`MAX_TRIM_DB`
+    pub fn set_track_trim(&mut self, track: usize, trim_db: f64) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.trim = 10f64.powf(trim_db.clamp(-MAX_TRIM_DB, MAX_TRIM_DB) / 20.0);
+        }
+    }
+
+    /// Track compressor: threshold in dB, ratio n:1, times in ms
+    pub fn set_track_compressor(
+        &mut self,
+        track: usize,
+        threshold_db: f64,
+        ratio: f64,
+        attack_ms: f64,
+        release_ms: f64,
+    ) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.compressor.set(threshold_db, ratio, attack_ms, release_ms, 0.0);
+        }
+    }
+
+    /// Key the track's compressor from `source`'s level (`None` for its own)
+    pub fn set_track_sidechain(&mut self, track: usize, source: Option<usize>) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.sidechain = source;
+        }
+    }
+
+    /// Switch a track's high-pass and set its corner. Switching it on
+    /// starts the filter from silence; retuning while on keeps it running.
+    pub fn set_track_hpf(&mut self, track: usize, frequency_hz: f64, on: bool) {
+        let sample_rate = self.sample_rate;
+        if let Some(strip) = self.strips.get_mut(track) {
+            let frequency = frequency_hz.clamp(MIN_HPF_HZ, MAX_HPF_HZ);
+            for band in &mut strip.hpf {
+                if on && !strip.hpf_on {
+                    *band = ChannelStrip::track_hpf(sample_rate);
+                }
+                if band.frequency != frequency {
+                    band.retune(frequency, sample_rate);
+                }
+            }
+            strip.hpf_on = on;
+        }
+        self.align_latency();
+    }
+
+    /// Switch a track's all-pass and set its frequency, starting and
+    /// retuning it the way `set_track_hpf` does
+    pub fn set_track_allpass(&mut self, track: usize, frequency_hz: f64, on: bool) {
+        let sample_rate = self.sample_rate;
+        if let Some(strip) = self.strips.get_mut(track) {
+            // Kept clear of Nyquist at low device rates
+            let highest = MAX_ALLPASS_HZ.min(sample_rate * 0.45);
+            let frequency = frequency_hz.clamp(MIN_ALLPASS_HZ, highest);
+            for band in &mut strip.allpass {
+                if on && !strip.allpass_on {
+                    *band = ChannelStrip::track_allpass(sample_rate);
+                }
+                if band.frequency != frequency {
+                    band.retune(frequency, sample_rate);
+                }
+            }
+            strip.allpass_on = on;
+        }
+        self.align_latency();
+    }
+
+    /// Threshold in dB (`GATE_OFF_DB` or below = off); times in ms
+    pub fn set_track_gate(
+        &mut self,
+        track: usize,
+        threshold_db: f64,
+        attack_ms: f64,
+        hold_ms: f64,
+        release_ms: f64,
+    ) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip
+                .gate
+                .set(threshold_db, attack_ms, hold_ms, release_ms, self.sample_rate);
+        }
+    }
+
+    /// Gate lookahead in ms; the other tracks are held back to match
+    pub fn set_track_gate_lookahead(&mut self, track: usize, ms: f64) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.gate.set_lookahead(ms, self.sample_rate);
+        }
+        self.align_latency();
+    }
+
+    /// Post-fader send amounts (0.0 to 1.0) to the delay and reverb
+    pub fn set_track_sends(&mut self, track: usize, delay: f64, reverb: f64) {
+        if let Some(strip) = self.strips.get_mut(track) {
+            strip.send_delay = delay.clamp(0.0, 1.0);
+            strip.send_reverb = reverb.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Run the soft clipper at 1x (off), 2x or 4x the sample rate
+    pub fn set_oversampling(&mut self, factor: usize) {
+        for oversampler in &mut self.oversamplers {
+            oversampler.set_factor(factor);
+        }
+    }
+
+    pub fn set_clip_mode(&mut self, mode: ClipMode) {
+        self.clipper.mode = mode;
+    }
+
+    /// Update limiter threshold
+    pub fn set_limiter_threshold(&mut self, threshold: f64) {
+        self.limiter.threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    pub fn set_limiter_true_peak(&mut self, on: bool) {
+        self.limiter.set_true_peak(on);
+    }
+
+    pub fn set_limiter_release_mode(&mut self, mode: LimiterRelease) {
+        self.limiter.release_mode = mode;
+    }
+
+    /// Update soft clipper amount
+    pub fn set_clip_amount(&mut self, amount: f64) {
+        self.clipper.set_amount(amount);
+    }
+
+    /// Update compressor settings (threshold/makeup in dB, times in ms)
+    pub fn set_compressor(
+        &mut self,
+        threshold_db: f64,
+        ratio: f64,
+        attack_ms: f64,
+        release_ms: f64,
+        makeup_db: f64,
+    ) {
+        self.compressor
+            .set(threshold_db, ratio, attack_ms, release_ms, makeup_db);
+    }
+
+    pub fn set_comp_knee(&mut self, knee_db: f64) {
+        self.compressor.set_knee(knee_db);
+    }
+
+    /// Tempo the delay syncs to
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.delay.set_tempo(bpm);
+    }
+
+    /// Update delay settings (feedback is capped below 1.0)
+    pub fn set_delay(&mut self, division: NoteDivision, feedback: f64, mix: f64) {
+        self.delay.set_division(division);
+        self.delay.set_feedback(feedback);
+        self.delay.set_mix(mix);
+    }
+
+    pub fn set_delay_pingpong(&mut self, on: bool) {
+        self.delay.set_pingpong(on);
+    }
+
+    /// Feedback low-pass corner in Hz, and an optional high-pass corner
+    pub fn set_delay_tone(&mut self, cutoff: f64, low_cut: Option<f64>) {
+        self.delay.set_tone(cutoff, low_cut);
+    }
+
+    /// Update chorus settings (rate in Hz, depth and mix 0.0 to 1.0)
+    pub fn set_chorus(&mut self, rate: f64, depth: f64, mix: f64) {
+        self.chorus.set_rate(rate);
+        self.chorus.set_depth(depth);
+        self.chorus.set_mix(mix);
+    }
+
+    /// Update reverb settings (all 0.0 to 1.0)
+    pub fn set_reverb(&mut self, size: f64, damping: f64, mix: f64) {
+        self.reverb.set_size(size);
+        self.reverb.set_damping(damping);
+        self.reverb.set_mix(mix);
+    }
+
+    pub fn set_stereo_width(&mut self, width: f64) {
+        self.stereo_width = width.clamp(0.0, MAX_STEREO_WIDTH);
+    }
+
+    /// Collapse the master bus to mono (for compatibility checks)
+    pub fn set_mono(&mut self, mono: bool) {
+        self.mono = mono;
+    }
+
+    pub fn set_pan_law(&mut self, law: PanLaw) {
+        self.pan_law = law;
+    }
+
+    pub fn set_clip_bypass(&mut self, bypass: bool) {
+        self.clipper.bypass = bypass;
+    }
+
+    /// Update soft clipper makeup gain (in dB)
+    pub fn set_clip_makeup(&mut self, makeup_db: f64) {
+        self.clipper.set_makeup_db(makeup_db);
+    }
+
+    pub fn set_dc_block(&mut self, on: bool) {
+        self.dc_block = on;
+    }
+
+    /// Run the master stages in `order`; see `validate::master_chain`
+    pub fn set_chain_order(&mut self, order: [MasterStage; MASTER_STAGES]) {
+        self.chain = order;
+    }
+
+    /// Headphone crossfeed amount (0.0 = off to 1.0)
+    pub fn set_crossfeed(&mut self, amount: f64) {
+        self.crossfeed.set_amount(amount);
+    }
+
+    /// Update the output ceiling (in dBFS, `MIN_OUTPUT_CEILING_DB` to 0)
+    pub fn set_output_ceiling(&mut self, ceiling_db: f64) {
+        self.output_ceiling = 10f64.powf(ceiling_db.clamp(MIN_OUTPUT_CEILING_DB, 0.0) / 20.0);
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stereo signal with nothing on the sends
+    fn stereo(left: f64, right: f64) -> MixBus {
+        MixBus {
+            dry: (left, right),
+            ..MixBus::default()
+        }
+    }
+
+    #[test]
+    fn test_silence_flushes_recursive_state_to_zero() {
+        let mut band = EqBand::new(100.0, 6.0, 0.7, 48000.0);
+        let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
+        for i in 0..4800 {
+            let loud = (i as f64 * 2.0 * PI * 100.0 / 48000.0).sin();
+            band.process(loud);
+            limiter.process_stereo(loud, loud);
+        }
+        for _ in 0..48000 * 10 {
+            band.process(0.0);
+            limiter.process_stereo(0.0, 0.0);
+        }
+        assert_eq!((band.y1, band.y2), (0.0, 0.0));
+        assert_eq!(limiter.envelope, 0.0);
+    }
+
+    #[test]
+    fn test_smoothed_param_ramps_monotonically() {
+        let mut param = SmoothedParam::new(0.0, 48000.0);
+        param.set_target(1.0);
+
+        let ramp: Vec<f64> = (0..1000).map(|_| param.next()).collect();
+        assert!(ramp[0] < 0.01, "no instantaneous jump");
+        assert!(ramp.windows(2).all(|w| w[1] >= w[0]), "monotonic");
+
+        // 10 ms at 48 kHz
+        assert!(ramp[478] < 1.0);
+        assert_eq!(ramp[479], 1.0);
+        assert_eq!(ramp[999], 1.0);
+    }
 
     #[test]
     fn test_eq_band() {
@@ -276,14 +2357,328 @@ mod tests {
         assert!(output > input); // Gain should boost
     }
 
+    /// Steady-state peak gain of `band` for a sine at `frequency`
+    fn sine_gain(band: &mut EqBand, frequency: f64, sample_rate: f64) -> f64 {
+        let len = sample_rate as usize;
+        let out: Vec<f64> = (0..len)
+            .map(|i| band.process((2.0 * PI * frequency * i as f64 / sample_rate).sin()))
+            .collect();
+        out[len / 2..].iter().fold(0.0, |m, x| m.max(x.abs()))
+    }
+
+    /// Gain at DC (z = 1) and Nyquist (z = -1) from the coefficients
+    fn dc_and_nyquist_gain(band: &EqBand) -> (f64, f64) {
+        let dc = (band.b0 + band.b1 + band.b2) / (1.0 + band.a1 + band.a2);
+        let nyquist = (band.b0 - band.b1 + band.b2) / (1.0 - band.a1 + band.a2);
+        (dc, nyquist)
+    }
+
+    #[test]
+    fn test_shelves_boost_only_their_end() {
+        let six_db = 10f64.powf(6.0 / 20.0);
+
+        let low = EqBand::with_kind(FilterKind::LowShelf, 100.0, 6.0, 0.7, 48000.0);
+        let (dc, nyquist) = dc_and_nyquist_gain(&low);
+        assert!((dc - six_db).abs() < 1e-9, "low shelf DC {}", dc);
+        assert!((nyquist - 1.0).abs() < 1e-9, "low shelf Nyquist {}", nyquist);
+
+        let high = EqBand::with_kind(FilterKind::HighShelf, 8000.0, 6.0, 0.7, 48000.0);
+        let (dc, nyquist) = dc_and_nyquist_gain(&high);
+        assert!((dc - 1.0).abs() < 1e-9, "high shelf DC {}", dc);
+        assert!((nyquist - six_db).abs() < 1e-9, "high shelf Nyquist {}", nyquist);
+
+        // A peak returns to unity at both ends, which is what the master
+        // low/high bands used to do
+        let (dc, nyquist) = dc_and_nyquist_gain(&EqBand::new(100.0, 6.0, 0.7, 48000.0));
+        assert!((dc - 1.0).abs() < 1e-9 && (nyquist - 1.0).abs() < 1e-9);
+
+        let mixer = Mixer::new(48000.0, 1);
+        assert_eq!(mixer.eq[0][0].kind, FilterKind::LowShelf);
+        assert_eq!(mixer.eq[0][2].kind, FilterKind::HighShelf);
+    }
+
+    #[test]
+    fn test_eq_response_reads_the_band_gains() {
+        let response = Mixer::eq_response([0.0, 6.0, 0.0], &[1000.0, 20.0, 20000.0], 48000.0);
+        assert!((response[0] - 6.0).abs() < 0.05, "mid center reads {}", response[0]);
+        assert!(response[1].abs() < 0.1 && response[2].abs() < 0.1, "{:?}", response);
+
+        // The formula agrees with what the band does to a sine
+        let mut band = EqBand::with_kind(FilterKind::HighShelf, 8000.0, -9.0, 0.7, 48000.0);
+        let measured = 20.0 * sine_gain(&mut band, 5000.0, 48000.0).log10();
+        assert!((band.magnitude_db(5000.0, 48000.0) - measured).abs() < 0.05, "{}", measured);
+    }
+
+    #[test]
+    fn test_side_eq_leaves_mono_untouched() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_eq_ms_mode(true);
+        mixer.set_side_eq(12.0, 12.0, 12.0);
+
+        let mut stereo_changed = false;
+        for i in 0..4800 {
+            let x = (2.0 * PI * 440.0 * i as f64 / 48000.0).sin();
+            let (l, r) = mixer.process_eq(x, x);
+            assert!((l - x).abs() < 1e-9 && (r - x).abs() < 1e-9, "sample {}", i);
+        }
+        for i in 0..4800 {
+            let x = (2.0 * PI * 440.0 * i as f64 / 48000.0).sin();
+            let (l, _) = mixer.process_eq(x, 0.0);
+            stereo_changed |= (l - x).abs() > 0.1;
+        }
+        assert!(stereo_changed, "side boost should change a one-sided signal");
+    }
+
+    #[test]
+    fn test_high_pass_blocks_lows_and_passes_highs() {
+        let sample_rate = 48000.0;
+        let hpf = || EqBand::with_kind(FilterKind::HighPass, 100.0, 0.0, 0.707, sample_rate);
+
+        let mut dc = hpf();
+        let settled = (0..48000).map(|_| dc.process(1.0)).last().unwrap();
+        assert!(settled.abs() < 1e-3, "DC {}", settled);
+
+        // 12 dB/octave: 25 Hz is two octaves down
+        assert!(sine_gain(&mut hpf(), 25.0, sample_rate) < 0.07);
+        assert!((sine_gain(&mut hpf(), 5000.0, sample_rate) - 1.0).abs() < 0.01);
+
+        let mut lpf = EqBand::with_kind(FilterKind::LowPass, 100.0, 0.0, 0.707, sample_rate);
+        assert!(sine_gain(&mut lpf, 5000.0, sample_rate) < 0.01);
+    }
+
     #[test]
     fn test_limiter() {
         let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
         let input = 1.0; // Above threshold
-        let output = limiter.process(input);
+        let output = limiter.process_stereo(input, input).0;
         assert!(output.abs() <= 0.51); // Should be limited
     }
 
+    #[test]
+    fn test_limiter_lookahead_catches_spike() {
+        let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
+        let mut peak: f64 = 0.0;
+        for i in 0..2000 {
+            let input = if i == 1000 { 1.0 } else { 0.0 }; // Spike after silence
+            peak = peak.max(limiter.process_stereo(input, input).0.abs());
+        }
+        assert!(peak <= 0.5 + 1e-9); // Never above threshold
+        assert!(peak > 0.4); // But the spike still comes through
+    }
+
+    #[test]
+    fn test_limiter_delay_is_exactly_lookahead() {
+        let mut limiter = Limiter::new(48000.0, 0.95, 0.1);
+        let lookahead = limiter.lookahead;
+        let input: Vec<f64> = (0..lookahead * 3).map(|i| 0.5 * (i as f64 * 0.01).sin()).collect();
+        let output: Vec<f64> = input.iter().map(|&x| limiter.process_stereo(x, x).0).collect();
+
+        // Below threshold: output is the input delayed by `lookahead` samples
+        assert!(output[..lookahead].iter().all(|&y| y == 0.0));
+        for i in lookahead..input.len() {
+            assert_eq!(output[i], input[i - lookahead]);
+        }
+    }
+
+    #[test]
+    fn test_limiter_gain_reduction_metering() {
+        let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
+        for _ in 0..1000 {
+            limiter.process_stereo(0.25, 0.25);
+        }
+        assert_eq!(limiter.current_gain_reduction_db(), 0.0);
+
+        // A full-scale input held long enough needs 6 dB of reduction
+        for _ in 0..1000 {
+            limiter.process_stereo(1.0, 1.0);
+        }
+        assert!((limiter.current_gain_reduction_db() - 6.02).abs() < 0.01);
+
+        limiter.reset_gain_reduction();
+        assert_eq!(limiter.current_gain_reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn test_limiter_release_modes_recover_at_different_rates() {
+        // Samples after a 10 ms full-scale transient until a -12 dBFS tail
+        // is back within 1% of unity gain
+        let recovery = |mode: LimiterRelease| {
+            let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
+            limiter.release_mode = mode;
+            for _ in 0..480 {
+                limiter.process_stereo(1.0, 1.0);
+            }
+            // The transient itself is still leaving the lookahead buffer
+            for _ in 0..limiter.lookahead {
+                limiter.process_stereo(0.25, 0.25);
+            }
+            (0..48000)
+                .position(|_| limiter.process_stereo(0.25, 0.25).0 >= 0.25 * 0.99)
+                .unwrap()
+        };
+        let exponential = recovery(LimiterRelease::Exponential);
+        let linear = recovery(LimiterRelease::Linear);
+        let dual = recovery(LimiterRelease::Dual);
+
+        // 1.0 -> 0.505: ~108 ms exponential, ~50 ms linear, and ~27 ms on
+        // the dual mode's fast stage (the slow one never charged)
+        assert!((5000..5500).contains(&exponential), "exponential {}", exponential);
+        assert!((2200..2700).contains(&linear), "linear {}", linear);
+        assert!((1100..1500).contains(&dual), "dual {}", dual);
+
+        // On sustained level the slow stage takes over and holds the
+        // reduction through a short gap, where a plain release recovers
+        let dip = |mode: LimiterRelease| {
+            let mut limiter = Limiter::new(48000.0, 0.5, 0.1);
+            limiter.release_mode = mode;
+            for _ in 0..48000 {
+                limiter.process_stereo(1.0, 1.0);
+            }
+            for _ in 0..4800 {
+                limiter.process_stereo(0.25, 0.25);
+            }
+            limiter.process_stereo(0.25, 0.25).0 / 0.25
+        };
+        assert!(dip(LimiterRelease::Dual) < 0.6);
+        assert!(dip(LimiterRelease::Exponential) > 0.9);
+    }
+
+    /// Peak of `samples[range]` reconstructed at 4x with a long windowed sinc
+    fn reconstructed_peak(samples: &[f64], range: std::ops::Range<usize>) -> f64 {
+        const HALF: isize = 256;
+        let mut peak: f64 = 0.0;
+        for i in range {
+            for quarter in 0..4 {
+                let t = i as f64 + quarter as f64 / 4.0;
+                let mut y = 0.0;
+                for j in (i as isize - HALF)..(i as isize + HALF) {
+                    let x = t - j as f64;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let hann = 0.5 + 0.5 * (PI * x / HALF as f64).cos();
+                    y += samples[j as usize] * sinc * hann;
+                }
+                peak = peak.max(y.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_true_peak_limiter_catches_inter_sample_overshoot() {
+        // Fs/4 at 45 degrees: every sample reads 0.707, the waveform between
+        // them reaches 1.0
+        let tone = |n: usize| (FRAC_PI_2 * n as f64 + PI / 4.0).sin();
+        let limited = |true_peak: bool| {
+            let mut limiter = Limiter::new(48000.0, 0.8, 0.1);
+            limiter.set_true_peak(true_peak);
+            let out: Vec<f64> = (0..4000).map(|n| limiter.process_stereo(tone(n), 0.0).0).collect();
+            reconstructed_peak(&out, 1000..3000)
+        };
+
+        let sample_peak = limited(false);
+        assert!(sample_peak > 0.95, "sample-peak mode left it at {}", sample_peak);
+        let true_peak = limited(true);
+        assert!(true_peak <= 0.8 * 1.001, "true-peak mode let {} through", true_peak);
+        assert!(true_peak > 0.75, "over-limited to {}", true_peak);
+    }
+
+    #[test]
+    fn test_compressor_static_gain_reduction() {
+        let mut comp = Compressor::new(48000.0);
+        comp.threshold = -20.0;
+        comp.ratio = 4.0;
+
+        // 0 dBFS is 20 dB over: 4:1 leaves 5 dB over, i.e. -15 dB out
+        let mut out = (0.0, 0.0);
+        for _ in 0..48000 {
+            out = comp.process(1.0, 0.5);
+        }
+        let expected = 10f64.powf(-15.0 / 20.0);
+        assert!((out.0 - expected).abs() < 1e-3);
+
+        // Stereo-linked: the quieter channel gets the same gain
+        assert!((out.1 - 0.5 * expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_soft_knee_starts_reducing_below_threshold() {
+        let mut comp = Compressor::new(48000.0);
+        comp.threshold = -20.0;
+        comp.ratio = 4.0;
+        let hard: Vec<f64> = (-40..=0).map(|db| comp.reduction_db(db as f64)).collect();
+        comp.set_knee(12.0);
+        let soft: Vec<f64> = (-40..=0).map(|db| comp.reduction_db(db as f64)).collect();
+
+        // -24 dB is 4 dB under the threshold: inside the knee, it's reduced
+        assert_eq!(hard[16], 0.0);
+        assert!(soft[16] > 0.0 && soft[16] < 0.2, "{}", soft[16]);
+        assert_eq!(soft[13], 0.0, "below the knee");
+        assert!((soft[40] - hard[40]).abs() < 1e-12, "above the knee");
+
+        // No step anywhere, and the slope only ever grows toward 3/4 dB/dB
+        let steps: Vec<f64> = soft.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(steps.windows(2).all(|s| s[1] >= s[0] - 1e-12));
+        assert!(steps.iter().all(|&s| s <= 0.75 + 1e-12));
+        let hard_jump = hard[21] - hard[20];
+        assert!(soft[21] - soft[20] < hard_jump, "knee eases in");
+    }
+
+    #[test]
+    fn test_compressor_below_threshold_is_unity() {
+        let mut comp = Compressor::new(48000.0);
+        comp.threshold = -6.0;
+        comp.ratio = 8.0;
+        for _ in 0..48000 {
+            let (l, r) = comp.process(0.25, -0.25);
+            assert!((l - 0.25).abs() < 1e-12 && (r + 0.25).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_clipper_curve_rises_then_saturates() {
+        for mode in [ClipMode::Rational, ClipMode::Tanh, ClipMode::ArcTan, ClipMode::HardClip] {
+            let mut clipper = SoftClipper::master();
+            clipper.mode = mode;
+            let curve = clipper.transfer_curve(201);
+            assert_eq!(curve.len(), 201);
+            assert_eq!((curve[0].input, curve[200].input), (-2.0, 2.0));
+            assert!(curve.windows(2).all(|w| w[1].output >= w[0].output), "{:?}", mode);
+
+            // Odd, unity through the middle, flattening out at the ends
+            assert!((curve[0].output + curve[200].output).abs() < 1e-12);
+            assert!((curve[110].output - curve[110].input).abs() < 1e-12);
+            assert!(curve[200].output <= 1.0);
+            let last_step = curve[200].output - curve[199].output;
+            assert!(last_step < 0.2 * (curve[101].output - curve[100].output), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_reordered_master_chain_changes_the_output() {
+        let render = |order: [MasterStage; MASTER_STAGES]| {
+            let mut mixer = Mixer::new(48000.0, 0);
+            mixer.set_compressor(-20.0, 4.0, 1.0, 100.0, 0.0);
+            mixer.set_master_volume(0.1);
+            mixer.set_chain_order(order);
+            (0..4800)
+                .map(|i| {
+                    let x = 0.9 * (2.0 * PI * 440.0 * i as f64 / 48000.0).sin();
+                    mixer.process_master(stereo(x, x)).0 as f64
+                })
+                .collect::<Vec<f64>>()
+        };
+        let default = render(DEFAULT_MASTER_CHAIN);
+        assert_eq!(render(DEFAULT_MASTER_CHAIN), default, "same order, same output");
+
+        // Turning the level down first leaves the compressor under threshold
+        let mut order = DEFAULT_MASTER_CHAIN;
+        order.swap(1, 5);
+        assert_eq!(order[1], MasterStage::Volume);
+        let peak = |out: &[f64]| out[2400..].iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        let (compressed, uncompressed) = (peak(&default), peak(&render(order)));
+        assert!(uncompressed > 1.5 * compressed, "{} vs {}", uncompressed, compressed);
+    }
+
     #[test]
     fn test_soft_clipper() {
         let clipper = SoftClipper::new(0.8, 2.0);
@@ -291,14 +2686,567 @@ mod tests {
         assert!(output.abs() < 1.5); // Should be clipped
     }
 
+    #[test]
+    fn test_soft_clipper_bypass() {
+        let mut clipper = SoftClipper::new(0.8, 2.0);
+        clipper.bypass = true;
+        clipper.makeup = 2.0;
+        assert_eq!(clipper.process(1.2), 1.2);
+        assert_eq!(clipper.process(-1.2), -1.2);
+
+        // Makeup applies after clipping once engaged
+        clipper.bypass = false;
+        assert_eq!(clipper.process(0.25), 0.5);
+    }
+
+    #[test]
+    fn test_clip_modes_meet_at_threshold_and_differ_above() {
+        let curve = |mode, input| {
+            let mut clipper = SoftClipper::new(0.8, 2.0);
+            clipper.mode = mode;
+            clipper.process(input)
+        };
+        let modes = [ClipMode::Rational, ClipMode::Tanh, ClipMode::ArcTan, ClipMode::HardClip];
+
+        for mode in modes {
+            // Untouched below the threshold, same level at it, odd-symmetric
+            assert_eq!(curve(mode, 0.5), 0.5);
+            assert!((curve(mode, 0.8) - 0.8).abs() < 1e-12);
+            assert_eq!(curve(mode, -1.3), -curve(mode, 1.3));
+            // Never past the 1.0 ceiling, and still rising just above the knee
+            for input in [0.81, 0.9, 1.2, 2.0, 10.0] {
+                let out = curve(mode, input);
+                assert!(out > 0.8 && out <= 1.0, "{:?} at {}: {}", mode, input, out);
+            }
+        }
+
+        // Hard clip is linear right up to the ceiling, then flat
+        assert!((curve(ClipMode::HardClip, 0.95) - 0.95).abs() < 1e-12);
+        assert_eq!(curve(ClipMode::HardClip, 1.5), 1.0);
+        assert_eq!(curve(ClipMode::HardClip, 10.0), 1.0);
+
+        // The smooth curves bend away from linear as soon as they pass it
+        for mode in [ClipMode::Rational, ClipMode::Tanh, ClipMode::ArcTan] {
+            assert!(curve(mode, 0.95) < 0.95);
+            assert!(curve(mode, 1.0) < curve(mode, 1.1));
+        }
+
+        // tanh sits above arctan everywhere and is all but flat by 2.0, where
+        // arctan is still climbing; the rational curve runs into 1.0 first
+        for input in [0.9, 1.2, 2.0] {
+            assert!(curve(ClipMode::Tanh, input) > curve(ClipMode::ArcTan, input));
+        }
+        assert_eq!(curve(ClipMode::Rational, 1.2), 1.0);
+        assert!(curve(ClipMode::Tanh, 1.2) < 1.0);
+        assert!(curve(ClipMode::Tanh, 2.0) > 0.999);
+        assert!(curve(ClipMode::ArcTan, 2.0) < 0.99);
+    }
+
+    #[test]
+    fn test_output_ceiling_holds_on_hot_signal() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_output_ceiling(-1.0);
+        mixer.set_clip_makeup(12.0);
+        let ceiling = 10f64.powf(-1.0 / 20.0) as f32;
+
+        for i in 0..48000 {
+            let hot = 4.0 * (i as f64 * 2.0 * PI * 100.0 / 48000.0).sin();
+            let (l, r) = mixer.process_master(stereo(hot, -hot));
+            assert!(l.abs() <= ceiling && r.abs() <= ceiling, "frame {}: {} {}", i, l, r);
+        }
+    }
+
+    #[test]
+    fn test_dc_block_removes_offset() {
+        let mean_tail = |mixer: &mut Mixer| {
+            let out: Vec<f32> = (0..48000).map(|_| mixer.process_master(stereo(0.1, 0.1)).0).collect();
+            out[43200..].iter().map(|&s| s as f64).sum::<f64>() / 4800.0
+        };
+
+        let mut mixer = Mixer::new(48000.0, 1);
+        assert!(mean_tail(&mut mixer).abs() < 1e-3);
+
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_dc_block(false);
+        assert!(mean_tail(&mut mixer) > 0.05);
+    }
+
+    #[test]
+    fn test_crossfeed_narrows_only_when_on() {
+        let sample_rate = 48000.0;
+        let hard_left = |i: usize| ((2.0 * PI * 200.0 * i as f64 / sample_rate).sin(), 0.0);
+
+        let mut off = Crossfeed::new(sample_rate);
+        for i in 0..4800 {
+            assert_eq!(off.process(hard_left(i).0, 0.0), hard_left(i));
+        }
+
+        let mut on = Crossfeed::new(sample_rate);
+        on.set_amount(0.5);
+        let (mut diff_in, mut diff_out) = (0.0, 0.0);
+        for i in 0..48000 {
+            let (l, r) = hard_left(i);
+            let (out_l, out_r) = on.process(l, r);
+            diff_in += (l - r).powi(2);
+            diff_out += (out_l - out_r).powi(2);
+        }
+        assert!(diff_out < 0.6 * diff_in, "difference {} -> {}", diff_in, diff_out);
+    }
+
+    #[test]
+    fn test_pan_law_gains() {
+        let close = |(l, r): (f64, f64), (el, er): (f64, f64)| {
+            assert!((l - el).abs() < 1e-12 && (r - er).abs() < 1e-12, "{} {}", l, r);
+        };
+        let half = 0.5f64.sqrt();
+
+        close(PanLaw::Linear.gains(-1.0), (1.0, 0.0));
+        close(PanLaw::Linear.gains(0.0), (0.5, 0.5));
+        close(PanLaw::Linear.gains(1.0), (0.0, 1.0));
+
+        close(PanLaw::MinusThreeDb.gains(-1.0), (1.0, 0.0));
+        close(PanLaw::MinusThreeDb.gains(0.0), (half, half));
+        close(PanLaw::MinusThreeDb.gains(1.0), (0.0, 1.0));
+
+        close(PanLaw::ConstantPower.gains(-1.0), (SQRT_2, 0.0));
+        close(PanLaw::ConstantPower.gains(0.0), (1.0, 1.0));
+        close(PanLaw::ConstantPower.gains(1.0), (0.0, SQRT_2));
+    }
+
+    #[test]
+    fn test_one_bit_crush_has_few_levels() {
+        let mut crusher = BitCrusher::default();
+        crusher.set(1, 1);
+        let mut levels: Vec<f64> = (0..4800)
+            .map(|i| crusher.process(0.9 * (i as f64 * 2.0 * PI * 220.0 / 48000.0).sin()))
+            .collect();
+        levels.sort_by(f64::total_cmp);
+        levels.dedup();
+        assert_eq!(levels, vec![-1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_crusher_downsample_holds() {
+        let mut crusher = BitCrusher::default();
+        crusher.set(16, 4);
+        let out: Vec<f64> = (0..8).map(|i| crusher.process(i as f64 * 0.1)).collect();
+        assert_eq!(out[0..4], [out[0]; 4]);
+        assert_eq!(out[4..8], [out[4]; 4]);
+        assert!(out[4] > out[0]);
+    }
+
+    #[test]
+    fn test_trim_scales_strip_input() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_track_trim(0, 20.0 * 2f64.log10());
+        let out = mixer.strips[0].process(0.25, None);
+        assert!((out - 0.5).abs() < 1e-9, "trimmed {}", out);
+    }
+
+    #[test]
+    fn test_gate_closes_below_threshold_and_reopens() {
+        let sample_rate = 48000.0;
+        let mut gate = Gate::new(sample_rate);
+        gate.set(-20.0, 1.0, 50.0, 50.0, sample_rate);
+        let sine = |i: usize, amp: f64| amp * (i as f64 * 2.0 * PI * 220.0 / sample_rate).sin();
+
+        // Loud: passes untouched once the attack has settled
+        let loud: Vec<f64> = (0..24000).map(|i| gate.process(sine(i, 0.5))).collect();
+        let loud_peak = loud[12000..].iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        assert!(loud_peak > 0.49);
+
+        // Bleed at -40 dB: held briefly, then faded to silence
+        let quiet: Vec<f64> = (0..48000).map(|i| gate.process(sine(i, 0.01))).collect();
+        assert!(quiet[..1000].iter().any(|x| x.abs() > 0.009), "still held open");
+        assert!(quiet[24000..].iter().all(|x| x.abs() < 1e-4), "closed");
+
+        // A transient above threshold opens it again
+        let back: Vec<f64> = (0..24000).map(|i| gate.process(sine(i, 0.5))).collect();
+        let back_peak = back[12000..].iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        assert!(back_peak > 0.49);
+    }
+
+    /// A mono, unmuted, unsoloed track
+    fn track(sample: f64, volume: f64, pan: f64) -> TrackInput {
+        TrackInput { left: sample, volume, pan, ..Default::default() }
+    }
+
     #[test]
     fn test_mixer() {
-        let mixer = Mixer::new(48000.0);
+        let mut mixer = Mixer::new(48000.0, 2);
         let channels = vec![
-            (0.5, 0.8, 0.0, false, false), // Center
-            (0.3, 0.6, -0.5, false, false), // Left
+            track(0.5, 0.8, 0.0),  // Center
+            track(0.3, 0.6, -0.5), // Left
         ];
-        let (l, r) = mixer.mix_channels(&channels, false);
+        let (l, r) = mixer.mix_channels(&channels, false).dry;
         assert!(l > 0.0 && r > 0.0);
     }
+
+    #[test]
+    fn test_inverted_copy_cancels() {
+        let mut mixer = Mixer::new(48000.0, 2);
+        mixer.set_track_polarity(1, true);
+        for i in 0..4800 {
+            let x = (2.0 * PI * 440.0 * i as f64 / 48000.0).sin();
+            let channels = [track(x, 0.8, 0.3), track(x, 0.8, 0.3)];
+            let (l, r) = mixer.mix_channels(&channels, false).dry;
+            assert!(l.abs() < 1e-12 && r.abs() < 1e-12, "sample {}: {} {}", i, l, r);
+        }
+    }
+
+    #[test]
+    fn test_hard_left_stereo_sample_stays_left() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        for i in 0..4800 {
+            let x = (2.0 * PI * 440.0 * i as f64 / 48000.0).sin();
+            let input = TrackInput { right: Some(0.0), ..track(x, 0.8, 0.0) };
+            let (l, r) = mixer.mix_channels(&[input], false).dry;
+            assert_eq!(r, 0.0, "sample {}", i);
+            // Balance at center leaves the left side at unity: only the
+            // fader (ramping up from 0) scales it
+            if i > 1000 {
+                assert!((l - 0.8 * x).abs() < 1e-9, "sample {}: {}", i, l);
+            }
+        }
+
+        // Balance toward the right fades the left side instead of moving it
+        let input = TrackInput { right: Some(0.0), ..track(0.5, 0.8, 0.5) };
+        for _ in 0..1000 {
+            mixer.mix_channels(&[input], false);
+        }
+        let (l, r) = mixer.mix_channels(&[input], false).dry;
+        assert!((l - 0.5 * 0.8 * 0.5).abs() < 1e-9 && r == 0.0, "{} {}", l, r);
+    }
+
+    #[test]
+    fn test_balance_fades_a_stereo_side_where_pan_folds_it_over() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        let input = TrackInput { right: Some(0.25), ..track(0.5, 1.0, 0.5) };
+        let settled = |mixer: &mut Mixer| {
+            for _ in 0..1000 {
+                mixer.mix_channels(&[input], false);
+            }
+            mixer.mix_channels(&[input], false).dry
+        };
+
+        // Auto is balance for stereo: the left side is only attenuated
+        let (l, r) = settled(&mut mixer);
+        assert!((l - 0.25).abs() < 1e-9 && (r - 0.25).abs() < 1e-9, "{} {}", l, r);
+        mixer.set_track_pan_mode(0, PanMode::Balance);
+        assert_eq!(settled(&mut mixer), (l, r));
+
+        // Pan moves half the left channel over to the right
+        mixer.set_track_pan_mode(0, PanMode::Pan);
+        let (l, r) = settled(&mut mixer);
+        assert!((l - 0.25).abs() < 1e-9 && (r - 0.5).abs() < 1e-9, "{} {}", l, r);
+    }
+
+    #[test]
+    fn test_solo_safe_track_plays_through_solo() {
+        let mut mixer = Mixer::new(48000.0, 3);
+        mixer.set_track_solo_safe(2, true);
+        // Track 0 is soloed but silent, so only the others can be heard
+        let output = |mixer: &mut Mixer, track: usize| {
+            let mut channels = [self::track(0.0, 0.8, 0.0); 3];
+            channels[0].soloed = true;
+            channels[track].left = 0.5;
+            let (l, r) = mixer.mix_channels(&channels, true).dry;
+            l.abs() + r.abs()
+        };
+        assert_eq!(output(&mut mixer, 1), 0.0, "non-soloed track is silenced");
+        assert!(output(&mut mixer, 2) > 0.0, "solo-safe track plays");
+    }
+
+    #[test]
+    fn test_pfl_hears_the_track_whatever_its_fader() {
+        let listened = |volume: f64, listen: Listen| {
+            let mut mixer = Mixer::new(48000.0, 2);
+            let listened = TrackInput { listen, ..track(0.5, volume, -1.0) };
+            let channels = [listened, track(0.3, 1.0, 0.0)];
+            for _ in 0..4800 {
+                mixer.mix_channels(&channels, false);
+            }
+            let bus = mixer.mix_channels(&channels, false);
+            (bus.dry, bus.listen)
+        };
+
+        // Pre-fader: the strip output on both sides, at any fader position
+        for volume in [0.0, 0.2, 1.0] {
+            let (_, listen) = listened(volume, Listen::Pfl);
+            let (l, r) = listen.unwrap();
+            assert!((l - 0.5).abs() < 1e-9 && (r - 0.5).abs() < 1e-9, "{}: {} {}", volume, l, r);
+        }
+        // After-fader: volume and pan applied
+        let (_, listen) = listened(0.2, Listen::Afl);
+        let (l, r) = listen.unwrap();
+        assert!((l - 0.1).abs() < 1e-9 && r.abs() < 1e-9, "{} {}", l, r);
+
+        // The main mix doesn't change, and nothing listened means no tap
+        let (dry, listen) = listened(0.2, Listen::Off);
+        assert_eq!(listen, None);
+        assert_eq!(listened(0.2, Listen::Pfl).0, dry);
+    }
+
+    #[test]
+    fn test_mute_fades_out_instead_of_cutting() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        let mut channels = [track(0.5, 1.0, 0.0)];
+        for _ in 0..4800 {
+            mixer.mix_channels(&channels, false);
+        }
+        let playing = mixer.mix_channels(&channels, false).dry.0;
+
+        channels[0].muted = true;
+        let fade_len = (48000.0 * MUTE_FADE_MS / 1000.0) as usize;
+        let fade: Vec<f64> =
+            (0..fade_len + 10).map(|_| mixer.mix_channels(&channels, false).dry.0).collect();
+        assert!(fade[0] > playing * 0.95, "first muted sample {} vs {}", fade[0], playing);
+        assert!(fade.windows(2).all(|w| w[1] <= w[0]), "fade is monotonic");
+        assert!(fade[fade_len / 2] > playing * 0.4 && fade[fade_len / 2] < playing * 0.6);
+        assert_eq!(fade[fade_len + 9], 0.0);
+    }
+
+    #[test]
+    fn test_track_hpf_cuts_rumble_and_passes_mids() {
+        // Peak level of the second half of a one-second tone through track 0
+        let level = |mixer: &mut Mixer, hz: f64| {
+            (0..48000)
+                .map(|i| {
+                    let x = (2.0 * PI * hz * i as f64 / 48000.0).sin();
+                    (i, mixer.strips[0].process(x, None))
+                })
+                .filter(|&(i, _)| i >= 24000)
+                .fold(0.0f64, |peak, (_, y)| peak.max(y.abs()))
+        };
+        let mut mixer = Mixer::new(48000.0, 1);
+        assert!((level(&mut mixer, 50.0) - 1.0).abs() < 1e-3, "off by default");
+
+        mixer.set_track_hpf(0, 200.0, true);
+        let rumble = level(&mut mixer, 50.0);
+        let mids = level(&mut mixer, 1000.0);
+        // Two octaves below a 12 dB/oct corner
+        assert!(20.0 * rumble.log10() < -20.0, "50 Hz at {}", rumble);
+        assert!((mids - 1.0).abs() < 0.01, "1 kHz at {}", mids);
+    }
+
+    #[test]
+    fn test_track_allpass_turns_phase_but_keeps_magnitude() {
+        use rustfft::num_complex::Complex;
+        use rustfft::FftPlanner;
+
+        const SIZE: usize = 1024;
+        let mut seed = 0x1234_5678u32;
+        let noise: Vec<f64> = (0..SIZE)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as f64 / u32::MAX as f64 - 0.5
+            })
+            .collect();
+
+        // The noise on a loop: once the filter settles, one period of the
+        // output is the noise's spectrum times the filter's response
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_track_allpass(0, 1000.0, true);
+        let mut out = Vec::new();
+        for _ in 0..4 {
+            out = noise.iter().map(|&x| mixer.strips[0].process(x, None)).collect();
+        }
+        let fft = FftPlanner::new().plan_fft_forward(SIZE);
+        let spectrum = |signal: &[f64]| {
+            let mut bins: Vec<Complex<f64>> =
+                signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
+            fft.process(&mut bins);
+            bins
+        };
+        let (dry, wet) = (spectrum(&noise), spectrum(&out));
+        for k in 1..SIZE / 2 {
+            let gain = wet[k].norm() / dry[k].norm();
+            assert!((gain - 1.0).abs() < 1e-6, "bin {} gain {}", k, gain);
+        }
+        // Bin 21 is ~1 kHz, where the phase has turned half a cycle
+        let (w, d) = (wet[21], dry[21]);
+        let turn = (w.im * d.re - w.re * d.im).atan2(w.re * d.re + w.im * d.im).abs();
+        assert!(turn > 2.5, "phase turned {} rad", turn);
+    }
+
+    #[test]
+    fn test_track_compressor_reduces_only_above_threshold() {
+        let settle = |strip: &mut ChannelStrip, level: f64| {
+            (0..48000).fold(0.0, |_, _| strip.process(level, None))
+        };
+        let mut strip = ChannelStrip::new(48000.0);
+        strip.compressor.set(-20.0, 4.0, 1.0, 100.0, 0.0);
+
+        // -26 dBFS stays below the threshold
+        assert!((settle(&mut strip, 0.05) - 0.05).abs() < 1e-6);
+
+        // -6 dBFS is 14 dB over; 4:1 leaves 3.5 dB of it, a 10.5 dB cut
+        let out = settle(&mut strip, 0.5);
+        let reduction = 20.0 * (0.5 / out).log10();
+        assert!((reduction - 10.5).abs() < 0.05, "{} dB", reduction);
+
+        // Stereo input gets the same linked reduction
+        let (l, r) = (0..48000).fold((0.0, 0.0), |_, _| strip.process_stereo(0.5, -0.5, None));
+        assert!((l - out).abs() < 1e-6 && (r + out).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sidechain_ducks_a_quiet_track_under_a_loud_source() {
+        let output = |sidechain: Option<usize>| {
+            let mut mixer = Mixer::new(48000.0, 2);
+            mixer.set_track_compressor(0, -20.0, 4.0, 1.0, 100.0);
+            mixer.set_track_sidechain(0, sidechain);
+            // The muted source keys the compressor without being heard
+            let mut channels = [track(0.05, 1.0, 0.0), track(0.5, 1.0, 0.0)];
+            channels[1].muted = true;
+            (0..48000).fold(0.0, |_, _| mixer.mix_channels(&channels, false).dry.0)
+        };
+
+        // -26 dBFS on its own is below the threshold; keyed from the -6 dBFS
+        // source it takes that source's 10.5 dB of reduction
+        let own = output(None);
+        let keyed = output(Some(1));
+        let reduction = 20.0 * (own / keyed).log10();
+        assert!((reduction - 10.5).abs() < 0.05, "{} dB", reduction);
+    }
+
+    #[test]
+    fn test_muted_bus_silences_its_tracks() {
+        let mut mixer = Mixer::new(48000.0, 3);
+        mixer.set_track_bus(0, Some(1));
+        mixer.set_track_bus(1, Some(1));
+        mixer.set_track_sends(1, 0.5, 0.5);
+        let output = |mixer: &mut Mixer, track: usize| {
+            let mut channels = [self::track(0.0, 0.8, 0.0); 3];
+            channels[track].left = 0.5;
+            let bus = mixer.mix_channels(&channels, false);
+            let sends = bus.delay_send.0 + bus.reverb_send.0;
+            bus.dry.0.abs() + bus.dry.1.abs() + sends.abs()
+        };
+        for track in 0..3 {
+            assert!(output(&mut mixer, track) > 0.0, "track {} plays", track);
+        }
+
+        mixer.set_bus_muted(1, true);
+        for track in 0..2 {
+            assert_eq!(output(&mut mixer, track), 0.0, "track {} is on the muted bus", track);
+        }
+        assert!(output(&mut mixer, 2) > 0.0, "track 2 goes straight to the master");
+    }
+
+    #[test]
+    fn test_zero_send_track_leaves_no_reverb_tail() {
+        // 10 ms burst, then the energy left half a second later
+        let tail_energy = |send: f64| {
+            let mut mixer = Mixer::new(48000.0, 1);
+            mixer.set_reverb(0.9, 0.2, 0.0);
+            mixer.set_track_sends(0, 0.0, send);
+            let mut energy = 0.0;
+            for i in 0..48000 {
+                let x = if i < 480 { 0.5 } else { 0.0 };
+                let bus = mixer.mix_channels(&[track(x, 1.0, 0.0)], false);
+                let (l, r) = mixer.process_master(bus);
+                if i >= 24000 {
+                    energy += (l * l + r * r) as f64;
+                }
+            }
+            energy
+        };
+
+        assert!(tail_energy(1.0) > 1e-6);
+        assert!(tail_energy(0.0) < 1e-20);
+    }
+
+    #[test]
+    fn test_zero_width_is_mono() {
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_stereo_width(0.0);
+        for i in 0..1000 {
+            let t = i as f64 / 48000.0;
+            let bus = stereo(0.5 * (t * 440.0).sin(), 0.3 * (t * 660.0).cos());
+            let (l, r) = mixer.process_master(bus);
+            assert_eq!(l, r);
+        }
+    }
+
+    #[test]
+    fn test_track_eq_is_per_track() {
+        let mut flat = Mixer::new(48000.0, 2);
+        let mut boosted = Mixer::new(48000.0, 2);
+        boosted.set_track_eq(1, 0.0, 12.0, 0.0);
+
+        // Track 0 only: the boost on track 1 must not touch it
+        let mut diff: f64 = 0.0;
+        for i in 0..1000 {
+            let x = (i as f64 * 2.0 * PI * 1000.0 / 48000.0).sin();
+            let channels = vec![track(x, 1.0, 0.0), track(0.0, 1.0, 0.0)];
+            let (a, _) = flat.mix_channels(&channels, false).dry;
+            let (b, _) = boosted.mix_channels(&channels, false).dry;
+            diff = diff.max((a - b).abs());
+        }
+        assert!(diff < 1e-12);
+
+        // Track 1 gets louder, and its filter state carries across calls
+        let mut flat_peak: f64 = 0.0;
+        let mut boosted_peak: f64 = 0.0;
+        for i in 0..4800 {
+            let x = (i as f64 * 2.0 * PI * 1000.0 / 48000.0).sin() * 0.1;
+            let channels = vec![track(0.0, 1.0, 0.0), track(x, 1.0, 0.0)];
+            flat_peak = flat_peak.max(flat.mix_channels(&channels, false).dry.0.abs());
+            boosted_peak = boosted_peak.max(boosted.mix_channels(&channels, false).dry.0.abs());
+        }
+        assert!(boosted_peak > flat_peak * 2.0);
+    }
+
+    #[test]
+    fn test_latency_compensation_lines_up_a_lookahead_track() {
+        // 1 ms of gate lookahead at 64 kHz is 64 samples on track 0
+        let lookahead_mixer = || {
+            let mut mixer = Mixer::new(64000.0, 2);
+            mixer.set_track_gate_lookahead(0, 1.0);
+            mixer
+        };
+        let mixer = lookahead_mixer();
+        assert_eq!(mixer.strips[0].report_latency(), 64);
+        let delays: Vec<usize> = mixer.strips.iter().map(|s| s.compensation.delay).collect();
+        assert_eq!(delays, [0, 64]);
+
+        // An impulse on either track reaches the master on the same sample,
+        // once the faders have ramped up
+        let arrival = |impulse_track: usize| {
+            let mut mixer = lookahead_mixer();
+            let mut peak = (0, 0.0);
+            for i in 0..2000 {
+                let x = if i == 1000 { 0.5 } else { 0.0 };
+                let mut inputs = [track(0.0, 0.8, 0.0); 2];
+                inputs[impulse_track].left = x;
+                let out = mixer.mix_channels(&inputs, false).dry.0.abs();
+                if out > peak.1 {
+                    peak = (i, out);
+                }
+            }
+            peak.0
+        };
+        assert_eq!(arrival(0), 1064);
+        assert_eq!(arrival(1), 1064);
+
+        // Without lookahead nothing is held back
+        let mut mixer = lookahead_mixer();
+        mixer.set_track_gate_lookahead(0, 0.0);
+        assert!(mixer.strips.iter().all(|strip| strip.compensation.delay == 0));
+    }
+
+    #[test]
+    fn test_allpass_reports_its_low_frequency_group_delay() {
+        // A second-order all-pass at Q 0.707 delays its lows by 2√2 / ω0
+        let mut mixer = Mixer::new(48000.0, 1);
+        mixer.set_track_allpass(0, 200.0, true);
+        let expected = 2.0 * SQRT_2 / (2.0 * PI * 200.0 / 48000.0);
+        let latency = mixer.strips[0].report_latency() as f64;
+        assert!((latency - expected).abs() <= 1.0, "{} vs {}", latency, expected);
+        mixer.set_track_allpass(0, 200.0, false);
+        assert_eq!(mixer.strips[0].report_latency(), 0);
+    }
 }
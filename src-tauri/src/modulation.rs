@@ -0,0 +1,173 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - MODULATION MATRIX
+// Free LFOs routed onto mix parameters, summed around each parameter's
+// own value once per buffer
+// ============================================================
+
+use serde::{Deserialize, Serialize};
+
+use crate::lfo::{LfoRate, LfoShape};
+use crate::midi::MidiParam;
+
+pub const MAX_MOD_LFOS: usize = 4;
+
+/// Routes the renderer reserves room for; `add_mod_route` refuses more
+pub const MAX_MOD_ROUTES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModLfo {
+    pub rate: LfoRate,
+    pub shape: LfoShape,
+    /// Offset into the cycle, 0.0..=1.0
+    pub phase: f64,
+}
+
+impl ModLfo {
+    /// -1.0..=1.0 at a transport position of `seconds` / `beats`
+    #[inline]
+    pub fn value(&self, seconds: f64, beats: f64) -> f64 {
+        self.shape.value(self.rate.cycles(seconds, beats) + self.phase)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModRoute {
+    pub lfo: usize,
+    pub dest: MidiParam,
+    /// -1.0..=1.0; at 1.0 the LFO swings the parameter half its range
+    /// (`MidiParam::range`) either side of its value
+    pub depth: f64,
+    /// The destination's unmodulated value; only the renderer keeps it
+    #[serde(skip)]
+    pub base: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModMatrix {
+    pub lfos: [ModLfo; MAX_MOD_LFOS],
+    pub routes: Vec<ModRoute>,
+}
+
+impl ModMatrix {
+    /// An empty matrix with room for every route, so routing never
+    /// allocates on the audio thread
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::with_capacity(MAX_MOD_ROUTES),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_lfo(&mut self, id: usize, lfo: ModLfo) {
+        if let Some(slot) = self.lfos.get_mut(id) {
+            *slot = lfo;
+        }
+    }
+
+    /// Route `lfo` to `dest`, or change the depth of that route. `base` is
+    /// `dest`'s current value, used unless another route already has it.
+    /// A new route past `MAX_MOD_ROUTES` is dropped.
+    pub fn add_route(&mut self, lfo: usize, dest: MidiParam, depth: f64, base: f64) {
+        if lfo >= MAX_MOD_LFOS {
+            return;
+        }
+        if let Some(route) = self.routes.iter_mut().find(|r| r.lfo == lfo && r.dest == dest) {
+            route.depth = depth;
+            return;
+        }
+        if self.routes.len() < MAX_MOD_ROUTES {
+            let base = self.base(dest).unwrap_or(base);
+            self.routes.push(ModRoute { lfo, dest, depth, base });
+        }
+    }
+
+    /// Remove the `lfo` to `dest` route. Returns `dest`'s base when no
+    /// other route moves it any more, so it can be put back.
+    pub fn remove_route(&mut self, lfo: usize, dest: MidiParam) -> Option<f64> {
+        let i = self.routes.iter().position(|r| r.lfo == lfo && r.dest == dest)?;
+        let removed = self.routes.remove(i);
+        self.base(dest).is_none().then_some(removed.base)
+    }
+
+    /// The unmodulated value of `dest`, if any route moves it
+    pub fn base(&self, dest: MidiParam) -> Option<f64> {
+        self.routes.iter().find(|r| r.dest == dest).map(|r| r.base)
+    }
+
+    /// A new unmodulated value for `dest`, for when something else sets it
+    pub fn set_base(&mut self, dest: MidiParam, value: f64) {
+        for route in self.routes.iter_mut().filter(|r| r.dest == dest) {
+            route.base = value;
+        }
+    }
+
+    /// Route `i`'s destination and base if it's the first route to that
+    /// destination, so each destination is visited once
+    pub fn destination(&self, i: usize) -> Option<(MidiParam, f64)> {
+        let route = self.routes.get(i)?;
+        let first = self.routes[..i].iter().all(|r| r.dest != route.dest);
+        first.then_some((route.dest, route.base))
+    }
+
+    /// `dest` at a transport position: its base plus every route's
+    /// swing, kept within the parameter's range
+    pub fn value(&self, dest: MidiParam, seconds: f64, beats: f64) -> Option<f64> {
+        let base = self.base(dest)?;
+        let (min, max) = dest.range();
+        let swing: f64 = self
+            .routes
+            .iter()
+            .filter(|r| r.dest == dest)
+            .map(|r| r.depth * self.lfos[r.lfo].value(seconds, beats))
+            .sum();
+        Some((base + swing * (max - min) * 0.5).clamp(min, max))
+    }
+
+    /// Follow `removed` leaving the track list: its routes are dropped and
+    /// later tracks' routes shift down
+    pub fn track_removed(&mut self, removed: usize) {
+        self.routes.retain_mut(|route| match route.dest.after_track_removed(removed) {
+            Some(dest) => {
+                route.dest = dest;
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_to_one_destination_sum_around_its_base() {
+        let mut matrix = ModMatrix::new();
+        let square = ModLfo { shape: LfoShape::Square, ..ModLfo::default() };
+        matrix.set_lfo(0, square);
+        matrix.set_lfo(1, ModLfo { phase: 0.5, ..square });
+        let dest = MidiParam::TrackPan { track: 2 };
+        matrix.add_route(0, dest, 0.5, 0.1);
+        // A second route keeps the first one's base
+        matrix.add_route(1, dest, 0.2, 0.9);
+        assert_eq!(matrix.base(dest), Some(0.1));
+        assert_eq!(matrix.destination(0), Some((dest, 0.1)));
+        assert_eq!(matrix.destination(1), None);
+
+        // Pan spans -1..1: +0.5 from LFO 0, -0.2 from the half-cycle-late LFO 1
+        let value = matrix.value(dest, 0.25, 0.0).unwrap();
+        assert!((value - 0.4).abs() < 1e-12, "{}", value);
+
+        matrix.track_removed(0);
+        let dest = MidiParam::TrackPan { track: 1 };
+        assert_eq!(matrix.base(dest), Some(0.1));
+        assert_eq!(matrix.remove_route(0, dest), None);
+        assert_eq!(matrix.remove_route(1, dest), Some(0.1));
+        assert!(matrix.routes.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - OVERSAMPLING
+// Runs a nonlinearity at 2x or 4x the sample rate so the harmonics it
+// creates are filtered out instead of folding back below Nyquist
+// ============================================================
+
+use std::f64::consts::PI;
+
+pub const MAX_OVERSAMPLING: usize = 4;
+
+/// FIR length per polyphase branch; the full kernel at factor N has
+/// `N * TAPS_PER_PHASE` taps
+const TAPS_PER_PHASE: usize = 32;
+const MAX_TAPS: usize = MAX_OVERSAMPLING * TAPS_PER_PHASE;
+
+/// Cutoff as a fraction of the original Nyquist; the Blackman transition
+/// band sits on either side of it
+const CUTOFF: f64 = 0.9;
+
+/// Input samples between a sample entering `TruePeakDetector` and the
+/// estimate that covers it
+pub const TRUE_PEAK_DELAY: usize = TAPS_PER_PHASE / 2;
+
+/// Windowed-sinc lowpass for interpolation by `factor`, normalized to unity
+/// DC gain. Fills all of `kernel`.
+fn design_lowpass(kernel: &mut [f64], factor: usize) {
+    let taps = kernel.len();
+    let cutoff = CUTOFF * 0.5 / factor as f64; // cycles per oversampled sample
+    let center = (taps - 1) as f64 / 2.0;
+    for (i, h) in kernel.iter_mut().enumerate() {
+        let t = i as f64 - center;
+        let sinc = if t == 0.0 {
+            2.0 * cutoff
+        } else {
+            (2.0 * PI * cutoff * t).sin() / (PI * t)
+        };
+        let phase = 2.0 * PI * i as f64 / (taps - 1) as f64;
+        let blackman = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+        *h = sinc * blackman;
+    }
+    let sum: f64 = kernel.iter().sum();
+    for h in kernel.iter_mut() {
+        *h /= sum;
+    }
+}
+
+/// One channel of upsample -> process -> downsample. Both filters share a
+/// windowed-sinc lowpass: upsampling runs it polyphase on the input
+/// history, downsampling runs it on the upsampled history and keeps one
+/// output per input sample. Filter state persists across buffers, so
+/// blocks of any size join seamlessly.
+#[derive(Clone, Debug)]
+pub struct Oversampler {
+    factor: usize,
+    kernel: [f64; MAX_TAPS],
+    input: [f64; TAPS_PER_PHASE], // ring of the newest input samples
+    input_pos: usize,
+    upsampled: [f64; MAX_TAPS], // ring of the newest processed samples
+    upsampled_pos: usize,
+}
+
+impl Oversampler {
+    pub fn new() -> Self {
+        let mut oversampler = Self {
+            factor: 0,
+            kernel: [0.0; MAX_TAPS],
+            input: [0.0; TAPS_PER_PHASE],
+            input_pos: 0,
+            upsampled: [0.0; MAX_TAPS],
+            upsampled_pos: 0,
+        };
+        oversampler.set_factor(1);
+        oversampler
+    }
+
+    /// 1 (off), 2 or 4; anything else rounds up to the next of those.
+    /// Changing the factor designs a new kernel and clears the history;
+    /// setting the current one again leaves the filters running.
+    pub fn set_factor(&mut self, factor: usize) {
+        let factor = factor.clamp(1, MAX_OVERSAMPLING).next_power_of_two();
+        if factor == self.factor {
+            return;
+        }
+        self.factor = factor;
+        self.input = [0.0; TAPS_PER_PHASE];
+        self.upsampled = [0.0; MAX_TAPS];
+        self.input_pos = 0;
+        self.upsampled_pos = 0;
+
+        let taps = self.taps();
+        design_lowpass(&mut self.kernel[..taps], factor);
+    }
+
+    fn taps(&self) -> usize {
+        self.factor * TAPS_PER_PHASE
+    }
+
+    /// Run `f` on `input` at the oversampled rate. At factor 1 this is just
+    /// `f(input)`; otherwise the output is delayed by the two filters.
+    #[inline]
+    pub fn process(&mut self, input: f64, f: impl Fn(f64) -> f64) -> f64 {
+        if self.factor == 1 {
+            return f(input);
+        }
+
+        let factor = self.factor;
+        self.input[self.input_pos] = input;
+        for phase in 0..factor {
+            // Zero-stuffed input: only every `factor`th kernel tap lands on
+            // a real sample
+            let mut up = 0.0;
+            for k in 0..TAPS_PER_PHASE {
+                let x = self.input[(self.input_pos + TAPS_PER_PHASE - k) % TAPS_PER_PHASE];
+                up += self.kernel[k * factor + phase] * x;
+            }
+            self.upsampled_pos = (self.upsampled_pos + 1) % MAX_TAPS;
+            self.upsampled[self.upsampled_pos] = f(up * factor as f64);
+        }
+        self.input_pos = (self.input_pos + 1) % TAPS_PER_PHASE;
+
+        let mut out = 0.0;
+        for j in 0..self.taps() {
+            out += self.kernel[j] * self.upsampled[(self.upsampled_pos + MAX_TAPS - j) % MAX_TAPS];
+        }
+        out
+    }
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inter-sample peak estimate for one channel: the input is interpolated
+/// 4x with the oversampling lowpass and the largest magnitude is reported.
+/// Sample peaks can read several dB under the waveform a DAC reconstructs
+/// from them; this catches most of that. The kernel is one tap short of
+/// `MAX_TAPS` so its centre, and the interpolated points, land on quarter
+/// sample positions.
+#[derive(Clone, Debug)]
+pub struct TruePeakDetector {
+    kernel: [f64; MAX_TAPS],
+    input: [f64; TAPS_PER_PHASE], // ring of the newest input samples
+    input_pos: usize,
+}
+
+impl TruePeakDetector {
+    pub fn new() -> Self {
+        let mut kernel = [0.0; MAX_TAPS];
+        design_lowpass(&mut kernel[..MAX_TAPS - 1], MAX_OVERSAMPLING);
+        Self {
+            kernel,
+            input: [0.0; TAPS_PER_PHASE],
+            input_pos: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.input = [0.0; TAPS_PER_PHASE];
+        self.input_pos = 0;
+    }
+
+    /// Push one sample; returns the peak magnitude of the reconstructed
+    /// waveform from the sample `TRUE_PEAK_DELAY` ago up to its successor
+    #[inline]
+    pub fn process(&mut self, input: f64) -> f64 {
+        self.input[self.input_pos] = input;
+        let at = |k: usize, pos: usize| self.input[(pos + TAPS_PER_PHASE - k) % TAPS_PER_PHASE];
+
+        let mut peak = at(TRUE_PEAK_DELAY, self.input_pos).abs();
+        for phase in 0..MAX_OVERSAMPLING {
+            let mut up = 0.0;
+            for k in 0..TAPS_PER_PHASE {
+                up += self.kernel[k * MAX_OVERSAMPLING + phase] * at(k, self.input_pos);
+            }
+            peak = peak.max((up * MAX_OVERSAMPLING as f64).abs());
+        }
+        self.input_pos = (self.input_pos + 1) % TAPS_PER_PHASE;
+        peak
+    }
+}
+
+impl Default for TruePeakDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::SoftClipper;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    const SIZE: usize = 4096;
+    const TONE_BIN: usize = 500; // ~5.9 kHz at 48k
+
+    /// Energy outside the tone's in-band harmonics (its 3rd is the only
+    /// other one below Nyquist), over one steady-state window
+    fn aliased_energy(factor: usize) -> f64 {
+        let clipper = SoftClipper::new(0.8, 2.0);
+        let mut oversampler = Oversampler::new();
+        oversampler.set_factor(factor);
+        let tone = |i: usize| 2.0 * (2.0 * PI * (TONE_BIN * i) as f64 / SIZE as f64).sin();
+
+        // Let the filters settle, then take exactly one period of the window
+        for i in 0..SIZE {
+            oversampler.process(tone(i), |x| clipper.process(x));
+        }
+        let mut buffer: Vec<Complex<f64>> = (SIZE..2 * SIZE)
+            .map(|i| Complex::new(oversampler.process(tone(i), |x| clipper.process(x)), 0.0))
+            .collect();
+        FftPlanner::new().plan_fft_forward(SIZE).process(&mut buffer);
+
+        (1..SIZE / 2)
+            .filter(|&bin| bin != TONE_BIN && bin != 3 * TONE_BIN)
+            .map(|bin| buffer[bin].norm_sqr())
+            .sum()
+    }
+
+    #[test]
+    fn test_oversampling_reduces_aliasing() {
+        let plain = aliased_energy(1);
+        let twice = aliased_energy(2);
+        let four = aliased_energy(4);
+        assert!(twice < plain * 0.1, "2x {} vs {}", twice, plain);
+        assert!(four < twice, "4x {} vs 2x {}", four, twice);
+    }
+
+    #[test]
+    fn test_oversampled_passband_is_unity() {
+        let mut oversampler = Oversampler::new();
+        oversampler.set_factor(4);
+        let out: Vec<f64> = (0..4800).map(|_| oversampler.process(0.5, |x| x)).collect();
+        assert!((out[4799] - 0.5).abs() < 1e-6, "{}", out[4799]);
+    }
+}
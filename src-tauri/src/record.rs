@@ -0,0 +1,202 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - RECORDING
+// Input capture into a growing take that loads into a track as a sample
+// ============================================================
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use ringbuf::traits::{Consumer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+
+use crate::sampler::Sample;
+
+/// Input the ring holds before the audio thread drains it; the run loop
+/// drains every 100 ms, so this leaves plenty of slack
+const RING_SECONDS: usize = 2;
+
+// Frames moved out of the ring per `pop_slice`
+const DRAIN_CHUNK: usize = 512;
+
+/// An input stream sample format, converted to -1.0..=1.0
+pub trait InputSample: Copy {
+    fn to_f32(self) -> f32;
+}
+
+impl InputSample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl InputSample for f64 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+impl InputSample for i8 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 128.0
+    }
+}
+
+impl InputSample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32_768.0
+    }
+}
+
+impl InputSample for i32 {
+    fn to_f32(self) -> f32 {
+        (self as f64 / 2_147_483_648.0) as f32
+    }
+}
+
+impl InputSample for u8 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
+    }
+}
+
+impl InputSample for u16 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 32_768.0) / 32_768.0
+    }
+}
+
+impl InputSample for u32 {
+    fn to_f32(self) -> f32 {
+        ((self as f64 - 2_147_483_648.0) / 2_147_483_648.0) as f32
+    }
+}
+
+/// A ring from an input callback (`RecordInput`) to the take it fills
+/// (`RecordTake`), for a device with `channels` channels
+pub fn record_channel(channels: usize, sample_rate: u32) -> (RecordInput, RecordTake) {
+    let capacity = sample_rate as usize * RING_SECONDS;
+    let (producer, consumer) = HeapRb::new(capacity.max(DRAIN_CHUNK)).split();
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let input = RecordInput {
+        producer,
+        channels: channels.max(1),
+        dropped: dropped.clone(),
+    };
+    let take = RecordTake {
+        consumer,
+        stereo: channels >= 2,
+        sample_rate,
+        left: Vec::new(),
+        right: Vec::new(),
+        dropped,
+    };
+    (input, take)
+}
+
+/// The input callback's end: converts interleaved device samples to frames
+/// and queues them without locking or allocating
+pub struct RecordInput {
+    producer: HeapProd<[f32; 2]>,
+    channels: usize,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl RecordInput {
+    /// Queue one callback's interleaved samples. Mono input fills both
+    /// channels of a frame; channels past the second are ignored.
+    pub fn push<T: InputSample>(&mut self, data: &[T]) {
+        for frame in data.chunks_exact(self.channels) {
+            let left = frame[0].to_f32();
+            let right = frame.get(1).map_or(left, |s| s.to_f32());
+            if self.producer.try_push([left, right]).is_err() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The recording so far, grown off the audio callback by `drain`
+pub struct RecordTake {
+    consumer: HeapCons<[f32; 2]>,
+    stereo: bool,
+    sample_rate: u32,
+    left: Vec<f32>,
+    right: Vec<f32>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl RecordTake {
+    /// Move everything queued so far into the take
+    pub fn drain(&mut self) {
+        let mut chunk = [[0.0f32; 2]; DRAIN_CHUNK];
+        loop {
+            let count = self.consumer.pop_slice(&mut chunk);
+            for &[left, right] in &chunk[..count] {
+                self.left.push(left);
+                if self.stereo {
+                    self.right.push(right);
+                }
+            }
+            if count < DRAIN_CHUNK {
+                break;
+            }
+        }
+    }
+
+    /// Frames lost because the ring was full when the callback pushed
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The whole take, including anything still queued
+    pub fn finish(mut self) -> Sample {
+        self.drain();
+        Sample {
+            data: self.left,
+            right: self.stereo.then_some(self.right),
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_accumulates_converted_frames_across_callbacks() {
+        let (mut input, mut take) = record_channel(2, 100);
+        input.push(&[i16::MIN, 16_384, 0, -16_384]);
+        take.drain();
+        input.push(&[8_192i16, 0]);
+        // A draining pass with nothing new changes nothing
+        take.drain();
+        take.drain();
+        let sample = take.finish();
+        assert_eq!(sample.sample_rate, 100);
+        assert_eq!(sample.data, vec![-1.0, 0.0, 0.25]);
+        assert_eq!(sample.right, Some(vec![0.5, -0.5, 0.0]));
+
+        // Mono unsigned input, longer than one drain chunk
+        let (mut input, take) = record_channel(1, 48000);
+        let block: Vec<u8> = (0..700).map(|i| if i % 2 == 0 { 192 } else { 64 }).collect();
+        input.push(&block);
+        input.push(&block);
+        let sample = take.finish();
+        assert_eq!(sample.right, None);
+        assert_eq!(sample.data.len(), 1400);
+        assert_eq!(&sample.data[..2], &[0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_full_ring_counts_dropped_frames() {
+        let (mut input, take) = record_channel(1, DRAIN_CHUNK as u32 / RING_SECONDS as u32);
+        input.push(&vec![0.5f32; DRAIN_CHUNK + 10]);
+        assert_eq!(take.dropped(), 10);
+        assert_eq!(take.finish().data.len(), DRAIN_CHUNK);
+    }
+}
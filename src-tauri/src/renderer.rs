@@ -0,0 +1,2522 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - RENDERER
+// Real-time render state, owned by value by the audio callback
+// ============================================================
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::automation::{
+    self, AutomationLane, AutomationPoint, AUTOMATION_CONTROL_FRAMES, MAX_AUTOMATION_LANES,
+};
+use crate::delay::{NoteDivision, MAX_DELAY_TONE_HZ};
+use crate::lfo::{LfoRate, LfoShape, MAX_LFO_RATE, MIN_LFO_RATE};
+use crate::meter::gain_to_db;
+use crate::metronome::Metronome;
+use crate::midi::MidiParam;
+use crate::loudness::DEFAULT_AUTOGAIN_TARGET;
+use crate::mixer::{
+    index_after_removal, ClipMode, Compressor, LimiterRelease, Listen, MasterStage, Mixer,
+    Monitor, PanLaw, PanMode, SoftClipper, TrackInput, TransferEffect, TransferPoint,
+    DEFAULT_ALLPASS_HZ, DEFAULT_HPF_HZ, DEFAULT_MASTER_CHAIN, GATE_OFF_DB, MASTER_STAGES,
+    MAX_CRUSH_BITS, NUM_SUB_BUSES,
+};
+use crate::modulation::{ModLfo, ModMatrix};
+use crate::sampler::{
+    LoadedSample, Replaced, Sample, SamplePlayer, MAX_LOOP_CROSSFADE_MS, MAX_SAMPLE_SPEED,
+    MIN_SAMPLE_SPEED,
+};
+use crate::sequencer::{Sequencer, TimeSignature, MAX_BPM, MIN_BPM, STEPS_PER_BEAT};
+use crate::synth::{Adsr, Oscillator, MAX_FREQUENCY, MIN_FREQUENCY};
+use crate::{
+    load_f64, AudioCommand, AudioState, EngineEvent, SharedState, DEFAULT_NUM_TRACKS, MAX_TRACKS,
+};
+
+// ============================================================
+// TRACK STATE (for per-track volume/pan/mute/solo)
+// ============================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackState {
+    pub volume: f64,
+    pub pan: f64,
+    pub muted: bool,
+    pub soloed: bool,
+    /// Keeps playing while other tracks are soloed
+    pub solo_safe: bool,
+    pub pan_mode: PanMode,
+    pub polarity_inverted: bool,
+    pub trim: f64,    // dB
+    pub hpf_on: bool,
+    pub hpf_freq: f64,  // Hz
+    pub allpass_on: bool,
+    pub allpass_freq: f64, // Hz
+    pub eq_low: f64,  // dB
+    pub eq_mid: f64,  // dB
+    pub eq_high: f64, // dB
+    pub frequency: f64, // oscillator pitch, Hz
+    pub crush_bits: u32,
+    pub crush_downsample: u32,
+    pub gate_threshold: f64, // dB
+    pub gate_attack: f64,    // ms
+    pub gate_hold: f64,      // ms
+    pub gate_release: f64,   // ms
+    pub gate_lookahead: f64, // ms
+    pub comp_threshold: f64, // dB
+    pub comp_ratio: f64,     // n:1, 1.0 = off
+    pub comp_attack: f64,    // ms
+    pub comp_release: f64,   // ms
+    // Track whose level drives the compressor instead of this one's own
+    pub sidechain: Option<usize>,
+    pub send_delay: f64,
+    pub send_reverb: f64,
+    pub env_attack: f64,  // ms
+    pub env_decay: f64,   // ms
+    pub env_sustain: f64, // level
+    pub env_release: f64, // ms
+    pub sample_speed: f64, // playback ratio, 1.0 = original pitch
+    pub sample_loop: bool,
+    pub sample_loop_crossfade: f64, // ms
+    pub sample_reverse: bool,
+    pub unison_voices: usize,
+    pub unison_detune: f64, // cents
+    // Track whose oscillator hard-syncs this one's
+    pub sync_source: Option<usize>,
+    // Sub-bus the track feeds; `None` goes straight to the master
+    pub bus: Option<usize>,
+    pub autopan_rate: f64,  // Hz
+    pub autopan_depth: f64, // 0.0 = static pan, 1.0 = full sweep
+    pub autopan_shape: LfoShape,
+    pub tremolo_rate: LfoRate,
+    pub tremolo_depth: f64, // 0.0 = steady, 1.0 = dips to silence
+    pub tremolo_shape: LfoShape,
+}
+
+// New tracks step up an octave from A1, wrapping after this many so high
+// tracks stay well below Nyquist
+const BASE_FREQUENCY: f64 = 55.0;
+const FREQUENCY_OCTAVES: usize = 7;
+
+impl TrackState {
+    pub fn for_track(track: usize) -> Self {
+        Self {
+            frequency: BASE_FREQUENCY * (1 << (track % FREQUENCY_OCTAVES)) as f64,
+            ..Self::default()
+        }
+    }
+
+    /// Static pan swept by the auto-pan LFO, `seconds` into the transport
+    #[inline]
+    pub fn pan_at(&self, seconds: f64) -> f64 {
+        if self.autopan_depth == 0.0 {
+            return self.pan;
+        }
+        let lfo = self.autopan_shape.value(seconds * self.autopan_rate);
+        (self.pan + self.autopan_depth * lfo).clamp(-1.0, 1.0)
+    }
+
+    /// Fader volume dipped by the tremolo LFO at the given transport position
+    #[inline]
+    pub fn volume_at(&self, seconds: f64, beats: f64) -> f64 {
+        if self.tremolo_depth == 0.0 {
+            return self.volume;
+        }
+        let lfo = self.tremolo_shape.value(self.tremolo_rate.cycles(seconds, beats));
+        self.volume * (1.0 - self.tremolo_depth * (1.0 - lfo) * 0.5)
+    }
+
+    /// Follow `removed` leaving the track list: sync and sidechain from it
+    /// are dropped and later indices shift down
+    pub fn track_removed(&mut self, removed: usize) {
+        self.sync_source = index_after_removal(self.sync_source, removed);
+        self.sidechain = index_after_removal(self.sidechain, removed);
+    }
+}
+
+/// `None` (the master) or an existing sub-bus
+pub fn bus_valid(bus: Option<usize>) -> bool {
+    bus.is_none_or(|b| b < NUM_SUB_BUSES)
+}
+
+/// Whether `track` may hard-sync to `master`, given each of `count` tracks'
+/// current `sync_source`. Chains aren't allowed, so every master can run
+/// before all of its slaves in one pass.
+pub fn sync_allowed(
+    count: usize,
+    sync_source: impl Fn(usize) -> Option<usize>,
+    track: usize,
+    master: usize,
+) -> bool {
+    track < count
+        && master < count
+        && track != master
+        && sync_source(master).is_none()
+        && (0..count).all(|i| sync_source(i) != Some(track))
+}
+
+impl Default for TrackState {
+    fn default() -> Self {
+        Self {
+            volume: 0.7,
+            pan: 0.0,
+            muted: false,
+            soloed: false,
+            solo_safe: false,
+            pan_mode: PanMode::Auto,
+            polarity_inverted: false,
+            trim: 0.0,
+            hpf_on: false,
+            hpf_freq: DEFAULT_HPF_HZ,
+            allpass_on: false,
+            allpass_freq: DEFAULT_ALLPASS_HZ,
+            eq_low: 0.0,
+            eq_mid: 0.0,
+            eq_high: 0.0,
+            frequency: BASE_FREQUENCY,
+            crush_bits: MAX_CRUSH_BITS,
+            crush_downsample: 1,
+            gate_threshold: GATE_OFF_DB,
+            gate_attack: 1.0,
+            gate_hold: 50.0,
+            gate_release: 100.0,
+            gate_lookahead: 0.0,
+            comp_threshold: 0.0,
+            comp_ratio: 1.0,
+            comp_attack: 10.0,
+            comp_release: 100.0,
+            sidechain: None,
+            send_delay: 0.0,
+            send_reverb: 0.0,
+            env_attack: 1.0,
+            env_decay: 300.0,
+            env_sustain: 0.0,
+            env_release: 300.0,
+            sample_speed: 1.0,
+            sample_loop: false,
+            sample_loop_crossfade: 0.0,
+            sample_reverse: false,
+            unison_voices: 1,
+            unison_detune: 0.0,
+            sync_source: None,
+            bus: None,
+            autopan_rate: 1.0,
+            autopan_depth: 0.0,
+            autopan_shape: LfoShape::Sine,
+            tremolo_rate: LfoRate::Hz(4.0),
+            tremolo_depth: 0.0,
+            tremolo_shape: LfoShape::Sine,
+        }
+    }
+}
+
+/// What soloing a track does to the other solos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoloMode {
+    /// Solos add up; every soloed track plays
+    #[default]
+    Additive,
+    /// Soloing a track un-solos all the others (radio buttons)
+    Exclusive,
+}
+
+// ============================================================
+// MASTER EFFECTS STATE
+// ============================================================
+
+/// One sub-bus's fader and processing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusState {
+    pub volume: f64,
+    pub muted: bool,
+    pub eq_low: f64,  // dB
+    pub eq_mid: f64,  // dB
+    pub eq_high: f64, // dB
+    pub comp_threshold: f64, // dB
+    pub comp_ratio: f64,
+    pub comp_attack: f64,  // ms
+    pub comp_release: f64, // ms
+    pub comp_makeup: f64,  // dB
+}
+
+impl Default for BusState {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+            eq_low: 0.0,
+            eq_mid: 0.0,
+            eq_high: 0.0,
+            comp_threshold: 0.0,
+            comp_ratio: 1.0,
+            comp_attack: 10.0,
+            comp_release: 100.0,
+            comp_makeup: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MasterEffects {
+    pub eq_low: f64,    // dB
+    pub eq_mid: f64,    // dB
+    pub eq_high: f64,   // dB
+    pub eq_ms_mode: bool,
+    pub side_eq_low: f64,  // dB
+    pub side_eq_mid: f64,  // dB
+    pub side_eq_high: f64, // dB
+    pub comp_threshold: f64, // dB
+    pub comp_ratio: f64,
+    pub comp_attack: f64,  // ms
+    pub comp_release: f64, // ms
+    pub comp_makeup: f64,  // dB
+    pub comp_knee: f64,    // dB, 0.0 = hard knee
+    pub delay_division: NoteDivision,
+    pub delay_feedback: f64,
+    pub delay_mix: f64,
+    pub delay_pingpong: bool,
+    pub delay_tone: f64,            // Hz
+    pub delay_low_cut: Option<f64>, // Hz
+    pub reverb_size: f64,
+    pub reverb_damping: f64,
+    pub reverb_mix: f64,
+    pub chorus_rate: f64, // Hz
+    pub chorus_depth: f64,
+    pub chorus_mix: f64,
+    pub limiter_threshold: f64,
+    pub limiter_true_peak: bool,
+    pub limiter_release: LimiterRelease,
+    pub clip_amount: f64,
+    pub clip_mode: ClipMode,
+    pub oversampling: usize, // 1, 2 or 4
+    pub clip_bypass: bool,
+    pub clip_makeup: f64, // dB
+    pub output_ceiling: f64, // dBFS
+    pub dc_block: bool,
+    pub crossfeed: f64,
+    /// Order the master stages run in
+    pub chain_order: [MasterStage; MASTER_STAGES],
+    pub stereo_width: f64,
+    pub mono: bool,
+    pub pan_law: PanLaw,
+    pub solo_mode: SoloMode,
+    pub autogain: bool,
+    pub autogain_target: f64, // LUFS
+    pub buses: [BusState; NUM_SUB_BUSES],
+}
+
+impl Default for MasterEffects {
+    fn default() -> Self {
+        Self {
+            eq_low: 0.0,
+            eq_mid: 0.0,
+            eq_high: 0.0,
+            eq_ms_mode: false,
+            side_eq_low: 0.0,
+            side_eq_mid: 0.0,
+            side_eq_high: 0.0,
+            comp_threshold: 0.0,
+            comp_ratio: 1.0,
+            comp_attack: 10.0,
+            comp_release: 100.0,
+            comp_makeup: 0.0,
+            comp_knee: 0.0,
+            delay_division: NoteDivision::default(),
+            delay_feedback: 0.35,
+            delay_mix: 0.0,
+            delay_pingpong: false,
+            delay_tone: MAX_DELAY_TONE_HZ,
+            delay_low_cut: None,
+            reverb_size: 0.5,
+            reverb_damping: 0.5,
+            reverb_mix: 0.0,
+            chorus_rate: 0.8,
+            chorus_depth: 0.5,
+            chorus_mix: 0.0,
+            limiter_threshold: 0.95,
+            limiter_true_peak: false,
+            limiter_release: LimiterRelease::default(),
+            clip_amount: 2.0,
+            clip_mode: ClipMode::default(),
+            oversampling: 1,
+            clip_bypass: false,
+            clip_makeup: 0.0,
+            output_ceiling: 0.0,
+            dc_block: true,
+            crossfeed: 0.0,
+            chain_order: DEFAULT_MASTER_CHAIN,
+            stereo_width: 1.0,
+            mono: false,
+            pan_law: PanLaw::default(),
+            solo_mode: SoloMode::default(),
+            autogain: false,
+            autogain_target: DEFAULT_AUTOGAIN_TARGET,
+            buses: Default::default(),
+        }
+    }
+}
+
+impl MasterEffects {
+    /// The master EQ's response in dB at `frequencies`: both channels, or
+    /// the mid in M/S mode
+    pub fn eq_response(&self, frequencies: &[f64], sample_rate: f64) -> Vec<f64> {
+        let gains = [self.eq_low, self.eq_mid, self.eq_high];
+        Mixer::eq_response(gains, frequencies, sample_rate)
+    }
+
+    /// `effect`'s static curve with these settings, built on fresh
+    /// processors so nothing live is touched
+    pub fn transfer_curve(&self, effect: TransferEffect, points: usize) -> Vec<TransferPoint> {
+        match effect {
+            TransferEffect::Clipper => {
+                let mut clipper = SoftClipper::master();
+                clipper.set_amount(self.clip_amount);
+                clipper.mode = self.clip_mode;
+                clipper.bypass = self.clip_bypass;
+                clipper.set_makeup_db(self.clip_makeup);
+                clipper.transfer_curve(points)
+            }
+            TransferEffect::Compressor => {
+                // Times don't shape a static curve, so any rate will do
+                let mut comp = Compressor::new(48000.0);
+                comp.set(
+                    self.comp_threshold,
+                    self.comp_ratio,
+                    self.comp_attack,
+                    self.comp_release,
+                    self.comp_makeup,
+                );
+                comp.set_knee(self.comp_knee);
+                comp.transfer_curve(points)
+            }
+        }
+    }
+}
+
+// ============================================================
+// RENDERER
+// ============================================================
+
+/// Heap memory the callback let go of, sent back to the audio thread's
+/// control loop to be freed there rather than in the callback
+pub enum Retired {
+    Mixer(Box<Mixer>),
+    Sample(Arc<Sample>),
+    Load(Receiver<LoadedSample>),
+    Player(Replaced),
+    Points(Vec<AutomationPoint>),
+}
+
+/// Everything the audio callback needs to produce sound.
+///
+/// The callback owns this by value and only ever changes it through
+/// `apply`, so rendering never takes a lock.
+pub struct Renderer {
+    mixer: Mixer,
+    track_states: Vec<TrackState>,
+    master_effects: MasterEffects,
+    oscillators: Vec<Oscillator>,
+    // Per-track oscillator amplitude, triggered on active steps and
+    // released on inactive ones
+    envelopes: Vec<Adsr>,
+    // Tracks with a loaded sample play it instead of the oscillator
+    players: Vec<SamplePlayer>,
+    // Frozen tracks play this render of their strip output, following the
+    // playhead, instead of their voice and strip
+    frozen: Vec<Option<Arc<Sample>>>,
+    sequencer: Sequencer,
+    // Parameter lanes followed during playback
+    automation: Vec<AutomationLane>,
+    // Frames played since Play, for the automation control rate
+    automation_frame: usize,
+    // LFOs moving parameters around the values last set for them
+    modulation: ModMatrix,
+    // Beat click; never copied into offline renders
+    metronome: Metronome,
+    // Dim / mono on the way to the device; likewise live-only
+    monitor: Monitor,
+    // Tracks the monitor listens to instead of the main mix; live-only
+    listen: [Listen; MAX_TRACKS],
+    // Transport as last commanded (the shared flag may be set early by the
+    // Tauri side)
+    playing: bool,
+    // Set by Play so the step under the playhead sounds immediately
+    trigger_pending: bool,
+    // Steps of count-in left before playback starts
+    count_in_steps: usize,
+    // Scratch buffer reused every frame to avoid allocating in the callback
+    track_samples: Vec<TrackInput>,
+    // This frame's oscillator wraps, read by hard-synced tracks
+    sync_wraps: [Option<f64>; MAX_TRACKS],
+    // Step phase accumulator (persists across callbacks so steps advance
+    // even when a buffer is shorter than one step)
+    step_phase: f64,
+    sample_rate: u32,
+
+    // Shared with the Tauri side
+    shared: SharedState,
+    state_tx: Sender<EngineEvent>,
+    // Everything replaced or removed goes back here to be freed off the
+    // audio thread
+    retired_tx: Sender<Retired>,
+}
+
+impl Renderer {
+    pub fn new(
+        sample_rate: u32,
+        shared: SharedState,
+        state_tx: Sender<EngineEvent>,
+        retired_tx: Sender<Retired>,
+    ) -> Self {
+        let mut renderer = Self {
+            mixer: Mixer::new(sample_rate as f64, 0),
+            track_states: Vec::with_capacity(MAX_TRACKS),
+            master_effects: MasterEffects::default(),
+            oscillators: Vec::with_capacity(MAX_TRACKS),
+            envelopes: Vec::with_capacity(MAX_TRACKS),
+            players: Vec::with_capacity(MAX_TRACKS),
+            frozen: Vec::with_capacity(MAX_TRACKS),
+            sequencer: Sequencer::new(0),
+            automation: Vec::with_capacity(MAX_AUTOMATION_LANES),
+            automation_frame: 0,
+            modulation: ModMatrix::new(),
+            metronome: Metronome::default(),
+            monitor: Monitor::new(sample_rate as f64, false, false),
+            listen: [Listen::Off; MAX_TRACKS],
+            playing: false,
+            trigger_pending: false,
+            count_in_steps: 0,
+            track_samples: Vec::with_capacity(MAX_TRACKS),
+            sync_wraps: [None; MAX_TRACKS],
+            step_phase: 0.0,
+            sample_rate,
+            shared,
+            state_tx,
+            retired_tx,
+        };
+        renderer.shared.sample_rate.store(sample_rate, Ordering::Relaxed);
+        renderer.set_track_count(DEFAULT_NUM_TRACKS);
+        renderer.sync_master_effects();
+        renderer
+    }
+
+    /// Append a default track, up to `MAX_TRACKS`
+    fn add_track(&mut self) {
+        let track = self.track_states.len();
+        if track >= MAX_TRACKS {
+            return;
+        }
+        self.track_states.push(TrackState::for_track(track));
+        self.oscillators.push(Oscillator::default());
+        self.envelopes.push(Adsr::new(self.sample_rate as f64));
+        self.players.push(SamplePlayer::default());
+        self.frozen.push(None);
+        self.sequencer.add_track();
+        self.mixer.add_track();
+        self.shared.meters.set_track_count(self.track_states.len());
+    }
+
+    /// Remove a track; the last one always stays
+    fn remove_track(&mut self, track: usize) {
+        if track >= self.track_states.len() || self.track_states.len() == 1 {
+            return;
+        }
+        self.track_states.remove(track);
+        for s in &mut self.track_states {
+            s.track_removed(track);
+        }
+        self.oscillators.remove(track);
+        self.envelopes.remove(track);
+        let player = self.players.remove(track);
+        self.retire(Retired::Player(player.into_replaced()));
+        if let Some(frozen) = self.frozen.remove(track) {
+            self.retire(Retired::Sample(frozen));
+        }
+        self.sequencer.remove_track(track);
+        self.listen.copy_within(track + 1.., track);
+        self.listen[MAX_TRACKS - 1] = Listen::Off;
+        let retired_tx = &self.retired_tx;
+        automation::track_removed(&mut self.automation, track, |lane| {
+            let _ = retired_tx.try_send(Retired::Points(lane.points));
+        });
+        self.modulation.track_removed(track);
+        self.mixer.remove_track(track);
+        self.shared.meters.set_track_count(self.track_states.len());
+    }
+
+    fn set_track_count(&mut self, count: usize) {
+        let count = count.clamp(1, MAX_TRACKS);
+        while self.track_states.len() < count {
+            self.add_track();
+        }
+        while self.track_states.len() > count {
+            self.remove_track(self.track_states.len() - 1);
+        }
+    }
+
+    /// A stopped-at-step-0 copy of the session with its own transport, for
+    /// rendering offline while this renderer keeps playing
+    pub fn offline_copy(&self, sample_rate: u32) -> Renderer {
+        // Offline renders don't report steps to the UI
+        let (state_tx, _) = bounded(1);
+        let shared = SharedState::new(
+            self.shared.bpm.load(Ordering::Relaxed),
+            self.track_states.len(),
+        );
+        let mut copy = Renderer::new(sample_rate, shared, state_tx, self.retired_tx.clone());
+        copy.set_track_count(self.track_states.len());
+
+        copy.track_states = self.track_states.clone();
+        copy.oscillators = self.oscillators.clone();
+        copy.oscillators.iter_mut().for_each(Oscillator::reset);
+        copy.master_effects = self.master_effects.clone();
+        copy.mixer.set_master_volume(self.mixer.master_volume());
+        copy.sequencer = self.sequencer.clone();
+        copy.automation.clone_from(&self.automation);
+        copy.modulation.clone_from(&self.modulation);
+        copy.players = self.players.clone();
+        for player in &mut copy.players {
+            player.stop();
+            player.detach_load();
+            player.resample(sample_rate);
+        }
+        copy.frozen = self.frozen.clone();
+
+        copy.sync_master_effects();
+        for track in 0..copy.track_states.len() {
+            copy.sync_track_strip(track);
+        }
+        copy
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.sequencer.time_signature()
+    }
+
+    /// Set every automated parameter to its lane's value at `step`. Track
+    /// volume and pan follow every frame; the rest, whose setters can
+    /// recompute filters, every `AUTOMATION_CONTROL_FRAMES` frames.
+    fn automate(&mut self, step: f64) {
+        let control = self.automation_frame.is_multiple_of(AUTOMATION_CONTROL_FRAMES);
+        self.automation_frame = self.automation_frame.wrapping_add(1);
+        for i in 0..self.automation.len() {
+            let lane = &self.automation[i];
+            let (param, Some(value)) = (lane.param, lane.value_at(step)) else {
+                continue;
+            };
+            let per_frame =
+                matches!(param, MidiParam::TrackVolume { .. } | MidiParam::TrackPan { .. });
+            if per_frame || control {
+                self.modulation.set_base(param, value);
+                self.set_param(param, value);
+            }
+        }
+    }
+
+    /// Make `track` the only one heard, unfrozen, and keep its pre-fader
+    /// signal for `captured`. For freezing, on an `offline_copy`.
+    pub fn capture_track(&mut self, track: usize) {
+        if track >= self.track_states.len() {
+            return;
+        }
+        for (i, s) in self.track_states.iter_mut().enumerate() {
+            s.muted = i != track;
+            s.soloed = false;
+        }
+        self.frozen[track] = None;
+        self.sync_track_strip(track);
+        self.mixer.set_capture(Some(track));
+    }
+
+    /// The captured track's strip output from the last rendered frame
+    pub fn captured(&self) -> (f64, Option<f64>) {
+        self.mixer.captured()
+    }
+
+    /// Re-create the mixer for a device running at a different rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.shared.sample_rate.store(sample_rate, Ordering::Relaxed);
+        if sample_rate == self.sample_rate {
+            return;
+        }
+
+        let master_volume = self.mixer.master_volume();
+        self.mixer = Mixer::new(sample_rate as f64, self.track_states.len());
+        self.monitor = Monitor::new(sample_rate as f64, self.monitor.dim(), self.monitor.mono());
+        self.mixer.set_master_volume(master_volume);
+        self.sample_rate = sample_rate;
+        self.sync_master_effects();
+        for track in 0..self.track_states.len() {
+            self.sync_track_strip(track);
+        }
+        for player in &mut self.players {
+            player.resample(sample_rate);
+        }
+    }
+
+    /// Put `mixer` (built off the audio thread for this rate and track
+    /// count) in place of the current one, which is sent back to be freed.
+    /// One that doesn't fit goes back instead and the current one stays.
+    fn swap_mixer(&mut self, mut mixer: Box<Mixer>) {
+        let fits = mixer.sample_rate() == self.sample_rate as f64
+            && mixer.track_count() == self.track_states.len();
+        if fits {
+            std::mem::swap(&mut self.mixer, &mut mixer);
+        }
+        self.retire(Retired::Mixer(mixer));
+    }
+
+    /// Hand `retired` back to be freed. With the channel full it's freed
+    /// here after all, which beats blocking the callback.
+    fn retire(&self, retired: Retired) {
+        let _ = self.retired_tx.try_send(retired);
+    }
+
+    /// Apply a single UI command to the render state. A parameter the mod
+    /// matrix moves takes the value it sets as the base to move around.
+    pub fn apply(&mut self, cmd: AudioCommand) {
+        if let Some((param, value)) = MidiParam::from_command(&cmd) {
+            self.modulation.set_base(param, value);
+        }
+        self.execute(cmd);
+    }
+
+    /// The current (possibly modulated) value of `param`
+    fn param_value(&self, param: MidiParam) -> f64 {
+        let track = |track: usize| self.track_states.get(track);
+        let effects = &self.master_effects;
+        match param {
+            MidiParam::MasterVolume => self.mixer.master_volume(),
+            MidiParam::TrackVolume { track: t } => track(t).map_or(0.0, |s| s.volume),
+            MidiParam::TrackPan { track: t } => track(t).map_or(0.0, |s| s.pan),
+            MidiParam::TrackSendDelay { track: t } => track(t).map_or(0.0, |s| s.send_delay),
+            MidiParam::TrackSendReverb { track: t } => track(t).map_or(0.0, |s| s.send_reverb),
+            MidiParam::EqLow => effects.eq_low,
+            MidiParam::EqMid => effects.eq_mid,
+            MidiParam::EqHigh => effects.eq_high,
+            MidiParam::CompThreshold => effects.comp_threshold,
+            MidiParam::CompRatio => effects.comp_ratio,
+            MidiParam::DelayMix => effects.delay_mix,
+            MidiParam::ReverbMix => effects.reverb_mix,
+            MidiParam::ChorusMix => effects.chorus_mix,
+            MidiParam::StereoWidth => effects.stereo_width,
+        }
+    }
+
+    /// Set `param` to `value`, pushing only the mixer settings it belongs
+    /// to, so automation and the mod matrix can move it every frame
+    fn set_param(&mut self, param: MidiParam, value: f64) {
+        let effects = &mut self.master_effects;
+        match param {
+            MidiParam::MasterVolume => self.mixer.set_master_volume(value),
+            MidiParam::TrackVolume { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.volume = value.clamp(0.0, 1.0);
+                }
+            }
+            MidiParam::TrackPan { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.pan = value.clamp(-1.0, 1.0);
+                }
+            }
+            MidiParam::TrackSendDelay { track } | MidiParam::TrackSendReverb { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    if matches!(param, MidiParam::TrackSendDelay { .. }) {
+                        s.send_delay = value;
+                    } else {
+                        s.send_reverb = value;
+                    }
+                    self.mixer.set_track_sends(track, s.send_delay, s.send_reverb);
+                }
+            }
+            MidiParam::EqLow | MidiParam::EqMid | MidiParam::EqHigh => {
+                match param {
+                    MidiParam::EqLow => effects.eq_low = value,
+                    MidiParam::EqMid => effects.eq_mid = value,
+                    _ => effects.eq_high = value,
+                }
+                self.mixer.set_eq(effects.eq_low, effects.eq_mid, effects.eq_high);
+            }
+            MidiParam::CompThreshold | MidiParam::CompRatio => {
+                if param == MidiParam::CompThreshold {
+                    effects.comp_threshold = value;
+                } else {
+                    effects.comp_ratio = value;
+                }
+                self.mixer.set_compressor(
+                    effects.comp_threshold,
+                    effects.comp_ratio,
+                    effects.comp_attack,
+                    effects.comp_release,
+                    effects.comp_makeup,
+                );
+            }
+            MidiParam::DelayMix => {
+                effects.delay_mix = value;
+                self.mixer
+                    .set_delay(effects.delay_division, effects.delay_feedback, effects.delay_mix);
+            }
+            MidiParam::ReverbMix => {
+                effects.reverb_mix = value;
+                self.mixer
+                    .set_reverb(effects.reverb_size, effects.reverb_damping, effects.reverb_mix);
+            }
+            MidiParam::ChorusMix => {
+                effects.chorus_mix = value;
+                self.mixer
+                    .set_chorus(effects.chorus_rate, effects.chorus_depth, effects.chorus_mix);
+            }
+            MidiParam::StereoWidth => {
+                effects.stereo_width = value;
+                self.mixer.set_stereo_width(value);
+            }
+        }
+    }
+
+    /// Move every mod matrix destination to its value at this transport
+    /// position
+    fn modulate(&mut self, seconds: f64, beats: f64) {
+        for i in 0..self.modulation.routes.len() {
+            let Some((dest, _)) = self.modulation.destination(i) else {
+                continue;
+            };
+            if let Some(value) = self.modulation.value(dest, seconds, beats) {
+                self.set_param(dest, value);
+            }
+        }
+    }
+
+    fn execute(&mut self, cmd: AudioCommand) {
+        match cmd {
+            AudioCommand::SetVolume { value } => self.set_param(MidiParam::MasterVolume, value),
+            AudioCommand::SetTrackVolume { track, value } => {
+                self.set_param(MidiParam::TrackVolume { track }, value);
+            }
+            AudioCommand::SetTrackPan { track, value } => {
+                self.set_param(MidiParam::TrackPan { track }, value);
+            }
+            AudioCommand::ToggleMute { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.muted = !s.muted;
+                }
+            }
+            AudioCommand::ToggleSolo { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.soloed = !s.soloed;
+                    self.exclusive_solo(track);
+                }
+            }
+            AudioCommand::AddTrack => self.add_track(),
+            AudioCommand::RemoveTrack { track } => self.remove_track(track),
+            AudioCommand::SetTrackCount { count } => self.set_track_count(count),
+            AudioCommand::SetMute { track, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.muted = on;
+                }
+            }
+            AudioCommand::SetSolo { track, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.soloed = on;
+                    self.exclusive_solo(track);
+                }
+            }
+            AudioCommand::SetTrackSoloSafe { track, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.solo_safe = on;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackPanMode { track, mode } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.pan_mode = mode;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetSoloMode { mode } => self.master_effects.solo_mode = mode,
+            AudioCommand::ToggleTrackPolarity { track } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.polarity_inverted = !s.polarity_inverted;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackPolarity { track, inverted } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.polarity_inverted = inverted;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackEqLow { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.eq_low = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackEqMid { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.eq_mid = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackEqHigh { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.eq_high = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackTrim { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.trim = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackBitcrush { track, bits, downsample } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.crush_bits = bits;
+                    s.crush_downsample = downsample;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackHpf { track, freq_hz, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.hpf_freq = freq_hz;
+                    s.hpf_on = on;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackAllpass { track, freq_hz, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.allpass_freq = freq_hz;
+                    s.allpass_on = on;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackGate { track, threshold, attack, hold, release } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.gate_threshold = threshold;
+                    s.gate_attack = attack;
+                    s.gate_hold = hold;
+                    s.gate_release = release;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackGateLookahead { track, ms } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.gate_lookahead = ms;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackCompThreshold { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.comp_threshold = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackCompRatio { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.comp_ratio = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackCompAttack { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.comp_attack = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackCompRelease { track, value } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.comp_release = value;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackSidechain { track, source } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.sidechain = source;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackFrequency { track, hz } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.frequency = hz.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+                }
+            }
+            AudioCommand::SetTrackAdsr { track, attack, decay, sustain, release } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.env_attack = attack;
+                    s.env_decay = decay;
+                    s.env_sustain = sustain;
+                    s.env_release = release;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackSendDelay { track, value } => {
+                self.set_param(MidiParam::TrackSendDelay { track }, value);
+            }
+            AudioCommand::SetTrackSendReverb { track, value } => {
+                self.set_param(MidiParam::TrackSendReverb { track }, value);
+            }
+            AudioCommand::LoadSample { track, pending } => {
+                if let Some(p) = self.players.get_mut(track) {
+                    if let Some(old) = p.begin_load(pending) {
+                        self.retire(Retired::Load(old));
+                    }
+                }
+            }
+            AudioCommand::FreezeTrack { track, sample } => {
+                if let Some(frozen) = self.frozen.get_mut(track) {
+                    let old = frozen.replace(sample);
+                    self.sync_track_strip(track);
+                    if let Some(sample) = old {
+                        self.retire(Retired::Sample(sample));
+                    }
+                }
+            }
+            AudioCommand::UnfreezeTrack { track } => {
+                if let Some(frozen) = self.frozen.get_mut(track) {
+                    let old = frozen.take();
+                    self.sync_track_strip(track);
+                    if let Some(sample) = old {
+                        self.retire(Retired::Sample(sample));
+                    }
+                }
+            }
+            AudioCommand::TriggerSample { track } => {
+                if let Some(p) = self.players.get_mut(track) {
+                    p.trigger();
+                }
+            }
+            AudioCommand::TriggerTrack { track } => {
+                if track < self.track_states.len() {
+                    self.trigger_track(track);
+                }
+            }
+            AudioCommand::ReleaseTrack { track } => {
+                if let Some(envelope) = self.envelopes.get_mut(track) {
+                    envelope.release();
+                }
+            }
+            AudioCommand::SetTrackSampleSpeed { track, ratio } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.sample_speed = ratio.clamp(MIN_SAMPLE_SPEED, MAX_SAMPLE_SPEED);
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackSampleLoop { track, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.sample_loop = on;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackLoopCrossfade { track, ms } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.sample_loop_crossfade = ms.clamp(0.0, MAX_LOOP_CROSSFADE_MS);
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackReverse { track, on } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.sample_reverse = on;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackBus { track, bus } => {
+                if bus_valid(bus) {
+                    if let Some(s) = self.track_states.get_mut(track) {
+                        s.bus = bus;
+                        self.sync_track_strip(track);
+                    }
+                }
+            }
+            AudioCommand::SetTrackSync { track, master } => {
+                let states = &self.track_states;
+                let source = |i: usize| states[i].sync_source;
+                if master.is_none_or(|m| sync_allowed(states.len(), source, track, m)) {
+                    if let Some(s) = self.track_states.get_mut(track) {
+                        s.sync_source = master;
+                    }
+                }
+            }
+            AudioCommand::SetTrackUnison { track, voices, detune_cents } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.unison_voices = voices;
+                    s.unison_detune = detune_cents;
+                    self.sync_track_strip(track);
+                }
+            }
+            AudioCommand::SetTrackAutopan { track, rate, depth, shape } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.autopan_rate = rate.clamp(MIN_LFO_RATE, MAX_LFO_RATE);
+                    s.autopan_depth = depth.clamp(0.0, 1.0);
+                    s.autopan_shape = shape;
+                }
+            }
+            AudioCommand::SetTrackTremolo { track, rate, depth, shape } => {
+                if let Some(s) = self.track_states.get_mut(track) {
+                    s.tremolo_rate = rate;
+                    s.tremolo_depth = depth.clamp(0.0, 1.0);
+                    s.tremolo_shape = shape;
+                }
+            }
+            AudioCommand::SetWaveform { track, waveform } => {
+                if let Some(osc) = self.oscillators.get_mut(track) {
+                    osc.waveform = waveform;
+                }
+            }
+            AudioCommand::SetStep { track, step, on } => {
+                self.sequencer.set_step(track, step, on);
+            }
+            AudioCommand::SetLoopLength { steps } => self.sequencer.set_loop_length(steps),
+            AudioCommand::SetLoopEnabled { on } => self.sequencer.set_loop_enabled(on),
+            AudioCommand::SetSwing { amount } => self.sequencer.set_swing(amount),
+            AudioCommand::SetTimeSignature { numerator, denominator } => {
+                // The command layer validates; an invalid signature is ignored
+                if let Ok(time_signature) = TimeSignature::new(numerator, denominator) {
+                    self.sequencer.set_time_signature(time_signature);
+                }
+            }
+            AudioCommand::ClearPattern { track } => {
+                self.sequencer.clear(track);
+            }
+            AudioCommand::SetBpm { bpm } => {
+                self.shared.bpm.store(bpm.clamp(MIN_BPM, MAX_BPM), Ordering::Relaxed);
+            }
+            AudioCommand::SetEqLow { value } => self.set_param(MidiParam::EqLow, value),
+            AudioCommand::SetEqMid { value } => self.set_param(MidiParam::EqMid, value),
+            AudioCommand::SetEqHigh { value } => self.set_param(MidiParam::EqHigh, value),
+            AudioCommand::SetEqMsMode { on } => {
+                self.master_effects.eq_ms_mode = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetSideEqLow { value } => {
+                self.master_effects.side_eq_low = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetSideEqMid { value } => {
+                self.master_effects.side_eq_mid = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetSideEqHigh { value } => {
+                self.master_effects.side_eq_high = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetCompThreshold { value } => {
+                self.set_param(MidiParam::CompThreshold, value);
+            }
+            AudioCommand::SetCompRatio { value } => self.set_param(MidiParam::CompRatio, value),
+            AudioCommand::SetCompAttack { value } => {
+                self.master_effects.comp_attack = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetCompRelease { value } => {
+                self.master_effects.comp_release = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetCompMakeup { value } => {
+                self.master_effects.comp_makeup = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetCompKnee { value } => {
+                self.master_effects.comp_knee = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetDelayTimeDivision { division } => {
+                self.master_effects.delay_division = division;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetDelayFeedback { value } => {
+                self.master_effects.delay_feedback = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetDelayMix { value } => self.set_param(MidiParam::DelayMix, value),
+            AudioCommand::SetDelayPingPong { on } => {
+                self.master_effects.delay_pingpong = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetDelayTone { cutoff, low_cut } => {
+                self.master_effects.delay_tone = cutoff;
+                self.master_effects.delay_low_cut = low_cut;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetReverbSize { value } => {
+                self.master_effects.reverb_size = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetReverbDamping { value } => {
+                self.master_effects.reverb_damping = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetReverbMix { value } => self.set_param(MidiParam::ReverbMix, value),
+            AudioCommand::SetChorusRate { value } => {
+                self.master_effects.chorus_rate = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetChorusDepth { value } => {
+                self.master_effects.chorus_depth = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetChorusMix { value } => self.set_param(MidiParam::ChorusMix, value),
+            AudioCommand::SetLimiter { value } => {
+                self.master_effects.limiter_threshold = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetLimiterTruePeak { on } => {
+                self.master_effects.limiter_true_peak = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetLimiterReleaseMode { mode } => {
+                self.master_effects.limiter_release = mode;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetStereoWidth { value } => self.set_param(MidiParam::StereoWidth, value),
+            AudioCommand::SetMono { on } => {
+                self.master_effects.mono = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetPanLaw { law } => {
+                self.master_effects.pan_law = law;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetClipAmount { value } => {
+                self.master_effects.clip_amount = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetClipMode { mode } => {
+                self.master_effects.clip_mode = mode;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetOversampling { factor } => {
+                self.master_effects.oversampling = factor;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetClipBypass { on } => {
+                self.master_effects.clip_bypass = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetClipMakeup { value } => {
+                self.master_effects.clip_makeup = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetOutputCeiling { value } => {
+                self.master_effects.output_ceiling = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetDcBlock { on } => {
+                self.master_effects.dc_block = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetCrossfeed { value } => {
+                self.master_effects.crossfeed = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetMasterChainOrder { order } => {
+                self.master_effects.chain_order = order;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetAutogain { on } => {
+                self.master_effects.autogain = on;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetAutogainTarget { value } => {
+                self.master_effects.autogain_target = value;
+                self.sync_master_effects();
+            }
+            AudioCommand::SetBusVolume { bus, value } => {
+                if let Some(b) = self.master_effects.buses.get_mut(bus) {
+                    b.volume = value;
+                    self.sync_master_effects();
+                }
+            }
+            AudioCommand::SetBusMute { bus, on } => {
+                if let Some(b) = self.master_effects.buses.get_mut(bus) {
+                    b.muted = on;
+                    self.sync_master_effects();
+                }
+            }
+            AudioCommand::SetBusEq { bus, low, mid, high } => {
+                if let Some(b) = self.master_effects.buses.get_mut(bus) {
+                    b.eq_low = low;
+                    b.eq_mid = mid;
+                    b.eq_high = high;
+                    self.sync_master_effects();
+                }
+            }
+            AudioCommand::SetBusCompressor { bus, threshold, ratio, attack, release, makeup } => {
+                if let Some(b) = self.master_effects.buses.get_mut(bus) {
+                    b.comp_threshold = threshold;
+                    b.comp_ratio = ratio;
+                    b.comp_attack = attack;
+                    b.comp_release = release;
+                    b.comp_makeup = makeup;
+                    self.sync_master_effects();
+                }
+            }
+            AudioCommand::Play => {
+                if !self.playing {
+                    self.playing = true;
+                    self.trigger_pending = true;
+                    self.mixer.reset_loudness();
+                    self.automation_frame = 0;
+                }
+                self.count_in_steps = 0;
+                self.mixer.set_autogain_running(true);
+                self.shared.is_running.store(true, Ordering::Relaxed);
+            }
+            AudioCommand::PlayWithCountIn { bars } => {
+                if self.playing || self.count_in_steps > 0 {
+                    return;
+                }
+                if bars == 0 {
+                    self.apply(AudioCommand::Play);
+                    return;
+                }
+                self.count_in_steps = bars as usize * self.steps_per_bar();
+                self.step_phase = 0.0;
+                self.count_in_beat();
+            }
+            AudioCommand::Stop => {
+                self.playing = false;
+                self.mixer.reset_loudness();
+                self.mixer.set_autogain_running(false);
+                self.count_in_steps = 0;
+                self.shared.is_running.store(false, Ordering::Relaxed);
+                self.metronome.stop();
+            }
+            AudioCommand::SetAutomation { param, points } => {
+                let old = automation::set_lane(&mut self.automation, param, points);
+                if old.capacity() > 0 {
+                    self.retire(Retired::Points(old));
+                }
+            }
+            AudioCommand::ClearAutomation => {
+                while let Some(lane) = self.automation.pop() {
+                    self.retire(Retired::Points(lane.points));
+                }
+            }
+            AudioCommand::SetModLfo { lfo, rate, shape, phase } => {
+                self.modulation.set_lfo(lfo, ModLfo { rate, shape, phase });
+            }
+            AudioCommand::AddModRoute { lfo, dest, depth } => {
+                let base = self.param_value(dest);
+                self.modulation.add_route(lfo, dest, depth, base);
+            }
+            AudioCommand::RemoveModRoute { lfo, dest } => {
+                if let Some(base) = self.modulation.remove_route(lfo, dest) {
+                    self.set_param(dest, base);
+                }
+            }
+            AudioCommand::ClearModRoutes => {
+                for i in 0..self.modulation.routes.len() {
+                    if let Some((dest, base)) = self.modulation.destination(i) {
+                        self.set_param(dest, base);
+                    }
+                }
+                self.modulation.routes.clear();
+            }
+            AudioCommand::ResetMixer { mixer } => {
+                self.swap_mixer(mixer);
+                for track in 0..self.track_states.len() {
+                    self.track_states[track] = TrackState::for_track(track);
+                    self.sync_track_strip(track);
+                }
+                self.mixer.set_autogain_running(self.playing);
+                self.master_effects = MasterEffects::default();
+                self.sync_master_effects();
+                // Routes would pull the defaults back to their old bases
+                self.modulation.routes.clear();
+            }
+            AudioCommand::Panic { mixer } => {
+                let master_volume = self.mixer.master_volume();
+                self.swap_mixer(mixer);
+                self.mixer.set_master_volume(master_volume);
+                self.mixer.set_autogain_running(self.playing);
+                self.sync_master_effects();
+                for track in 0..self.track_states.len() {
+                    self.sync_track_strip(track);
+                    self.oscillators[track].reset();
+                    self.envelopes[track].kill();
+                    self.players[track].stop();
+                }
+                self.metronome.stop();
+            }
+            AudioCommand::SetPosition { step } => {
+                let step = self.sequencer.seek_step(step);
+                self.shared.current_step.store(step as u64, Ordering::Relaxed);
+                self.step_phase = 0.0;
+                // Nothing keeps sounding from the old position; the new
+                // step triggers on the next frame (or at Play)
+                for track in 0..self.track_states.len() {
+                    self.players[track].stop();
+                    self.envelopes[track].release();
+                }
+                self.trigger_pending = self.playing;
+                self.report_step(step);
+            }
+            AudioCommand::SetMetronome { on } => {
+                self.metronome.enabled = on;
+                if !on {
+                    self.metronome.stop();
+                }
+            }
+            AudioCommand::SetMonitorDim { on } => self.monitor.set_dim(on),
+            AudioCommand::SetMonitorMono { on } => self.monitor.set_mono(on),
+            AudioCommand::SetTrackListen { track, mode } => {
+                if track < self.track_states.len() {
+                    self.listen[track] = mode;
+                }
+            }
+        }
+    }
+
+    /// In exclusive solo mode, a track that just got soloed takes the solo
+    /// from every other track
+    fn exclusive_solo(&mut self, track: usize) {
+        if self.master_effects.solo_mode != SoloMode::Exclusive || !self.track_states[track].soloed
+        {
+            return;
+        }
+        for (i, s) in self.track_states.iter_mut().enumerate() {
+            s.soloed = i == track;
+        }
+    }
+
+    /// Push a track's polarity, solo safe, bus, trim, HPF, gate, EQ, compressor,
+    /// bitcrush, send and freeze settings into its strip,
+    /// and its envelope, unison and sample playback settings into the voice
+    fn sync_track_strip(&mut self, track: usize) {
+        let s = &self.track_states[track];
+        self.envelopes[track].set(
+            s.env_attack,
+            s.env_decay,
+            s.env_sustain,
+            s.env_release,
+            self.sample_rate as f64,
+        );
+        self.oscillators[track].set_unison(s.unison_voices, s.unison_detune);
+        self.players[track].set_speed(s.sample_speed);
+        self.players[track].set_looping(s.sample_loop);
+        self.players[track].set_loop_crossfade(s.sample_loop_crossfade);
+        self.players[track].set_reverse(s.sample_reverse);
+        self.mixer.set_track_polarity(track, s.polarity_inverted);
+        self.mixer.set_track_solo_safe(track, s.solo_safe);
+        self.mixer.set_track_pan_mode(track, s.pan_mode);
+        self.mixer.set_track_bus(track, s.bus);
+        self.mixer.set_track_trim(track, s.trim);
+        self.mixer.set_track_hpf(track, s.hpf_freq, s.hpf_on);
+        self.mixer.set_track_allpass(track, s.allpass_freq, s.allpass_on);
+        self.mixer
+            .set_track_gate(track, s.gate_threshold, s.gate_attack, s.gate_hold, s.gate_release);
+        self.mixer.set_track_gate_lookahead(track, s.gate_lookahead);
+        self.mixer.set_track_eq(track, s.eq_low, s.eq_mid, s.eq_high);
+        self.mixer.set_track_compressor(
+            track,
+            s.comp_threshold,
+            s.comp_ratio,
+            s.comp_attack,
+            s.comp_release,
+        );
+        self.mixer.set_track_sidechain(track, s.sidechain);
+        self.mixer.set_track_bitcrush(track, s.crush_bits, s.crush_downsample);
+        self.mixer.set_track_sends(track, s.send_delay, s.send_reverb);
+        self.mixer.set_track_frozen(track, self.frozen[track].is_some());
+    }
+
+    /// Push the master effect parameters into the mixer
+    fn sync_master_effects(&mut self) {
+        let effects = &self.master_effects;
+        self.mixer.set_eq(effects.eq_low, effects.eq_mid, effects.eq_high);
+        self.mixer
+            .set_side_eq(effects.side_eq_low, effects.side_eq_mid, effects.side_eq_high);
+        self.mixer.set_eq_ms_mode(effects.eq_ms_mode);
+        self.mixer.set_compressor(
+            effects.comp_threshold,
+            effects.comp_ratio,
+            effects.comp_attack,
+            effects.comp_release,
+            effects.comp_makeup,
+        );
+        self.mixer.set_comp_knee(effects.comp_knee);
+        self.mixer
+            .set_delay(effects.delay_division, effects.delay_feedback, effects.delay_mix);
+        self.mixer.set_delay_pingpong(effects.delay_pingpong);
+        self.mixer.set_delay_tone(effects.delay_tone, effects.delay_low_cut);
+        self.mixer
+            .set_reverb(effects.reverb_size, effects.reverb_damping, effects.reverb_mix);
+        self.mixer
+            .set_chorus(effects.chorus_rate, effects.chorus_depth, effects.chorus_mix);
+        self.mixer.set_limiter_threshold(effects.limiter_threshold);
+        self.mixer.set_limiter_true_peak(effects.limiter_true_peak);
+        self.mixer.set_limiter_release_mode(effects.limiter_release);
+        self.mixer.set_clip_amount(effects.clip_amount);
+        self.mixer.set_clip_mode(effects.clip_mode);
+        self.mixer.set_oversampling(effects.oversampling);
+        self.mixer.set_clip_bypass(effects.clip_bypass);
+        self.mixer.set_clip_makeup(effects.clip_makeup);
+        self.mixer.set_output_ceiling(effects.output_ceiling);
+        self.mixer.set_dc_block(effects.dc_block);
+        self.mixer.set_crossfeed(effects.crossfeed);
+        self.mixer.set_chain_order(effects.chain_order);
+        self.mixer.set_stereo_width(effects.stereo_width);
+        self.mixer.set_mono(effects.mono);
+        self.mixer.set_pan_law(effects.pan_law);
+        self.mixer.set_autogain(effects.autogain);
+        self.mixer.set_autogain_target(effects.autogain_target);
+        for (bus, b) in effects.buses.iter().enumerate() {
+            self.mixer.set_bus_volume(bus, b.volume);
+            self.mixer.set_bus_muted(bus, b.muted);
+            self.mixer.set_bus_eq(bus, b.eq_low, b.eq_mid, b.eq_high);
+            self.mixer.set_bus_compressor(
+                bus,
+                b.comp_threshold,
+                b.comp_ratio,
+                b.comp_attack,
+                b.comp_release,
+                b.comp_makeup,
+            );
+        }
+    }
+
+    /// Restart a track's voice: oscillator, envelope and sample
+    fn trigger_track(&mut self, track: usize) {
+        self.oscillators[track].reset();
+        self.envelopes[track].trigger();
+        self.players[track].trigger();
+    }
+
+    /// Steps in a bar and in a beat of the time signature
+    fn steps_per_bar(&self) -> usize {
+        self.time_signature().steps_per_bar() as usize
+    }
+
+    fn steps_per_beat(&self) -> usize {
+        self.time_signature().steps_per_beat() as usize
+    }
+
+    /// Retrigger every track whose pattern bit is set at `step`, and click
+    /// the metronome on beats, accenting each bar's first
+    fn trigger_step(&mut self, step: usize) {
+        if self.metronome.enabled && step.is_multiple_of(self.steps_per_beat()) {
+            let accent = step.is_multiple_of(self.steps_per_bar());
+            self.metronome.trigger(accent, self.sample_rate as f64);
+        }
+
+        for track in 0..self.track_states.len() {
+            if self.sequencer.is_active(track, step) {
+                self.trigger_track(track);
+            } else {
+                self.envelopes[track].release();
+            }
+        }
+    }
+
+    /// Click a count-in beat and tell the UI how many are left
+    fn count_in_beat(&mut self) {
+        let accent = self.count_in_steps.is_multiple_of(self.steps_per_bar());
+        self.metronome.trigger(accent, self.sample_rate as f64);
+        let beats_remaining = self.count_in_steps.div_ceil(self.steps_per_beat());
+        let _ = self.state_tx.try_send(EngineEvent::CountIn { beats_remaining });
+    }
+
+    /// Advance the count-in by one frame, starting playback when it runs out
+    fn advance_count_in(&mut self, samples_per_step: f64) {
+        self.step_phase += 1.0;
+        if self.step_phase < samples_per_step {
+            return;
+        }
+        self.step_phase -= samples_per_step;
+        self.count_in_steps -= 1;
+
+        if self.count_in_steps == 0 {
+            let _ = self.state_tx.try_send(EngineEvent::CountIn { beats_remaining: 0 });
+            self.apply(AudioCommand::Play);
+        } else if self.count_in_steps.is_multiple_of(self.steps_per_beat()) {
+            self.count_in_beat();
+        }
+    }
+
+    /// Next sample of a track's freeze, sample player or oscillator voice.
+    /// `position` is the playhead in frames, which a freeze is read at.
+    #[inline]
+    fn track_voice(&mut self, track: usize, sample_rate: f64, position: f64) -> (f64, Option<f64>) {
+        self.sync_wraps[track] = None;
+        if let Some(frozen) = &self.frozen[track] {
+            return frozen.frame_at(position * frozen.sample_rate as f64 / sample_rate);
+        }
+        if self.players[track].is_loaded() {
+            return self.players[track].next(sample_rate);
+        }
+        if !self.envelopes[track].is_active() {
+            return (0.0, None);
+        }
+
+        let frequency = self.track_states[track].frequency;
+        let envelope = self.envelopes[track].next();
+        let osc = &mut self.oscillators[track];
+        let value = osc.next(frequency, sample_rate) * envelope;
+        self.sync_wraps[track] = osc.wrapped();
+        let master = self.track_states[track].sync_source;
+        if let Some(elapsed) = master.and_then(|m| self.sync_wraps[m]) {
+            osc.sync(elapsed, frequency, sample_rate);
+        }
+        (value, None)
+    }
+
+    /// Tell the UI the playhead moved to `step`
+    fn report_step(&self, step: usize) {
+        let position = self.sequencer.position(step);
+        let _ = self.state_tx.try_send(EngineEvent::State(AudioState {
+            is_playing: self.playing,
+            current_step: step,
+            bar: position.bar,
+            beat: position.beat,
+            tick: position.tick,
+            bpm: self.shared.bpm.load(Ordering::Relaxed),
+            cpu_usage: load_f64(&self.shared.cpu_usage),
+            master_gain_db: gain_to_db(self.mixer.master_volume()),
+            master_peak_dbfs: self.shared.meters.master_peak_dbfs(),
+        }));
+    }
+
+    /// Fill an interleaved output buffer
+    pub fn render(&mut self, data: &mut [f32], channels: usize) {
+        let sample_rate = self.sample_rate as f64;
+
+        // Calculate step timing
+        let bpm_val = self.shared.bpm.load(Ordering::Relaxed) as f64;
+        let samples_per_step = (sample_rate * 60.0) / (bpm_val * 4.0);
+        self.mixer.set_tempo(bpm_val);
+
+        // The mod matrix moves once per buffer, from where the playhead starts
+        let step = self.shared.current_step.load(Ordering::Relaxed) as usize;
+        let position = self.sequencer.step_start(step, samples_per_step) + self.step_phase;
+        let beats = position / (samples_per_step * STEPS_PER_BEAT as f64);
+        self.modulate(position / sample_rate, beats);
+
+        let any_soloed = self.track_states.iter().any(|s| s.soloed);
+
+        // Samples finished loading take over from the start of a buffer
+        for track in 0..self.players.len() {
+            if let Some(replaced) = self.players[track].poll_load() {
+                self.retire(Retired::Player(replaced));
+            }
+        }
+
+        // Fill audio buffer
+        for frame in data.chunks_mut(channels) {
+            let running = self.shared.is_running.load(Ordering::Relaxed);
+            let counting_in = self.count_in_steps > 0;
+            if running && self.trigger_pending {
+                self.trigger_pending = false;
+                let step = self.shared.current_step.load(Ordering::Relaxed) as usize;
+                self.trigger_step(step);
+            }
+
+            // Modulation follows the playhead, so an export moves exactly
+            // like playback from the same position
+            let step = self.shared.current_step.load(Ordering::Relaxed) as usize;
+            let position = self.sequencer.step_start(step, samples_per_step) + self.step_phase;
+            let seconds = position / sample_rate;
+            let beats = position / (samples_per_step * STEPS_PER_BEAT as f64);
+            if running {
+                self.automate(position / samples_per_step);
+            }
+
+            // Generate samples for each track (silence while stopped, so
+            // strips and meters still ring out)
+            self.track_samples.clear();
+            for (state, &listen) in self.track_states.iter().zip(&self.listen) {
+                self.track_samples.push(TrackInput {
+                    volume: state.volume_at(seconds, beats),
+                    pan: state.pan_at(seconds),
+                    muted: state.muted,
+                    soloed: state.soloed,
+                    listen,
+                    ..TrackInput::default()
+                });
+            }
+            if running {
+                // Sync masters first, so slaves see this frame's wraps
+                for synced in [false, true] {
+                    for i in 0..self.track_states.len() {
+                        if self.track_states[i].sync_source.is_some() == synced {
+                            let (left, right) = self.track_voice(i, sample_rate, position);
+                            self.track_samples[i].left = left;
+                            self.track_samples[i].right = right;
+                        }
+                    }
+                }
+            }
+
+            // Mix all tracks
+            let mut bus = self.mixer.mix_channels(&self.track_samples, any_soloed);
+
+            // Metronome joins at the master, after the track meters
+            let click = if running || counting_in {
+                self.metronome.next(sample_rate)
+            } else {
+                0.0
+            };
+            bus.dry.0 += click;
+            bus.dry.1 += click;
+
+            // Process through master bus
+            let (out_l, out_r) = self.mixer.process_master(bus);
+            self.shared.spectrum.push((out_l + out_r) * 0.5);
+            self.shared.goniometer.push(out_l, out_r);
+            // Listened-to tracks replace the main mix on the monitor only
+            let (out_l, out_r) = match bus.listen {
+                Some((left, right)) => (left as f32, right as f32),
+                None => (out_l, out_r),
+            };
+            let (out_l, out_r) = self.monitor.process(out_l, out_r);
+
+            // Output stereo
+            if frame.len() >= 2 {
+                frame[0] = out_l;
+                frame[1] = out_r;
+            } else if frame.len() == 1 {
+                frame[0] = (out_l + out_r) * 0.5;
+            }
+
+            // Update step counter (the playhead holds its position while
+            // stopped or counting in)
+            if counting_in {
+                self.advance_count_in(samples_per_step);
+                continue;
+            }
+            if !running {
+                continue;
+            }
+            self.step_phase += 1.0;
+            let current = self.shared.current_step.load(Ordering::Relaxed) as usize;
+            let step_length = self.sequencer.step_length(current, samples_per_step);
+            if self.step_phase >= step_length {
+                self.step_phase -= step_length;
+                let step = self.sequencer.next_step(current);
+                self.shared.current_step.store(step as u64, Ordering::Relaxed);
+                self.trigger_step(step);
+                self.report_step(step);
+            }
+        }
+
+        // Publish meter readings once per buffer
+        let meters = &self.shared.meters;
+        let (track_overs, master_overs) = self.mixer.take_overs();
+        for (track, meter) in self.mixer.track_meters().iter().enumerate() {
+            meters.publish_track(track, meter, track_overs[track]);
+        }
+        meters.publish_master(self.mixer.master_meters(), master_overs);
+        meters.publish_gain_reduction(self.mixer.take_limiter_gain_reduction_db());
+        meters.publish_correlation(self.mixer.correlation());
+        meters.publish_loudness(self.mixer.loudness());
+    }
+}
+
+// ============================================================
+// RENDERER SLOT (hands the renderer back when a stream is dropped)
+// ============================================================
+
+/// Holds the renderer inside a stream callback.
+///
+/// When the stream is torn down (device switch, shutdown) the callback and
+/// this slot are dropped, and the renderer is sent back to the audio thread
+/// so the next stream continues with the same mixer and track state.
+///
+/// The audio thread can also borrow it from a running stream by setting
+/// `lend`: the callback sends it home the same way and plays silence until
+/// it comes back on `back`.
+pub struct RendererSlot {
+    renderer: Option<Renderer>,
+    home: Sender<Renderer>,
+    lend: Arc<AtomicBool>,
+    back: Receiver<Renderer>,
+}
+
+impl RendererSlot {
+    pub fn new(
+        renderer: Renderer,
+        home: Sender<Renderer>,
+        lend: Arc<AtomicBool>,
+        back: Receiver<Renderer>,
+    ) -> Self {
+        Self {
+            renderer: Some(renderer),
+            home,
+            lend,
+            back,
+        }
+    }
+
+    /// The renderer before the stream starts, when it can't be lent out
+    #[inline]
+    pub fn get(&mut self) -> &mut Renderer {
+        self.renderer
+            .as_mut()
+            .expect("renderer is only lent once the stream runs")
+    }
+
+    /// The renderer for this callback, or `None` while it's lent out.
+    /// Takes it back once returned, and lends it if asked to.
+    #[inline]
+    pub fn poll(&mut self) -> Option<&mut Renderer> {
+        if self.renderer.is_none() {
+            self.renderer = self.back.try_recv().ok();
+        }
+        if self.renderer.is_some() && self.lend.swap(false, Ordering::AcqRel) {
+            if let Some(renderer) = self.renderer.take() {
+                self.renderer = self.home.try_send(renderer).err().map(|e| e.into_inner());
+            }
+        }
+        self.renderer.as_mut()
+    }
+}
+
+impl Drop for RendererSlot {
+    fn drop(&mut self) {
+        if let Some(renderer) = self.renderer.take().or_else(|| self.back.try_recv().ok()) {
+            let _ = self.home.try_send(renderer);
+        }
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::{AutomationPoint, Interpolation};
+
+    fn test_renderer(sample_rate: u32) -> Renderer {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, _retired_rx) = bounded(1);
+        Renderer::new(sample_rate, SharedState::new(120, 7), state_tx, retired_tx)
+    }
+
+    #[test]
+    fn test_exclusive_solo_clears_other_solos() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetSolo { track: 0, on: true });
+        renderer.apply(AudioCommand::SetSolo { track: 1, on: true });
+        assert!(renderer.track_states[0].soloed && renderer.track_states[1].soloed);
+
+        // Switching modes leaves existing solos alone; the next solo clears them
+        renderer.apply(AudioCommand::SetSoloMode { mode: SoloMode::Exclusive });
+        assert!(renderer.track_states[0].soloed && renderer.track_states[1].soloed);
+        renderer.apply(AudioCommand::ToggleSolo { track: 3 });
+        let soloed: Vec<bool> = renderer.track_states.iter().map(|s| s.soloed).collect();
+        assert_eq!(soloed, [false, false, false, true, false, false, false]);
+
+        // Un-soloing doesn't bring anything back
+        renderer.apply(AudioCommand::ToggleSolo { track: 3 });
+        assert!(renderer.track_states.iter().all(|s| !s.soloed));
+    }
+
+    #[test]
+    fn test_disabled_step_is_silent() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::ToggleSolo { track: 0 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 1, on: true });
+        renderer.apply(AudioCommand::Play);
+
+        // 120 BPM at 48 kHz = 6000 frames per 16th-note step
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0), "step 0 is off");
+
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().any(|&s| s != 0.0), "step 1 is on");
+
+        renderer.apply(AudioCommand::ClearPattern { track: 0 });
+        for _ in 0..32 {
+            renderer.render(&mut buffer, 2);
+        }
+        // Only the decayed tail of the last hit remains
+        assert!(buffer.iter().all(|&s| s.abs() < 1e-4), "cleared pattern");
+    }
+
+    #[test]
+    fn test_metronome_clicks_only_when_enabled_and_playing() {
+        let mut renderer = test_renderer(48000);
+        let mut beat = vec![0.0f32; 24000 * 2];
+
+        renderer.apply(AudioCommand::SetMetronome { on: true });
+        renderer.render(&mut beat, 2);
+        assert!(beat.iter().all(|&s| s == 0.0), "stopped");
+
+        renderer.apply(AudioCommand::Play);
+        renderer.render(&mut beat, 2);
+        assert!(beat.iter().any(|&s| s != 0.0), "playing");
+
+        renderer.apply(AudioCommand::SetMetronome { on: false });
+        for _ in 0..4 {
+            renderer.render(&mut beat, 2);
+        }
+        assert!(beat.iter().all(|&s| s.abs() < 1e-4), "disabled");
+    }
+
+    #[test]
+    fn test_count_in_holds_the_playhead() {
+        let (state_tx, state_rx) = bounded(64);
+        let (retired_tx, _retired_rx) = bounded(1);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        renderer.apply(AudioCommand::PlayWithCountIn { bars: 1 });
+
+        // One bar at 120 BPM = 4 beats of 24000 frames
+        let mut beat = vec![0.0f32; 24000 * 2];
+        for _ in 0..4 {
+            assert!(!renderer.shared.is_running.load(Ordering::Relaxed));
+            renderer.render(&mut beat, 2);
+            assert!(beat.iter().any(|&s| s != 0.0), "count-in clicks");
+            assert_eq!(renderer.shared.current_step.load(Ordering::Relaxed), 0);
+        }
+        assert!(renderer.shared.is_running.load(Ordering::Relaxed));
+
+        let countdown: Vec<usize> = state_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                EngineEvent::CountIn { beats_remaining } => Some(beats_remaining),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(countdown, vec![4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_count_in_follows_the_time_signature() {
+        let (state_tx, state_rx) = bounded(64);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, bounded(1).0);
+        renderer.apply(AudioCommand::SetTimeSignature { numerator: 3, denominator: 4 });
+        renderer.apply(AudioCommand::PlayWithCountIn { bars: 1 });
+
+        // One bar of 3/4 at 120 BPM = 3 beats of 24000 frames
+        let mut beat = vec![0.0f32; 24000 * 2];
+        for _ in 0..3 {
+            assert!(!renderer.shared.is_running.load(Ordering::Relaxed));
+            renderer.render(&mut beat, 2);
+        }
+        assert!(renderer.shared.is_running.load(Ordering::Relaxed));
+
+        let countdown: Vec<usize> = state_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                EngineEvent::CountIn { beats_remaining } => Some(beats_remaining),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(countdown, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_added_track_plays_and_meters() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::AddTrack);
+        renderer.apply(AudioCommand::SetStep { track: 7, step: 0, on: true });
+        renderer.apply(AudioCommand::ToggleSolo { track: 7 });
+        renderer.apply(AudioCommand::Play);
+
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().any(|&s| s != 0.0));
+        let meters = renderer.shared.meters.snapshot();
+        assert_eq!(meters.tracks.len(), 8);
+        assert!(meters.tracks[7].peak > 0.0);
+
+        // Removing a track shifts the ones after it down, solo included
+        renderer.apply(AudioCommand::RemoveTrack { track: 0 });
+        assert!(renderer.track_states[6].soloed);
+        assert!(renderer.sequencer.is_active(6, 0));
+        assert_eq!(renderer.shared.meters.snapshot().tracks.len(), 7);
+
+        // Never below one track or above the cap
+        renderer.apply(AudioCommand::SetTrackCount { count: 0 });
+        assert_eq!(renderer.track_states.len(), 1);
+        renderer.apply(AudioCommand::RemoveTrack { track: 0 });
+        assert_eq!(renderer.track_states.len(), 1);
+        renderer.apply(AudioCommand::SetTrackCount { count: 100 });
+        assert_eq!(renderer.track_states.len(), MAX_TRACKS);
+        renderer.render(&mut buffer, 2);
+    }
+
+    #[test]
+    fn test_clip_indicator_latches_until_cleared() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::ToggleSolo { track: 0 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 0, on: true });
+        renderer.apply(AudioCommand::SetTrackTrim { track: 0, value: 24.0 });
+        renderer.apply(AudioCommand::Play);
+
+        let mut buffer = vec![0.0f32; 1000 * 2];
+        renderer.render(&mut buffer, 2);
+        let meters = renderer.shared.meters.snapshot();
+        assert!(meters.tracks[0].clipped);
+        assert!(!meters.tracks[1].clipped);
+
+        // Quiet buffers leave it lit...
+        renderer.apply(AudioCommand::Stop);
+        for _ in 0..50 {
+            renderer.render(&mut buffer, 2);
+        }
+        assert!(buffer.iter().all(|s| s.abs() < 1e-3));
+        assert!(renderer.shared.meters.snapshot().tracks[0].clipped);
+
+        // ...until it's cleared
+        renderer.shared.meters.clear_clips();
+        renderer.render(&mut buffer, 2);
+        assert!(!renderer.shared.meters.snapshot().tracks[0].clipped);
+    }
+
+    #[test]
+    fn test_volume_automation_ramps_over_its_steps() {
+        let volumes = |curve: Interpolation| {
+            let mut renderer = test_renderer(48000);
+            let points = vec![
+                AutomationPoint { step: 0.0, value: 0.0, curve },
+                AutomationPoint { step: 1.0, value: 1.0, curve },
+            ];
+            let param = MidiParam::TrackVolume { track: 0 };
+            renderer.apply(AudioCommand::SetAutomation { param, points });
+            renderer.apply(AudioCommand::Play);
+            let mut frame = [0.0f32; 2];
+            (0..7000)
+                .map(|_| {
+                    renderer.render(&mut frame, 2);
+                    renderer.track_samples[0].volume
+                })
+                .collect::<Vec<f64>>()
+        };
+
+        // 120 BPM at 48 kHz: one step is 6000 frames, each 1/6000 louder
+        let linear = volumes(Interpolation::Linear);
+        assert_eq!(linear[0], 0.0);
+        assert!((linear[3000] - 0.5).abs() < 1e-9);
+        assert!(linear.windows(2).take(5999).all(|w| (w[1] - w[0] - 1.0 / 6000.0).abs() < 1e-9));
+        assert_eq!(linear.iter().position(|&v| v == 1.0), Some(6000));
+
+        // Hold jumps at the second point instead
+        let hold = volumes(Interpolation::Hold);
+        assert!(hold[..6000].iter().all(|&v| v == 0.0));
+        assert!(hold[6000..].iter().all(|&v| v == 1.0));
+    }
+
+    #[test]
+    fn test_replaced_automation_goes_back_to_be_freed() {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, retired_rx) = bounded(8);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        let lane =
+            |value: f64| vec![AutomationPoint { step: 0.0, value, curve: Interpolation::Hold }];
+        let points = |retired: Result<Retired, _>| match retired {
+            Ok(Retired::Points(points)) => points,
+            _ => panic!("no automation points came back"),
+        };
+        let reverb = MidiParam::ReverbMix;
+        renderer.apply(AudioCommand::SetAutomation { param: reverb, points: lane(0.1) });
+        assert!(retired_rx.try_recv().is_err(), "a new lane replaces nothing");
+
+        // A lane's new points, a removed track's lanes, and a cleared lane
+        renderer.apply(AudioCommand::SetAutomation { param: reverb, points: lane(0.2) });
+        assert_eq!(points(retired_rx.try_recv()), lane(0.1));
+        let param = MidiParam::TrackVolume { track: 2 };
+        renderer.apply(AudioCommand::SetAutomation { param, points: lane(0.3) });
+        renderer.apply(AudioCommand::RemoveTrack { track: 2 });
+        assert!(matches!(retired_rx.try_recv(), Ok(Retired::Player(_))));
+        assert_eq!(points(retired_rx.try_recv()), lane(0.3));
+        renderer.apply(AudioCommand::ClearAutomation);
+        assert_eq!(points(retired_rx.try_recv()), lane(0.2));
+        assert!(renderer.automation.is_empty());
+    }
+
+    #[test]
+    fn test_reported_steps_follow_loop_length() {
+        let (state_tx, state_rx) = bounded(64);
+        let (retired_tx, _retired_rx) = bounded(1);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        renderer.apply(AudioCommand::SetLoopLength { steps: 16 });
+        renderer.apply(AudioCommand::Play);
+
+        // 20 steps of 6000 frames
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        for _ in 0..20 {
+            renderer.render(&mut buffer, 2);
+        }
+        let steps: Vec<usize> = state_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                EngineEvent::State(state) => Some(state.current_step),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<usize> = (1..=20).map(|s| s % 16).collect();
+        assert_eq!(steps, expected);
+    }
+
+    #[test]
+    fn test_autopan_sweeps_only_with_depth() {
+        let pans = |depth: f64| {
+            let mut renderer = test_renderer(48000);
+            renderer.apply(AudioCommand::SetTrackPan { track: 0, value: 0.2 });
+            let shape = LfoShape::Sine;
+            renderer.apply(AudioCommand::SetTrackAutopan { track: 0, rate: 2.0, depth, shape });
+            renderer.apply(AudioCommand::Play);
+            let mut buffer = vec![0.0f32; 480 * 2];
+            (0..100)
+                .map(|_| {
+                    renderer.render(&mut buffer, 2);
+                    renderer.track_samples[0].pan
+                })
+                .collect::<Vec<f64>>()
+        };
+
+        assert!(pans(0.0).iter().all(|&pan| pan == 0.2));
+
+        // Two cycles in the second, swinging 0.5 either side of the static pan
+        let swept = pans(0.5);
+        let min = swept.iter().copied().fold(f64::MAX, f64::min);
+        let max = swept.iter().copied().fold(f64::MIN, f64::max);
+        assert!(min < -0.29 && max > 0.69, "{} to {}", min, max);
+        let rising = swept.windows(2).filter(|w| w[1] > w[0]).count();
+        assert!((40..=60).contains(&rising), "{} rising blocks", rising);
+
+        // Derived from the playhead, so a second run moves identically
+        assert_eq!(swept, pans(0.5));
+    }
+
+    #[test]
+    fn test_tremolo_oscillates_at_its_rate() {
+        let peaks = |rate: LfoRate| {
+            let mut renderer = test_renderer(48000);
+            let shape = LfoShape::Sine;
+            renderer.apply(AudioCommand::SetTrackTremolo { track: 0, rate, depth: 0.6, shape });
+            renderer.apply(AudioCommand::Play);
+            let mut buffer = vec![0.0f32; 240 * 2];
+            let volumes: Vec<f64> = (0..400)
+                .map(|_| {
+                    renderer.render(&mut buffer, 2);
+                    renderer.track_samples[0].volume
+                })
+                .collect();
+            let full = renderer.track_states[0].volume;
+            let min = volumes.iter().copied().fold(f64::MAX, f64::min);
+            assert!((min - full * 0.4).abs() < 0.01 && volumes.iter().all(|&v| v <= full));
+            volumes.windows(3).filter(|w| w[1] > w[0] && w[1] >= w[2]).count()
+        };
+
+        // Two seconds: 3 Hz peaks 6 times, quarter notes at 120 BPM 4 times
+        assert_eq!(peaks(LfoRate::Hz(3.0)), 6);
+        assert_eq!(peaks(LfoRate::Synced(NoteDivision::Quarter)), 4);
+    }
+
+    #[test]
+    fn test_mod_route_swings_master_volume_at_the_lfo_rate() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetTrackAdsr {
+            track: 0,
+            attack: 1.0,
+            decay: 1.0,
+            sustain: 1.0,
+            release: 1.0,
+        });
+        // 400 Hz: whole cycles in every 480-frame buffer, so each buffer's
+        // RMS follows the volume alone
+        renderer.apply(AudioCommand::SetTrackFrequency { track: 0, hz: 400.0 });
+        renderer.apply(AudioCommand::SetVolume { value: 0.5 });
+        let (rate, shape) = (LfoRate::Hz(2.0), LfoShape::Sine);
+        renderer.apply(AudioCommand::SetModLfo { lfo: 0, rate, shape, phase: 0.25 });
+        renderer.apply(AudioCommand::AddModRoute {
+            lfo: 0,
+            dest: MidiParam::MasterVolume,
+            depth: 0.8,
+        });
+        // Every step on holds the note throughout
+        renderer.apply(AudioCommand::SetLoopLength { steps: 16 });
+        for step in 0..16 {
+            renderer.apply(AudioCommand::SetStep { track: 0, step, on: true });
+        }
+        renderer.apply(AudioCommand::Play);
+
+        // Two seconds of a 2 Hz swing between 0.1 and 0.9
+        let mut buffer = vec![0.0f32; 480 * 2];
+        let rms: Vec<f64> = (0..200)
+            .map(|_| {
+                renderer.render(&mut buffer, 2);
+                let power = buffer.iter().map(|&x| (x as f64).powi(2)).sum::<f64>();
+                (power / buffer.len() as f64).sqrt()
+            })
+            .collect();
+        let loudest = rms.iter().copied().fold(0.0, f64::max);
+        let quietest = rms.iter().copied().fold(f64::MAX, f64::min);
+        assert!(loudest > 4.0 * quietest, "rms {} to {}", quietest, loudest);
+        // Past the first buffer, which holds the note's attack
+        let middle = (loudest + quietest) * 0.5;
+        let rises = rms[1..].windows(2).filter(|w| w[0] < middle && w[1] >= middle).count();
+        assert_eq!(rises, 4);
+
+        // Setting the volume moves the base; removing the route restores it
+        renderer.apply(AudioCommand::SetVolume { value: 0.3 });
+        assert_eq!(renderer.modulation.base(MidiParam::MasterVolume), Some(0.3));
+        let dest = MidiParam::MasterVolume;
+        renderer.apply(AudioCommand::RemoveModRoute { lfo: 0, dest });
+        renderer.render(&mut buffer, 2);
+        assert_eq!(renderer.mixer.master_volume(), 0.3);
+    }
+
+    #[test]
+    fn test_seek_moves_and_reports_the_playhead() {
+        let (state_tx, state_rx) = bounded(64);
+        let (retired_tx, _retired_rx) = bounded(1);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        renderer.apply(AudioCommand::SetLoopLength { steps: 16 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 5, on: true });
+        renderer.apply(AudioCommand::Play);
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut buffer, 2);
+        renderer.render(&mut buffer, 2);
+
+        renderer.apply(AudioCommand::SetPosition { step: 5 });
+        assert_eq!(renderer.shared.current_step.load(Ordering::Relaxed), 5);
+        // The seeked-to step sounds right away, then the playhead moves on
+        let mut frame = [0.0f32; 2];
+        renderer.render(&mut frame, 2);
+        assert!(renderer.envelopes[0].is_active());
+        renderer.render(&mut buffer, 2);
+
+        // Seeks past the loop end wrap into it
+        renderer.apply(AudioCommand::SetPosition { step: 16 + 3 });
+        let steps: Vec<usize> = state_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                EngineEvent::State(state) => Some(state.current_step),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(steps, [1, 2, 5, 6, 3]);
+    }
+
+    /// Frame offsets at which the playhead moved to a new step
+    fn step_boundaries(sample_rate: u32, frames: usize, swing: f64) -> Vec<usize> {
+        let mut renderer = test_renderer(sample_rate);
+        renderer.apply(AudioCommand::SetSwing { amount: swing });
+        renderer.apply(AudioCommand::Play);
+
+        let mut frame = [0.0f32; 2];
+        let mut last_step = 0;
+        let mut boundaries = Vec::new();
+        for i in 0..frames {
+            renderer.render(&mut frame, 2);
+            let step = renderer.shared.current_step.load(Ordering::Relaxed);
+            if step != last_step {
+                boundaries.push(i + 1);
+                last_step = step;
+            }
+        }
+        boundaries
+    }
+
+    #[test]
+    fn test_step_timing_follows_sample_rate() {
+        // 120 BPM 16th notes last 125 ms
+        let at_96k = step_boundaries(96000, 96000, 0.0);
+        assert_eq!(at_96k.len(), 8);
+        assert!(at_96k.iter().enumerate().all(|(i, &f)| f == (i + 1) * 12000));
+
+        // 5512.5 samples per step: boundaries never drift more than a frame
+        let at_44k = step_boundaries(44100, 44100, 0.0);
+        assert_eq!(at_44k.len(), 8);
+        for (i, &f) in at_44k.iter().enumerate() {
+            let exact = (i + 1) as f64 * 5512.5;
+            assert!((f as f64 - exact).abs() <= 1.0, "step {} at {}", i + 1, f);
+        }
+    }
+
+    #[test]
+    fn test_swing_moves_only_odd_steps() {
+        // One bar at 120 BPM, 48 kHz: 16 steps of 6000 frames
+        let straight = step_boundaries(48000, 96000, 0.0);
+        let swung = step_boundaries(48000, 96000, 0.5);
+        assert_eq!(straight.len(), 16);
+        assert_eq!(swung.len(), 16);
+
+        for (i, (&s, &w)) in straight.iter().zip(&swung).enumerate() {
+            let step = i + 1;
+            if step % 2 == 1 {
+                assert_eq!(w, s + 3000, "odd step {} is pushed half a step late", step);
+            } else {
+                assert_eq!(w, s, "even step {} stays on the grid", step);
+            }
+        }
+        assert_eq!(swung[15], 96000, "bar length is unchanged");
+    }
+
+    #[test]
+    fn test_panic_cuts_a_reverb_tail_but_keeps_settings() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetVolume { value: 0.5 });
+        renderer.apply(AudioCommand::SetReverbSize { value: 1.0 });
+        renderer.apply(AudioCommand::SetReverbMix { value: 1.0 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 0, on: true });
+        renderer.apply(AudioCommand::Play);
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut buffer, 2);
+        renderer.apply(AudioCommand::Stop);
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().any(|&s| s != 0.0), "the tail rings on after Stop");
+
+        let mixer = Box::new(Mixer::new(48000.0, 7));
+        renderer.apply(AudioCommand::Panic { mixer });
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0), "no tail after the panic");
+        assert_eq!(renderer.mixer.master_volume(), 0.5);
+        assert_eq!(renderer.master_effects.reverb_mix, 1.0);
+        assert!(renderer.sequencer.is_active(0, 0));
+    }
+
+    #[test]
+    fn test_reset_mixer_restores_defaults() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetVolume { value: 0.1 });
+        renderer.apply(AudioCommand::SetTrackVolume { track: 1, value: 0.2 });
+        renderer.apply(AudioCommand::SetTrackPan { track: 2, value: -1.0 });
+        renderer.apply(AudioCommand::ToggleMute { track: 3 });
+        renderer.apply(AudioCommand::SetSolo { track: 4, on: true });
+        renderer.apply(AudioCommand::SetTrackEqLow { track: 0, value: 6.0 });
+        renderer.apply(AudioCommand::SetEqHigh { value: -6.0 });
+        renderer.apply(AudioCommand::SetLimiter { value: 0.5 });
+        renderer.apply(AudioCommand::SetClipAmount { value: 8.0 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 0, on: true });
+
+        // Leave a delay tail ringing
+        renderer.apply(AudioCommand::SetDelayMix { value: 1.0 });
+        renderer.apply(AudioCommand::Play);
+        let mut buffer = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut buffer, 2);
+        renderer.apply(AudioCommand::Stop);
+
+        let mixer = Box::new(Mixer::new(48000.0, 7));
+        renderer.apply(AudioCommand::ResetMixer { mixer });
+        for (track, state) in renderer.track_states.iter().enumerate() {
+            assert_eq!(*state, TrackState::for_track(track));
+        }
+        assert_eq!(renderer.master_effects, MasterEffects::default());
+        assert_eq!(renderer.mixer.master_volume(), 0.8);
+        assert!(renderer.sequencer.is_active(0, 0), "patterns are kept");
+
+        renderer.render(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0), "no tail after the reset");
+    }
+
+    #[test]
+    fn test_swapped_out_mixers_go_back_to_be_freed() {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, retired_rx) = bounded(2);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        renderer.apply(AudioCommand::SetReverbMix { value: 1.0 });
+
+        let retired_mixer = || match retired_rx.try_recv() {
+            Ok(Retired::Mixer(mixer)) => mixer,
+            _ => panic!("no mixer came back"),
+        };
+        renderer.apply(AudioCommand::ResetMixer { mixer: Box::new(Mixer::new(48000.0, 7)) });
+        assert_eq!(retired_mixer().track_count(), 7);
+
+        // Built for another rate: it goes back and the current one stays
+        renderer.apply(AudioCommand::SetReverbMix { value: 1.0 });
+        let wrong_rate = Box::new(Mixer::new(44100.0, 7));
+        renderer.apply(AudioCommand::ResetMixer { mixer: wrong_rate });
+        assert_eq!(retired_mixer().sample_rate(), 44100.0);
+        assert_eq!(renderer.mixer.sample_rate(), 48000.0);
+        assert_eq!(renderer.master_effects, MasterEffects::default());
+    }
+
+    #[test]
+    fn test_offline_copy_renders_identically() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetStep { track: 2, step: 0, on: true });
+        renderer.apply(AudioCommand::SetStep { track: 5, step: 3, on: true });
+        renderer.apply(AudioCommand::SetTrackEqHigh { track: 2, value: 6.0 });
+        renderer.apply(AudioCommand::SetEqLow { value: -3.0 });
+
+        let mut offline = renderer.offline_copy(48000);
+        renderer.apply(AudioCommand::Play);
+        offline.apply(AudioCommand::Play);
+
+        let mut live_buf = vec![0.0f32; 24000 * 2];
+        let mut offline_buf = vec![0.0f32; 24000 * 2];
+        renderer.render(&mut live_buf, 2);
+        offline.render(&mut offline_buf, 2);
+        assert_eq!(live_buf, offline_buf);
+        assert!(live_buf.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn test_monitor_dim_and_mono_skip_the_offline_render() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::ToggleSolo { track: 0 });
+        renderer.apply(AudioCommand::SetStep { track: 0, step: 0, on: true });
+        renderer.apply(AudioCommand::SetTrackPan { track: 0, value: -1.0 });
+        renderer.apply(AudioCommand::SetMonitorDim { on: true });
+        renderer.apply(AudioCommand::SetMonitorMono { on: true });
+
+        // Stopped first, so the pan and monitor ramps have settled
+        let mut offline = renderer.offline_copy(48000);
+        let mut live_buf = vec![0.0f32; 6000 * 2];
+        let mut offline_buf = vec![0.0f32; 6000 * 2];
+        renderer.render(&mut live_buf, 2);
+        offline.render(&mut offline_buf, 2);
+        renderer.apply(AudioCommand::Play);
+        offline.apply(AudioCommand::Play);
+        renderer.render(&mut live_buf, 2);
+        offline.render(&mut offline_buf, 2);
+
+        // The export keeps the hard-left pan at full level...
+        let peak = |buf: &[f32], channel: usize| {
+            buf.chunks(2).map(|frame| frame[channel].abs()).fold(0.0, f32::max)
+        };
+        assert!(peak(&offline_buf, 0) > 0.1);
+        assert!(peak(&offline_buf, 1) < 1e-6);
+
+        // ...while the live output is folded to mono 20 dB down
+        assert!(live_buf.chunks(2).all(|frame| frame[0] == frame[1]));
+        let ratio = peak(&live_buf, 0) / peak(&offline_buf, 0);
+        assert!((ratio - 0.5 * 0.1).abs() < 0.005, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_frozen_track_matches_live_playback() {
+        let mut renderer = test_renderer(48000);
+        renderer.apply(AudioCommand::SetStep { track: 1, step: 0, on: true });
+        renderer.apply(AudioCommand::SetStep { track: 1, step: 6, on: true });
+        renderer.apply(AudioCommand::SetTrackEqHigh { track: 1, value: 6.0 });
+        renderer.apply(AudioCommand::SetTrackBitcrush { track: 1, bits: 6, downsample: 2 });
+        renderer.apply(AudioCommand::SetTrackPan { track: 1, value: -0.4 });
+        let play = |renderer: &Renderer| {
+            let mut copy = renderer.offline_copy(48000);
+            copy.apply(AudioCommand::Play);
+            copy
+        };
+
+        // One bar at 120 BPM
+        let frames = 96000;
+        let mut live = vec![0.0f32; frames * 2];
+        play(&renderer).render(&mut live, 2);
+
+        let sample = crate::export::render_track(&mut play(&renderer), 1, 1, 120);
+        assert_eq!(sample.data.len(), frames);
+        renderer.apply(AudioCommand::FreezeTrack { track: 1, sample: Arc::new(sample) });
+        let mut frozen = vec![0.0f32; frames * 2];
+        play(&renderer).render(&mut frozen, 2);
+
+        let error = live.iter().zip(&frozen).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(error < 1e-5, "frozen output differs by {}", error);
+        assert!(live.iter().any(|&s| s.abs() > 0.01));
+
+        // Live synthesis is back after unfreezing (and follows edits again)
+        renderer.apply(AudioCommand::UnfreezeTrack { track: 1 });
+        renderer.apply(AudioCommand::SetStep { track: 1, step: 6, on: false });
+        let mut edited = vec![0.0f32; frames * 2];
+        play(&renderer).render(&mut edited, 2);
+        assert_ne!(edited, live);
+    }
+
+    #[test]
+    fn test_replaced_freezes_go_back_to_be_freed() {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, retired_rx) = bounded(4);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        let freeze = |frames| {
+            let sample = Sample { data: vec![0.0; frames], right: None, sample_rate: 48000 };
+            AudioCommand::FreezeTrack { track: 2, sample: Arc::new(sample) }
+        };
+        let retired_frames = || match retired_rx.try_recv() {
+            Ok(Retired::Sample(sample)) => sample.data.len(),
+            _ => panic!("no sample came back"),
+        };
+
+        renderer.apply(freeze(10));
+        assert!(retired_rx.try_recv().is_err());
+        renderer.apply(freeze(20));
+        assert_eq!(retired_frames(), 10);
+        renderer.apply(AudioCommand::UnfreezeTrack { track: 2 });
+        assert_eq!(retired_frames(), 20);
+    }
+
+    #[test]
+    fn test_replaced_samples_go_back_to_be_freed() {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, retired_rx) = bounded(4);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        let mut buffer = [0.0f32; 2];
+        let mut load = |value| {
+            let sample = Arc::new(Sample { data: vec![value; 8], right: None, sample_rate: 48000 });
+            let (loaded_tx, pending) = bounded(1);
+            loaded_tx.send(LoadedSample { sample: sample.clone(), source: sample }).unwrap();
+            renderer.apply(AudioCommand::LoadSample { track: 0, pending });
+            renderer.render(&mut buffer, 2);
+        };
+
+        load(0.25);
+        match retired_rx.try_recv() {
+            Ok(Retired::Player(replaced)) => assert!(replaced.sample.is_none()),
+            _ => panic!("the spent load channel didn't come back"),
+        }
+        load(0.75);
+        match retired_rx.try_recv() {
+            Ok(Retired::Player(replaced)) => assert_eq!(replaced.sample.unwrap().data[0], 0.25),
+            _ => panic!("the replaced sample didn't come back"),
+        }
+    }
+
+    #[test]
+    fn test_slot_lends_its_renderer_and_plays_on_once_it_is_back() {
+        let (home_tx, home_rx) = bounded(1);
+        let (back_tx, back_rx) = bounded(1);
+        let lend = Arc::new(AtomicBool::new(false));
+        let mut slot = RendererSlot::new(test_renderer(48000), home_tx, lend.clone(), back_rx);
+        assert!(slot.poll().is_some());
+
+        // Lent out: nothing to render with until it comes back
+        lend.store(true, Ordering::Release);
+        assert!(slot.poll().is_none());
+        let renderer = home_rx.try_recv().expect("the renderer wasn't sent home");
+        assert!(slot.poll().is_none());
+        back_tx.send(renderer).unwrap();
+        assert!(slot.poll().is_some());
+        assert!(!lend.load(Ordering::Acquire));
+
+        // Dropped while lent, it still goes home once it's back
+        lend.store(true, Ordering::Release);
+        assert!(slot.poll().is_none());
+        back_tx.send(home_rx.try_recv().unwrap()).unwrap();
+        drop(slot);
+        assert!(home_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_removed_tracks_samples_go_back_to_be_freed() {
+        let (state_tx, _state_rx) = bounded(64);
+        let (retired_tx, retired_rx) = bounded(4);
+        let mut renderer = Renderer::new(48000, SharedState::new(120, 7), state_tx, retired_tx);
+        let sample = Arc::new(Sample { data: vec![0.5; 8], right: None, sample_rate: 48000 });
+        let _ = renderer.players[3].load(sample.clone(), sample.clone());
+        renderer.apply(AudioCommand::FreezeTrack { track: 3, sample: sample.clone() });
+
+        renderer.apply(AudioCommand::RemoveTrack { track: 3 });
+        match retired_rx.try_recv() {
+            Ok(Retired::Player(player)) => assert!(Arc::ptr_eq(&player.sample.unwrap(), &sample)),
+            _ => panic!("the removed track's sample didn't come back"),
+        }
+        assert!(matches!(retired_rx.try_recv(), Ok(Retired::Sample(_))), "nor its freeze");
+        // Both handed back and freed there, nothing is left holding it
+        assert_eq!(Arc::strong_count(&sample), 1);
+    }
+}
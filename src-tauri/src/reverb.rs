@@ -0,0 +1,210 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - REVERB
+// Freeverb-style stereo reverb (parallel combs into series allpasses)
+// ============================================================
+
+// Delay line lengths from the original Freeverb, tuned for 44.1 kHz
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const TUNING_RATE: f64 = 44100.0;
+
+// Right channel lines are longer by this much to decorrelate the channels
+const STEREO_SPREAD: usize = 23;
+
+const INPUT_GAIN: f64 = 0.015;
+const WET_GAIN: f64 = 3.0;
+const ALLPASS_FEEDBACK: f64 = 0.5;
+
+// Room size 0..1 maps onto this comb feedback range
+const ROOM_OFFSET: f64 = 0.7;
+const ROOM_SCALE: f64 = 0.28;
+const DAMP_SCALE: f64 = 0.4;
+
+/// Feedback comb with a one-pole low-pass in the loop
+#[derive(Clone, Debug)]
+struct Comb {
+    buffer: Vec<f64>,
+    pos: usize,
+    filter_store: f64,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64, feedback: f64, damp: f64) -> f64 {
+        let output = self.buffer[self.pos];
+        self.filter_store = output * (1.0 - damp) + self.filter_store * damp;
+        self.buffer[self.pos] = input + self.filter_store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Allpass {
+    buffer: Vec<f64>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64) -> f64 {
+        let buffered = self.buffer[self.pos];
+        self.buffer[self.pos] = input + buffered * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+/// One channel's comb bank and allpass chain
+#[derive(Clone, Debug)]
+struct ReverbChannel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl ReverbChannel {
+    fn new(sample_rate: f64, spread: usize) -> Self {
+        let scale = |len: usize| ((len + spread) as f64 * sample_rate / TUNING_RATE) as usize;
+        Self {
+            combs: COMB_TUNING.iter().map(|&len| Comb::new(scale(len))).collect(),
+            allpasses: ALLPASS_TUNING.iter().map(|&len| Allpass::new(scale(len))).collect(),
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64, feedback: f64, damp: f64) -> f64 {
+        let mut out = 0.0;
+        for comb in &mut self.combs {
+            out += comb.process(input, feedback, damp);
+        }
+        for allpass in &mut self.allpasses {
+            out = allpass.process(out);
+        }
+        out
+    }
+}
+
+/// Stereo reverb, run as a parallel wet path: `out = dry + wet(dry * mix + sends)`
+#[derive(Clone, Debug)]
+pub struct Reverb {
+    left: ReverbChannel,
+    right: ReverbChannel,
+    feedback: f64,
+    damp: f64,
+    mix: f64,
+    // Track sends queued for the next `process` call
+    send: (f64, f64),
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut reverb = Self {
+            left: ReverbChannel::new(sample_rate, 0),
+            right: ReverbChannel::new(sample_rate, STEREO_SPREAD),
+            feedback: 0.0,
+            damp: 0.0,
+            mix: 0.0,
+            send: (0.0, 0.0),
+        };
+        reverb.set_size(0.5);
+        reverb.set_damping(0.5);
+        reverb
+    }
+
+    /// Room size, 0.0 (small) to 1.0 (large)
+    pub fn set_size(&mut self, size: f64) {
+        self.feedback = ROOM_OFFSET + size.clamp(0.0, 1.0) * ROOM_SCALE;
+    }
+
+    /// High-frequency damping, 0.0 (bright) to 1.0 (dark)
+    pub fn set_damping(&mut self, damping: f64) {
+        self.damp = damping.clamp(0.0, 1.0) * DAMP_SCALE;
+    }
+
+    pub fn set_mix(&mut self, mix: f64) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Wet signal only
+    #[inline]
+    pub fn wet(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let input = (left + right) * INPUT_GAIN;
+        (
+            self.left.process(input, self.feedback, self.damp) * WET_GAIN,
+            self.right.process(input, self.feedback, self.damp) * WET_GAIN,
+        )
+    }
+
+    /// Add send-bus signal to the reverb input of the next `process` call
+    #[inline]
+    pub fn add_send(&mut self, left: f64, right: f64) {
+        self.send.0 += left;
+        self.send.1 += right;
+    }
+
+    #[inline]
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        let (send_l, send_r) = std::mem::take(&mut self.send);
+        let (wet_l, wet_r) = self.wet(left * self.mix + send_l, right * self.mix + send_r);
+        (left + wet_l, right + wet_r)
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impulse_tail_decays() {
+        let sample_rate = 48000.0;
+        let mut reverb = Reverb::new(sample_rate);
+        reverb.set_mix(1.0);
+
+        let tail: Vec<f64> = (0..(sample_rate as usize * 3))
+            .map(|i| reverb.wet(if i == 0 { 1.0 } else { 0.0 }, 0.0).0)
+            .collect();
+
+        // There is a tail...
+        let window = sample_rate as usize / 10;
+        let energies: Vec<f64> = tail
+            .chunks(window)
+            .skip(1) // combs are still filling during the first window
+            .map(|w| w.iter().map(|x| x * x).sum())
+            .collect();
+        assert!(energies[0] > 1e-6);
+
+        // ...and its energy drops every window
+        assert!(energies.windows(2).all(|w| w[1] < w[0]), "{:?}", energies);
+    }
+
+    #[test]
+    fn test_reverb_is_deterministic() {
+        let render = || {
+            let mut reverb = Reverb::new(48000.0);
+            reverb.set_mix(0.5);
+            (0..4800)
+                .map(|i| reverb.process(if i % 1000 == 0 { 1.0 } else { 0.0 }, 0.0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(render(), render());
+    }
+}
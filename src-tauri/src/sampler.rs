@@ -0,0 +1,724 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - SAMPLER
+// WAV decoding, resampling + per-track one-shot sample playback
+// ============================================================
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crossbeam_channel::{Receiver, TryRecvError};
+
+/// Zero crossings of the resampling kernel on each side of its center
+const SINC_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Output frames between `resampled_reporting` progress calls
+const RESAMPLE_PROGRESS_FRAMES: usize = 4096;
+
+/// A decoded audio sample (mono or stereo, normalized to -1.0..=1.0)
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// The only channel, or the left one of a stereo file
+    pub data: Vec<f32>,
+    /// Right channel of a stereo file, the same length as `data`
+    pub right: Option<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+impl Sample {
+    /// Frame at fractional `position` (in this sample's frames), linearly
+    /// interpolated; silent outside the buffer
+    #[inline]
+    pub fn frame_at(&self, position: f64) -> (f64, Option<f64>) {
+        let silence = (0.0, self.right.as_ref().map(|_| 0.0));
+        if position < 0.0 || position >= self.data.len() as f64 {
+            return silence;
+        }
+        let index = position as usize;
+        let frac = position - index as f64;
+        let read = |data: &[f32]| {
+            let a = data[index] as f64;
+            let b = data.get(index + 1).copied().unwrap_or(0.0) as f64;
+            a + (b - a) * frac
+        };
+        (read(&self.data), self.right.as_deref().map(read))
+    }
+
+    /// This sample converted to `rate` by windowed-sinc interpolation, the
+    /// same length in seconds and at the same pitch. Going down, the kernel
+    /// also cuts what the new rate can't hold. Allocates: load time only.
+    pub fn resampled(&self, rate: u32) -> Sample {
+        self.resampled_reporting(rate, |_| {})
+    }
+
+    /// `resampled`, passing `progress` the fraction done (0.0..=1.0) as it
+    /// goes, for a load the UI is waiting on
+    pub fn resampled_reporting(&self, rate: u32, mut progress: impl FnMut(f64)) -> Sample {
+        if rate == self.sample_rate || self.data.is_empty() {
+            return self.clone();
+        }
+        let ratio = rate as f64 / self.sample_rate as f64;
+        let frames = (self.data.len() as f64 * ratio).round() as usize;
+        // Kernel cutoff as a fraction of the source Nyquist, and its reach
+        // in source frames
+        let cutoff = ratio.min(1.0);
+        let reach = SINC_ZERO_CROSSINGS / cutoff;
+        let total = (frames * if self.right.is_some() { 2 } else { 1 }).max(1) as f64;
+        let mut convert = |data: &[f32], done: usize| -> Vec<f32> {
+            (0..frames)
+                .map(|n| {
+                    if n.is_multiple_of(RESAMPLE_PROGRESS_FRAMES) {
+                        progress((done + n) as f64 / total);
+                    }
+                    let center = n as f64 / ratio;
+                    let first = (center - reach).ceil().max(0.0) as usize;
+                    let last = ((center + reach).floor() as usize).min(data.len() - 1);
+                    let sum: f64 = (first..=last)
+                        .map(|i| {
+                            let offset = i as f64 - center;
+                            let tap = cutoff * sinc(offset * cutoff) * blackman(offset / reach);
+                            data[i] as f64 * tap
+                        })
+                        .sum();
+                    sum as f32
+                })
+                .collect()
+        };
+        let data = convert(&self.data, 0);
+        let right = self.right.as_deref().map(|right| convert(right, frames));
+        progress(1.0);
+        Sample { data, right, sample_rate: rate }
+    }
+}
+
+#[inline]
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window over `t` in -1.0..=1.0
+#[inline]
+fn blackman(t: f64) -> f64 {
+    0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos()
+}
+
+// WAVE format tags
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+fn read_u16(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+/// Decode a RIFF/WAVE byte buffer (8/16/24/32-bit PCM or 32-bit float).
+/// Stereo files keep both channels; files with more are mixed down to mono.
+#[cfg(test)]
+pub fn decode_wav(bytes: &[u8]) -> Result<Sample, String> {
+    parse_wav(bytes).map(|wav| wav.decode(bytes))
+}
+
+/// How a WAV file's samples are stored and where, read from its header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WavLayout {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+    // Byte range of the data chunk's body
+    data_start: usize,
+    data_end: usize,
+}
+
+/// Check a RIFF/WAVE byte buffer and find its samples without decoding
+/// them; `Err` for anything `WavLayout::decode` can't play
+pub fn parse_wav(bytes: &[u8]) -> Result<WavLayout, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF/WAVE file".to_string());
+    }
+
+    // (format tag, channels, sample rate, bits per sample)
+    let mut format: Option<(u16, u16, u32, u16)> = None;
+    let mut data: Option<(usize, usize)> = None;
+
+    // Walk the chunk list
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = read_u32(bytes, pos + 4) as usize;
+        let body_start = pos + 8;
+        let body_end = body_start.saturating_add(size).min(bytes.len());
+        let body = &bytes[body_start..body_end];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err("Truncated fmt chunk".to_string());
+                }
+                let mut tag = read_u16(body, 0);
+                // Extensible files carry the real format in the sub-format GUID
+                if tag == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    tag = read_u16(body, 24);
+                }
+                format = Some((tag, read_u16(body, 2), read_u32(body, 4), read_u16(body, 14)));
+            }
+            b"data" => data = Some((body_start, body_end)),
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        pos = body_start.saturating_add(size + (size & 1));
+    }
+
+    let (tag, channels, sample_rate, bits) = format.ok_or("Missing fmt chunk")?;
+    let (data_start, data_end) = data.ok_or("Missing data chunk")?;
+
+    if channels == 0 || sample_rate == 0 {
+        return Err("Invalid channel count or sample rate".to_string());
+    }
+
+    match (tag, bits) {
+        (FORMAT_PCM, 8 | 16 | 24 | 32) | (FORMAT_IEEE_FLOAT, 32) => {}
+        _ => return Err(format!("Unsupported WAV format (tag {}, {} bits)", tag, bits)),
+    }
+
+    Ok(WavLayout { tag, channels, sample_rate, bits, data_start, data_end })
+}
+
+impl WavLayout {
+    /// The samples of `bytes`, the buffer this layout was parsed from.
+    /// Allocates: load time only.
+    pub fn decode(&self, bytes: &[u8]) -> Sample {
+        let &WavLayout { tag, channels, sample_rate, bits, .. } = self;
+        let bytes_per_sample = bits as usize / 8;
+        let data = &bytes[self.data_start..self.data_end];
+
+        let decode = |s: &[u8]| -> f32 {
+            match (tag, bits) {
+                (FORMAT_IEEE_FLOAT, _) => f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+                (_, 8) => (s[0] as f32 - 128.0) / 128.0,
+                (_, 16) => i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+                (_, 24) => (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8_388_608.0,
+                _ => i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0,
+            }
+        };
+
+        let frame_size = bytes_per_sample * channels as usize;
+        let frames = data.chunks_exact(frame_size);
+        let channel = |index: usize| -> Vec<f32> {
+            let start = index * bytes_per_sample;
+            frames
+                .clone()
+                .map(|frame| decode(&frame[start..start + bytes_per_sample]))
+                .collect()
+        };
+
+        let (samples, right) = if channels == 2 {
+            (channel(0), Some(channel(1)))
+        } else {
+            let mixdown = frames
+                .clone()
+                .map(|frame| {
+                    let sum: f32 = frame.chunks_exact(bytes_per_sample).map(decode).sum();
+                    sum / channels as f32
+                })
+                .collect();
+            (mixdown, None)
+        };
+
+        Sample {
+            data: samples,
+            right,
+            sample_rate,
+        }
+    }
+}
+
+/// Varispeed range: three octaves either way
+pub const MIN_SAMPLE_SPEED: f64 = 0.125;
+pub const MAX_SAMPLE_SPEED: f64 = 8.0;
+
+/// Longest loop crossfade; a fade never takes more than half the sample
+pub const MAX_LOOP_CROSSFADE_MS: f64 = 100.0;
+
+/// A sample decoded and resampled off the audio thread, on its way to a
+/// player: `source` as loaded and `sample` at the output rate
+#[derive(Debug)]
+pub struct LoadedSample {
+    pub sample: Arc<Sample>,
+    pub source: Arc<Sample>,
+}
+
+/// What a player lets go of when it takes a new sample: the sample and
+/// source it had and the channel the new one came through. Handed back so
+/// the callback can pass them on to be freed elsewhere.
+#[derive(Debug, Default)]
+pub struct Replaced {
+    pub sample: Option<Arc<Sample>>,
+    pub source: Option<Arc<Sample>>,
+    pub pending: Option<Receiver<LoadedSample>>,
+}
+
+/// Playback of a loaded sample on a single track, one-shot or looping
+#[derive(Clone, Debug)]
+pub struct SamplePlayer {
+    // What plays: `source` at the output rate (or `source` itself)
+    sample: Option<Arc<Sample>>,
+    // The sample as loaded, kept to resample from when the rate changes
+    source: Option<Arc<Sample>>,
+    position: f64, // fractional read position in source frames
+    playing: bool,
+    // Varispeed ratio: 2.0 plays an octave up in half the time
+    speed: f64,
+    looping: bool,
+    // Read from the end toward the start
+    reverse: bool,
+    // A loop blends this long a stretch of the end into the start, so the
+    // seam doesn't click
+    crossfade_ms: f64,
+    // A load finishing on another thread; the old sample plays until it
+    // arrives
+    pending: Option<Receiver<LoadedSample>>,
+}
+
+impl Default for SamplePlayer {
+    fn default() -> Self {
+        Self {
+            sample: None,
+            source: None,
+            position: 0.0,
+            playing: false,
+            speed: 1.0,
+            looping: false,
+            reverse: false,
+            crossfade_ms: 0.0,
+            pending: None,
+        }
+    }
+}
+
+impl SamplePlayer {
+    /// Play `sample`: `source` converted to the output rate, or `source`
+    /// itself. Any rate left over is followed by interpolating frames.
+    /// Returns the sample and source it replaces.
+    pub fn load(&mut self, sample: Arc<Sample>, source: Arc<Sample>) -> Replaced {
+        self.position = 0.0;
+        self.playing = false;
+        Replaced {
+            sample: self.sample.replace(sample),
+            source: self.source.replace(source),
+            pending: None,
+        }
+    }
+
+    /// Everything the player holds, for a track being removed to hand off
+    /// the way a replaced sample is
+    pub fn into_replaced(self) -> Replaced {
+        Replaced { sample: self.sample, source: self.source, pending: self.pending }
+    }
+
+    /// Take the sample `pending` delivers, once it's ready, in place of
+    /// whatever this player has (or is still waiting for, whose channel is
+    /// returned)
+    pub fn begin_load(
+        &mut self,
+        pending: Receiver<LoadedSample>,
+    ) -> Option<Receiver<LoadedSample>> {
+        self.pending.replace(pending)
+    }
+
+    /// Swap in a finished load if one has arrived. A single `try_recv`, so
+    /// the callback can poll without ever waiting on the loader; a load
+    /// that failed (its sender gone) is forgotten. Returns what the player
+    /// let go of, if anything.
+    #[inline]
+    pub fn poll_load(&mut self) -> Option<Replaced> {
+        let result = self.pending.as_ref()?.try_recv();
+        match result {
+            Ok(loaded) => {
+                let replaced = self.load(loaded.sample, loaded.source);
+                Some(Replaced { pending: self.pending.take(), ..replaced })
+            }
+            Err(TryRecvError::Disconnected) => {
+                Some(Replaced { pending: self.pending.take(), ..Replaced::default() })
+            }
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+
+    /// Stop waiting for a load in flight, e.g. in an offline copy that
+    /// shouldn't take it from the live player
+    pub fn detach_load(&mut self) {
+        self.pending = None;
+    }
+
+    /// Re-convert the loaded sample from its source for a new output rate.
+    /// Allocates, so only between streams.
+    pub fn resample(&mut self, output_rate: u32) {
+        let (Some(sample), Some(source)) = (&self.sample, &self.source) else {
+            return;
+        };
+        if sample.sample_rate == output_rate {
+            return;
+        }
+        let resampled = if source.sample_rate == output_rate {
+            source.clone()
+        } else {
+            Arc::new(source.resampled(output_rate))
+        };
+        // Same place in seconds
+        self.position *= output_rate as f64 / sample.sample_rate as f64;
+        self.sample = Some(resampled);
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.sample.is_some()
+    }
+
+    /// Restart playback from the beginning (the last frame when reversed)
+    pub fn trigger(&mut self) {
+        let len = self.sample.as_ref().map_or(0, |s| s.data.len());
+        self.position = if self.reverse { len.saturating_sub(1) as f64 } else { 0.0 };
+        self.playing = self.sample.is_some();
+    }
+
+    /// Playback speed ratio (pitch follows), clamped to the varispeed range
+    pub fn set_speed(&mut self, ratio: f64) {
+        self.speed = ratio.clamp(MIN_SAMPLE_SPEED, MAX_SAMPLE_SPEED);
+    }
+
+    /// Loop from the start at the end of the buffer instead of stopping
+    pub fn set_looping(&mut self, on: bool) {
+        self.looping = on;
+    }
+
+    /// Crossfade the loop seam over `ms` (0 = a hard wrap). Only the first
+    /// pass plays the head on its own; later passes start where its fade-in
+    /// ends.
+    pub fn set_loop_crossfade(&mut self, ms: f64) {
+        self.crossfade_ms = ms.clamp(0.0, MAX_LOOP_CROSSFADE_MS);
+    }
+
+    /// Play backwards; takes effect from the current position
+    pub fn set_reverse(&mut self, on: bool) {
+        self.reverse = on;
+    }
+
+    /// Halt playback and rewind
+    pub fn stop(&mut self) {
+        self.position = 0.0;
+        self.playing = false;
+    }
+
+    /// Next output frame at `output_rate`, resampling with linear
+    /// interpolation when the file rate differs. The right channel is
+    /// `Some` (silent or not) whenever a stereo sample is loaded.
+    #[inline]
+    pub fn next(&mut self, output_rate: f64) -> (f64, Option<f64>) {
+        let Some(sample) = &self.sample else {
+            return (0.0, None);
+        };
+        let silence = (0.0, sample.right.as_ref().map(|_| 0.0));
+
+        // Either direction wraps around when looping, or runs off an end.
+        // With a crossfade the loop skips the faded-in head: the seam runs
+        // from the last `fade` frames straight into frame `fade`.
+        let len = sample.data.len();
+        let fade = if self.looping {
+            let frames = self.crossfade_ms * 0.001 * sample.sample_rate as f64;
+            frames.min((len / 2) as f64).floor()
+        } else {
+            0.0
+        };
+        if self.looping && len > 0 && !(0.0..len as f64).contains(&self.position) {
+            let cycle = len as f64 - fade;
+            self.position = fade + (self.position - fade).rem_euclid(cycle);
+        }
+        if !self.playing || self.position < 0.0 || self.position >= len as f64 {
+            self.playing = false;
+            return silence;
+        }
+
+        // A loop interpolates across its seam; a one-shot fades into silence
+        let looping = self.looping;
+        let read = |data: &[f32], position: f64| {
+            let index = position as usize;
+            let frac = position - index as f64;
+            let a = data[index] as f64;
+            let next = if looping { (index + 1) % len } else { index + 1 };
+            let b = data.get(next).copied().unwrap_or(0.0) as f64;
+            a + (b - a) * frac
+        };
+
+        // Inside the seam (the tail going forward, the head in reverse),
+        // blend the tail out as the head comes in
+        let tail_start = len as f64 - fade;
+        let into_fade = if self.reverse {
+            self.position
+        } else {
+            self.position - tail_start
+        };
+        let frame = if fade > 0.0 && (0.0..fade).contains(&into_fade) {
+            let t = into_fade / fade;
+            let blend = |data: &[f32]| {
+                read(data, tail_start + into_fade) * (1.0 - t) + read(data, into_fade) * t
+            };
+            (blend(&sample.data), sample.right.as_deref().map(blend))
+        } else {
+            let position = self.position;
+            (read(&sample.data, position), sample.right.as_deref().map(|d| read(d, position)))
+        };
+
+        let step = self.speed * sample.sample_rate as f64 / output_rate;
+        self.position += if self.reverse { -step } else { step };
+        frame
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a 16-bit PCM WAV file in memory
+    fn wav_16bit(samples: &[i16], channels: u16, sample_rate: u32) -> Vec<u8> {
+        let data_len = (samples.len() * 2) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for s in samples {
+            bytes.extend_from_slice(&s.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_decode_wav_16bit() {
+        let bytes = wav_16bit(&[0, 16384, -16384, 32767], 1, 44100);
+        let sample = decode_wav(&bytes).unwrap();
+        assert_eq!(sample.sample_rate, 44100);
+        assert_eq!(sample.data.len(), 4);
+        assert!((sample.data[1] - 0.5).abs() < 1e-4);
+        assert!((sample.data[2] + 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_decode_wav_stereo_keeps_channels() {
+        let bytes = wav_16bit(&[16384, 0, 16384, 16384], 2, 48000);
+        let sample = decode_wav(&bytes).unwrap();
+        assert_eq!(sample.data.len(), 2);
+        assert!((sample.data[0] - 0.5).abs() < 1e-4);
+        assert!((sample.data[1] - 0.5).abs() < 1e-4);
+        let right = sample.right.unwrap();
+        assert!(right[0].abs() < 1e-4);
+        assert!((right[1] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_decode_wav_multichannel_mixdown() {
+        let bytes = wav_16bit(&[16384, 0, 16384, 16384, 16384, 16384], 3, 48000);
+        let sample = decode_wav(&bytes).unwrap();
+        assert!(sample.right.is_none());
+        assert_eq!(sample.data.len(), 2);
+        assert!((sample.data[0] - 1.0 / 3.0).abs() < 1e-4);
+        assert!((sample.data[1] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_decode_wav_rejects_garbage() {
+        assert!(decode_wav(b"definitely not a wav file").is_err());
+        assert!(decode_wav(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_wav_checks_the_header_alone() {
+        let mut bytes = wav_16bit(&[0, 16384], 1, 44100);
+        assert!(parse_wav(&bytes).is_ok());
+        // 12-bit PCM: the header says so, no samples need decoding
+        bytes[34] = 12;
+        let error = parse_wav(&bytes).unwrap_err();
+        assert!(error.contains("Unsupported"), "{}", error);
+    }
+
+    #[test]
+    fn test_player_resamples_to_output_rate() {
+        let mut player = SamplePlayer::default();
+        let sample = Arc::new(Sample {
+            data: vec![1.0; 100],
+            right: None,
+            sample_rate: 24000,
+        });
+        player.load(sample.clone(), sample);
+        player.trigger();
+
+        // 100 frames at 24 kHz last 200 frames at 48 kHz
+        let played = (0..400).filter(|_| player.next(48000.0).0 != 0.0).count();
+        assert_eq!(played, 200);
+    }
+
+    #[test]
+    fn test_double_speed_plays_in_half_the_frames() {
+        let mut player = SamplePlayer::default();
+        let sample = Arc::new(Sample {
+            data: vec![1.0; 100],
+            right: None,
+            sample_rate: 48000,
+        });
+        player.load(sample.clone(), sample);
+        player.set_speed(2.0);
+        player.trigger();
+        let played = (0..400).filter(|_| player.next(48000.0).0 != 0.0).count();
+        assert_eq!(played, 50);
+
+        // Looping keeps going past the end, at the same rate
+        player.set_looping(true);
+        player.trigger();
+        let played = (0..400).filter(|_| player.next(48000.0).0 != 0.0).count();
+        assert_eq!(played, 400);
+        assert!(player.playing);
+    }
+
+    #[test]
+    fn test_loop_crossfade_smooths_the_seam() {
+        // A ramp that jumps from 1.0 back to 0.0 at the seam
+        let data: Vec<f32> = (0..4800).map(|i| i as f32 / 4800.0).collect();
+        let sample = Arc::new(Sample { data, right: None, sample_rate: 48000 });
+        let biggest_step = |player: &mut SamplePlayer| {
+            player.trigger();
+            let out: Vec<f64> = (0..4800 * 3).map(|_| player.next(48000.0).0).collect();
+            out.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f64::max)
+        };
+
+        let mut player = SamplePlayer::default();
+        player.load(sample.clone(), sample);
+        player.set_looping(true);
+        assert!(biggest_step(&mut player) > 0.99);
+
+        // 10 ms = 480 frames: the ramp's 1.0 fades into its 0.1
+        player.set_loop_crossfade(10.0);
+        assert!(biggest_step(&mut player) < 0.01);
+        player.set_reverse(true);
+        assert!(biggest_step(&mut player) < 0.01);
+    }
+
+    #[test]
+    fn test_resampled_sample_keeps_pitch_and_duration() {
+        // One second of 441 Hz at 44.1 kHz
+        let tone = |i: usize, rate: f64| (2.0 * PI * 441.0 * i as f64 / rate).sin();
+        let data: Vec<f32> = (0..44100).map(|i| 0.5 * tone(i, 44100.0) as f32).collect();
+        let mut player = SamplePlayer::default();
+        let sample = Arc::new(Sample { data, right: None, sample_rate: 44100 });
+        player.load(sample.clone(), sample);
+        player.resample(48000);
+        assert_eq!(player.sample.as_ref().unwrap().sample_rate, 48000);
+
+        // One second at the new rate, then it stops
+        player.trigger();
+        let out: Vec<f64> = (0..48000).map(|_| player.next(48000.0).0).collect();
+        assert!(player.playing);
+        player.next(48000.0);
+        assert!(!player.playing);
+        // Away from the edges, where the kernel runs off the buffer, it's
+        // the same tone sampled at 48 kHz
+        for (i, &y) in out.iter().enumerate().take(47000).skip(1000) {
+            assert!((y - 0.5 * tone(i, 48000.0)).abs() < 2e-3, "frame {}: {}", i, y);
+        }
+
+        // Back at the source's rate, the source itself plays
+        player.resample(44100);
+        assert!(Arc::ptr_eq(player.sample.as_ref().unwrap(), player.source.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_player_picks_up_a_finished_load_whole() {
+        let sample = |value: f32| {
+            Arc::new(Sample { data: vec![value; 64], right: None, sample_rate: 48000 })
+        };
+        let mut player = SamplePlayer::default();
+        let old = sample(0.25);
+        player.load(old.clone(), old.clone());
+
+        // Until the loader delivers, the old sample keeps playing
+        let (loaded_tx, loaded_rx) = crossbeam_channel::bounded(1);
+        assert!(player.begin_load(loaded_rx).is_none());
+        assert!(player.poll_load().is_none());
+        player.trigger();
+        assert_eq!(player.next(48000.0).0, 0.25);
+
+        // Delivered from another thread, it's taken as one piece
+        let new = sample(0.75);
+        let sent = new.clone();
+        std::thread::spawn(move || {
+            loaded_tx.send(LoadedSample { sample: sent.clone(), source: sent }).unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(player.next(48000.0).0, 0.25, "only poll_load swaps");
+        // What it replaced comes back to be freed by the caller
+        let replaced = player.poll_load().unwrap();
+        assert!(Arc::ptr_eq(replaced.sample.as_ref().unwrap(), &old));
+        assert!(Arc::ptr_eq(replaced.source.as_ref().unwrap(), &old));
+        assert!(replaced.pending.is_some());
+        assert!(Arc::ptr_eq(player.sample.as_ref().unwrap(), &new));
+        assert!(Arc::ptr_eq(player.source.as_ref().unwrap(), &new));
+        assert!(player.pending.is_none());
+        player.trigger();
+        assert_eq!(player.next(48000.0).0, 0.75);
+
+        // A load whose loader gave up leaves the sample alone
+        let (loaded_tx, loaded_rx) = crossbeam_channel::bounded::<LoadedSample>(1);
+        player.begin_load(loaded_rx);
+        drop(loaded_tx);
+        let replaced = player.poll_load().unwrap();
+        assert!(replaced.sample.is_none() && replaced.pending.is_some());
+        assert!(player.pending.is_none());
+        assert!(Arc::ptr_eq(player.sample.as_ref().unwrap(), &new));
+    }
+
+    #[test]
+    fn test_reverse_plays_buffer_backwards() {
+        let mut player = SamplePlayer::default();
+        let sample = Arc::new(Sample {
+            data: vec![0.1, 0.2, 0.3, 0.4],
+            right: None,
+            sample_rate: 48000,
+        });
+        player.load(sample.clone(), sample);
+        player.set_reverse(true);
+        player.trigger();
+        let out: Vec<f64> = (0..6).map(|_| player.next(48000.0).0).collect();
+        let expected = [0.4, 0.3, 0.2, 0.1, 0.0, 0.0];
+        for (a, b) in out.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{:?}", out);
+        }
+
+        // Looping wraps from the start back to the end
+        player.set_looping(true);
+        player.trigger();
+        let out: Vec<f64> = (0..6).map(|_| player.next(48000.0).0).collect();
+        let expected = [0.4, 0.3, 0.2, 0.1, 0.4, 0.3];
+        for (a, b) in out.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{:?}", out);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - SEQUENCER
+// Per-track step patterns (tracks x steps)
+// ============================================================
+
+use serde::{Deserialize, Serialize};
+
+use crate::MAX_TRACKS;
+
+/// Steps stored per pattern (the longest loop)
+pub const MAX_STEPS: usize = 64;
+
+pub const DEFAULT_LOOP_LENGTH: usize = 32;
+
+/// Steps are 16th notes, four to the quarter-note beat the tempo counts
+pub const STEPS_PER_BEAT: usize = 4;
+
+/// Tempo range the transport runs at
+pub const MIN_BPM: u64 = 20;
+pub const MAX_BPM: u64 = 999;
+
+/// 16th-note steps in a whole note; a time signature's beat unit divides it
+const STEPS_PER_WHOLE_NOTE: u32 = 16;
+
+/// Resolution of the tick field of a `TransportPosition`
+pub const TICKS_PER_BEAT: u32 = 960;
+
+pub const MAX_TIME_SIGNATURE_NUMERATOR: u32 = 32;
+
+/// Beats per bar and the note value of one beat (4/4, 6/8, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { numerator: 4, denominator: 4 }
+    }
+}
+
+impl TimeSignature {
+    /// The beat unit must be a whole number of steps: 1, 2, 4, 8 or 16
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self, String> {
+        if numerator == 0 || numerator > MAX_TIME_SIGNATURE_NUMERATOR {
+            return Err(format!(
+                "Beats per bar must be between 1 and {}",
+                MAX_TIME_SIGNATURE_NUMERATOR
+            ));
+        }
+        if !denominator.is_power_of_two() || denominator > STEPS_PER_WHOLE_NOTE {
+            return Err("Beat unit must be 1, 2, 4, 8 or 16".to_string());
+        }
+        Ok(Self { numerator, denominator })
+    }
+
+    pub fn steps_per_beat(self) -> u32 {
+        STEPS_PER_WHOLE_NOTE / self.denominator
+    }
+
+    pub fn steps_per_bar(self) -> u32 {
+        self.numerator * self.steps_per_beat()
+    }
+
+    /// Musical position of the start of `step`
+    pub fn position(self, step: usize) -> TransportPosition {
+        let step = step as u32;
+        let in_bar = step % self.steps_per_bar();
+        TransportPosition {
+            bar: step / self.steps_per_bar() + 1,
+            beat: in_bar / self.steps_per_beat() + 1,
+            tick: in_bar % self.steps_per_beat() * TICKS_PER_BEAT / self.steps_per_beat(),
+        }
+    }
+}
+
+/// Bars and beats count from 1, ticks from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TransportPosition {
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sequencer {
+    // Room for every track up front, so adding and removing tracks on the
+    // audio thread never allocates or frees
+    pattern: [[bool; MAX_STEPS]; MAX_TRACKS],
+    // Tracks in use, the first rows of `pattern`
+    tracks: usize,
+    // Steps of the pattern that play before it repeats
+    loop_length: usize,
+    // When off the playhead keeps counting past the loop end (the pattern
+    // itself still repeats every `loop_length` steps)
+    loop_enabled: bool,
+    // Fraction of a step that odd steps are pushed late (0.0..=MAX_SWING)
+    swing: f64,
+    time_signature: TimeSignature,
+}
+
+pub const MAX_SWING: f64 = 0.75;
+
+impl Sequencer {
+    pub fn new(num_tracks: usize) -> Self {
+        Self {
+            pattern: [[false; MAX_STEPS]; MAX_TRACKS],
+            tracks: num_tracks.min(MAX_TRACKS),
+            loop_length: DEFAULT_LOOP_LENGTH,
+            loop_enabled: true,
+            swing: 0.0,
+            time_signature: TimeSignature::default(),
+        }
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    /// Bar, beat and tick of `step`; the playhead wraps at the loop end, so
+    /// this does too
+    pub fn position(&self, step: usize) -> TransportPosition {
+        self.time_signature.position(step)
+    }
+
+    pub fn set_swing(&mut self, amount: f64) {
+        self.swing = amount.clamp(0.0, MAX_SWING);
+    }
+
+    /// Samples from the start of `step` to the next one. Even steps are
+    /// lengthened and odd steps shortened by the same amount, so each pair
+    /// (and so the bar) keeps its straight length.
+    #[inline]
+    pub fn step_length(&self, step: usize, samples_per_step: f64) -> f64 {
+        let shift = self.swing * samples_per_step;
+        if step.is_multiple_of(2) {
+            samples_per_step + shift
+        } else {
+            samples_per_step - shift
+        }
+    }
+
+    /// Samples from step 0 to the start of `step`, swing included
+    #[inline]
+    pub fn step_start(&self, step: usize, samples_per_step: f64) -> f64 {
+        let shift = if step.is_multiple_of(2) { 0.0 } else { self.swing * samples_per_step };
+        step as f64 * samples_per_step + shift
+    }
+
+    pub fn set_loop_length(&mut self, steps: usize) {
+        self.loop_length = steps.clamp(1, MAX_STEPS);
+    }
+
+    pub fn set_loop_enabled(&mut self, on: bool) {
+        self.loop_enabled = on;
+    }
+
+    /// Where a seek to `step` lands: inside the loop while looping
+    pub fn seek_step(&self, step: usize) -> usize {
+        if self.loop_enabled {
+            step % self.loop_length
+        } else {
+            step
+        }
+    }
+
+    /// Playhead position after `step`
+    #[inline]
+    pub fn next_step(&self, step: usize) -> usize {
+        if self.loop_enabled {
+            (step + 1) % self.loop_length
+        } else {
+            step + 1
+        }
+    }
+
+    /// Append an empty track, up to `MAX_TRACKS`
+    pub fn add_track(&mut self) {
+        if self.tracks < MAX_TRACKS {
+            self.pattern[self.tracks] = [false; MAX_STEPS];
+            self.tracks += 1;
+        }
+    }
+
+    pub fn remove_track(&mut self, track: usize) {
+        if track < self.tracks {
+            self.pattern.copy_within(track + 1..self.tracks, track);
+            self.tracks -= 1;
+        }
+    }
+
+    pub fn set_step(&mut self, track: usize, step: usize, on: bool) {
+        let steps = self.pattern[..self.tracks].get_mut(track);
+        if let Some(cell) = steps.and_then(|t| t.get_mut(step)) {
+            *cell = on;
+        }
+    }
+
+    pub fn clear(&mut self, track: usize) {
+        if let Some(steps) = self.pattern[..self.tracks].get_mut(track) {
+            steps.fill(false);
+        }
+    }
+
+    #[inline]
+    pub fn is_active(&self, track: usize, step: usize) -> bool {
+        self.pattern[..self.tracks]
+            .get(track)
+            .and_then(|t| t.get(step % self.loop_length))
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_clear_steps() {
+        let mut seq = Sequencer::new(2);
+        seq.set_step(0, 4, true);
+        seq.set_step(1, 4, true);
+        assert!(seq.is_active(0, 4));
+        assert!(!seq.is_active(0, 5));
+
+        seq.clear(0);
+        assert!(!seq.is_active(0, 4));
+        assert!(seq.is_active(1, 4));
+
+        // Out of range is ignored rather than panicking
+        seq.set_step(9, 0, true);
+        seq.set_step(0, MAX_STEPS, true);
+        assert!(!seq.is_active(9, 0));
+    }
+
+    #[test]
+    fn test_remove_track_shifts_patterns() {
+        let mut seq = Sequencer::new(3);
+        seq.set_step(2, 7, true);
+        seq.remove_track(0);
+        assert!(seq.is_active(1, 7));
+
+        seq.add_track();
+        assert!(!seq.is_active(2, 7));
+
+        // Tracks past `MAX_TRACKS` are ignored
+        for _ in 0..MAX_TRACKS {
+            seq.add_track();
+        }
+        seq.set_step(MAX_TRACKS, 0, true);
+        assert!(!seq.is_active(MAX_TRACKS, 0));
+        seq.set_step(MAX_TRACKS - 1, 0, true);
+        assert!(seq.is_active(MAX_TRACKS - 1, 0));
+    }
+
+    #[test]
+    fn test_transport_position() {
+        let mut seq = Sequencer::new(1);
+        let at = |seq: &Sequencer, step| {
+            let p = seq.position(step);
+            (p.bar, p.beat, p.tick)
+        };
+        assert_eq!(at(&seq, 0), (1, 1, 0));
+        assert_eq!(at(&seq, 4), (1, 2, 0));
+        assert_eq!(at(&seq, 6), (1, 2, TICKS_PER_BEAT / 2));
+        assert_eq!(at(&seq, 17), (2, 1, TICKS_PER_BEAT / 4));
+
+        // 6/8: six 8th-note beats of two steps each
+        seq.set_time_signature(TimeSignature::new(6, 8).unwrap());
+        assert_eq!(at(&seq, 11), (1, 6, TICKS_PER_BEAT / 2));
+        assert_eq!(at(&seq, 12), (2, 1, 0));
+
+        assert!(TimeSignature::new(4, 3).is_err());
+        assert!(TimeSignature::new(0, 4).is_err());
+        assert!(TimeSignature::new(4, 32).is_err());
+    }
+
+    #[test]
+    fn test_loop_length_wraps_playhead_and_pattern() {
+        let mut seq = Sequencer::new(1);
+        seq.set_step(0, 3, true);
+        seq.set_loop_length(16);
+        assert_eq!(seq.next_step(14), 15);
+        assert_eq!(seq.next_step(15), 0);
+        assert!(seq.is_active(0, 19));
+
+        // Free-running playhead, pattern still repeats
+        seq.set_loop_enabled(false);
+        assert_eq!(seq.next_step(15), 16);
+        assert!(seq.is_active(0, 35));
+    }
+}
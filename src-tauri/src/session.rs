@@ -0,0 +1,936 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - SESSION
+// Saveable mirror of the live mix state
+// ============================================================
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::automation::{self, AutomationLane, MAX_AUTOMATION_LANES};
+use crate::chorus::{MAX_CHORUS_RATE, MIN_CHORUS_RATE};
+use crate::delay::{
+    MAX_DELAY_LOW_CUT_HZ, MAX_DELAY_TONE_HZ, MAX_FEEDBACK, MIN_DELAY_LOW_CUT_HZ, MIN_DELAY_TONE_HZ,
+};
+use crate::lfo::{MAX_LFO_RATE, MIN_LFO_RATE};
+use crate::loudness::LUFS_FLOOR;
+use crate::midi::{self, CcMapping};
+use crate::mixer::{
+    GATE_OFF_DB, MAX_ALLPASS_HZ, MAX_CLIP_AMOUNT, MAX_CLIP_MAKEUP_DB, MAX_COMP_KNEE_DB,
+    MAX_CRUSH_BITS, MAX_CRUSH_DOWNSAMPLE, MAX_GATE_LOOKAHEAD_MS, MAX_HPF_HZ, MAX_STEREO_WIDTH,
+    MIN_ALLPASS_HZ, MIN_HPF_HZ, MIN_OUTPUT_CEILING_DB,
+};
+use crate::modulation::{ModLfo, ModMatrix, ModRoute, MAX_MOD_ROUTES};
+use crate::renderer::{bus_valid, sync_allowed, BusState, MasterEffects, SoloMode, TrackState};
+use crate::sampler::{MAX_LOOP_CROSSFADE_MS, MAX_SAMPLE_SPEED, MIN_SAMPLE_SPEED};
+use crate::sequencer::{TimeSignature, DEFAULT_LOOP_LENGTH, MAX_STEPS, MAX_SWING};
+use crate::synth::{
+    Waveform, MAX_FREQUENCY, MAX_UNISON_DETUNE_CENTS, MAX_UNISON_VOICES, MIN_FREQUENCY,
+};
+use crate::validate;
+use crate::{AudioCommand, DEFAULT_NUM_TRACKS, MAX_TRACKS};
+
+/// Bumped whenever the file layout changes incompatibly
+pub const SESSION_VERSION: u32 = 1;
+
+pub const DEFAULT_BPM: u64 = 128;
+pub const DEFAULT_MASTER_VOLUME: f64 = 0.8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackSession {
+    #[serde(flatten)]
+    pub mix: TrackState,
+    pub waveform: Waveform,
+    pub pattern: Vec<bool>,
+}
+
+impl TrackSession {
+    fn for_track(track: usize) -> Self {
+        Self {
+            mix: TrackState::for_track(track),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for TrackSession {
+    fn default() -> Self {
+        Self {
+            mix: TrackState::default(),
+            waveform: Waveform::default(),
+            pattern: vec![false; MAX_STEPS],
+        }
+    }
+}
+
+/// Everything needed to rebuild a session's sound, minus loaded samples.
+///
+/// The Tauri side keeps one of these in step with every command it sends,
+/// so saving never has to ask the audio thread. Missing fields (older or
+/// newer files) fall back to their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    pub version: u32,
+    pub bpm: u64,
+    pub master_volume: f64,
+    pub loop_length: usize,
+    pub loop_enabled: bool,
+    pub swing: f64,
+    pub time_signature: TimeSignature,
+    pub master: MasterEffects,
+    pub tracks: Vec<TrackSession>,
+    /// MIDI learn assignments
+    pub midi_mappings: Vec<CcMapping>,
+    /// Breakpoint automation lanes
+    pub automation: Vec<AutomationLane>,
+    /// Mod matrix LFOs and their routes
+    pub modulation: ModMatrix,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            bpm: DEFAULT_BPM,
+            master_volume: DEFAULT_MASTER_VOLUME,
+            loop_length: DEFAULT_LOOP_LENGTH,
+            loop_enabled: true,
+            swing: 0.0,
+            time_signature: TimeSignature::default(),
+            master: MasterEffects::default(),
+            tracks: (0..DEFAULT_NUM_TRACKS).map(TrackSession::for_track).collect(),
+            midi_mappings: Vec::new(),
+            automation: Vec::new(),
+            modulation: ModMatrix::default(),
+        }
+    }
+}
+
+impl SessionState {
+    /// Mirror a command that was sent to the audio thread
+    pub fn apply(&mut self, cmd: &AudioCommand) {
+        let master = &mut self.master;
+        match *cmd {
+            AudioCommand::SetBpm { bpm } => self.bpm = bpm,
+            AudioCommand::SetVolume { value } => self.master_volume = value,
+            AudioCommand::SetLoopLength { steps } => self.loop_length = steps,
+            AudioCommand::SetLoopEnabled { on } => self.loop_enabled = on,
+            AudioCommand::SetSwing { amount } => self.swing = amount,
+            AudioCommand::SetTimeSignature { numerator, denominator } => {
+                if let Ok(time_signature) = TimeSignature::new(numerator, denominator) {
+                    self.time_signature = time_signature;
+                }
+            }
+            AudioCommand::SetAutomation { param, ref points } => {
+                automation::set_lane(&mut self.automation, param, points.clone());
+            }
+            AudioCommand::ClearAutomation => self.automation.clear(),
+            AudioCommand::SetModLfo { lfo, rate, shape, phase } => {
+                self.modulation.set_lfo(lfo, ModLfo { rate, shape, phase });
+            }
+            AudioCommand::AddModRoute { lfo, dest, depth } => {
+                self.modulation.add_route(lfo, dest, depth, 0.0);
+            }
+            AudioCommand::RemoveModRoute { lfo, dest } => {
+                self.modulation.remove_route(lfo, dest);
+            }
+            AudioCommand::ClearModRoutes => self.modulation.routes.clear(),
+            AudioCommand::SetEqLow { value } => master.eq_low = value,
+            AudioCommand::SetEqMid { value } => master.eq_mid = value,
+            AudioCommand::SetEqHigh { value } => master.eq_high = value,
+            AudioCommand::SetEqMsMode { on } => master.eq_ms_mode = on,
+            AudioCommand::SetSideEqLow { value } => master.side_eq_low = value,
+            AudioCommand::SetSideEqMid { value } => master.side_eq_mid = value,
+            AudioCommand::SetSideEqHigh { value } => master.side_eq_high = value,
+            AudioCommand::SetCompThreshold { value } => master.comp_threshold = value,
+            AudioCommand::SetCompRatio { value } => master.comp_ratio = value,
+            AudioCommand::SetCompAttack { value } => master.comp_attack = value,
+            AudioCommand::SetCompRelease { value } => master.comp_release = value,
+            AudioCommand::SetCompMakeup { value } => master.comp_makeup = value,
+            AudioCommand::SetCompKnee { value } => master.comp_knee = value,
+            AudioCommand::SetDelayTimeDivision { division } => master.delay_division = division,
+            AudioCommand::SetDelayFeedback { value } => master.delay_feedback = value,
+            AudioCommand::SetDelayPingPong { on } => master.delay_pingpong = on,
+            AudioCommand::SetDelayTone { cutoff, low_cut } => {
+                master.delay_tone = cutoff;
+                master.delay_low_cut = low_cut;
+            }
+            AudioCommand::SetDelayMix { value } => master.delay_mix = value,
+            AudioCommand::SetReverbSize { value } => master.reverb_size = value,
+            AudioCommand::SetReverbDamping { value } => master.reverb_damping = value,
+            AudioCommand::SetReverbMix { value } => master.reverb_mix = value,
+            AudioCommand::SetChorusRate { value } => master.chorus_rate = value,
+            AudioCommand::SetChorusDepth { value } => master.chorus_depth = value,
+            AudioCommand::SetChorusMix { value } => master.chorus_mix = value,
+            AudioCommand::SetLimiter { value } => master.limiter_threshold = value,
+            AudioCommand::SetLimiterTruePeak { on } => master.limiter_true_peak = on,
+            AudioCommand::SetLimiterReleaseMode { mode } => master.limiter_release = mode,
+            AudioCommand::SetClipAmount { value } => master.clip_amount = value,
+            AudioCommand::SetClipMode { mode } => master.clip_mode = mode,
+            AudioCommand::SetOversampling { factor } => master.oversampling = factor,
+            AudioCommand::SetClipBypass { on } => master.clip_bypass = on,
+            AudioCommand::SetClipMakeup { value } => master.clip_makeup = value,
+            AudioCommand::SetOutputCeiling { value } => master.output_ceiling = value,
+            AudioCommand::SetDcBlock { on } => master.dc_block = on,
+            AudioCommand::SetCrossfeed { value } => master.crossfeed = value,
+            AudioCommand::SetMasterChainOrder { order } => master.chain_order = order,
+            AudioCommand::SetStereoWidth { value } => master.stereo_width = value,
+            AudioCommand::SetMono { on } => master.mono = on,
+            AudioCommand::SetPanLaw { law } => master.pan_law = law,
+            AudioCommand::SetSoloMode { mode } => master.solo_mode = mode,
+            AudioCommand::SetAutogain { on } => master.autogain = on,
+            AudioCommand::SetAutogainTarget { value } => master.autogain_target = value,
+            AudioCommand::SetBusVolume { bus, value } => {
+                if let Some(b) = master.buses.get_mut(bus) {
+                    b.volume = value;
+                }
+            }
+            AudioCommand::SetBusMute { bus, on } => {
+                if let Some(b) = master.buses.get_mut(bus) {
+                    b.muted = on;
+                }
+            }
+            AudioCommand::SetBusEq { bus, low, mid, high } => {
+                if let Some(b) = master.buses.get_mut(bus) {
+                    b.eq_low = low;
+                    b.eq_mid = mid;
+                    b.eq_high = high;
+                }
+            }
+            AudioCommand::SetBusCompressor { bus, threshold, ratio, attack, release, makeup } => {
+                if let Some(b) = master.buses.get_mut(bus) {
+                    b.comp_threshold = threshold;
+                    b.comp_ratio = ratio;
+                    b.comp_attack = attack;
+                    b.comp_release = release;
+                    b.comp_makeup = makeup;
+                }
+            }
+            AudioCommand::AddTrack => self.set_track_count(self.tracks.len() + 1),
+            AudioCommand::RemoveTrack { track } => {
+                if track < self.tracks.len() && self.tracks.len() > 1 {
+                    self.tracks.remove(track);
+                    for t in &mut self.tracks {
+                        t.mix.track_removed(track);
+                    }
+                    automation::track_removed(&mut self.automation, track, drop);
+                    midi::track_removed(&mut self.midi_mappings, track);
+                    self.modulation.track_removed(track);
+                }
+            }
+            // Same as the renderer
+            AudioCommand::ResetMixer { .. } => {
+                self.master_volume = DEFAULT_MASTER_VOLUME;
+                self.master = MasterEffects::default();
+                for (track, t) in self.tracks.iter_mut().enumerate() {
+                    t.mix = TrackState::for_track(track);
+                }
+                self.modulation.routes.clear();
+            }
+            AudioCommand::SetTrackSync { track, master } => {
+                // Same checks as the renderer
+                let tracks = &self.tracks;
+                let source = |i: usize| tracks[i].mix.sync_source;
+                if master.is_none_or(|m| sync_allowed(tracks.len(), source, track, m)) {
+                    if let Some(t) = self.tracks.get_mut(track) {
+                        t.mix.sync_source = master;
+                    }
+                }
+            }
+            AudioCommand::SetTrackCount { count } => self.set_track_count(count),
+            AudioCommand::ToggleSolo { track } | AudioCommand::SetSolo { track, .. } => {
+                self.apply_track(cmd);
+                // Same as the renderer: a new exclusive solo clears the rest
+                let soloed = self.tracks.get(track).is_some_and(|t| t.mix.soloed);
+                if self.master.solo_mode == SoloMode::Exclusive && soloed {
+                    for (i, t) in self.tracks.iter_mut().enumerate() {
+                        t.mix.soloed = i == track;
+                    }
+                }
+            }
+            _ => self.apply_track(cmd),
+        }
+    }
+
+    fn apply_track(&mut self, cmd: &AudioCommand) {
+        let Some(t) = track_of(cmd).and_then(|track| self.tracks.get_mut(track)) else {
+            return;
+        };
+
+        match *cmd {
+            AudioCommand::SetTrackVolume { value, .. } => t.mix.volume = value,
+            AudioCommand::SetTrackPan { value, .. } => t.mix.pan = value,
+            AudioCommand::ToggleMute { .. } => t.mix.muted = !t.mix.muted,
+            AudioCommand::ToggleSolo { .. } => t.mix.soloed = !t.mix.soloed,
+            AudioCommand::SetMute { on, .. } => t.mix.muted = on,
+            AudioCommand::SetSolo { on, .. } => t.mix.soloed = on,
+            AudioCommand::SetTrackSoloSafe { on, .. } => t.mix.solo_safe = on,
+            AudioCommand::SetTrackPanMode { mode, .. } => t.mix.pan_mode = mode,
+            AudioCommand::ToggleTrackPolarity { .. } => {
+                t.mix.polarity_inverted = !t.mix.polarity_inverted
+            }
+            AudioCommand::SetTrackPolarity { inverted, .. } => t.mix.polarity_inverted = inverted,
+            AudioCommand::SetTrackTrim { value, .. } => t.mix.trim = value,
+            AudioCommand::SetTrackEqLow { value, .. } => t.mix.eq_low = value,
+            AudioCommand::SetTrackEqMid { value, .. } => t.mix.eq_mid = value,
+            AudioCommand::SetTrackEqHigh { value, .. } => t.mix.eq_high = value,
+            AudioCommand::SetTrackBitcrush { bits, downsample, .. } => {
+                t.mix.crush_bits = bits;
+                t.mix.crush_downsample = downsample;
+            }
+            AudioCommand::SetTrackHpf { freq_hz, on, .. } => {
+                t.mix.hpf_freq = freq_hz;
+                t.mix.hpf_on = on;
+            }
+            AudioCommand::SetTrackAllpass { freq_hz, on, .. } => {
+                t.mix.allpass_freq = freq_hz;
+                t.mix.allpass_on = on;
+            }
+            AudioCommand::SetTrackGate { threshold, attack, hold, release, .. } => {
+                t.mix.gate_threshold = threshold;
+                t.mix.gate_attack = attack;
+                t.mix.gate_hold = hold;
+                t.mix.gate_release = release;
+            }
+            AudioCommand::SetTrackGateLookahead { ms, .. } => t.mix.gate_lookahead = ms,
+            AudioCommand::SetTrackCompThreshold { value, .. } => t.mix.comp_threshold = value,
+            AudioCommand::SetTrackCompRatio { value, .. } => t.mix.comp_ratio = value,
+            AudioCommand::SetTrackCompAttack { value, .. } => t.mix.comp_attack = value,
+            AudioCommand::SetTrackCompRelease { value, .. } => t.mix.comp_release = value,
+            AudioCommand::SetTrackSidechain { source, .. } => t.mix.sidechain = source,
+            AudioCommand::SetTrackTremolo { rate, depth, shape, .. } => {
+                t.mix.tremolo_rate = rate;
+                t.mix.tremolo_depth = depth;
+                t.mix.tremolo_shape = shape;
+            }
+            AudioCommand::SetTrackAutopan { rate, depth, shape, .. } => {
+                t.mix.autopan_rate = rate;
+                t.mix.autopan_depth = depth;
+                t.mix.autopan_shape = shape;
+            }
+            AudioCommand::SetTrackFrequency { hz, .. } => t.mix.frequency = hz,
+            AudioCommand::SetTrackAdsr { attack, decay, sustain, release, .. } => {
+                t.mix.env_attack = attack;
+                t.mix.env_decay = decay;
+                t.mix.env_sustain = sustain;
+                t.mix.env_release = release;
+            }
+            AudioCommand::SetTrackSampleSpeed { ratio, .. } => t.mix.sample_speed = ratio,
+            AudioCommand::SetTrackSampleLoop { on, .. } => t.mix.sample_loop = on,
+            AudioCommand::SetTrackLoopCrossfade { ms, .. } => t.mix.sample_loop_crossfade = ms,
+            AudioCommand::SetTrackReverse { on, .. } => t.mix.sample_reverse = on,
+            AudioCommand::SetTrackBus { bus, .. } if bus_valid(bus) => t.mix.bus = bus,
+            AudioCommand::SetTrackUnison { voices, detune_cents, .. } => {
+                t.mix.unison_voices = voices;
+                t.mix.unison_detune = detune_cents;
+            }
+            AudioCommand::SetTrackSendDelay { value, .. } => t.mix.send_delay = value,
+            AudioCommand::SetTrackSendReverb { value, .. } => t.mix.send_reverb = value,
+            AudioCommand::SetWaveform { waveform, .. } => t.waveform = waveform,
+            AudioCommand::SetStep { step, on, .. } => {
+                if let Some(cell) = t.pattern.get_mut(step) {
+                    *cell = on;
+                }
+            }
+            AudioCommand::ClearPattern { .. } => t.pattern.fill(false),
+            _ => {}
+        }
+    }
+
+    /// Same limits as the renderer: 1..=MAX_TRACKS, new tracks at the end
+    fn set_track_count(&mut self, count: usize) {
+        let count = count.clamp(1, MAX_TRACKS);
+        self.tracks.truncate(count);
+        while self.tracks.len() < count {
+            self.tracks.push(TrackSession::for_track(self.tracks.len()));
+        }
+    }
+
+    /// Make a loaded file fit this build (track count, pattern and loop length)
+    pub fn normalize(&mut self) {
+        self.set_track_count(self.tracks.len());
+        self.loop_length = self.loop_length.clamp(1, MAX_STEPS);
+        for track in &mut self.tracks {
+            track.pattern.resize(MAX_STEPS, false);
+        }
+    }
+
+    /// Err naming the first setting outside the range its command accepts,
+    /// for files edited by hand or written by something else
+    pub fn validate(&self) -> Result<(), String> {
+        validate::bpm(self.bpm)?;
+        validate::level("Volume", self.master_volume)?;
+        validate::in_range("Swing", self.swing, 0.0, MAX_SWING)?;
+        TimeSignature::new(self.time_signature.numerator, self.time_signature.denominator)?;
+        validate_master(&self.master)?;
+        let count = self.tracks.len();
+        for (i, track) in self.tracks.iter().enumerate() {
+            validate_track(&track.mix).map_err(|e| format!("Track {}: {}", i, e))?;
+        }
+        for mapping in &self.midi_mappings {
+            validate::midi_param(mapping.param, count)?;
+        }
+        if self.automation.len() > MAX_AUTOMATION_LANES {
+            return Err(format!("At most {} automation lanes are supported", MAX_AUTOMATION_LANES));
+        }
+        for lane in &self.automation {
+            validate::midi_param(lane.param, count)?;
+            validate::automation_points(lane.param, &lane.points)?;
+        }
+        for lfo in &self.modulation.lfos {
+            validate::lfo_rate("LFO rate (Hz)", lfo.rate)?;
+            validate::lfo_phase(lfo.phase)?;
+        }
+        if self.modulation.routes.len() > MAX_MOD_ROUTES {
+            return Err(format!("At most {} mod routes are supported", MAX_MOD_ROUTES));
+        }
+        for route in &self.modulation.routes {
+            validate::mod_lfo(route.lfo)?;
+            validate::midi_param(route.dest, count)?;
+            validate::mod_depth(route.depth)?;
+        }
+        Ok(())
+    }
+
+    /// Commands that bring a freshly started engine to this state
+    pub fn commands(&self) -> Vec<AudioCommand> {
+        let m = &self.master;
+        let mut cmds = vec![
+            AudioCommand::SetTrackCount { count: self.tracks.len() },
+            AudioCommand::SetBpm { bpm: self.bpm },
+            AudioCommand::SetVolume { value: self.master_volume },
+            AudioCommand::SetLoopLength { steps: self.loop_length },
+            AudioCommand::SetLoopEnabled { on: self.loop_enabled },
+            AudioCommand::SetSwing { amount: self.swing },
+            AudioCommand::SetTimeSignature {
+                numerator: self.time_signature.numerator,
+                denominator: self.time_signature.denominator,
+            },
+            AudioCommand::SetEqLow { value: m.eq_low },
+            AudioCommand::SetEqMid { value: m.eq_mid },
+            AudioCommand::SetEqHigh { value: m.eq_high },
+            AudioCommand::SetEqMsMode { on: m.eq_ms_mode },
+            AudioCommand::SetSideEqLow { value: m.side_eq_low },
+            AudioCommand::SetSideEqMid { value: m.side_eq_mid },
+            AudioCommand::SetSideEqHigh { value: m.side_eq_high },
+            AudioCommand::SetCompThreshold { value: m.comp_threshold },
+            AudioCommand::SetCompRatio { value: m.comp_ratio },
+            AudioCommand::SetCompAttack { value: m.comp_attack },
+            AudioCommand::SetCompRelease { value: m.comp_release },
+            AudioCommand::SetCompMakeup { value: m.comp_makeup },
+            AudioCommand::SetCompKnee { value: m.comp_knee },
+            AudioCommand::SetDelayTimeDivision { division: m.delay_division },
+            AudioCommand::SetDelayFeedback { value: m.delay_feedback },
+            AudioCommand::SetDelayPingPong { on: m.delay_pingpong },
+            AudioCommand::SetDelayTone { cutoff: m.delay_tone, low_cut: m.delay_low_cut },
+            AudioCommand::SetDelayMix { value: m.delay_mix },
+            AudioCommand::SetReverbSize { value: m.reverb_size },
+            AudioCommand::SetReverbDamping { value: m.reverb_damping },
+            AudioCommand::SetReverbMix { value: m.reverb_mix },
+            AudioCommand::SetChorusRate { value: m.chorus_rate },
+            AudioCommand::SetChorusDepth { value: m.chorus_depth },
+            AudioCommand::SetChorusMix { value: m.chorus_mix },
+            AudioCommand::SetLimiter { value: m.limiter_threshold },
+            AudioCommand::SetLimiterTruePeak { on: m.limiter_true_peak },
+            AudioCommand::SetLimiterReleaseMode { mode: m.limiter_release },
+            AudioCommand::SetClipAmount { value: m.clip_amount },
+            AudioCommand::SetClipMode { mode: m.clip_mode },
+            AudioCommand::SetOversampling { factor: m.oversampling },
+            AudioCommand::SetClipBypass { on: m.clip_bypass },
+            AudioCommand::SetClipMakeup { value: m.clip_makeup },
+            AudioCommand::SetOutputCeiling { value: m.output_ceiling },
+            AudioCommand::SetDcBlock { on: m.dc_block },
+            AudioCommand::SetCrossfeed { value: m.crossfeed },
+            AudioCommand::SetMasterChainOrder { order: m.chain_order },
+            AudioCommand::SetStereoWidth { value: m.stereo_width },
+            AudioCommand::SetMono { on: m.mono },
+            AudioCommand::SetPanLaw { law: m.pan_law },
+            AudioCommand::SetAutogain { on: m.autogain },
+            AudioCommand::SetAutogainTarget { value: m.autogain_target },
+        ];
+
+        for (bus, b) in m.buses.iter().enumerate() {
+            cmds.extend([
+                AudioCommand::SetBusVolume { bus, value: b.volume },
+                AudioCommand::SetBusMute { bus, on: b.muted },
+                AudioCommand::SetBusEq { bus, low: b.eq_low, mid: b.eq_mid, high: b.eq_high },
+                AudioCommand::SetBusCompressor {
+                    bus,
+                    threshold: b.comp_threshold,
+                    ratio: b.comp_ratio,
+                    attack: b.comp_attack,
+                    release: b.comp_release,
+                    makeup: b.comp_makeup,
+                },
+            ]);
+        }
+
+        for (track, t) in self.tracks.iter().enumerate() {
+            cmds.extend([
+                AudioCommand::SetTrackVolume { track, value: t.mix.volume },
+                AudioCommand::SetTrackPan { track, value: t.mix.pan },
+                AudioCommand::SetMute { track, on: t.mix.muted },
+                AudioCommand::SetSolo { track, on: t.mix.soloed },
+                AudioCommand::SetTrackSoloSafe { track, on: t.mix.solo_safe },
+                AudioCommand::SetTrackPanMode { track, mode: t.mix.pan_mode },
+                AudioCommand::SetTrackPolarity { track, inverted: t.mix.polarity_inverted },
+                AudioCommand::SetTrackTrim { track, value: t.mix.trim },
+                AudioCommand::SetTrackEqLow { track, value: t.mix.eq_low },
+                AudioCommand::SetTrackEqMid { track, value: t.mix.eq_mid },
+                AudioCommand::SetTrackEqHigh { track, value: t.mix.eq_high },
+                AudioCommand::SetTrackBitcrush {
+                    track,
+                    bits: t.mix.crush_bits,
+                    downsample: t.mix.crush_downsample,
+                },
+                AudioCommand::SetTrackHpf { track, freq_hz: t.mix.hpf_freq, on: t.mix.hpf_on },
+                AudioCommand::SetTrackAllpass {
+                    track,
+                    freq_hz: t.mix.allpass_freq,
+                    on: t.mix.allpass_on,
+                },
+                AudioCommand::SetTrackGate {
+                    track,
+                    threshold: t.mix.gate_threshold,
+                    attack: t.mix.gate_attack,
+                    hold: t.mix.gate_hold,
+                    release: t.mix.gate_release,
+                },
+                AudioCommand::SetTrackGateLookahead { track, ms: t.mix.gate_lookahead },
+                AudioCommand::SetTrackCompThreshold { track, value: t.mix.comp_threshold },
+                AudioCommand::SetTrackCompRatio { track, value: t.mix.comp_ratio },
+                AudioCommand::SetTrackCompAttack { track, value: t.mix.comp_attack },
+                AudioCommand::SetTrackCompRelease { track, value: t.mix.comp_release },
+                AudioCommand::SetTrackSidechain { track, source: t.mix.sidechain },
+                AudioCommand::SetTrackTremolo {
+                    track,
+                    rate: t.mix.tremolo_rate,
+                    depth: t.mix.tremolo_depth,
+                    shape: t.mix.tremolo_shape,
+                },
+                AudioCommand::SetTrackAutopan {
+                    track,
+                    rate: t.mix.autopan_rate,
+                    depth: t.mix.autopan_depth,
+                    shape: t.mix.autopan_shape,
+                },
+                AudioCommand::SetTrackFrequency { track, hz: t.mix.frequency },
+                AudioCommand::SetTrackAdsr {
+                    track,
+                    attack: t.mix.env_attack,
+                    decay: t.mix.env_decay,
+                    sustain: t.mix.env_sustain,
+                    release: t.mix.env_release,
+                },
+                AudioCommand::SetTrackSampleSpeed { track, ratio: t.mix.sample_speed },
+                AudioCommand::SetTrackSampleLoop { track, on: t.mix.sample_loop },
+                AudioCommand::SetTrackLoopCrossfade { track, ms: t.mix.sample_loop_crossfade },
+                AudioCommand::SetTrackReverse { track, on: t.mix.sample_reverse },
+                AudioCommand::SetTrackUnison {
+                    track,
+                    voices: t.mix.unison_voices,
+                    detune_cents: t.mix.unison_detune,
+                },
+                AudioCommand::SetTrackSync { track, master: t.mix.sync_source },
+                AudioCommand::SetTrackBus { track, bus: t.mix.bus },
+                AudioCommand::SetTrackSendDelay { track, value: t.mix.send_delay },
+                AudioCommand::SetTrackSendReverb { track, value: t.mix.send_reverb },
+                AudioCommand::SetWaveform { track, waveform: t.waveform },
+                AudioCommand::ClearPattern { track },
+            ]);
+            for (step, &on) in t.pattern.iter().enumerate() {
+                if on {
+                    cmds.push(AudioCommand::SetStep { track, step, on });
+                }
+            }
+        }
+
+        // After the track count, so per-track lanes find their tracks
+        cmds.push(AudioCommand::ClearAutomation);
+        for lane in &self.automation {
+            // A hand-edited file may list them out of order
+            let mut points = lane.points.clone();
+            automation::sort_points(&mut points);
+            cmds.push(AudioCommand::SetAutomation { param: lane.param, points });
+        }
+        cmds.push(AudioCommand::ClearModRoutes);
+        for (lfo, &ModLfo { rate, shape, phase }) in self.modulation.lfos.iter().enumerate() {
+            cmds.push(AudioCommand::SetModLfo { lfo, rate, shape, phase });
+        }
+        for &ModRoute { lfo, dest, depth, .. } in &self.modulation.routes {
+            cmds.push(AudioCommand::AddModRoute { lfo, dest, depth });
+        }
+
+        // Last, so restoring several additive solos doesn't clear them
+        cmds.push(AudioCommand::SetSoloMode { mode: m.solo_mode });
+
+        cmds
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid session file: {}", e))
+    }
+}
+
+fn validate_master(m: &MasterEffects) -> Result<(), String> {
+    for gain in [m.eq_low, m.eq_mid, m.eq_high, m.side_eq_low, m.side_eq_mid, m.side_eq_high] {
+        validate::eq_gain(gain)?;
+    }
+    validate::comp_threshold(m.comp_threshold)?;
+    validate::comp_ratio(m.comp_ratio)?;
+    validate::comp_attack(m.comp_attack)?;
+    validate::comp_release(m.comp_release)?;
+    validate::comp_makeup(m.comp_makeup)?;
+    validate::in_range("Compressor knee (dB)", m.comp_knee, 0.0, MAX_COMP_KNEE_DB)?;
+    validate::in_range("Delay feedback", m.delay_feedback, 0.0, MAX_FEEDBACK)?;
+    validate::level("Delay mix", m.delay_mix)?;
+    validate::in_range("Delay tone (Hz)", m.delay_tone, MIN_DELAY_TONE_HZ, MAX_DELAY_TONE_HZ)?;
+    if let Some(hz) = m.delay_low_cut {
+        validate::in_range("Delay low cut (Hz)", hz, MIN_DELAY_LOW_CUT_HZ, MAX_DELAY_LOW_CUT_HZ)?;
+    }
+    validate::level("Reverb size", m.reverb_size)?;
+    validate::level("Reverb damping", m.reverb_damping)?;
+    validate::level("Reverb mix", m.reverb_mix)?;
+    validate::in_range("Chorus rate (Hz)", m.chorus_rate, MIN_CHORUS_RATE, MAX_CHORUS_RATE)?;
+    validate::level("Chorus depth", m.chorus_depth)?;
+    validate::level("Chorus mix", m.chorus_mix)?;
+    validate::level("Limiter threshold", m.limiter_threshold)?;
+    validate::in_range("Clipper drive", m.clip_amount, 0.0, MAX_CLIP_AMOUNT)?;
+    validate::in_range("Clipper makeup (dB)", m.clip_makeup, 0.0, MAX_CLIP_MAKEUP_DB)?;
+    let ceiling = m.output_ceiling;
+    validate::in_range("Output ceiling (dBFS)", ceiling, MIN_OUTPUT_CEILING_DB, 0.0)?;
+    validate::level("Crossfeed", m.crossfeed)?;
+    validate::in_range("Stereo width", m.stereo_width, 0.0, MAX_STEREO_WIDTH)?;
+    validate::in_range("Auto-gain target (LUFS)", m.autogain_target, LUFS_FLOOR, 0.0)?;
+    validate::master_chain(&m.chain_order)?;
+    for (i, bus) in m.buses.iter().enumerate() {
+        validate_bus(bus).map_err(|e| format!("Bus {}: {}", i, e))?;
+    }
+    Ok(())
+}
+
+fn validate_bus(bus: &BusState) -> Result<(), String> {
+    validate::level("Bus volume", bus.volume)?;
+    for gain in [bus.eq_low, bus.eq_mid, bus.eq_high] {
+        validate::eq_gain(gain)?;
+    }
+    validate::comp_threshold(bus.comp_threshold)?;
+    validate::comp_ratio(bus.comp_ratio)?;
+    validate::comp_attack(bus.comp_attack)?;
+    validate::comp_release(bus.comp_release)?;
+    validate::comp_makeup(bus.comp_makeup)
+}
+
+fn validate_track(t: &TrackState) -> Result<(), String> {
+    validate::level("Track volume", t.volume)?;
+    validate::pan(t.pan)?;
+    validate::trim(t.trim)?;
+    for gain in [t.eq_low, t.eq_mid, t.eq_high] {
+        validate::eq_gain(gain)?;
+    }
+    validate::in_range("HPF frequency (Hz)", t.hpf_freq, MIN_HPF_HZ, MAX_HPF_HZ)?;
+    let allpass = t.allpass_freq;
+    validate::in_range("All-pass frequency (Hz)", allpass, MIN_ALLPASS_HZ, MAX_ALLPASS_HZ)?;
+    validate::in_range("Frequency (Hz)", t.frequency, MIN_FREQUENCY, MAX_FREQUENCY)?;
+    validate::in_range("Bit depth", t.crush_bits as f64, 1.0, MAX_CRUSH_BITS as f64)?;
+    let downsample = t.crush_downsample as f64;
+    validate::in_range("Downsample factor", downsample, 1.0, MAX_CRUSH_DOWNSAMPLE as f64)?;
+    validate::in_range("Gate threshold (dB)", t.gate_threshold, GATE_OFF_DB, 0.0)?;
+    validate::time_ms("Gate attack (ms)", t.gate_attack)?;
+    validate::time_ms("Gate hold (ms)", t.gate_hold)?;
+    validate::time_ms("Gate release (ms)", t.gate_release)?;
+    validate::in_range("Gate lookahead (ms)", t.gate_lookahead, 0.0, MAX_GATE_LOOKAHEAD_MS)?;
+    validate::comp_threshold(t.comp_threshold)?;
+    validate::comp_ratio(t.comp_ratio)?;
+    validate::comp_attack(t.comp_attack)?;
+    validate::comp_release(t.comp_release)?;
+    validate::level("Delay send", t.send_delay)?;
+    validate::level("Reverb send", t.send_reverb)?;
+    validate::time_ms("Envelope attack (ms)", t.env_attack)?;
+    validate::time_ms("Envelope decay (ms)", t.env_decay)?;
+    validate::level("Envelope sustain", t.env_sustain)?;
+    validate::time_ms("Envelope release (ms)", t.env_release)?;
+    validate::in_range("Sample speed", t.sample_speed, MIN_SAMPLE_SPEED, MAX_SAMPLE_SPEED)?;
+    let crossfade = t.sample_loop_crossfade;
+    validate::in_range("Loop crossfade (ms)", crossfade, 0.0, MAX_LOOP_CROSSFADE_MS)?;
+    let voices = t.unison_voices as f64;
+    validate::in_range("Unison voices", voices, 1.0, MAX_UNISON_VOICES as f64)?;
+    let detune = t.unison_detune;
+    validate::in_range("Unison detune (cents)", detune, 0.0, MAX_UNISON_DETUNE_CENTS)?;
+    validate::in_range("Auto-pan rate (Hz)", t.autopan_rate, MIN_LFO_RATE, MAX_LFO_RATE)?;
+    validate::level("Auto-pan depth", t.autopan_depth)?;
+    validate::lfo_rate("Tremolo rate (Hz)", t.tremolo_rate)?;
+    validate::level("Tremolo depth", t.tremolo_depth)
+}
+
+/// Track a per-track command targets
+pub fn track_of(cmd: &AudioCommand) -> Option<usize> {
+    match *cmd {
+        AudioCommand::SetTrackVolume { track, .. }
+        | AudioCommand::SetTrackPan { track, .. }
+        | AudioCommand::ToggleMute { track }
+        | AudioCommand::ToggleSolo { track }
+        | AudioCommand::SetMute { track, .. }
+        | AudioCommand::SetSolo { track, .. }
+        | AudioCommand::SetTrackSoloSafe { track, .. }
+        | AudioCommand::SetTrackPanMode { track, .. }
+        | AudioCommand::ToggleTrackPolarity { track }
+        | AudioCommand::SetTrackPolarity { track, .. }
+        | AudioCommand::SetTrackTrim { track, .. }
+        | AudioCommand::SetTrackEqLow { track, .. }
+        | AudioCommand::SetTrackEqMid { track, .. }
+        | AudioCommand::SetTrackEqHigh { track, .. }
+        | AudioCommand::SetTrackBitcrush { track, .. }
+        | AudioCommand::SetTrackHpf { track, .. }
+        | AudioCommand::SetTrackAllpass { track, .. }
+        | AudioCommand::SetTrackGate { track, .. }
+        | AudioCommand::SetTrackGateLookahead { track, .. }
+        | AudioCommand::SetTrackCompThreshold { track, .. }
+        | AudioCommand::SetTrackCompRatio { track, .. }
+        | AudioCommand::SetTrackCompAttack { track, .. }
+        | AudioCommand::SetTrackCompRelease { track, .. }
+        | AudioCommand::SetTrackSidechain { track, .. }
+        | AudioCommand::SetTrackAutopan { track, .. }
+        | AudioCommand::SetTrackTremolo { track, .. }
+        | AudioCommand::SetTrackFrequency { track, .. }
+        | AudioCommand::SetTrackAdsr { track, .. }
+        | AudioCommand::SetTrackSampleSpeed { track, .. }
+        | AudioCommand::SetTrackSampleLoop { track, .. }
+        | AudioCommand::SetTrackLoopCrossfade { track, .. }
+        | AudioCommand::SetTrackReverse { track, .. }
+        | AudioCommand::SetTrackUnison { track, .. }
+        | AudioCommand::SetTrackSync { track, .. }
+        | AudioCommand::SetTrackBus { track, .. }
+        | AudioCommand::SetTrackSendDelay { track, .. }
+        | AudioCommand::SetTrackSendReverb { track, .. }
+        | AudioCommand::SetWaveform { track, .. }
+        | AudioCommand::SetStep { track, .. }
+        | AudioCommand::ClearPattern { track } => Some(track),
+        _ => None,
+    }
+}
+
+/// Sub-bus a per-bus command targets
+pub fn bus_of(cmd: &AudioCommand) -> Option<usize> {
+    match *cmd {
+        AudioCommand::SetBusVolume { bus, .. }
+        | AudioCommand::SetBusMute { bus, .. }
+        | AudioCommand::SetBusEq { bus, .. }
+        | AudioCommand::SetBusCompressor { bus, .. } => Some(bus),
+        _ => None,
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::{AutomationPoint, Interpolation};
+    use crate::delay::NoteDivision;
+    use crate::lfo::LfoRate;
+    use crate::midi::{Cc, MidiParam};
+    use crate::mixer::MasterStage;
+
+    fn edited_session() -> SessionState {
+        let mut session = SessionState {
+            bpm: 95,
+            master_volume: 0.5,
+            loop_length: 48,
+            ..Default::default()
+        };
+        session.master.eq_low = -3.0;
+        session.master.comp_ratio = 4.0;
+        session.master.delay_division = NoteDivision::DottedEighth;
+        session.master.clip_amount = 3.0;
+        session.master.mono = true;
+        session.tracks[2].mix.volume = 0.25;
+        session.tracks[2].mix.pan = -0.5;
+        session.tracks[2].mix.muted = true;
+        session.tracks[4].mix.soloed = true;
+        session.tracks[4].mix.eq_high = 6.0;
+        session.tracks[4].waveform = Waveform::Saw;
+        session.tracks[0].pattern[0] = true;
+        session.tracks[0].pattern[8] = true;
+        session.tracks[6].pattern[31] = true;
+        session.tracks.push(TrackSession::for_track(7));
+        session.tracks[7].pattern[4] = true;
+        session.automation.push(AutomationLane {
+            param: MidiParam::TrackPan { track: 2 },
+            points: vec![AutomationPoint { step: 4.0, value: 0.5, curve: Interpolation::Hold }],
+        });
+        session.modulation.set_lfo(1, ModLfo { phase: 0.25, ..ModLfo::default() });
+        session.modulation.add_route(1, MidiParam::ReverbMix, -0.5, 0.0);
+        session
+    }
+
+    #[test]
+    fn test_restore_commands_round_trip() {
+        let saved = edited_session();
+
+        // Replaying the restore commands over a dirty session reproduces it
+        let mut restored = SessionState::default();
+        restored.tracks[6].pattern[3] = true;
+        restored.tracks[1].mix.muted = true;
+        let points = vec![AutomationPoint { step: 0.0, value: 0.0, curve: Interpolation::Linear }];
+        automation::set_lane(&mut restored.automation, MidiParam::ReverbMix, points);
+        for cmd in saved.commands() {
+            restored.apply(&cmd);
+        }
+        assert_eq!(restored, saved);
+    }
+
+    #[test]
+    fn test_removing_a_track_reindexes_midi_mappings() {
+        let mut session = SessionState::default();
+        let cc = |controller| Cc { channel: 0, controller };
+        session.midi_mappings = vec![
+            CcMapping { cc: cc(1), param: MidiParam::TrackVolume { track: 1 } },
+            CcMapping { cc: cc(2), param: MidiParam::TrackPan { track: 3 } },
+            CcMapping { cc: cc(3), param: MidiParam::ReverbMix },
+        ];
+        session.apply(&AudioCommand::RemoveTrack { track: 1 });
+        assert_eq!(
+            session.midi_mappings,
+            vec![
+                CcMapping { cc: cc(2), param: MidiParam::TrackPan { track: 2 } },
+                CcMapping { cc: cc(3), param: MidiParam::ReverbMix },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_fits_track_and_step_counts() {
+        let mut session = SessionState::default();
+        session.tracks.truncate(2);
+        session.tracks[0].pattern.truncate(4);
+        session.normalize();
+        assert_eq!(session.tracks.len(), 2);
+        assert!(session.tracks.iter().all(|t| t.pattern.len() == MAX_STEPS));
+
+        session.tracks.clear();
+        session.normalize();
+        assert_eq!(session.tracks.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_rejects_out_of_range_settings() {
+        assert_eq!(SessionState::default().validate(), Ok(()));
+        assert_eq!(edited_session().validate(), Ok(()));
+
+        let mut session = edited_session();
+        session.bpm = 5000;
+        assert_eq!(session.validate(), Err("BPM must be between 20 and 999, got 5000".into()));
+
+        let mut session = edited_session();
+        session.tracks[2].mix.pan = 3.0;
+        assert_eq!(session.validate(), Err("Track 2: Pan must be between -1 and 1, got 3".into()));
+
+        let mut session = edited_session();
+        session.master.buses[1].volume = -1.0;
+        let error = session.validate().unwrap_err();
+        assert!(error.starts_with("Bus 1: Bus volume"), "{}", error);
+    }
+
+    /// `edited_session` with an edit someone could make to the saved file
+    fn validate_edited(edit: impl FnOnce(&mut SessionState)) -> Result<(), String> {
+        let mut session = edited_session();
+        edit(&mut session);
+        session.validate()
+    }
+
+    #[test]
+    fn test_validate_rejects_hand_edited_files() {
+        assert_eq!(
+            validate_edited(|s| s.automation[0].points[0].step = 100.0),
+            Err("Automation step must be between 0 and 64, got 100".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.automation[0].points[0].value = -2.0),
+            Err("Automation value must be between -1 and 1, got -2".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.automation[0].points[0].value = f64::NAN),
+            Err("Automation value must be between -1 and 1, got NaN".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.automation[0].param = MidiParam::TrackPan { track: 9 }),
+            Err("Track 9 does not exist (there are 8 tracks)".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.modulation.lfos[1].rate = LfoRate::Hz(50.0)),
+            Err("LFO rate (Hz) must be between 0.01 and 20, got 50".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.modulation.routes[0].depth = 3.0),
+            Err("Modulation depth must be between -1 and 1, got 3".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.modulation.routes[0].lfo = 4),
+            Err("LFO 4 does not exist (there are 4 LFOs)".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| {
+                let cc = Cc { channel: 0, controller: 7 };
+                let param = MidiParam::TrackVolume { track: 12 };
+                s.midi_mappings.push(CcMapping { cc, param });
+            }),
+            Err("Track 12 does not exist (there are 8 tracks)".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.master.chain_order[0] = MasterStage::Limiter),
+            Err("Master stage Limiter appears more than once".to_string())
+        );
+        assert_eq!(
+            validate_edited(|s| s.tracks[3].mix.tremolo_rate = LfoRate::Hz(0.0)),
+            Err("Track 3: Tremolo rate (Hz) must be between 0.01 and 20, got 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_restore_sorts_hand_edited_automation() {
+        let mut session = edited_session();
+        session.automation[0].points = vec![
+            AutomationPoint { step: 8.0, value: 0.1, curve: Interpolation::Linear },
+            AutomationPoint { step: 2.0, value: 0.9, curve: Interpolation::Linear },
+        ];
+        assert_eq!(session.validate(), Ok(()));
+        let points = session.commands().into_iter().find_map(|cmd| match cmd {
+            AudioCommand::SetAutomation { points, .. } => Some(points),
+            _ => None,
+        });
+        let steps: Vec<f64> = points.unwrap().iter().map(|p| p.step).collect();
+        assert_eq!(steps, [2.0, 8.0]);
+    }
+
+    #[test]
+    fn test_session_json_round_trip() {
+        let saved = edited_session();
+        let json = serde_json::to_string(&saved).unwrap();
+        let loaded: SessionState = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, saved);
+    }
+
+    #[test]
+    fn test_session_json_missing_fields_default() {
+        let json = r#"{"version": 0, "bpm": 90, "tracks": [{"volume": 0.5}]}"#;
+        let loaded: SessionState = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.bpm, 90);
+        assert_eq!(loaded.master_volume, DEFAULT_MASTER_VOLUME);
+        assert_eq!(loaded.master, MasterEffects::default());
+        assert_eq!(loaded.tracks[0].mix.volume, 0.5);
+        assert_eq!(loaded.tracks[0].mix.pan, 0.0);
+        assert_eq!(loaded.tracks[0].pattern.len(), MAX_STEPS);
+    }
+}
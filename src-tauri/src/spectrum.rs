@@ -0,0 +1,149 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - SPECTRUM
+// Master output FFT, fed lock-free by the callback and computed on a
+// helper thread
+// ============================================================
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// Window length; bin `k` is centered on `k * sample_rate / FFT_SIZE` Hz
+pub const FFT_SIZE: usize = 2048;
+
+/// Bins reported (DC up to just below Nyquist)
+pub const SPECTRUM_BINS: usize = FFT_SIZE / 2;
+
+/// Reported for empty bins
+pub const SPECTRUM_FLOOR_DB: f32 = -120.0;
+
+/// The newest `FFT_SIZE` master samples (mono), overwritten in place.
+///
+/// The callback only stores atomics, so it never waits on the analyzer; a
+/// read racing a write can tear at the oldest samples, where the window is
+/// near zero anyway.
+pub struct SpectrumFeed {
+    samples: Vec<AtomicU32>, // f32 bits
+    written: AtomicUsize,
+}
+
+impl SpectrumFeed {
+    pub fn new() -> Self {
+        Self {
+            samples: (0..FFT_SIZE).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn push(&self, sample: f32) {
+        let pos = self.written.load(Ordering::Relaxed);
+        self.samples[pos % FFT_SIZE].store(sample.to_bits(), Ordering::Relaxed);
+        self.written.store(pos.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Samples pushed so far (wrapping); unchanged means nothing new
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    /// Copy the window out, oldest sample first
+    fn read(&self, out: &mut [f64]) {
+        let start = self.written();
+        for (i, x) in out.iter_mut().enumerate() {
+            let bits = self.samples[start.wrapping_add(i) % FFT_SIZE].load(Ordering::Relaxed);
+            *x = f32::from_bits(bits) as f64;
+        }
+    }
+}
+
+impl Default for SpectrumFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hann-windowed magnitude spectrum of a `SpectrumFeed`, in dBFS (a
+/// full-scale sine reads 0 dB in its bin)
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f64>>,
+    window: Vec<f64>,
+    samples: Vec<f64>,
+    buffer: Vec<Complex<f64>>,
+    magnitudes: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let window: Vec<f64> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos())
+            .collect();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            samples: vec![0.0; FFT_SIZE],
+            buffer: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            magnitudes: vec![SPECTRUM_FLOOR_DB; SPECTRUM_BINS],
+        }
+    }
+
+    /// Analyze the feed's current window
+    pub fn analyze(&mut self, feed: &SpectrumFeed) -> &[f32] {
+        feed.read(&mut self.samples);
+        for ((out, x), w) in self.buffer.iter_mut().zip(&self.samples).zip(&self.window) {
+            *out = Complex::new(x * w, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        // Hann coherent gain is 0.5, and each real sine splits across the
+        // positive and negative bins
+        let scale = 2.0 / (0.5 * FFT_SIZE as f64);
+        for (db, bin) in self.magnitudes.iter_mut().zip(&self.buffer) {
+            let magnitude = bin.norm() * scale;
+            *db = if magnitude > 0.0 {
+                ((20.0 * magnitude.log10()) as f32).max(SPECTRUM_FLOOR_DB)
+            } else {
+                SPECTRUM_FLOOR_DB
+            };
+        }
+        &self.magnitudes
+    }
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1khz_tone_peaks_in_its_bin() {
+        let sample_rate = 48000.0;
+        let feed = SpectrumFeed::new();
+        for i in 0..FFT_SIZE * 2 {
+            feed.push((0.5 * (2.0 * PI * 1000.0 * i as f64 / sample_rate).sin()) as f32);
+        }
+
+        let mut analyzer = SpectrumAnalyzer::new();
+        let spectrum = analyzer.analyze(&feed);
+        let peak = (0..SPECTRUM_BINS)
+            .max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b]))
+            .unwrap();
+
+        let expected = (1000.0 * FFT_SIZE as f64 / sample_rate).round() as usize;
+        assert_eq!(peak, expected);
+        // Within the Hann scalloping loss of -6 dBFS
+        assert!((spectrum[peak] + 6.0).abs() < 1.5, "peak {} dB", spectrum[peak]);
+    }
+}
@@ -0,0 +1,519 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - SYNTH
+// Band-limited oscillators for the synth tracks
+// ============================================================
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    /// Flat spectrum; ignores pitch and unison
+    WhiteNoise,
+    /// -3 dB/octave spectrum; ignores pitch and unison
+    PinkNoise,
+}
+
+/// Playable oscillator range, Hz
+pub const MIN_FREQUENCY: f64 = 1.0;
+pub const MAX_FREQUENCY: f64 = 20000.0;
+
+/// Equal-tempered pitch of a MIDI note, A4 (69) = 440 Hz
+pub fn midi_to_frequency(note: u8) -> f64 {
+    440.0 * 2f64.powf((note as f64 - 69.0) / 12.0)
+}
+
+/// PolyBLEP residual for a unit step at phase 0, where `dt` is the phase
+/// increment per sample
+#[inline]
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Unison voices per oscillator; phases are stored for this many so
+/// changing the count never allocates on the audio thread
+pub const MAX_UNISON_VOICES: usize = 8;
+pub const MAX_UNISON_DETUNE_CENTS: f64 = 100.0;
+
+// Each new oscillator's noise seed; stepping by the golden ratio keeps
+// tracks' noise uncorrelated
+static NEXT_SEED: AtomicU32 = AtomicU32::new(0x9E37_79B9);
+
+/// Brings the pink filter's output back to about the white level
+const PINK_GAIN: f64 = 0.11;
+
+/// Noise for the noise waveforms: xorshift32 white noise, and pink noise
+/// from it through Paul Kellet's filter (seven first-order sections)
+#[derive(Clone, Debug)]
+struct Noise {
+    rng: u32,
+    pink: [f64; 7],
+}
+
+impl Noise {
+    fn new() -> Self {
+        let seed = NEXT_SEED.fetch_add(0x9E37_79B9, Ordering::Relaxed);
+        Self {
+            rng: seed.max(1),
+            pink: [0.0; 7],
+        }
+    }
+
+    /// Uniform in -1.0..1.0
+    #[inline]
+    fn white(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f64 / 2_147_483_648.0 - 1.0
+    }
+
+    #[inline]
+    fn pink(&mut self) -> f64 {
+        let white = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let sum: f64 = b.iter().sum();
+        b[6] = white * 0.115926;
+        ((sum + white * 0.5362) * PINK_GAIN).clamp(-1.0, 1.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    phases: [f64; MAX_UNISON_VOICES], // 0.0..1.0, one per voice
+    voices: usize,
+    // The outer voices sit this far above and below the pitch
+    detune_cents: f64,
+    // Set when the last `next` wrapped the first voice: how far past the
+    // wrap it ended, in samples
+    wrapped: Option<f64>,
+    // Kept across notes and resets, so every hit is different noise
+    noise: Noise,
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::default(),
+            phases: [0.0; MAX_UNISON_VOICES],
+            voices: 1,
+            detune_cents: 0.0,
+            wrapped: None,
+            noise: Noise::new(),
+        }
+    }
+}
+
+impl Oscillator {
+    pub fn reset(&mut self) {
+        self.phases = [0.0; MAX_UNISON_VOICES];
+        self.wrapped = None;
+    }
+
+    /// Stack `voices` copies spread evenly across +/-`detune_cents`
+    pub fn set_unison(&mut self, voices: usize, detune_cents: f64) {
+        self.voices = voices.clamp(1, MAX_UNISON_VOICES);
+        self.detune_cents = detune_cents.clamp(0.0, MAX_UNISON_DETUNE_CENTS);
+    }
+
+    /// Phase increment of unison voice `v` for a base increment `dt`
+    #[inline]
+    fn voice_dt(&self, v: usize, dt: f64) -> f64 {
+        if self.voices == 1 {
+            return dt;
+        }
+        let spread = (self.voices - 1) as f64;
+        let cents = self.detune_cents * (2.0 * v as f64 / spread - 1.0);
+        dt * 2f64.powf(cents / 1200.0)
+    }
+
+    /// Next sample in -1.0..=1.0 (the voices are averaged)
+    #[inline]
+    pub fn next(&mut self, frequency: f64, sample_rate: f64) -> f64 {
+        match self.waveform {
+            Waveform::WhiteNoise => return self.noise.white(),
+            Waveform::PinkNoise => return self.noise.pink(),
+            _ => {}
+        }
+        let dt = frequency / sample_rate;
+        let before = self.phases[0];
+        let mut sum = 0.0;
+        for v in 0..self.voices {
+            let voice_dt = self.voice_dt(v, dt);
+            sum += Self::voice(self.waveform, &mut self.phases[v], voice_dt);
+        }
+        self.wrapped = (self.phases[0] < before).then(|| self.phases[0] / self.voice_dt(0, dt));
+        sum / self.voices as f64
+    }
+
+    /// Samples since the first voice wrapped, if the last `next` wrapped it
+    pub fn wrapped(&self) -> Option<f64> {
+        self.wrapped
+    }
+
+    /// Hard sync: restart every voice as if it had been at phase 0
+    /// `elapsed` samples ago
+    pub fn sync(&mut self, elapsed: f64, frequency: f64, sample_rate: f64) {
+        let dt = frequency / sample_rate;
+        for v in 0..self.voices {
+            self.phases[v] = (elapsed * self.voice_dt(v, dt)).fract();
+        }
+    }
+
+    /// Sample at `phase`, then advance it by `dt`
+    #[inline]
+    fn voice(waveform: Waveform, phase: &mut f64, dt: f64) -> f64 {
+        let t = *phase;
+        let value = match waveform {
+            Waveform::Sine => (t * 2.0 * PI).sin(),
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Square => {
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep((t + 0.5) % 1.0, dt)
+            }
+            // Harmonics already fall at 12 dB/octave, so aliasing stays low
+            // without correction
+            Waveform::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+            Waveform::WhiteNoise | Waveform::PinkNoise => 0.0,
+        };
+
+        *phase += dt;
+        if *phase >= 1.0 {
+            *phase -= 1.0;
+        }
+
+        value
+    }
+}
+
+// ============================================================
+// ENVELOPE
+// ============================================================
+
+// Level below which decay / release count as finished
+const ENVELOPE_FLOOR: f64 = 1e-5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Stage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// ADSR amplitude envelope: a linear attack, then exponential decay to the
+/// sustain level and exponential release after note-off
+#[derive(Clone, Debug)]
+pub struct Adsr {
+    stage: Stage,
+    level: f64,
+    attack_step: f64, // level added per sample
+    decay_coeff: f64,
+    sustain: f64,
+    release_coeff: f64,
+}
+
+impl Adsr {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut adsr = Self {
+            stage: Stage::Idle,
+            level: 0.0,
+            attack_step: 1.0,
+            decay_coeff: 0.0,
+            sustain: 0.0,
+            release_coeff: 0.0,
+        };
+        adsr.set(1.0, 300.0, 0.0, 300.0, sample_rate);
+        adsr
+    }
+
+    /// Attack, decay and release in ms; sustain level 0.0..=1.0
+    pub fn set(
+        &mut self,
+        attack_ms: f64,
+        decay_ms: f64,
+        sustain: f64,
+        release_ms: f64,
+        sample_rate: f64,
+    ) {
+        let samples = |ms: f64| (ms * 0.001 * sample_rate).max(1.0);
+        self.attack_step = 1.0 / samples(attack_ms);
+        self.decay_coeff = (-1.0 / samples(decay_ms)).exp();
+        self.sustain = sustain.clamp(0.0, 1.0);
+        self.release_coeff = (-1.0 / samples(release_ms)).exp();
+    }
+
+    /// Note-on; the attack starts from the current level so retriggers
+    /// don't click
+    pub fn trigger(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    /// Note-off
+    pub fn release(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    /// Cut to silence with no release
+    pub fn kill(&mut self) {
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+    }
+
+    /// Advance one sample and return the level
+    #[inline]
+    pub fn next(&mut self) -> f64 {
+        match self.stage {
+            Stage::Idle => {}
+            Stage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level = self.sustain + (self.level - self.sustain) * self.decay_coeff;
+                if self.level - self.sustain < ENVELOPE_FLOOR {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            // Follows sustain changes made while the note is held
+            Stage::Sustain => self.level = self.sustain,
+            Stage::Release => {
+                self.level *= self.release_coeff;
+                if self.level < ENVELOPE_FLOOR {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    fn render(waveform: Waveform, frequency: f64, sample_rate: f64, len: usize) -> Vec<f64> {
+        let mut osc = Oscillator {
+            waveform,
+            ..Default::default()
+        };
+        (0..len).map(|_| osc.next(frequency, sample_rate)).collect()
+    }
+
+    /// Magnitude of DFT bin `k` of `signal`
+    fn dft_magnitude(signal: &[f64], k: usize) -> f64 {
+        let n = signal.len() as f64;
+        let (re, im) = signal.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+            let w = 2.0 * PI * k as f64 * i as f64 / n;
+            (re + x * w.cos(), im - x * w.sin())
+        });
+        (re * re + im * im).sqrt()
+    }
+
+    #[test]
+    fn test_square_has_no_dc() {
+        // 3520 Hz doesn't divide 48 kHz evenly, so every BLEP position is hit
+        let signal = render(Waveform::Square, 3520.0, 48000.0, 48000);
+        let mean = signal.iter().sum::<f64>() / signal.len() as f64;
+        assert!(mean.abs() < 1e-3, "DC offset {}", mean);
+    }
+
+    #[test]
+    fn test_saw_harmonics_fall_as_one_over_n() {
+        // 100 Hz at 48 kHz: exactly 10 periods in 4800 samples, so
+        // harmonic n lands on bin 10 * n
+        let signal = render(Waveform::Saw, 100.0, 48000.0, 4800);
+        let fundamental = dft_magnitude(&signal, 10);
+        for n in 2..=8 {
+            let ratio = dft_magnitude(&signal, 10 * n) / fundamental;
+            let expected = 1.0 / n as f64;
+            assert!((ratio - expected).abs() < 0.02 * expected + 1e-3, "harmonic {}: {}", n, ratio);
+        }
+    }
+
+    #[test]
+    fn test_midi_note_frequency_and_phase_increment() {
+        assert_eq!(midi_to_frequency(69), 440.0);
+        assert!((midi_to_frequency(57) - 220.0).abs() < 1e-9);
+        assert!((midi_to_frequency(60) - 261.625_565).abs() < 1e-5);
+
+        let mut osc = Oscillator::default();
+        osc.next(440.0, 48000.0);
+        assert!((osc.phases[0] - 440.0 / 48000.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_adsr_attack_and_release() {
+        let sample_rate = 48000.0;
+        let mut adsr = Adsr::new(sample_rate);
+        adsr.set(1.0, 100.0, 0.5, 50.0, sample_rate);
+        adsr.trigger();
+
+        // 1 ms attack: full level within 48 samples
+        let peak = (0..50).map(|_| adsr.next()).fold(0.0, f64::max);
+        assert!(peak > 0.99, "peak {}", peak);
+
+        // Settles on the sustain level while held
+        let held = (0..48000).map(|_| adsr.next()).last().unwrap();
+        assert!((held - 0.5).abs() < 1e-4, "sustain {}", held);
+
+        // Release reaches silence well within a second
+        adsr.release();
+        let released = (0..48000).map(|_| adsr.next()).last().unwrap();
+        assert_eq!(released, 0.0);
+        assert!(!adsr.is_active());
+    }
+
+    #[test]
+    fn test_unison_one_voice_is_plain_and_three_beat() {
+        let plain = render(Waveform::Saw, 220.0, 48000.0, 4800);
+        let mut osc = Oscillator {
+            waveform: Waveform::Saw,
+            ..Default::default()
+        };
+        osc.set_unison(1, 50.0);
+        let single: Vec<f64> = (0..4800).map(|_| osc.next(220.0, 48000.0)).collect();
+        assert_eq!(single, plain);
+
+        // +/-10 cents around 440 Hz beats a few times a second, so the
+        // loudness of 10 ms windows swings over a second
+        let mut osc = Oscillator::default();
+        osc.set_unison(3, 10.0);
+        let signal: Vec<f64> = (0..48000).map(|_| osc.next(440.0, 48000.0)).collect();
+        let rms: Vec<f64> = signal
+            .chunks(480)
+            .map(|w| (w.iter().map(|x| x * x).sum::<f64>() / w.len() as f64).sqrt())
+            .collect();
+        let loudest = rms.iter().cloned().fold(0.0, f64::max);
+        let quietest = rms.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(loudest > 3.0 * quietest, "rms {} to {}", quietest, loudest);
+        assert!(signal.iter().all(|x| x.abs() <= 1.0 + 1e-9));
+    }
+
+    #[test]
+    fn test_hard_sync_resets_slave_on_master_wraps() {
+        let (master_hz, slave_hz, sample_rate) = (110.0, 310.0, 48000.0);
+        let mut master = Oscillator::default();
+        let mut slave = Oscillator::default();
+        let mut wraps = 0;
+        for _ in 0..48000 {
+            master.next(master_hz, sample_rate);
+            let before = slave.phases[0];
+            slave.next(slave_hz, sample_rate);
+            let natural = (before + slave_hz / sample_rate).fract();
+            match master.wrapped() {
+                Some(elapsed) => {
+                    slave.sync(elapsed, slave_hz, sample_rate);
+                    // Restarted from 0 at the master's wrap point, not a
+                    // whole sample later
+                    assert!(elapsed < 1.0);
+                    assert!((slave.phases[0] - elapsed * slave_hz / sample_rate).abs() < 1e-12);
+                    assert!((slave.phases[0] - natural).abs() > 1e-6);
+                    wraps += 1;
+                }
+                None => assert!((slave.phases[0] - natural).abs() < 1e-12),
+            }
+        }
+        assert_eq!(wraps, 110);
+    }
+
+    /// Mean power per FFT bin of `waveform` in each octave from 375 Hz to
+    /// 12 kHz at 48 kHz, in dB
+    fn octave_band_db(waveform: Waveform) -> Vec<f64> {
+        const SIZE: usize = 1024;
+        const FRAMES: usize = 128;
+        let signal = render(waveform, 440.0, 48000.0, SIZE * FRAMES);
+        let mut power = vec![0.0; SIZE / 2];
+        let fft = FftPlanner::new().plan_fft_forward(SIZE);
+        for frame in signal.chunks_exact(SIZE) {
+            let mut buffer: Vec<Complex<f64>> =
+                frame.iter().map(|&x| Complex::new(x, 0.0)).collect();
+            fft.process(&mut buffer);
+            for (p, bin) in power.iter_mut().zip(&buffer) {
+                *p += bin.norm_sqr();
+            }
+        }
+        // 375 Hz is bin 8
+        (3..8)
+            .map(|octave| {
+                let bins = &power[1 << octave..2 << octave];
+                10.0 * (bins.iter().sum::<f64>() / bins.len() as f64).log10()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_white_noise_is_flat_and_pink_falls_3db_per_octave() {
+        let white = octave_band_db(Waveform::WhiteNoise);
+        for pair in white.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 0.5, "white bands {:?}", white);
+        }
+        let pink = octave_band_db(Waveform::PinkNoise);
+        for pair in pink.windows(2) {
+            let slope = pair[1] - pair[0];
+            assert!((slope + 3.01).abs() < 0.5, "pink bands {:?}", pink);
+        }
+
+        // Two tracks' noise doesn't repeat each other
+        let a = render(Waveform::WhiteNoise, 440.0, 48000.0, 16);
+        let b = render(Waveform::WhiteNoise, 440.0, 48000.0, 16);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_outputs_stay_in_range() {
+        for waveform in [
+            Waveform::Sine,
+            Waveform::Saw,
+            Waveform::Square,
+            Waveform::Triangle,
+            Waveform::WhiteNoise,
+            Waveform::PinkNoise,
+        ] {
+            let signal = render(waveform, 3520.0, 48000.0, 4800);
+            assert!(signal.iter().all(|x| x.abs() <= 1.0 + 1e-9), "{:?}", waveform);
+        }
+    }
+}
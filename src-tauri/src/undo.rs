@@ -0,0 +1,216 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - UNDO
+// Undo / redo of mix parameter changes, kept on the Tauri side
+// ============================================================
+
+use std::mem::{discriminant, Discriminant};
+use std::time::{Duration, Instant};
+
+use crate::session::{bus_of, track_of, SessionState};
+use crate::AudioCommand;
+
+/// Oldest changes are forgotten past this many
+pub const MAX_UNDO_STEPS: usize = 200;
+
+/// Changes to the same parameter closer together than this (a knob drag)
+/// become one undo step
+pub const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Which parameter a command sets: its variant, plus the track or bus for
+/// per-track and per-bus commands
+type ParamKey = (Discriminant<AudioCommand>, Option<usize>);
+
+fn param_key(cmd: &AudioCommand) -> Option<ParamKey> {
+    let key = |cmd: &AudioCommand| (discriminant(cmd), track_of(cmd).or(bus_of(cmd)));
+    match *cmd {
+        // Toggles are recorded as the absolute setting they produce
+        AudioCommand::ToggleMute { track } => {
+            Some(key(&AudioCommand::SetMute { track, on: false }))
+        }
+        AudioCommand::ToggleSolo { track } => {
+            Some(key(&AudioCommand::SetSolo { track, on: false }))
+        }
+        AudioCommand::ToggleTrackPolarity { track } => {
+            Some(key(&AudioCommand::SetTrackPolarity { track, inverted: false }))
+        }
+        // Transport, pattern, tempo, track layout and monitoring aren't mix
+        // parameters
+        AudioCommand::Play
+        | AudioCommand::PlayWithCountIn { .. }
+        | AudioCommand::Stop
+        | AudioCommand::SetPosition { .. }
+        | AudioCommand::AddTrack
+        | AudioCommand::RemoveTrack { .. }
+        | AudioCommand::SetTrackCount { .. }
+        | AudioCommand::ResetMixer { .. }
+        | AudioCommand::Panic { .. }
+        | AudioCommand::LoadSample { .. }
+        | AudioCommand::FreezeTrack { .. }
+        | AudioCommand::UnfreezeTrack { .. }
+        | AudioCommand::TriggerSample { .. }
+        | AudioCommand::TriggerTrack { .. }
+        | AudioCommand::ReleaseTrack { .. }
+        | AudioCommand::SetWaveform { .. }
+        | AudioCommand::SetStep { .. }
+        | AudioCommand::ClearPattern { .. }
+        | AudioCommand::SetLoopLength { .. }
+        | AudioCommand::SetLoopEnabled { .. }
+        | AudioCommand::SetSwing { .. }
+        | AudioCommand::SetBpm { .. }
+        | AudioCommand::SetTimeSignature { .. }
+        | AudioCommand::SetMetronome { .. }
+        | AudioCommand::SetMonitorDim { .. }
+        | AudioCommand::SetMonitorMono { .. }
+        | AudioCommand::SetTrackListen { .. }
+        | AudioCommand::SetAutomation { .. }
+        | AudioCommand::ClearAutomation
+        | AudioCommand::SetModLfo { .. }
+        | AudioCommand::AddModRoute { .. }
+        | AudioCommand::RemoveModRoute { .. }
+        | AudioCommand::ClearModRoutes => None,
+        _ => Some(key(cmd)),
+    }
+}
+
+/// The command that sets `key` to its value in `session`
+fn current(session: &SessionState, key: ParamKey) -> Option<AudioCommand> {
+    session.commands().into_iter().find(|cmd| param_key(cmd) == Some(key))
+}
+
+struct Change {
+    key: ParamKey,
+    before: AudioCommand,
+    after: AudioCommand,
+    at: Instant,
+}
+
+#[derive(Default)]
+pub struct UndoHistory {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl UndoHistory {
+    /// Mirror `cmd` into `session`, recording the change if it sets a mix
+    /// parameter
+    pub fn apply(&mut self, session: &mut SessionState, cmd: &AudioCommand, now: Instant) {
+        let Some(key) = param_key(cmd) else {
+            // Recorded track indices would point at the wrong tracks, and
+            // a reset can't be stepped back through one parameter at a time
+            if matches!(
+                cmd,
+                AudioCommand::AddTrack
+                    | AudioCommand::RemoveTrack { .. }
+                    | AudioCommand::SetTrackCount { .. }
+                    | AudioCommand::ResetMixer { .. }
+            ) {
+                self.clear();
+            }
+            session.apply(cmd);
+            return;
+        };
+
+        let before = current(session, key);
+        session.apply(cmd);
+        let (Some(before), Some(after)) = (before, current(session, key)) else {
+            return;
+        };
+
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if last.key == key && now.duration_since(last.at) < COALESCE_WINDOW {
+                last.after = after;
+                last.at = now;
+                return;
+            }
+        }
+        self.undo.push(Change { key, before, after, at: now });
+        if self.undo.len() > MAX_UNDO_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Revert the newest change in `session`; returns the command that does
+    /// the same on the audio thread
+    pub fn undo(&mut self, session: &mut SessionState) -> Option<AudioCommand> {
+        let change = self.undo.pop()?;
+        let cmd = change.before.clone();
+        session.apply(&cmd);
+        self.redo.push(change);
+        Some(cmd)
+    }
+
+    /// Re-apply the newest undone change; the counterpart of `undo`
+    pub fn redo(&mut self, session: &mut SessionState) -> Option<AudioCommand> {
+        let change = self.redo.pop()?;
+        let cmd = change.after.clone();
+        session.apply(&cmd);
+        self.undo.push(change);
+        Some(cmd)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(session: &SessionState) -> f64 {
+        session.tracks[0].mix.volume
+    }
+
+    #[test]
+    fn test_undo_redo_volume_changes() {
+        let mut session = SessionState::default();
+        let mut history = UndoHistory::default();
+        let start = volume(&session);
+        let t0 = Instant::now();
+        let set = |value| AudioCommand::SetTrackVolume { track: 0, value };
+
+        // A drag (coalesced), then a separate change after a pause
+        history.apply(&mut session, &set(0.5), t0);
+        history.apply(&mut session, &set(0.4), t0 + Duration::from_millis(100));
+        history.apply(&mut session, &set(0.2), t0 + Duration::from_secs(2));
+
+        assert!(matches!(history.undo(&mut session), Some(AudioCommand::SetTrackVolume { .. })));
+        assert_eq!(volume(&session), 0.4);
+        history.undo(&mut session);
+        assert_eq!(volume(&session), start);
+        assert!(history.undo(&mut session).is_none());
+
+        history.redo(&mut session);
+        assert_eq!(volume(&session), 0.4);
+        history.redo(&mut session);
+        assert_eq!(volume(&session), 0.2);
+        assert!(history.redo(&mut session).is_none());
+
+        // A new change drops the redo branch
+        history.undo(&mut session);
+        history.apply(&mut session, &set(0.9), t0 + Duration::from_secs(4));
+        assert!(history.redo(&mut session).is_none());
+        history.undo(&mut session);
+        assert_eq!(volume(&session), 0.4);
+    }
+
+    #[test]
+    fn test_toggles_undo_and_non_mix_commands_are_not_recorded() {
+        let mut session = SessionState::default();
+        let mut history = UndoHistory::default();
+        let now = Instant::now();
+
+        history.apply(&mut session, &AudioCommand::ToggleMute { track: 1 }, now);
+        history.apply(&mut session, &AudioCommand::SetStep { track: 1, step: 0, on: true }, now);
+        history.undo(&mut session);
+        assert!(!session.tracks[1].mix.muted);
+        assert!(session.tracks[1].pattern[0]);
+        assert!(history.undo(&mut session).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+// ============================================================
+// NEXUS-X RUST AUDIO ENGINE - VALIDATION
+// Range checks for Tauri command arguments. The audio thread still clamps
+// what it receives; these turn bad input into an error the UI can show.
+// ============================================================
+
+use crate::automation::AutomationPoint;
+use crate::lfo::{LfoRate, MAX_LFO_RATE, MIN_LFO_RATE};
+use crate::mixer::{
+    MasterStage, MASTER_STAGES, MAX_COMP_ATTACK_MS, MAX_COMP_MAKEUP_DB, MAX_COMP_RATIO,
+    MAX_COMP_RELEASE_MS, MAX_EQ_DB, MAX_TRIM_DB, MIN_COMP_ATTACK_MS, MIN_COMP_RELEASE_MS,
+    MIN_COMP_THRESHOLD_DB, NUM_SUB_BUSES,
+};
+use crate::midi::MidiParam;
+use crate::modulation::MAX_MOD_LFOS;
+use crate::sequencer::{MAX_BPM, MAX_STEPS, MIN_BPM};
+
+pub fn track(track: usize, count: usize) -> Result<(), String> {
+    if track >= count {
+        return Err(format!("Track {} does not exist (there are {} tracks)", track, count));
+    }
+    Ok(())
+}
+
+pub fn bus(bus: usize) -> Result<(), String> {
+    if bus >= NUM_SUB_BUSES {
+        let count = NUM_SUB_BUSES;
+        return Err(format!("Bus {} does not exist (there are {} sub-buses)", bus, count));
+    }
+    Ok(())
+}
+
+pub fn mod_lfo(lfo: usize) -> Result<(), String> {
+    if lfo >= MAX_MOD_LFOS {
+        return Err(format!("LFO {} does not exist (there are {} LFOs)", lfo, MAX_MOD_LFOS));
+    }
+    Ok(())
+}
+
+/// A pattern step
+pub fn step(step: usize) -> Result<(), String> {
+    if step >= MAX_STEPS {
+        return Err(format!("Step {} does not exist (patterns have {} steps)", step, MAX_STEPS));
+    }
+    Ok(())
+}
+
+/// A learnable / automatable parameter, on one of `count` tracks if it's a
+/// track's
+pub fn midi_param(param: MidiParam, count: usize) -> Result<(), String> {
+    param.track().map_or(Ok(()), |t| track(t, count))
+}
+
+/// Breakpoints for `param`'s lane: steps within the pattern, values within
+/// the parameter's range
+pub fn automation_points(param: MidiParam, points: &[AutomationPoint]) -> Result<(), String> {
+    let (min, max) = param.range();
+    for point in points {
+        in_range("Automation step", point.step, 0.0, MAX_STEPS as f64)?;
+        in_range("Automation value", point.value, min, max)?;
+    }
+    Ok(())
+}
+
+pub fn bpm(bpm: u64) -> Result<(), String> {
+    if !(MIN_BPM..=MAX_BPM).contains(&bpm) {
+        return Err(format!("BPM must be between {} and {}, got {}", MIN_BPM, MAX_BPM, bpm));
+    }
+    Ok(())
+}
+
+/// `what` names the parameter in the message; NaN is always out of range
+pub fn in_range(what: &str, value: f64, min: f64, max: f64) -> Result<(), String> {
+    if !(min..=max).contains(&value) {
+        return Err(format!("{} must be between {} and {}, got {}", what, min, max, value));
+    }
+    Ok(())
+}
+
+pub fn eq_gain(db: f64) -> Result<(), String> {
+    in_range("EQ gain (dB)", db, -MAX_EQ_DB, MAX_EQ_DB)
+}
+
+pub fn trim(db: f64) -> Result<(), String> {
+    in_range("Trim (dB)", db, -MAX_TRIM_DB, MAX_TRIM_DB)
+}
+
+/// Volumes and send levels, 0.0 to 1.0
+pub fn level(what: &str, value: f64) -> Result<(), String> {
+    in_range(what, value, 0.0, 1.0)
+}
+
+pub fn pan(value: f64) -> Result<(), String> {
+    in_range("Pan", value, -1.0, 1.0)
+}
+
+/// Free-running LFO rates; tempo-synced divisions always fit
+pub fn lfo_rate(what: &str, rate: LfoRate) -> Result<(), String> {
+    match rate {
+        LfoRate::Hz(hz) => in_range(what, hz, MIN_LFO_RATE, MAX_LFO_RATE),
+        LfoRate::Synced(_) => Ok(()),
+    }
+}
+
+pub fn lfo_phase(phase: f64) -> Result<(), String> {
+    in_range("LFO phase", phase, 0.0, 1.0)
+}
+
+pub fn mod_depth(depth: f64) -> Result<(), String> {
+    in_range("Modulation depth", depth, -1.0, 1.0)
+}
+
+/// Longest envelope or gate time, in ms
+pub const MAX_TIME_MS: f64 = 10_000.0;
+
+/// Envelope and gate attack / hold / decay / release times
+pub fn time_ms(what: &str, ms: f64) -> Result<(), String> {
+    in_range(what, ms, 0.0, MAX_TIME_MS)
+}
+
+// Compressor settings, shared by the master, track and bus compressors
+
+pub fn comp_threshold(db: f64) -> Result<(), String> {
+    in_range("Compressor threshold (dB)", db, MIN_COMP_THRESHOLD_DB, 0.0)
+}
+
+pub fn comp_ratio(ratio: f64) -> Result<(), String> {
+    in_range("Compressor ratio", ratio, 1.0, MAX_COMP_RATIO)
+}
+
+pub fn comp_attack(ms: f64) -> Result<(), String> {
+    in_range("Compressor attack (ms)", ms, MIN_COMP_ATTACK_MS, MAX_COMP_ATTACK_MS)
+}
+
+pub fn comp_release(ms: f64) -> Result<(), String> {
+    in_range("Compressor release (ms)", ms, MIN_COMP_RELEASE_MS, MAX_COMP_RELEASE_MS)
+}
+
+pub fn comp_makeup(db: f64) -> Result<(), String> {
+    in_range("Compressor makeup (dB)", db, 0.0, MAX_COMP_MAKEUP_DB)
+}
+
+/// A master chain order naming every stage exactly once
+pub fn master_chain(order: &[MasterStage]) -> Result<[MasterStage; MASTER_STAGES], String> {
+    for (i, stage) in order.iter().enumerate() {
+        if order[..i].contains(stage) {
+            return Err(format!("Master stage {:?} appears more than once", stage));
+        }
+    }
+    order.try_into().map_err(|_| {
+        let count = order.len();
+        format!("Master chain needs all {} stages, got {}", MASTER_STAGES, count)
+    })
+}
+
+// ============================================================
+// TESTS
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::Interpolation;
+    use crate::delay::NoteDivision;
+    use crate::mixer::DEFAULT_MASTER_CHAIN;
+
+    #[test]
+    fn test_out_of_range_inputs_name_the_problem() {
+        assert_eq!(track(3, 4), Ok(()));
+        assert_eq!(track(4, 4), Err("Track 4 does not exist (there are 4 tracks)".to_string()));
+        assert_eq!(bus(4), Err("Bus 4 does not exist (there are 4 sub-buses)".to_string()));
+        assert_eq!(bpm(20), Ok(()));
+        assert_eq!(bpm(1000), Err("BPM must be between 20 and 999, got 1000".to_string()));
+        assert_eq!(bpm(0), Err("BPM must be between 20 and 999, got 0".to_string()));
+        assert_eq!(eq_gain(-24.0), Ok(()));
+        assert_eq!(
+            eq_gain(30.0),
+            Err("EQ gain (dB) must be between -24 and 24, got 30".to_string())
+        );
+        assert_eq!(pan(-1.5), Err("Pan must be between -1 and 1, got -1.5".to_string()));
+        assert_eq!(
+            level("Track volume", f64::NAN),
+            Err("Track volume must be between 0 and 1, got NaN".to_string())
+        );
+        assert_eq!(step(63), Ok(()));
+        assert_eq!(step(64), Err("Step 64 does not exist (patterns have 64 steps)".to_string()));
+        assert_eq!(midi_param(MidiParam::ReverbMix, 0), Ok(()));
+        assert_eq!(
+            midi_param(MidiParam::TrackPan { track: 7 }, 7),
+            Err("Track 7 does not exist (there are 7 tracks)".to_string())
+        );
+        assert_eq!(comp_ratio(20.0), Ok(()));
+        assert_eq!(
+            comp_ratio(0.5),
+            Err("Compressor ratio must be between 1 and 20, got 0.5".to_string())
+        );
+        assert_eq!(
+            comp_threshold(3.0),
+            Err("Compressor threshold (dB) must be between -60 and 0, got 3".to_string())
+        );
+        assert_eq!(
+            comp_attack(0.0),
+            Err("Compressor attack (ms) must be between 0.1 and 200, got 0".to_string())
+        );
+        assert_eq!(
+            comp_release(5000.0),
+            Err("Compressor release (ms) must be between 10 and 2000, got 5000".to_string())
+        );
+        assert_eq!(
+            comp_makeup(-1.0),
+            Err("Compressor makeup (dB) must be between 0 and 24, got -1".to_string())
+        );
+        assert_eq!(
+            time_ms("Gate hold (ms)", -5.0),
+            Err("Gate hold (ms) must be between 0 and 10000, got -5".to_string())
+        );
+        assert_eq!(
+            in_range("Swing", 0.8, 0.0, 0.75),
+            Err("Swing must be between 0 and 0.75, got 0.8".to_string())
+        );
+        assert_eq!(lfo_rate("LFO rate (Hz)", LfoRate::Synced(NoteDivision::Quarter)), Ok(()));
+        assert_eq!(
+            lfo_rate("LFO rate (Hz)", LfoRate::Hz(25.0)),
+            Err("LFO rate (Hz) must be between 0.01 and 20, got 25".to_string())
+        );
+        let point = AutomationPoint { step: 2.0, value: 1.5, curve: Interpolation::Hold };
+        assert_eq!(
+            automation_points(MidiParam::ReverbMix, &[point]),
+            Err("Automation value must be between 0 and 1, got 1.5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_master_chain_must_name_each_stage_once() {
+        let mut order = DEFAULT_MASTER_CHAIN.to_vec();
+        order.swap(8, 9);
+        assert!(master_chain(&order).is_ok());
+        order[0] = MasterStage::Limiter;
+        assert_eq!(
+            master_chain(&order),
+            Err("Master stage Limiter appears more than once".to_string())
+        );
+        assert_eq!(
+            master_chain(&order[1..]),
+            Err("Master chain needs all 10 stages, got 9".to_string())
+        );
+    }
+}